@@ -26,7 +26,7 @@ delete() {
 }
 
 ADDRESS_HOST=http://localhost
-PORTS=( $ORC_MAIN_PORT $ORC_PEER_PORT $ORC_MODALITY_ONE_PORT $ORC_MODALITY_TWO_PORT )
+PORTS=( $ORC_MAIN_PORT $ORC_PEER_PORT $ORC_MODALITY_ONE_PORT $ORC_MODALITY_TWO_PORT $ORC_PLUGINS_PORT )
 
 for port in "${PORTS[@]}"
 do
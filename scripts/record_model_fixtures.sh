@@ -0,0 +1,75 @@
+#!/usr/bin/env bash
+
+# Record the responses, the models are tested against (tests/test_models.rs), from the Docker
+# images of the Orthanc versions listed in ORC_FIXTURES_VERSIONS. A single DICOM file of the test
+# data is uploaded to each version.
+
+set -eo pipefail
+
+if ! hash jq 2>/dev/null; then
+    echo "jq not found, cannot continue"
+    exit 1
+fi
+
+VERSIONS=( ${ORC_FIXTURES_VERSIONS:-1.6.1 1.9.1 1.12.5} )
+PORT=${ORC_FIXTURES_PORT:-8031}
+FIXTURES_PATH=${ORC_FIXTURES_PATH:-./tests/data/models}
+ADDRESS=http://localhost:$PORT
+
+if [ ! -d $ORC_DATAFILES_PATH/initial ]; then
+    # ftp://medical.nema.org/medical/dicom/DataSets/WG16/Philips/
+    curl https://minuteware.net/orc/test_data.tar.bz2 > /tmp/test_data.tar.bz2
+    mkdir -p $ORC_DATAFILES_PATH
+    tar xjf /tmp/test_data.tar.bz2 -C $ORC_DATAFILES_PATH
+fi
+file=$(find $ORC_DATAFILES_PATH/initial -type f | sort | head -n 1)
+
+curl_command="curl -sf -H Expect:"
+if [ -n "$ORC_ORTHANC_USERNAME" ] && [ -n "$ORC_ORTHANC_PASSWORD" ]; then
+    curl_command="$curl_command --user $ORC_ORTHANC_USERNAME:$ORC_ORTHANC_PASSWORD"
+fi
+
+# Labels are supported since Orthanc 1.12.0
+has_labels() {
+    [ "$(printf '%s\n' 1.12.0 $1 | sort -V | head -n 1)" = "1.12.0" ]
+}
+
+record() {
+    $curl_command $ADDRESS/$1 | jq --indent 4 . > $dir/$2.json
+}
+
+for version in "${VERSIONS[@]}"; do
+    echo "Recording Orthanc $version"
+    container=$(docker run -d --rm -p 127.0.0.1:$PORT:8042 jodogne/orthanc:$version)
+    trap "docker stop $container > /dev/null" EXIT
+    until $curl_command $ADDRESS/system > /dev/null; do
+        sleep 1
+    done
+
+    dir=$FIXTURES_PATH/$version
+    mkdir -p $dir
+    instance=$($curl_command -X POST --data-binary @$file $ADDRESS/instances | jq -r .ID)
+    series=$($curl_command $ADDRESS/instances/$instance | jq -r .ParentSeries)
+    study=$($curl_command $ADDRESS/series/$series | jq -r .ParentStudy)
+    patient=$($curl_command $ADDRESS/studies/$study | jq -r .ParentPatient)
+    if has_labels $version; then
+        for resource in patients/$patient studies/$study series/$series instances/$instance; do
+            $curl_command -X PUT -d '' $ADDRESS/$resource/labels/Reviewed > /dev/null
+        done
+    fi
+
+    record system system
+    record patients/$patient patient
+    record studies/$study study
+    record series/$series series
+    record instances/$instance instance
+
+    job=$($curl_command -X POST -d '{"Asynchronous": true}' $ADDRESS/studies/$study/archive | jq -r .ID)
+    until [ "$($curl_command $ADDRESS/jobs/$job | jq -r .State)" = "Success" ]; do
+        sleep 1
+    done
+    record jobs/$job job
+
+    docker stop $container > /dev/null
+    trap - EXIT
+done
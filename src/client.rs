@@ -1,16 +1,26 @@
 use crate::entity::*;
 use crate::error::Error;
 use crate::models::*;
-use crate::utils::check_http_error;
+use crate::query::RemoteQuery;
+use crate::raw::RawClient;
+use crate::utils::{
+    check_http_error, check_label, echo_status, read_dicom, render_template,
+};
 use crate::Result;
 use bytes::Bytes;
 use reqwest;
 use serde_json::json;
 use serde_json::Value;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::convert::TryFrom;
+use std::fs;
 use std::io::prelude::*;
+#[cfg(feature = "zip")]
+use std::io::Seek;
+use std::path::{Path, PathBuf};
 use std::str;
+use std::sync::{mpsc, Mutex, PoisonError, RwLock};
+use std::thread;
 use std::time;
 
 /// Client type
@@ -41,6 +51,7 @@ pub struct Client {
     username: Option<String>,
     password: Option<String>,
     client: reqwest::blocking::Client,
+    capabilities: RwLock<Option<Capabilities>>,
 }
 
 impl Client {
@@ -60,6 +71,7 @@ impl Client {
             username: None,
             password: None,
             client,
+            capabilities: RwLock::new(None),
         }
     }
 
@@ -90,7 +102,12 @@ impl Client {
 
     ////////// HTTP //////////
 
-    fn get(&self, path: &str) -> Result<Bytes> {
+    // `http_get`, `http_post`, `http_post_bytes`, `http_put`, `http_delete` and `http_request`
+    // are `pub(crate)`, because `RawClient` (and through it, the plugin sub-clients) is built on
+    // them. They are not public: `RawClient` exposes them, so they can change without breaking
+    // the API.
+
+    pub(crate) fn http_get(&self, path: &str) -> Result<Bytes> {
         let url = format!("{}/{}", self.server, &path);
         let mut request = self.client.get(&url);
         request = self.add_auth(request);
@@ -100,7 +117,7 @@ impl Client {
         check_http_error(status, body)
     }
 
-    fn get_stream<W: Write>(&self, path: &str, mut writer: W) -> Result<()> {
+    fn http_get_stream<W: Write>(&self, path: &str, mut writer: W) -> Result<()> {
         let url = format!("{}/{}", self.server, &path);
         let mut request = self.client.get(&url);
         request = self.add_auth(request);
@@ -120,7 +137,28 @@ impl Client {
         Ok(())
     }
 
-    fn post(&self, path: &str, data: Option<Value>) -> Result<Bytes> {
+    // Like `http_get_stream`, but hands the response over to the caller
+    fn http_get_response(
+        &self,
+        path: &str,
+        offset: Option<u64>,
+    ) -> Result<reqwest::blocking::Response> {
+        let url = format!("{}/{}", self.server, &path);
+        let mut request = self.client.get(&url);
+        if let Some(o) = offset {
+            request = request.header(reqwest::header::RANGE, format!("bytes={}-", o));
+        }
+        request = self.add_auth(request);
+        let resp = request.send()?;
+        let status = resp.status();
+        if status >= reqwest::StatusCode::BAD_REQUEST {
+            // Always an error, given the status
+            return Err(check_http_error(status, resp.bytes()?).unwrap_err());
+        }
+        Ok(resp)
+    }
+
+    pub(crate) fn http_post(&self, path: &str, data: Option<Value>) -> Result<Bytes> {
         let url = format!("{}/{}", self.server, path);
         let mut request = self.client.post(&url);
         if let Some(d) = data {
@@ -133,32 +171,35 @@ impl Client {
         check_http_error(status, body)
     }
 
-    fn post_receive_stream<W: Write>(
+    fn http_post_response(
         &self,
         path: &str,
         data: Value,
-        mut writer: W,
-    ) -> Result<()> {
+    ) -> Result<reqwest::blocking::Response> {
         let url = format!("{}/{}", self.server, path);
         let mut request = self.client.post(&url).json(&data);
         request = self.add_auth(request);
-        let mut resp = request.send()?;
+        let resp = request.send()?;
         let status = resp.status();
-
-        // TODO: Simplify this
         if status >= reqwest::StatusCode::BAD_REQUEST {
-            let message = format!("API error: {}", status);
-            let body = resp.bytes()?;
-            if body.is_empty() {
-                return Err(Error::new(&message, None));
-            };
-            return Err(Error::new(&message, serde_json::from_slice(&body)?));
+            // Always an error, given the status
+            return Err(check_http_error(status, resp.bytes()?).unwrap_err());
         }
+        Ok(resp)
+    }
+
+    fn http_post_receive_stream<W: Write>(
+        &self,
+        path: &str,
+        data: Value,
+        mut writer: W,
+    ) -> Result<()> {
+        let mut resp = self.http_post_response(path, data)?;
         resp.copy_to(&mut writer)?;
         Ok(())
     }
 
-    fn post_bytes(&self, path: &str, data: &[u8]) -> Result<Bytes> {
+    pub(crate) fn http_post_bytes(&self, path: &str, data: &[u8]) -> Result<Bytes> {
         let url = format!("{}/{}", self.server, path);
         // TODO: .to_vec() here is probably not a good idea?
         let mut request = self.client.post(&url).body(data.to_vec());
@@ -169,7 +210,17 @@ impl Client {
         check_http_error(status, body)
     }
 
-    fn put(&self, path: &str, data: Value) -> Result<Bytes> {
+    fn http_post_body(&self, path: &str, body: reqwest::blocking::Body) -> Result<Bytes> {
+        let url = format!("{}/{}", self.server, path);
+        let mut request = self.client.post(&url).body(body);
+        request = self.add_auth(request);
+        let resp = request.send()?;
+        let status = resp.status();
+        let body = resp.bytes()?;
+        check_http_error(status, body)
+    }
+
+    pub(crate) fn http_put(&self, path: &str, data: Value) -> Result<Bytes> {
         let url = format!("{}/{}", self.server, path);
         let mut request = self.client.put(&url).json(&data);
         request = self.add_auth(request);
@@ -179,7 +230,7 @@ impl Client {
         check_http_error(status, body)
     }
 
-    fn delete(&self, path: &str) -> Result<Bytes> {
+    pub(crate) fn http_delete(&self, path: &str) -> Result<Bytes> {
         let url = format!("{}/{}", self.server, &path);
         let mut request = self.client.delete(&url);
         request = self.add_auth(request);
@@ -189,63 +240,87 @@ impl Client {
         check_http_error(status, body)
     }
 
+    // Send a request with custom headers, handing the response over to the caller
+    pub(crate) fn http_request(
+        &self,
+        method: reqwest::Method,
+        path: &str,
+        headers: &[(&str, &str)],
+        body: Option<reqwest::blocking::Body>,
+    ) -> Result<reqwest::blocking::Response> {
+        let url = format!("{}/{}", self.server, path);
+        let mut request = self.client.request(method, &url);
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+        if let Some(b) = body {
+            request = request.body(b);
+        }
+        request = self.add_auth(request);
+        let resp = request.send()?;
+        let status = resp.status();
+        if status >= reqwest::StatusCode::BAD_REQUEST {
+            // Always an error, given the status
+            return Err(check_http_error(status, resp.bytes()?).unwrap_err());
+        }
+        Ok(resp)
+    }
+
     ////////// Helpers //////////
 
-    fn list(&self, entity: &str) -> Result<Vec<String>> {
-        let resp = self.get(entity)?;
+    fn list_ids(&self, entity: &str) -> Result<Vec<String>> {
+        let resp = self.http_get(entity)?;
         let json: Vec<String> = serde_json::from_slice(&resp)?;
         Ok(json)
     }
 
-    fn anonymize(
+    fn anonymize_entity(
         &self,
         entity: &str,
         id: &str,
         anonymization: Option<Anonymization>,
     ) -> Result<ModificationResult> {
-        let data = match anonymization {
-            Some(a) => a,
-            // TODO: Just pass an empty object?
-            None => Anonymization {
-                replace: None,
-                keep: None,
-                keep_private_tags: None,
-                dicom_version: None,
-                force: None,
-            },
-        };
-        let resp = self.post(
-            &format!("{}/{}/anonymize", entity, id),
-            Some(serde_json::to_value(data)?),
-        )?;
+        let data = serde_json::to_value(anonymization.unwrap_or_default())?;
+        self.require_transcoding(&data)?;
+        let resp = self.http_post(&format!("{}/{}/anonymize", entity, id), Some(data))?;
         let json: ModificationResult = serde_json::from_slice(&resp)?;
         Ok(json)
     }
 
-    fn modify(
+    fn modify_entity(
         &self,
         entity: &str,
         id: &str,
         modification: Modification,
     ) -> Result<ModificationResult> {
-        let resp = self.post(
-            &format!("{}/{}/modify", entity, id),
-            Some(serde_json::to_value(modification)?),
-        )?;
+        let data = serde_json::to_value(modification)?;
+        self.require_transcoding(&data)?;
+        let resp = self.http_post(&format!("{}/{}/modify", entity, id), Some(data))?;
         let json: ModificationResult = serde_json::from_slice(&resp)?;
         Ok(json)
     }
 
+    fn related<T: serde::de::DeserializeOwned>(
+        &self,
+        kind: EntityKind,
+        id: &str,
+        relation: &str,
+    ) -> Result<T> {
+        let resp = self.http_get(&format!("{}/{}/{}", kind.resource()?, id, relation))?;
+        let json: T = serde_json::from_slice(&resp)?;
+        Ok(json)
+    }
+
     ////////// Modalities //////////
 
     /// List modalities
     pub fn modalities(&self) -> Result<Vec<String>> {
-        self.list("modalities")
+        self.list_ids("modalities")
     }
 
     /// List all modalities in an expanded format
     pub fn modalities_expanded(&self) -> Result<HashMap<String, Modality>> {
-        let resp = self.get("modalities?expand")?;
+        let resp = self.http_get("modalities?expand")?;
         let json: HashMap<String, Modality> = serde_json::from_slice(&resp)?;
         Ok(json)
     }
@@ -253,7 +328,7 @@ impl Client {
     // TODO: The following two methods are exactly the same
     /// Create a modality
     pub fn create_modality(&self, name: &str, modality: Modality) -> Result<()> {
-        self.put(
+        self.http_put(
             &format!("modalities/{}", name),
             serde_json::to_value(modality)?,
         )
@@ -262,7 +337,7 @@ impl Client {
 
     /// Modify a modality
     pub fn modify_modality(&self, name: &str, modality: Modality) -> Result<()> {
-        self.put(
+        self.http_put(
             &format!("modalities/{}", name),
             serde_json::to_value(modality)?,
         )
@@ -271,24 +346,112 @@ impl Client {
 
     /// Delete a modality
     pub fn delete_modality(&self, name: &str) -> Result<()> {
-        self.delete(&format!("modalities/{}", name)).map(|_| ())
+        self.http_delete(&format!("modalities/{}", name))
+            .map(|_| ())
     }
 
     /// Send a C-ECHO request to a remote modality
     ///
     /// If no error is returned, the request was successful
     pub fn modality_echo(&self, modality: &str, timeout: Option<u32>) -> Result<()> {
-        let mut data = HashMap::new();
-        if let Some(to) = timeout {
-            data.insert("Timeout", to);
-        }
-        self.post(
+        self.http_post(
             &format!("modalities/{}/echo", modality),
-            Some(serde_json::json!(data)),
+            Some(echo_request(timeout)),
         )
         .map(|_| ())
     }
 
+    /// Send a C-ECHO request to a remote modality and report its outcome
+    ///
+    /// Unlike [`Client::modality_echo`], a failed C-ECHO is not an error. The reason of the
+    /// failure is reported in [`EchoReport::status`] instead. An error is only returned if Orthanc
+    /// itself could not process the request (e.g. the modality does not exist).
+    pub fn modality_echo_report(
+        &self,
+        modality: &str,
+        timeout: Option<u32>,
+    ) -> Result<EchoReport> {
+        let remote_aet = self.modalities_expanded()?.remove(modality).map(|m| m.aet);
+        self.echo_report(modality, remote_aet, timeout)
+    }
+
+    /// Send a C-ECHO request to all configured modalities, at most `concurrency` at a time
+    ///
+    /// Returns the outcome for each modality, keyed by the modality name. Failing to check one
+    /// modality does not affect the others. An error is only returned if the modalities could
+    /// not be listed.
+    ///
+    /// ```no_run
+    /// # use orthanc::Client;
+    /// # let client = Client::new("http://localhost:8042");
+    /// for (name, report) in client.check_all_modalities(Some(5), 4).unwrap() {
+    ///     match report {
+    ///         Ok(r) => println!("{}: {:?} in {:?}", name, r.status, r.latency),
+    ///         Err(e) => println!("{}: {}", name, e),
+    ///     }
+    /// }
+    /// ```
+    pub fn check_all_modalities(
+        &self,
+        timeout: Option<u32>,
+        concurrency: usize,
+    ) -> Result<HashMap<String, Result<EchoReport>>> {
+        let modalities = self.modalities_expanded()?;
+        let workers = concurrency.max(1).min(modalities.len());
+        let modalities = Mutex::new(modalities.into_iter());
+        let reports = Mutex::new(HashMap::new());
+        thread::scope(|s| {
+            for _ in 0..workers {
+                s.spawn(|| loop {
+                    let next = modalities.lock().expect("modalities poisoned").next();
+                    let (name, modality) = match next {
+                        Some(n) => n,
+                        None => break,
+                    };
+                    let report = self.echo_report(&name, Some(modality.aet), timeout);
+                    reports
+                        .lock()
+                        .expect("echo reports poisoned")
+                        .insert(name, report);
+                });
+            }
+        });
+        Ok(reports.into_inner().expect("echo reports poisoned"))
+    }
+
+    // Orthanc reports a failed C-ECHO with a 500 status
+    fn echo_report(
+        &self,
+        modality: &str,
+        remote_aet: Option<String>,
+        timeout: Option<u32>,
+    ) -> Result<EchoReport> {
+        let url = format!("{}/modalities/{}/echo", self.server, modality);
+        let mut request = self.client.post(&url).json(&echo_request(timeout));
+        request = self.add_auth(request);
+        let start = time::Instant::now();
+        let resp = request.send()?;
+        let latency = start.elapsed();
+        let status = resp.status();
+        let body = resp.bytes()?;
+        let echo_status = if status == reqwest::StatusCode::INTERNAL_SERVER_ERROR {
+            if body.is_empty() {
+                echo_status(None)
+            } else {
+                echo_status(Some(serde_json::from_slice(&body)?))
+            }
+        } else {
+            check_http_error(status, body)?;
+            EchoStatus::Success
+        };
+        Ok(EchoReport {
+            modality: modality.to_string(),
+            remote_aet,
+            latency,
+            status: echo_status,
+        })
+    }
+
     /// Send a C-ECHO request to a remote modality
     ///
     /// If no error is returned, the request was successful
@@ -306,7 +469,7 @@ impl Client {
         modality: &str,
         ids: &[&str],
     ) -> Result<ModalityStoreResult> {
-        let resp = self.post(
+        let resp = self.http_post(
             &format!("modalities/{}/store", modality),
             Some(serde_json::json!(ids)),
         )?;
@@ -327,7 +490,7 @@ impl Client {
     ///
     /// If no error is returned, the request was successful
     pub fn modality_move(&self, modality: &str, move_request: ModalityMove) -> Result<()> {
-        self.post(
+        self.http_post(
             &format!("modalities/{}/move", modality),
             Some(serde_json::to_value(move_request)?),
         )
@@ -349,7 +512,7 @@ impl Client {
             query,
             normalize,
         };
-        let resp = self.post(
+        let resp = self.http_post(
             &format!("modalities/{}/query", modality),
             Some(serde_json::to_value(body)?),
         )?;
@@ -357,36 +520,63 @@ impl Client {
         Ok(json)
     }
 
+    /// Send a C-FIND request to a remote modality
+    ///
+    /// Same as [`Client::modality_find`], but returns a [`RemoteQuery`], that allows inspecting
+    /// and retrieving the answers of the query
+    pub fn modality_query(
+        &self,
+        modality: &str,
+        level: EntityKind,
+        query: HashMap<String, String>,
+        normalize: Option<bool>,
+    ) -> Result<RemoteQuery<'_>> {
+        let resp = self.modality_find(modality, level, query, normalize)?;
+        Ok(RemoteQuery::new(self, &resp.id))
+    }
+
     ////////// Peers //////////
 
     /// List peers
     pub fn peers(&self) -> Result<Vec<String>> {
-        self.list("peers")
+        self.list_ids("peers")
     }
 
     /// List all peers in an expanded format
     pub fn peers_expanded(&self) -> Result<HashMap<String, Peer>> {
-        let resp = self.get("peers?expand")?;
+        let resp = self.http_get("peers?expand")?;
         let json: HashMap<String, Peer> = serde_json::from_slice(&resp)?;
         Ok(json)
     }
 
-    // TODO: The following two methods are exactly the same
     /// Create a peer
+    ///
+    /// Returns an error if the peer has HTTP headers with unknown values (see [`HttpHeaders`])
     pub fn create_peer(&self, name: &str, peer: Peer) -> Result<()> {
-        self.put(&format!("peers/{}", name), serde_json::to_value(peer)?)
-            .map(|_| ())
+        self.put_peer(name, peer)
     }
 
     /// Modify a peer
+    ///
+    /// Returns an error if the peer has HTTP headers with unknown values (see [`HttpHeaders`]).
+    /// This is the case for a peer, obtained from [`Client::peers_expanded`], that has HTTP
+    /// headers configured. Their values have to be provided again before the peer is written back,
+    /// otherwise the headers would be lost.
     pub fn modify_peer(&self, name: &str, peer: Peer) -> Result<()> {
-        self.put(&format!("peers/{}", name), serde_json::to_value(peer)?)
+        self.put_peer(name, peer)
+    }
+
+    fn put_peer(&self, name: &str, peer: Peer) -> Result<()> {
+        if let Some(h) = &peer.http_headers {
+            h.require_values(&format!("peer {}", name))?;
+        }
+        self.http_put(&format!("peers/{}", name), serde_json::to_value(peer)?)
             .map(|_| ())
     }
 
     /// Delete a peer
     pub fn delete_peer(&self, name: &str) -> Result<()> {
-        self.delete(&format!("peers/{}", name)).map(|_| ())
+        self.http_delete(&format!("peers/{}", name)).map(|_| ())
     }
 
     /// Send entities to a peer
@@ -394,7 +584,7 @@ impl Client {
     /// `ids` is a slice of entity IDs to send. An ID can signify either of [`Patient`], [`Study`],
     /// [`Series`] or [`Instance`]
     pub fn peer_store(&self, peer: &str, ids: &[&str]) -> Result<PeerStoreResult> {
-        let resp = self.post(
+        let resp = self.http_post(
             &format!("peers/{}/store", peer),
             Some(serde_json::json!(ids)),
         )?;
@@ -402,23 +592,611 @@ impl Client {
         Ok(json)
     }
 
+    /// Send entities to a peer, with additional options
+    ///
+    /// If the request is executed asynchronously, the job that sends the entities is returned
+    pub fn peer_store_with_options(
+        &self,
+        peer: &str,
+        ids: &[&str],
+        options: &PeerStoreOptions,
+    ) -> Result<OperationResult<PeerStoreResult>> {
+        let mut data = serde_json::to_value(options)?;
+        self.require_transcoding(&data)?;
+        data["Resources"] = json!(ids);
+        let resp = self.http_post(&format!("peers/{}/store", peer), Some(data))?;
+        let json: OperationResult<PeerStoreResult> = serde_json::from_slice(&resp)?;
+        Ok(json)
+    }
+
+    /// System information of a peer
+    ///
+    /// Can be used to test the connectivity to a peer
+    pub fn peer_system(&self, peer: &str) -> Result<System> {
+        let resp = self.http_get(&format!("peers/{}/system", peer))?;
+        let json: System = serde_json::from_slice(&resp)?;
+        Ok(json)
+    }
+
+    ////////// Transfers //////////
+
+    /// Availability of the transfers accelerator plugin on the peers
+    #[cfg(feature = "transfers")]
+    pub fn transfer_peers(
+        &self,
+    ) -> Result<HashMap<String, crate::transfers::PeerTransfers>> {
+        let resp = self.http_get("transfers/peers")?;
+        let json: HashMap<String, crate::transfers::PeerTransfers> =
+            serde_json::from_slice(&resp)?;
+        Ok(json)
+    }
+
+    /// Whether a peer has the transfers accelerator plugin
+    ///
+    /// Returns `false` if the server itself does not have the plugin (i.e. `transfers` is not
+    /// among its [`Client::plugins`]), as no entities can be transferred then, whatever the peer
+    /// has. Returns an error if the peer is unknown.
+    #[cfg(feature = "transfers")]
+    pub fn peer_has_transfers(&self, peer: &str) -> Result<bool> {
+        if !self.plugins()?.iter().any(|p| p == "transfers") {
+            return Ok(false);
+        }
+        match self.transfer_peers()?.get(peer) {
+            Some(p) => Ok(p.is_available()),
+            None => Err(Error::new(&format!("Unknown peer: {}", peer), None)),
+        }
+    }
+
+    /// Send entities to a peer with the transfers accelerator plugin
+    ///
+    /// Returns the job that sends the entities. Both the server and the peer must have the
+    /// plugin (see [`Client::peer_has_transfers`]).
+    #[cfg(feature = "transfers")]
+    pub fn transfer_send(
+        &self,
+        peer: &str,
+        resources: &[crate::transfers::TransferResource],
+        options: &crate::transfers::TransferOptions,
+    ) -> Result<CreatedJob> {
+        self.transfer("transfers/send", peer, resources, options)
+    }
+
+    /// Retrieve entities from a peer with the transfers accelerator plugin
+    ///
+    /// Returns the job that retrieves the entities. Both the server and the peer must have the
+    /// plugin (see [`Client::peer_has_transfers`]).
+    #[cfg(feature = "transfers")]
+    pub fn transfer_pull(
+        &self,
+        peer: &str,
+        resources: &[crate::transfers::TransferResource],
+        options: &crate::transfers::TransferOptions,
+    ) -> Result<CreatedJob> {
+        self.transfer("transfers/pull", peer, resources, options)
+    }
+
+    #[cfg(feature = "transfers")]
+    fn transfer(
+        &self,
+        path: &str,
+        peer: &str,
+        resources: &[crate::transfers::TransferResource],
+        options: &crate::transfers::TransferOptions,
+    ) -> Result<CreatedJob> {
+        let mut data = serde_json::to_value(options)?;
+        data["Peer"] = json!(peer);
+        data["Resources"] = json!(resources);
+        let resp = self.http_post(path, Some(data))?;
+        let json: CreatedJob = serde_json::from_slice(&resp)?;
+        Ok(json)
+    }
+
+    ////////// Entities //////////
+
+    /// Get an entity by its ID
+    ///
+    /// The kind of the entity is defined by the type parameter:
+    ///
+    /// ```no_run
+    /// # use orthanc::{entity::Study, Client};
+    /// # let client = Client::new("http://localhost:8042");
+    /// let study: Study = client.get("9357491d-427a6c94-4080b6c8-1997f4aa-af658240").unwrap();
+    /// ```
+    pub fn get<T: Entity>(&self, id: &str) -> Result<T> {
+        let resp = self.http_get(&format!("{}/{}", T::kind().resource()?, id))?;
+        let json: T = serde_json::from_slice(&resp)?;
+        Ok(json)
+    }
+
+    /// List entities of a kind
+    ///
+    /// ```no_run
+    /// # use orthanc::{entity::Series, Client};
+    /// # let client = Client::new("http://localhost:8042");
+    /// let ids = client.list::<Series>().unwrap();
+    /// ```
+    pub fn list<T: Entity>(&self) -> Result<Vec<String>> {
+        self.list_ids(T::kind().resource()?)
+    }
+
+    /// List all entities of a kind in an expanded format
+    pub fn list_expanded<T: Entity>(&self) -> Result<Vec<T>> {
+        let resp = self.http_get(&format!("{}?expand", T::kind().resource()?))?;
+        let json: Vec<T> = serde_json::from_slice(&resp)?;
+        Ok(json)
+    }
+
+    /// Delete an entity
+    pub fn delete<T: Entity>(&self, id: &str) -> Result<RemainingAncestor> {
+        let resp = self.http_delete(&format!("{}/{}", T::kind().resource()?, id))?;
+        let json: RemainingAncestor = serde_json::from_slice(&resp)?;
+        Ok(json)
+    }
+
+    /// Modify an entity
+    ///
+    /// Unlike [`Client::modify_instance`], which returns the modified DICOM file, modifying an
+    /// [`Instance`] this way stores the modified copy in Orthanc, same as for the other entity
+    /// kinds.
+    pub fn modify<T: Entity>(
+        &self,
+        id: &str,
+        modification: Modification,
+    ) -> Result<ModificationResult> {
+        if T::kind() == EntityKind::Instance {
+            return self.store_modified_instance(id, modification, false);
+        }
+        self.modify_entity(T::kind().resource()?, id, modification)
+    }
+
+    /// Anonymize an entity
+    ///
+    /// Unlike [`Client::anonymize_instance`], which returns the anonymized DICOM file,
+    /// anonymizing an [`Instance`] this way stores the anonymized copy in Orthanc, same as for
+    /// the other entity kinds.
+    pub fn anonymize<T: Entity>(
+        &self,
+        id: &str,
+        anonymization: Option<Anonymization>,
+    ) -> Result<ModificationResult> {
+        if T::kind() == EntityKind::Instance {
+            return self.store_anonymized_instance(id, anonymization, false);
+        }
+        self.anonymize_entity(T::kind().resource()?, id, anonymization)
+    }
+
+    /// Download an entity
+    ///
+    /// An [`Instance`] is downloaded as a DICOM file, the other entity kinds as a ZIP archive.
+    pub fn download<T: Entity, W: Write>(&self, id: &str, writer: W) -> Result<()> {
+        let path = match T::kind() {
+            EntityKind::Instance => format!("instances/{}/file", id),
+            k => format!("{}/{}/archive", k.resource()?, id),
+        };
+        self.http_get_stream(&path, writer)
+    }
+
+    /// Modify multiple entities at once
+    ///
+    /// `ids` can signify entities of different kinds (e.g. a study, and a series of another
+    /// study).
+    ///
+    /// ```no_run
+    /// # use orthanc::{models::Modification, Client};
+    /// # let client = Client::new("http://localhost:8042");
+    /// let ids = &[
+    ///     "e34c7a50-e7a94a8d-c0ae8e30-2a1ed4b8-3bf8b6f0",
+    ///     "3693b9d5-8b0e2a80-2cf45dda-d19e7c22-8749103c",
+    /// ];
+    /// let result = client
+    ///     .bulk_modify(ids, Modification::default().replace("InstitutionName", "Foo"))
+    ///     .unwrap();
+    /// for (original, modified) in result.resources {
+    ///     println!("{} -> {}", original, modified.id);
+    /// }
+    /// ```
+    pub fn bulk_modify(
+        &self,
+        ids: &[&str],
+        modification: Modification,
+    ) -> Result<BulkModificationResult> {
+        let resp =
+            self.bulk_request("modify", ids, serde_json::to_value(modification)?, false)?;
+        self.bulk_modification_output(serde_json::from_slice(&resp)?, ids)
+    }
+
+    /// Modify multiple entities at once, asynchronously
+    ///
+    /// Once the returned job has finished, its result can be obtained with
+    /// [`Client::bulk_modification_result`].
+    pub fn bulk_modify_async(
+        &self,
+        ids: &[&str],
+        modification: Modification,
+    ) -> Result<CreatedJob> {
+        let resp =
+            self.bulk_request("modify", ids, serde_json::to_value(modification)?, true)?;
+        let json: CreatedJob = serde_json::from_slice(&resp)?;
+        Ok(json)
+    }
+
+    /// Anonymize multiple entities at once
+    ///
+    /// `ids` can signify entities of different kinds (e.g. a study, and a series of another
+    /// study).
+    pub fn bulk_anonymize(
+        &self,
+        ids: &[&str],
+        anonymization: Option<Anonymization>,
+    ) -> Result<BulkModificationResult> {
+        let data = serde_json::to_value(anonymization.unwrap_or_default())?;
+        let resp = self.bulk_request("anonymize", ids, data, false)?;
+        self.bulk_modification_output(serde_json::from_slice(&resp)?, ids)
+    }
+
+    /// Anonymize multiple entities at once, asynchronously
+    ///
+    /// Once the returned job has finished, its result can be obtained with
+    /// [`Client::bulk_modification_result`].
+    pub fn bulk_anonymize_async(
+        &self,
+        ids: &[&str],
+        anonymization: Option<Anonymization>,
+    ) -> Result<CreatedJob> {
+        let data = serde_json::to_value(anonymization.unwrap_or_default())?;
+        let resp = self.bulk_request("anonymize", ids, data, true)?;
+        let json: CreatedJob = serde_json::from_slice(&resp)?;
+        Ok(json)
+    }
+
+    /// Get the result of a finished bulk modification or anonymization job
+    ///
+    /// ```no_run
+    /// # use orthanc::{models::Modification, Client};
+    /// # use std::time::Duration;
+    /// # let client = Client::new("http://localhost:8042");
+    /// # let modification = Modification::default();
+    /// let ids = &["e34c7a50-e7a94a8d-c0ae8e30-2a1ed4b8-3bf8b6f0"];
+    /// let job = client.bulk_modify_async(ids, modification).unwrap();
+    /// let job = client
+    ///     .wait_for_job(&job.id, Duration::from_secs(1), Duration::from_secs(600), |_| ())
+    ///     .unwrap();
+    /// let result = client.bulk_modification_result(&job).unwrap();
+    /// ```
+    pub fn bulk_modification_result(&self, job: &Job) -> Result<BulkModificationResult> {
+        if job.state != JobState::Success {
+            return Err(Error::new(
+                &format!("Job {} has not succeeded: {:?}", job.id, job.state),
+                None,
+            ));
+        }
+        self.bulk_modification_output(serde_json::from_value(job.content.clone())?, &[])
+    }
+
+    fn bulk_request(
+        &self,
+        operation: &str,
+        ids: &[&str],
+        mut data: Value,
+        asynchronous: bool,
+    ) -> Result<Bytes> {
+        self.require(Feature::BulkTools)?;
+        self.require_transcoding(&data)?;
+        data["Resources"] = json!(ids);
+        data["Asynchronous"] = json!(asynchronous);
+        self.http_post(&format!("tools/bulk-{}", operation), Some(data))
+    }
+
+    /// Map the resources created by a bulk modification or anonymization to the IDs of the
+    /// requested resources they were created from
+    ///
+    /// The origin of a created resource is only known to the resource itself, so the resources
+    /// are looked up from the top of the hierarchy down, until all the requested resources are
+    /// found. The resources the origin of which is unknown (e.g. unmodified parents) are skipped:
+    /// the server has already modified the data by now, so failing would only lose the result.
+    fn bulk_modification_output(
+        &self,
+        output: BulkModificationOutput,
+        ids: &[&str],
+    ) -> Result<BulkModificationResult> {
+        let mut requested: HashSet<&str> = output
+            .parent_resources
+            .iter()
+            .map(String::as_str)
+            .chain(ids.iter().copied())
+            .collect();
+        let mut created = output.resources;
+        created.sort_by_key(|r| match r.entity {
+            EntityKind::Patient => 0,
+            EntityKind::Study => 1,
+            EntityKind::Series => 2,
+            EntityKind::Instance => 3,
+            EntityKind::Unknown(_) => 4,
+        });
+        let mut resources = HashMap::new();
+        let all = requested.is_empty();
+        for resource in created {
+            if !all && requested.is_empty() {
+                break;
+            }
+            let kind = match resource.entity.resource() {
+                Ok(k) => k,
+                Err(_) => continue,
+            };
+            let resp = self.http_get(&format!("{}/{}", kind, resource.id))?;
+            let json: Value = serde_json::from_slice(&resp)?;
+            let original = json["ModifiedFrom"]
+                .as_str()
+                .or_else(|| json["AnonymizedFrom"].as_str());
+            if let Some(o) = original {
+                if all || requested.remove(o) {
+                    resources.insert(o.to_string(), resource);
+                }
+            }
+        }
+        Ok(BulkModificationResult {
+            instances_count: output.instances_count,
+            failed_instances_count: output.failed_instances_count,
+            resources,
+        })
+    }
+
+    /// Delete an entity, with additional options
+    ///
+    /// With [`DeleteOptions::dry_run`], nothing is deleted, and the report describes what would
+    /// have been deleted. Otherwise, the remaining ancestor in the report is the one reported by
+    /// Orthanc.
+    ///
+    /// Only the entity and its ancestors are looked up, not the whole hierarchy of its patient.
+    ///
+    /// ```no_run
+    /// # use orthanc::{entity::Study, models::DeleteOptions, Client};
+    /// # let client = Client::new("http://localhost:8042");
+    /// let options = DeleteOptions {
+    ///     dry_run: true,
+    ///     require_stable: true,
+    /// };
+    /// let id = "e34c7a50-e7a94a8d-c0ae8e30-2a1ed4b8-3bf8b6f0";
+    /// let report = client.delete_with_options::<Study>(id, &options).unwrap();
+    /// println!("{} instances, {} bytes", report.instances.len(), report.size);
+    /// ```
+    pub fn delete_with_options<T: Entity>(
+        &self,
+        id: &str,
+        options: &DeleteOptions,
+    ) -> Result<DeletionReport> {
+        self.bulk_delete_with_options(&[(T::kind(), id)], options)
+    }
+
+    /// Delete multiple entities at once
+    ///
+    /// `ids` can signify entities of different kinds (e.g. a study, and a series of another
+    /// study).
+    pub fn bulk_delete(&self, ids: &[&str]) -> Result<()> {
+        self.require(Feature::BulkTools)?;
+        self.http_post("tools/bulk-delete", Some(json!({ "Resources": ids })))
+            .map(|_| ())
+    }
+
+    /// Delete multiple entities of different kinds at once, with additional options
+    ///
+    /// Same as [`Client::delete_with_options`], but for entities, given as pairs of entity kind
+    /// and ID. The entities are deleted one by one, so that the report contains the remaining
+    /// ancestors, reported by Orthanc. The entities, contained in the other deleted entities, are
+    /// deleted along with them, and their remaining ancestors are the planned ones.
+    ///
+    /// ```no_run
+    /// # use orthanc::{entity::EntityKind, models::DeleteOptions, Client};
+    /// # let client = Client::new("http://localhost:8042");
+    /// let entities = &[
+    ///     (EntityKind::Study, "e34c7a50-e7a94a8d-c0ae8e30-2a1ed4b8-3bf8b6f0"),
+    ///     (EntityKind::Instance, "f689ddd2-662f8fe1-8c18ed2b-dafa2d51-1c9b2ec2"),
+    /// ];
+    /// let report = client
+    ///     .bulk_delete_with_options(entities, &DeleteOptions::default())
+    ///     .unwrap();
+    /// ```
+    pub fn bulk_delete_with_options(
+        &self,
+        entities: &[(EntityKind, &str)],
+        options: &DeleteOptions,
+    ) -> Result<DeletionReport> {
+        let (mut report, roots) = self.plan_deletion(entities, options.require_stable)?;
+        if !options.dry_run {
+            for (kind, id) in roots {
+                let resp = self.http_delete(&format!("{}/{}", kind.resource()?, id))?;
+                let remaining_ancestor: RemainingAncestor = serde_json::from_slice(&resp)?;
+                report
+                    .remaining_ancestors
+                    .insert(id.to_string(), remaining_ancestor);
+            }
+        }
+        Ok(report)
+    }
+
+    /// Load an entity to plan its deletion, or the deletion of its descendants
+    fn deletion_node(&self, kind: &EntityKind, id: &str) -> Result<DeletionNode> {
+        let node = match kind {
+            EntityKind::Patient => {
+                let p = self.patient(id)?;
+                DeletionNode::new(kind, None, p.studies, p.is_stable, 0)
+            }
+            EntityKind::Study => {
+                let s = self.study(id)?;
+                DeletionNode::new(kind, Some(s.parent_patient), s.series, s.is_stable, 0)
+            }
+            EntityKind::Series => {
+                let s = self.series(id)?;
+                DeletionNode::new(kind, Some(s.parent_study), s.instances, s.is_stable, 0)
+            }
+            EntityKind::Instance => {
+                let i = self.instance(id)?;
+                // Instances do not have a stability of their own, the one of the series is used
+                DeletionNode::new(kind, Some(i.parent_series), vec![], true, i.file_size)
+            }
+            EntityKind::Unknown(_) => return Err(kind.unsupported()),
+        };
+        Ok(node)
+    }
+
+    /// Describe the deletion of entities, given as pairs of entity kind and ID
+    ///
+    /// Only the entities and their ancestors are loaded. Returns the report, and the entities to
+    /// delete, i.e. the ones not contained in the other deleted entities.
+    fn plan_deletion<'a>(
+        &self,
+        entities: &[(EntityKind, &'a str)],
+        require_stable: bool,
+    ) -> Result<(DeletionReport, Vec<(EntityKind, &'a str)>)> {
+        let mut nodes: HashMap<String, DeletionNode> = HashMap::new();
+        for (kind, id) in entities {
+            let mut next = Some((kind.clone(), id.to_string()));
+            while let Some((kind, id)) = next {
+                if nodes.contains_key(&id) {
+                    break;
+                }
+                let node = self.deletion_node(&kind, &id)?;
+                next = node.parent();
+                nodes.insert(id, node);
+            }
+        }
+        let ancestors = |id: &str| {
+            std::iter::successors(nodes[id].parent.as_deref(), |p| {
+                nodes[*p].parent.as_deref()
+            })
+        };
+
+        let targets: HashSet<&str> = entities.iter().map(|(_, id)| *id).collect();
+        let mut roots = vec![];
+        for (kind, id) in entities {
+            let node = &nodes[*id];
+            let is_stable = match &node.parent {
+                Some(p) if node.kind == EntityKind::Instance => nodes[p].is_stable,
+                _ => node.is_stable,
+            };
+            if require_stable && !is_stable {
+                return Err(Error::new(
+                    &format!("Refusing to delete unstable {:?} {}", kind, id),
+                    None,
+                ));
+            }
+            let contained = ancestors(id).any(|a| targets.contains(a));
+            if !contained && !roots.iter().any(|(_, r)| r == id) {
+                roots.push((kind.clone(), *id));
+            }
+        }
+
+        // Entities, that are gone after the deletion: the deleted ones, and the ones all
+        // children of which are deleted
+        let mut report = DeletionReport::default();
+        let mut gone: HashSet<String> = HashSet::new();
+        for (kind, id) in &roots {
+            let instances = match kind {
+                EntityKind::Instance => vec![(id.to_string(), nodes[*id].file_size)],
+                k => self
+                    .related::<Vec<Instance>>(k.clone(), id, "instances")?
+                    .into_iter()
+                    .map(|i| (i.id, i.file_size))
+                    .collect(),
+            };
+            for (instance, size) in instances {
+                report.size += size;
+                report.instances.push(instance.clone());
+                gone.insert(instance);
+            }
+        }
+        for (id, _) in nodes.iter() {
+            if targets.contains(id.as_str()) || ancestors(id).any(|a| targets.contains(a)) {
+                gone.insert(id.clone());
+            }
+        }
+        for kind in &[EntityKind::Series, EntityKind::Study, EntityKind::Patient] {
+            for (id, node) in nodes.iter().filter(|(_, n)| &n.kind == kind) {
+                if !node.children.is_empty()
+                    && node.children.iter().all(|c| gone.contains(c))
+                {
+                    gone.insert(id.clone());
+                }
+            }
+        }
+
+        for (_, id) in entities {
+            let remaining_ancestor = match ancestors(id).find(|a| !gone.contains(*a)) {
+                Some(a) => {
+                    let kind = &nodes[a].kind;
+                    Some(Ancestor {
+                        id: a.to_string(),
+                        path: format!("/{}/{}", kind.resource()?, a),
+                        entity: kind.clone(),
+                    })
+                }
+                None => None,
+            };
+            report
+                .remaining_ancestors
+                .insert(id.to_string(), RemainingAncestor { remaining_ancestor });
+        }
+        Ok((report, roots))
+    }
+
+    /// Stream the DICOM file, returned by a modification or anonymization of an instance,
+    /// straight back into Orthanc
+    fn store_instance_copy(
+        &self,
+        id: &str,
+        operation: &str,
+        data: Value,
+        delete_original: bool,
+    ) -> Result<ModificationResult> {
+        self.require_transcoding(&data)?;
+        let resp =
+            self.http_post_response(&format!("instances/{}/{}", id, operation), data)?;
+        let body = match resp.content_length() {
+            Some(l) => reqwest::blocking::Body::sized(resp, l),
+            None => reqwest::blocking::Body::new(resp),
+        };
+        let resp = self.http_post_body("instances", body)?;
+        let json: UploadResult = serde_json::from_slice(&resp)?;
+        // If the modification keeps the UIDs, the copy has the ID of the original. Orthanc then
+        // only stores it with `OverwriteInstances` enabled, otherwise the copy is dropped
+        if json.id == id && json.status == "AlreadyStored" {
+            return Err(Error::new(
+                &format!(
+                    "The modified copy of instance {} was not stored: it keeps the UIDs of \
+                     the original, and Orthanc does not overwrite instances",
+                    id
+                ),
+                None,
+            ));
+        }
+        if delete_original && json.id != id {
+            self.delete_instance(id)?;
+        }
+        Ok(ModificationResult {
+            id: json.id,
+            patient_id: json.parent_patient,
+            path: json.path,
+            entity: EntityKind::Instance,
+        })
+    }
+
     ////////// Patients //////////
 
     /// List patients
     pub fn patients(&self) -> Result<Vec<String>> {
-        self.list("patients")
+        self.list_ids("patients")
     }
 
     /// List all patients in an expanded format
     pub fn patients_expanded(&self) -> Result<Vec<Patient>> {
-        let resp = self.get("patients?expand")?;
+        let resp = self.http_get("patients?expand")?;
         let json: Vec<Patient> = serde_json::from_slice(&resp)?;
         Ok(json)
     }
 
     /// Get a patient by its ID
     pub fn patient(&self, id: &str) -> Result<Patient> {
-        let resp = self.get(&format!("patients/{}", id))?;
+        let resp = self.http_get(&format!("patients/{}", id))?;
         let json: Patient = serde_json::from_slice(&resp)?;
         Ok(json)
     }
@@ -438,7 +1216,7 @@ impl Client {
     /// ```
     pub fn patient_dicom<W: Write>(&self, id: &str, writer: W) -> Result<()> {
         let path = format!("patients/{}/archive", id);
-        self.get_stream(&path, writer)
+        self.http_get_stream(&path, writer)
     }
 
     /// Anonymize a patient
@@ -447,7 +1225,7 @@ impl Client {
         id: &str,
         anonymization: Option<Anonymization>,
     ) -> Result<ModificationResult> {
-        self.anonymize("patients", id, anonymization)
+        self.anonymize_entity("patients", id, anonymization)
     }
 
     /// Modify a patient
@@ -456,33 +1234,99 @@ impl Client {
         id: &str,
         modification: Modification,
     ) -> Result<ModificationResult> {
-        self.modify("patients", id, modification)
+        self.modify_entity("patients", id, modification)
     }
 
     /// Delete a patient
     pub fn delete_patient(&self, id: &str) -> Result<RemainingAncestor> {
-        let resp = self.delete(&format!("patients/{}", id))?;
+        let resp = self.http_delete(&format!("patients/{}", id))?;
         let json: RemainingAncestor = serde_json::from_slice(&resp)?;
         Ok(json)
     }
 
+    /// Protect a patient from recycling
+    ///
+    /// Orthanc recycles the oldest patients once `MaximumStorageSize` or `MaximumPatientCount`
+    /// is reached, unless they are protected.
+    pub fn protect_patient(&self, id: &str) -> Result<()> {
+        self.http_put(&format!("patients/{}/protected", id), json!(1))
+            .map(|_| ())
+    }
+
+    /// Allow a patient to be recycled
+    pub fn unprotect_patient(&self, id: &str) -> Result<()> {
+        self.http_put(&format!("patients/{}/protected", id), json!(0))
+            .map(|_| ())
+    }
+
+    /// Whether a patient is protected from recycling
+    pub fn is_patient_protected(&self, id: &str) -> Result<bool> {
+        let resp = self.http_get(&format!("patients/{}/protected", id))?;
+        match str::from_utf8(&resp)?.trim() {
+            "1" => Ok(true),
+            "0" => Ok(false),
+            v => Err(Error::new(
+                &format!("Unknown protection status: {}", v),
+                None,
+            )),
+        }
+    }
+
+    /// Get all studies of a patient
+    pub fn patient_studies(&self, id: &str) -> Result<Vec<Study>> {
+        self.related(EntityKind::Patient, id, "studies")
+    }
+
+    /// Get all series of a patient
+    pub fn patient_series(&self, id: &str) -> Result<Vec<Series>> {
+        self.related(EntityKind::Patient, id, "series")
+    }
+
+    /// Get all instances of a patient
+    pub fn patient_instances(&self, id: &str) -> Result<Vec<Instance>> {
+        self.related(EntityKind::Patient, id, "instances")
+    }
+
+    /// Load the whole hierarchy of a patient: its studies, their series, and their instances
+    ///
+    /// Each level of the hierarchy is fetched with a single request, regardless of the number
+    /// of entities in it.
+    ///
+    /// ```no_run
+    /// # use orthanc::Client;
+    /// # let client = Client::new("http://localhost:8042");
+    /// let tree = client.resource_tree("7e43f8d3-e50280e6-470079e9-02241af1-d286bdbe").unwrap();
+    /// for study in &tree.studies {
+    ///     for series in &study.series {
+    ///         println!("{}: {} instances", series.series.id, series.instances.len());
+    ///     }
+    /// }
+    /// ```
+    pub fn resource_tree(&self, patient_id: &str) -> Result<ResourceTree> {
+        let patient = self.patient(patient_id)?;
+        let studies = self.patient_studies(patient_id)?;
+        let series = self.patient_series(patient_id)?;
+        let instances = self.patient_instances(patient_id)?;
+        Ok(ResourceTree::new(patient, studies, series, instances))
+    }
+
     ////////// Studies //////////
 
     /// List studies
     pub fn studies(&self) -> Result<Vec<String>> {
-        self.list("studies")
+        self.list_ids("studies")
     }
 
     /// List all studies in an expanded format
     pub fn studies_expanded(&self) -> Result<Vec<Study>> {
-        let resp = self.get("studies?expand")?;
+        let resp = self.http_get("studies?expand")?;
         let json: Vec<Study> = serde_json::from_slice(&resp)?;
         Ok(json)
     }
 
     /// Get a study by its ID
     pub fn study(&self, id: &str) -> Result<Study> {
-        let resp = self.get(&format!("studies/{}", id))?;
+        let resp = self.http_get(&format!("studies/{}", id))?;
         let json: Study = serde_json::from_slice(&resp)?;
         Ok(json)
     }
@@ -502,7 +1346,7 @@ impl Client {
     /// ```
     pub fn study_dicom<W: Write>(&self, id: &str, writer: W) -> Result<()> {
         let path = format!("studies/{}/archive", id);
-        self.get_stream(&path, writer)?;
+        self.http_get_stream(&path, writer)?;
         Ok(())
     }
 
@@ -512,7 +1356,7 @@ impl Client {
         id: &str,
         anonymization: Option<Anonymization>,
     ) -> Result<ModificationResult> {
-        self.anonymize("studies", id, anonymization)
+        self.anonymize_entity("studies", id, anonymization)
     }
 
     /// Modify a study
@@ -521,33 +1365,48 @@ impl Client {
         id: &str,
         modification: Modification,
     ) -> Result<ModificationResult> {
-        self.modify("studies", id, modification)
+        self.modify_entity("studies", id, modification)
     }
 
     /// Delete a study
     pub fn delete_study(&self, id: &str) -> Result<RemainingAncestor> {
-        let resp = self.delete(&format!("studies/{}", id))?;
+        let resp = self.http_delete(&format!("studies/{}", id))?;
         let json: RemainingAncestor = serde_json::from_slice(&resp)?;
         Ok(json)
     }
 
+    /// Get the patient of a study
+    pub fn study_patient(&self, id: &str) -> Result<Patient> {
+        self.related(EntityKind::Study, id, "patient")
+    }
+
+    /// Get all series of a study
+    pub fn study_series(&self, id: &str) -> Result<Vec<Series>> {
+        self.related(EntityKind::Study, id, "series")
+    }
+
+    /// Get all instances of a study
+    pub fn study_instances(&self, id: &str) -> Result<Vec<Instance>> {
+        self.related(EntityKind::Study, id, "instances")
+    }
+
     ////////// Series //////////
 
     /// List series
     pub fn series_list(&self) -> Result<Vec<String>> {
-        self.list("series")
+        self.list_ids("series")
     }
 
     /// List all series in an expanded format
     pub fn series_expanded(&self) -> Result<Vec<Series>> {
-        let resp = self.get("series?expand")?;
+        let resp = self.http_get("series?expand")?;
         let json: Vec<Series> = serde_json::from_slice(&resp)?;
         Ok(json)
     }
 
     /// Get a series by its ID
     pub fn series(&self, id: &str) -> Result<Series> {
-        let resp = self.get(&format!("series/{}", id))?;
+        let resp = self.http_get(&format!("series/{}", id))?;
         let json: Series = serde_json::from_slice(&resp)?;
         Ok(json)
     }
@@ -567,7 +1426,7 @@ impl Client {
     /// ```
     pub fn series_dicom<W: Write>(&self, id: &str, writer: W) -> Result<()> {
         let path = format!("series/{}/archive", id);
-        self.get_stream(&path, writer)
+        self.http_get_stream(&path, writer)
     }
 
     /// Anonymize a series
@@ -576,7 +1435,7 @@ impl Client {
         id: &str,
         anonymization: Option<Anonymization>,
     ) -> Result<ModificationResult> {
-        self.anonymize("series", id, anonymization)
+        self.anonymize_entity("series", id, anonymization)
     }
 
     /// Modify a series
@@ -585,33 +1444,48 @@ impl Client {
         id: &str,
         modification: Modification,
     ) -> Result<ModificationResult> {
-        self.modify("series", id, modification)
+        self.modify_entity("series", id, modification)
     }
 
     /// Delete a series
     pub fn delete_series(&self, id: &str) -> Result<RemainingAncestor> {
-        let resp = self.delete(&format!("series/{}", id))?;
+        let resp = self.http_delete(&format!("series/{}", id))?;
         let json: RemainingAncestor = serde_json::from_slice(&resp)?;
         Ok(json)
     }
 
+    /// Get the patient of a series
+    pub fn series_patient(&self, id: &str) -> Result<Patient> {
+        self.related(EntityKind::Series, id, "patient")
+    }
+
+    /// Get the study of a series
+    pub fn series_study(&self, id: &str) -> Result<Study> {
+        self.related(EntityKind::Series, id, "study")
+    }
+
+    /// Get all instances of a series
+    pub fn series_instances(&self, id: &str) -> Result<Vec<Instance>> {
+        self.related(EntityKind::Series, id, "instances")
+    }
+
     ////////// Instances //////////
 
     /// List instances
     pub fn instances(&self) -> Result<Vec<String>> {
-        self.list("instances")
+        self.list_ids("instances")
     }
 
     /// List all instances in an expanded format
     pub fn instances_expanded(&self) -> Result<Vec<Instance>> {
-        let resp = self.get("instances?expand")?;
+        let resp = self.http_get("instances?expand")?;
         let json: Vec<Instance> = serde_json::from_slice(&resp)?;
         Ok(json)
     }
 
     /// Get an instance by its ID
     pub fn instance(&self, id: &str) -> Result<Instance> {
-        let resp = self.get(&format!("instances/{}", id))?;
+        let resp = self.http_get(&format!("instances/{}", id))?;
         let json: Instance = serde_json::from_slice(&resp)?;
         Ok(json)
     }
@@ -622,7 +1496,7 @@ impl Client {
     /// [section](https://book.orthanc-server.com/users/rest.html#accessing-the-dicom-fields-of-an-instance-as-a-json-file)
     /// for details
     pub fn instance_tags(&self, id: &str) -> Result<Value> {
-        let resp = self.get(&format!("instances/{}/simplified-tags", id))?;
+        let resp = self.http_get(&format!("instances/{}/simplified-tags", id))?;
         let json: Value = serde_json::from_slice(&resp)?;
         Ok(json)
     }
@@ -633,7 +1507,7 @@ impl Client {
     /// [section](https://book.orthanc-server.com/users/rest.html#accessing-the-dicom-fields-of-an-instance-as-a-json-file)
     /// for details
     pub fn instance_tags_expanded(&self, id: &str) -> Result<Value> {
-        let resp = self.get(&format!("instances/{}/tags", id))?;
+        let resp = self.http_get(&format!("instances/{}/tags", id))?;
         let json: Value = serde_json::from_slice(&resp)?;
         Ok(json)
     }
@@ -642,7 +1516,7 @@ impl Client {
     ///
     /// Returns a [`Vec`]<[`String`]> of the following format: `["0008-0018", "0040-0260", "0040-0254"]`
     pub fn instance_content(&self, id: &str) -> Result<Vec<String>> {
-        let resp = self.get(&format!("instances/{}/content", id))?;
+        let resp = self.http_get(&format!("instances/{}/content", id))?;
         let json = serde_json::from_slice(&resp)?;
         Ok(json)
     }
@@ -651,7 +1525,7 @@ impl Client {
     ///
     /// `tag` is the DICOM tag coding, e.g. `0008-0018`
     pub fn instance_tag(&self, id: &str, tag: &str) -> Result<String> {
-        let resp = self.get(&format!("instances/{}/content/{}", id, tag))?;
+        let resp = self.http_get(&format!("instances/{}/content/{}", id, tag))?;
         Ok(String::from_utf8_lossy(&resp).trim().to_string())
     }
 
@@ -668,7 +1542,7 @@ impl Client {
     /// ```
     pub fn instance_dicom<W: Write>(&self, id: &str, writer: W) -> Result<()> {
         let path = format!("instances/{}/file", id);
-        self.get_stream(&path, writer)
+        self.http_get_stream(&path, writer)
     }
 
     /// Anonymize an instance
@@ -688,20 +1562,11 @@ impl Client {
         anonymization: Option<Anonymization>,
         writer: W,
     ) -> Result<()> {
-        let data = match anonymization {
-            Some(a) => a,
-            // TODO: Just pass an empty object?
-            None => Anonymization {
-                replace: None,
-                keep: None,
-                keep_private_tags: None,
-                dicom_version: None,
-                force: None,
-            },
-        };
-        self.post_receive_stream(
+        let data = serde_json::to_value(anonymization.unwrap_or_default())?;
+        self.require_transcoding(&data)?;
+        self.http_post_receive_stream(
             &format!("instances/{}/anonymize", id),
-            serde_json::to_value(data)?,
+            data,
             writer,
         )?;
         Ok(())
@@ -716,11 +1581,7 @@ impl Client {
     ///
     /// ```
     /// let mut file = fs::File::create("/tmp/modified_instance.dcm").unwrap();
-    /// let modification = Modification {
-    ///     replace: None,
-    ///     remove: vec!["PatientName"],
-    ///     force: false,
-    /// };
+    /// let modification = Modification::default().remove("PatientName");
     /// client().modify_instance("3693b9d5-8b0e2a80-2cf45dda-d19e7c22-8749103c", modification, &mut file).unwrap();
     /// ```
     pub fn modify_instance<W: Write>(
@@ -729,58 +1590,159 @@ impl Client {
         modification: Modification,
         writer: W,
     ) -> Result<()> {
-        self.post_receive_stream(
-            &format!("instances/{}/modify", id),
-            serde_json::to_value(modification)?,
-            writer,
-        )?;
+        let data = serde_json::to_value(modification)?;
+        self.require_transcoding(&data)?;
+        self.http_post_receive_stream(&format!("instances/{}/modify", id), data, writer)?;
         Ok(())
     }
 
+    /// Modify an instance, storing the modified copy in Orthanc
+    ///
+    /// The modified DICOM file is streamed from Orthanc back into it, without being buffered in
+    /// memory. If `delete_original` is `true`, the original instance is deleted once the copy
+    /// has been stored.
+    ///
+    /// A modification that keeps the UIDs of the instance can only be stored if Orthanc is
+    /// configured with `OverwriteInstances`, the copy then replaces the original. Otherwise
+    /// Orthanc drops the copy, and an error is returned.
+    ///
+    /// ```no_run
+    /// # use orthanc::{models::Modification, Client};
+    /// # let client = Client::new("http://localhost:8042");
+    /// let modification = Modification::default().replace("InstitutionName", "Foo");
+    /// let id = "3693b9d5-8b0e2a80-2cf45dda-d19e7c22-8749103c";
+    /// let result = client.store_modified_instance(id, modification, true).unwrap();
+    /// ```
+    pub fn store_modified_instance(
+        &self,
+        id: &str,
+        modification: Modification,
+        delete_original: bool,
+    ) -> Result<ModificationResult> {
+        let data = serde_json::to_value(modification)?;
+        self.store_instance_copy(id, "modify", data, delete_original)
+    }
+
+    /// Anonymize an instance, storing the anonymized copy in Orthanc
+    ///
+    /// The anonymized DICOM file is streamed from Orthanc back into it, without being buffered
+    /// in memory. If `delete_original` is `true`, the original instance is deleted once the
+    /// copy has been stored.
+    pub fn store_anonymized_instance(
+        &self,
+        id: &str,
+        anonymization: Option<Anonymization>,
+        delete_original: bool,
+    ) -> Result<ModificationResult> {
+        let data = serde_json::to_value(anonymization.unwrap_or_default())?;
+        self.store_instance_copy(id, "anonymize", data, delete_original)
+    }
+
     /// Delete an instance
     pub fn delete_instance(&self, id: &str) -> Result<RemainingAncestor> {
-        let resp = self.delete(&format!("instances/{}", id))?;
+        let resp = self.http_delete(&format!("instances/{}", id))?;
         let json: RemainingAncestor = serde_json::from_slice(&resp)?;
         Ok(json)
     }
 
+    /// Get the patient of an instance
+    pub fn instance_patient(&self, id: &str) -> Result<Patient> {
+        self.related(EntityKind::Instance, id, "patient")
+    }
+
+    /// Get the study of an instance
+    pub fn instance_study(&self, id: &str) -> Result<Study> {
+        self.related(EntityKind::Instance, id, "study")
+    }
+
+    /// Get the series of an instance
+    pub fn instance_series(&self, id: &str) -> Result<Series> {
+        self.related(EntityKind::Instance, id, "series")
+    }
+
+    ////////// Labels //////////
+
+    /// List the labels of an entity
+    pub fn labels(&self, kind: EntityKind, id: &str) -> Result<Vec<String>> {
+        self.require(Feature::Labels)?;
+        let resp = self.http_get(&format!("{}/{}/labels", kind.resource()?, id))?;
+        let json: Vec<String> = serde_json::from_slice(&resp)?;
+        Ok(json)
+    }
+
+    /// Add a label to an entity
+    ///
+    /// Labels can only contain alphanumeric characters, `_` and `-`, and are at most 64
+    /// characters long. Invalid labels are rejected without sending a request.
+    pub fn add_label(&self, kind: EntityKind, id: &str, label: &str) -> Result<()> {
+        check_label(label)?;
+        self.require(Feature::Labels)?;
+        self.http_put(
+            &format!("{}/{}/labels/{}", kind.resource()?, id, label),
+            json!(""),
+        )
+        .map(|_| ())
+    }
+
+    /// Remove a label from an entity
+    ///
+    /// Invalid labels (see [`Client::add_label`]) are rejected without sending a request.
+    pub fn remove_label(&self, kind: EntityKind, id: &str, label: &str) -> Result<()> {
+        check_label(label)?;
+        self.require(Feature::Labels)?;
+        self.http_delete(&format!("{}/{}/labels/{}", kind.resource()?, id, label))
+            .map(|_| ())
+    }
+
+    /// List all labels, used by any entity
+    pub fn all_labels(&self) -> Result<Vec<String>> {
+        self.require(Feature::Labels)?;
+        let resp = self.http_get("tools/labels")?;
+        let json: Vec<String> = serde_json::from_slice(&resp)?;
+        Ok(json)
+    }
+
     ////////// Queries //////////
 
     /// List queries
     pub fn queries(&self) -> Result<Vec<String>> {
-        self.list("queries")
+        self.list_ids("queries")
+    }
+
+    /// Get a query by its ID
+    pub fn remote_query(&self, id: &str) -> RemoteQuery<'_> {
+        RemoteQuery::new(self, id)
     }
 
     /// Get query level
     pub fn query_level(&self, id: &str) -> Result<EntityKind> {
-        Ok(EntityKind::try_from(
-            self.get(&format!("queries/{}/level", id))?,
-        )?)
+        EntityKind::try_from(self.http_get(&format!("queries/{}/level", id))?)
     }
 
     /// Get query modality
     pub fn query_modality(&self, id: &str) -> Result<String> {
-        let resp = self.get(&format!("queries/{}/modality", id))?;
+        let resp = self.http_get(&format!("queries/{}/modality", id))?;
         Ok(str::from_utf8(&resp)?.to_string())
     }
 
     /// Get query query
     pub fn query_query(&self, id: &str) -> Result<Value> {
-        let resp = self.get(&format!("queries/{}/query", id))?;
+        let resp = self.http_get(&format!("queries/{}/query", id))?;
         let json: Value = serde_json::from_slice(&resp)?;
         Ok(json)
     }
 
     /// List query answers
     pub fn query_answers(&self, id: &str) -> Result<Vec<String>> {
-        let resp = self.get(&format!("queries/{}/answers", id))?;
+        let resp = self.http_get(&format!("queries/{}/answers", id))?;
         let json: Vec<String> = serde_json::from_slice(&resp)?;
         Ok(json)
     }
 
     /// Get query answer
     pub fn query_answer(&self, id: &str, answer_id: &str) -> Result<Value> {
-        let resp = self.get(&format!("queries/{}/answers/{}/content", id, answer_id))?;
+        let resp =
+            self.http_get(&format!("queries/{}/answers/{}/content", id, answer_id))?;
         let json: Value = serde_json::from_slice(&resp)?;
         Ok(json)
     }
@@ -792,7 +1754,7 @@ impl Client {
         answer_id: &str,
         target_aet: Option<&str>,
     ) -> Result<()> {
-        self.post(
+        self.http_post(
             &format!("queries/{}/answers/{}/retrieve", id, answer_id),
             target_aet.map(|t| {
                 json!(ModalityRetrieve {
@@ -805,7 +1767,7 @@ impl Client {
 
     /// Retrieve all query answers
     pub fn retrieve_query_answers(&self, id: &str, target_aet: Option<&str>) -> Result<()> {
-        self.post(
+        self.http_post(
             &format!("queries/{}/retrieve", id),
             target_aet.map(|t| {
                 json!(ModalityRetrieve {
@@ -816,15 +1778,397 @@ impl Client {
         .map(|_| ())
     }
 
+    ////////// Jobs //////////
+
+    /// List jobs
+    pub fn jobs(&self) -> Result<Vec<String>> {
+        self.list_ids("jobs")
+    }
+
+    /// Get a job by its ID
+    pub fn job(&self, id: &str) -> Result<Job> {
+        let resp = self.http_get(&format!("jobs/{}", id))?;
+        let json: Job = serde_json::from_slice(&resp)?;
+        Ok(json)
+    }
+
+    /// Wait for a job to finish
+    ///
+    /// The job is polled every `interval`, and `on_progress` is called with its state after each
+    /// poll. Returns the finished job if it succeeded, and an error if it failed, if it did not
+    /// finish within `timeout`, or if it is in a state, unknown to this crate.
+    ///
+    /// ```no_run
+    /// # use orthanc::Client;
+    /// # use std::time::Duration;
+    /// # let client = Client::new("http://localhost:8042");
+    /// client
+    ///     .wait_for_job(
+    ///         "8c0f2a35-6b91-4b4c-a5a4-2e8b1c7d6a12",
+    ///         Duration::from_secs(1),
+    ///         Duration::from_secs(600),
+    ///         |job| println!("{}%", job.progress),
+    ///     )
+    ///     .unwrap();
+    /// ```
+    pub fn wait_for_job<F: FnMut(&Job)>(
+        &self,
+        id: &str,
+        interval: time::Duration,
+        timeout: time::Duration,
+        mut on_progress: F,
+    ) -> Result<Job> {
+        let started = time::Instant::now();
+        loop {
+            let job = self.job(id)?;
+            on_progress(&job);
+            match job.state {
+                JobState::Success => return Ok(job),
+                JobState::Failure => {
+                    return Err(Error::new(
+                        &format!("Job {} failed: {}", id, job.error_description),
+                        None,
+                    ))
+                }
+                JobState::Unknown(ref state) => {
+                    return Err(Error::new(
+                        &format!("Job {} is in an unknown state: {}", id, state),
+                        None,
+                    ))
+                }
+                _ => {
+                    let elapsed = started.elapsed();
+                    if elapsed >= timeout {
+                        return Err(Error::new(
+                            &format!(
+                                "Job {} did not finish in {:?}, its state is {}",
+                                id,
+                                timeout,
+                                job.state.as_str()
+                            ),
+                            None,
+                        ));
+                    }
+                    thread::sleep(interval.min(timeout - elapsed));
+                }
+            }
+        }
+    }
+
+    ////////// Plugins //////////
+
+    /// List the IDs of the plugins, loaded by the server
+    pub fn plugins(&self) -> Result<Vec<String>> {
+        self.list_ids("plugins")
+    }
+
+    /// Get the information about a plugin
+    pub fn plugin(&self, id: &str) -> Result<Plugin> {
+        let resp = self.http_get(&format!("plugins/{}", id))?;
+        let json: Plugin = serde_json::from_slice(&resp)?;
+        Ok(json)
+    }
+
+    /// Raw access to the API, e.g. for the endpoints of the plugins, not covered by the client
+    ///
+    /// ```no_run
+    /// # use orthanc::Client;
+    /// let client = Client::new("http://localhost:8042");
+    /// let stats = client.raw().get("statistics").unwrap();
+    /// ```
+    pub fn raw(&self) -> RawClient<'_> {
+        RawClient::new(self)
+    }
+
+    /// DICOMweb client, talking to the DICOMweb plugin of the server
+    #[cfg(feature = "dicomweb")]
+    pub fn dicomweb(&self) -> crate::dicomweb::DicomWebClient<'_> {
+        crate::dicomweb::DicomWebClient::new(self)
+    }
+
+    /// Client of the worklists plugin of the server
+    #[cfg(feature = "worklists")]
+    pub fn worklists(&self) -> crate::worklists::WorklistsClient<'_> {
+        crate::worklists::WorklistsClient::new(self)
+    }
+
     ////////// Orther //////////
 
     /// System information
     pub fn system(&self) -> Result<System> {
-        let resp = self.get("system")?;
+        let resp = self.http_get("system")?;
         let json: System = serde_json::from_slice(&resp)?;
         Ok(json)
     }
 
+    /// Capabilities of the server
+    ///
+    /// System information is fetched on the first call, and reused afterwards (see
+    /// [`Client::refresh_capabilities`]). Methods, that use features not supported by all
+    /// versions of Orthanc (see [`Feature`]), check the capabilities before sending a request,
+    /// and fail with [`Error::unsupported`] if the server is too old. If the capabilities can
+    /// not be determined (e.g. the system information is forbidden to the user), the request is
+    /// sent anyway.
+    ///
+    /// ```no_run
+    /// # use orthanc::{models::Feature, Client};
+    /// # let client = Client::new("http://localhost:8042");
+    /// if client.capabilities().unwrap().supports(Feature::Labels) {
+    ///     println!("{:?}", client.all_labels().unwrap());
+    /// }
+    /// ```
+    pub fn capabilities(&self) -> Result<Capabilities> {
+        if let Some(c) = self.capabilities.read().unwrap().as_ref() {
+            return Ok(c.clone());
+        }
+        self.refresh_capabilities()
+    }
+
+    /// Fetch the capabilities of the server again, e.g. after it has been upgraded
+    pub fn refresh_capabilities(&self) -> Result<Capabilities> {
+        let capabilities = Capabilities::from_system(&self.system()?);
+        *self.capabilities.write().unwrap() = Some(capabilities.clone());
+        Ok(capabilities)
+    }
+
+    fn require(&self, feature: Feature) -> Result<()> {
+        match self.capabilities() {
+            Ok(c) => c.require(feature),
+            // Let the server decide
+            Err(_) => Ok(()),
+        }
+    }
+
+    /// Transcoding is requested through the `Transcode` option of the request body
+    fn require_transcoding(&self, data: &Value) -> Result<()> {
+        match data.get("Transcode") {
+            Some(Value::Null) | None => Ok(()),
+            Some(_) => self.require(Feature::Transcoding),
+        }
+    }
+
+    /// Generate a new random DICOM identifier for the given entity kind
+    ///
+    /// A DICOM UID for studies, series and instances, a UUID for patients.
+    pub fn generate_uid(&self, kind: EntityKind) -> Result<String> {
+        let level = match kind {
+            EntityKind::Patient => "patient",
+            EntityKind::Study => "study",
+            EntityKind::Series => "series",
+            EntityKind::Instance => "instance",
+            k => return Err(k.unsupported()),
+        };
+        let resp = self.http_get(&format!("tools/generate-uid?level={}", level))?;
+        Ok(str::from_utf8(&resp)?.trim().to_string())
+    }
+
+    /// Get the information about an attachment of an instance
+    ///
+    /// The DICOM file of an instance is stored as the `dicom` attachment.
+    pub fn instance_attachment_info(&self, id: &str, name: &str) -> Result<AttachmentInfo> {
+        let resp = self.http_get(&format!("instances/{}/attachments/{}/info", id, name))?;
+        let json: AttachmentInfo = serde_json::from_slice(&resp)?;
+        Ok(json)
+    }
+
+    /// Download an entity into a file
+    ///
+    /// Instances are downloaded as DICOM files, other entities as ZIP archives. The data is
+    /// written to a temporary `<path>.part` file, which is renamed to `path` only after its size
+    /// (and for instances, its MD5 checksum, unless Orthanc is configured not to store it) has
+    /// been verified.
+    ///
+    /// If the download of an instance fails mid-transfer, the temporary file is kept, and the
+    /// next call resumes the download from where it stopped. Archives are generated by Orthanc
+    /// on each request, so their download always starts over.
+    ///
+    /// ```no_run
+    /// # use orthanc::{entity::EntityKind, Client};
+    /// # let client = Client::new("http://localhost:8042");
+    /// let id = "9357491d-427a6c94-4080b6c8-1997f4aa-af658240";
+    /// client.download_to_file(EntityKind::Study, id, "/tmp/study.zip").unwrap();
+    /// ```
+    pub fn download_to_file<P: AsRef<Path>>(
+        &self,
+        kind: EntityKind,
+        id: &str,
+        path: P,
+    ) -> Result<()> {
+        let path = path.as_ref();
+        let mut partial = path.as_os_str().to_owned();
+        partial.push(".part");
+        let partial = PathBuf::from(partial);
+
+        if kind != EntityKind::Instance {
+            let resp = self
+                .http_get_response(&format!("{}/{}/archive", kind.resource()?, id), None)?;
+            let expected_size = resp.content_length();
+            let size = match write_response(resp, &partial, false) {
+                Ok(s) => s,
+                Err(e) => {
+                    let _ = fs::remove_file(&partial);
+                    return Err(e);
+                }
+            };
+            if let Some(expected) = expected_size {
+                check_download_size(&partial, size, expected)?;
+            }
+            fs::rename(&partial, path)?;
+            return Ok(());
+        }
+
+        let info = self.instance_attachment_info(id, "dicom")?;
+        let offset = fs::metadata(&partial).map(|m| m.len()).unwrap_or(0);
+        if offset < info.uncompressed_size {
+            let resp = self.http_get_response(
+                &format!("instances/{}/file", id),
+                Some(offset).filter(|o| *o > 0),
+            )?;
+            // Orthanc ignores the Range header if it does not support it
+            let append = resp.status() == reqwest::StatusCode::PARTIAL_CONTENT;
+            write_response(resp, &partial, append)?;
+        }
+
+        let size = fs::metadata(&partial)?.len();
+        check_download_size(&partial, size, info.uncompressed_size)?;
+        // Empty with `StoreMD5ForAttachments` disabled
+        if info.uncompressed_md5.is_empty() {
+            fs::rename(&partial, path)?;
+            return Ok(());
+        }
+        let md5 = file_md5(&partial)?;
+        if md5 != info.uncompressed_md5 {
+            fs::remove_file(&partial)?;
+            return Err(Error::new(
+                &format!(
+                    "MD5 mismatch: expected {}, got {}",
+                    info.uncompressed_md5, md5
+                ),
+                None,
+            ));
+        }
+        fs::rename(&partial, path)?;
+        Ok(())
+    }
+
+    /// Export an entity into a `Patient/Study/Series/instance.dcm` directory tree
+    ///
+    /// The names of the directories and files are built from the main DICOM tags of the
+    /// entities, according to `layout`. All ancestors of the entity are part of the tree, e.g.
+    /// exporting a series creates the directories of its patient and study as well.
+    ///
+    /// Files, that are already present and have the expected size, are not downloaded again, so
+    /// an interrupted export can be restarted. The files are downloaded with
+    /// [`Client::download_to_file`], hence checked and resumed the same way.
+    ///
+    /// ```no_run
+    /// # use orthanc::{entity::EntityKind, models::ExportLayout, Client};
+    /// # let client = Client::new("http://localhost:8042");
+    /// let layout = ExportLayout {
+    ///     study: "{StudyDate} {StudyDescription}".to_string(),
+    ///     series: "{SeriesNumber} {Modality}".to_string(),
+    ///     ..Default::default()
+    /// };
+    /// let id = "9357491d-427a6c94-4080b6c8-1997f4aa-af658240";
+    /// client.export_to_dir(id, EntityKind::Study, "/tmp/export", &layout).unwrap();
+    /// ```
+    pub fn export_to_dir<P: AsRef<Path>>(
+        &self,
+        id: &str,
+        kind: EntityKind,
+        dir: P,
+        layout: &ExportLayout,
+    ) -> Result<ExportReport> {
+        let mut export = Export {
+            dir: dir.as_ref(),
+            layout,
+            report: ExportReport::default(),
+            paths: HashSet::new(),
+        };
+        match kind {
+            EntityKind::Patient => {
+                for study in self.patient_studies(id)? {
+                    self.export_study(&mut export, &study)?;
+                }
+            }
+            EntityKind::Study => {
+                let study = self.study(id)?;
+                self.export_study(&mut export, &study)?;
+            }
+            EntityKind::Series => {
+                let series = self.series(id)?;
+                let study = self.study(&series.parent_study)?;
+                let instances = self.series_instances(id)?;
+                self.export_series(&mut export, &study, &series, &instances)?;
+            }
+            EntityKind::Instance => {
+                let instance = self.instance(id)?;
+                let series = self.series(&instance.parent_series)?;
+                let study = self.study(&series.parent_study)?;
+                self.export_series(&mut export, &study, &series, &[instance])?;
+            }
+            k => return Err(k.unsupported()),
+        }
+        Ok(export.report)
+    }
+
+    fn export_study(&self, export: &mut Export, study: &Study) -> Result<()> {
+        for series in self.study_series(&study.id)? {
+            let instances = self.series_instances(&series.id)?;
+            self.export_series(export, study, &series, &instances)?;
+        }
+        Ok(())
+    }
+
+    fn export_series(
+        &self,
+        export: &mut Export,
+        study: &Study,
+        series: &Series,
+        instances: &[Instance],
+    ) -> Result<()> {
+        let layout = export.layout;
+        let dir = export
+            .dir
+            .join(render_template(
+                &layout.patient,
+                &study.patient_main_dicom_tags,
+                &study.parent_patient,
+            ))
+            .join(render_template(
+                &layout.study,
+                &study.main_dicom_tags,
+                &study.id,
+            ))
+            .join(render_template(
+                &layout.series,
+                &series.main_dicom_tags,
+                &series.id,
+            ));
+        fs::create_dir_all(&dir)?;
+        for instance in instances {
+            let mut path = dir.join(render_template(
+                &layout.instance,
+                &instance.main_dicom_tags,
+                &format!("{}.dcm", instance.id),
+            ));
+            // Instances, rendered to the same file name, are told apart by their IDs
+            if export.paths.contains(&path) {
+                path = with_id(&path, &instance.id);
+            }
+            export.paths.insert(path.clone());
+            if let Ok(m) = fs::metadata(&path) {
+                if m.len() == instance.file_size {
+                    export.report.skipped.push(path);
+                    continue;
+                }
+            }
+            self.download_to_file(EntityKind::Instance, &instance.id, &path)?;
+            export.report.exported.push(path);
+        }
+        Ok(())
+    }
+
     /// Upload a DICOM file to Orthanc
     ///
     /// ```
@@ -833,11 +2177,188 @@ impl Client {
     /// client.upload(&data).unwrap();
     /// ```
     pub fn upload(&self, data: &[u8]) -> Result<UploadResult> {
-        let resp = self.post_bytes("instances", data)?;
+        let resp = self.http_post_bytes("instances", data)?;
         let json: UploadResult = serde_json::from_slice(&resp)?;
         Ok(json)
     }
 
+    /// Upload DICOM data to Orthanc, streaming it from a reader
+    ///
+    /// The data can be either a single DICOM file or a ZIP archive of DICOM files. If `length` is
+    /// known it is sent as `Content-Length`, otherwise the body is sent with chunked transfer
+    /// encoding. A result is returned for each instance found in the data.
+    ///
+    /// ```no_run
+    /// # use orthanc::Client;
+    /// # use std::fs;
+    /// # let client = Client::new("http://localhost:8042");
+    /// let file = fs::File::open("/tmp/study.zip").unwrap();
+    /// let length = file.metadata().unwrap().len();
+    /// let results = client.upload_reader(file, Some(length)).unwrap();
+    /// ```
+    pub fn upload_reader<R: Read + Send + 'static>(
+        &self,
+        reader: R,
+        length: Option<u64>,
+    ) -> Result<Vec<UploadResult>> {
+        let body = match length {
+            Some(l) => reqwest::blocking::Body::sized(reader, l),
+            None => reqwest::blocking::Body::new(reader),
+        };
+        let resp = self.http_post_body("instances", body)?;
+        // A single instance is reported as an object, a ZIP archive as an array
+        let json: Value = serde_json::from_slice(&resp)?;
+        match json {
+            Value::Array(_) => Ok(serde_json::from_value(json)?),
+            _ => Ok(vec![serde_json::from_value(json)?]),
+        }
+    }
+
+    /// Validate a DICOM file locally and upload it to Orthanc
+    ///
+    /// The file is not sent if it fails the validation. See [`DicomFile::parse`] for the checks
+    /// performed. The reason of the rejection is available through [`Error::validation_error`].
+    ///
+    /// [`DicomFile::parse`]: crate::dicom::DicomFile::parse
+    /// [`Error::validation_error`]: crate::Error::validation_error
+    #[cfg(feature = "dicom")]
+    pub fn upload_validated(&self, data: &[u8]) -> Result<UploadResult> {
+        crate::dicom::DicomFile::parse(data)?;
+        self.upload(data)
+    }
+
+    /// Upload all DICOM files found in a directory (recursively) to Orthanc
+    ///
+    /// Files that are not DICOM are skipped. Failing to read or upload a file does not stop the
+    /// upload, the error is recorded in the report instead. An error is only returned if the
+    /// directory can't be listed, or if an upload thread panicked.
+    ///
+    /// ```no_run
+    /// # use orthanc::{models::UploadOptions, Client};
+    /// # let client = Client::new("http://localhost:8042");
+    /// let report = client.upload_dir("/tmp/dicom", &UploadOptions::default()).unwrap();
+    /// for file in report.failed() {
+    ///     println!("{}: {:?}", file.path, file.result);
+    /// }
+    /// ```
+    pub fn upload_dir<P: AsRef<Path>>(
+        &self,
+        path: P,
+        options: &UploadOptions,
+    ) -> Result<UploadReport> {
+        let mut files = vec![];
+        collect_files(path.as_ref(), &mut files)?;
+        files.sort();
+        let files = files.into_iter().map(|f| {
+            let data = fs::File::open(&f).map_err(Error::from).and_then(read_dicom);
+            (f.display().to_string(), data)
+        });
+        self.upload_all(files, options)
+    }
+
+    /// Upload all DICOM files contained in a ZIP archive to Orthanc
+    ///
+    /// Available with the `zip` feature. Files that are not DICOM are skipped. Failing to read or
+    /// upload a file does not stop the upload, the error is recorded in the report instead.
+    #[cfg(feature = "zip")]
+    pub fn upload_zip<R: Read + Seek>(
+        &self,
+        reader: R,
+        options: &UploadOptions,
+    ) -> Result<UploadReport> {
+        let mut archive = zip::ZipArchive::new(reader)?;
+        let files = (0..archive.len()).filter_map(move |i| {
+            let file = match archive.by_index(i) {
+                Ok(f) => f,
+                Err(e) => return Some((format!("#{}", i), Err(Error::from(e)))),
+            };
+            if file.is_dir() {
+                return None;
+            }
+            let name = file.name().to_string();
+            Some((name, read_dicom(file)))
+        });
+        self.upload_all(files, options)
+    }
+
+    // Files are read by the calling thread and handed over to at most `options.concurrency`
+    // uploading threads, so that only a bounded number of files is held in memory
+    fn upload_all<I>(&self, files: I, options: &UploadOptions) -> Result<UploadReport>
+    where
+        I: Iterator<Item = (String, Result<Option<Vec<u8>>>)>,
+    {
+        let concurrency = options.concurrency.max(1);
+        let (sender, receiver) =
+            mpsc::sync_channel::<(usize, String, Vec<u8>)>(concurrency);
+        let receiver = Mutex::new(receiver);
+        let results = Mutex::new(vec![]);
+        let mut report = UploadReport::default();
+
+        let panicked = thread::scope(|s| {
+            let workers: Vec<_> = (0..concurrency)
+                .map(|_| {
+                    s.spawn(|| loop {
+                        let next = receiver
+                            .lock()
+                            .unwrap_or_else(PoisonError::into_inner)
+                            .recv();
+                        let (index, path, data) = match next {
+                            Ok(n) => n,
+                            Err(_) => break,
+                        };
+                        let result = self.upload(&data);
+                        results
+                            .lock()
+                            .unwrap_or_else(PoisonError::into_inner)
+                            .push((index, path, result));
+                    })
+                })
+                .collect();
+
+            for (index, (path, data)) in files.enumerate() {
+                match data {
+                    Ok(Some(d)) => {
+                        // Workers only stop early if they all panicked, which is reported below
+                        if sender.send((index, path, d)).is_err() {
+                            break;
+                        }
+                    }
+                    Ok(None) => report.skipped.push(path),
+                    Err(e) => results
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .push((index, path, Err(e))),
+                }
+            }
+            drop(sender);
+            workers
+                .into_iter()
+                .map(|w| w.join())
+                .filter(|r| r.is_err())
+                .count()
+        });
+        if panicked > 0 {
+            return Err(Error::new(
+                &format!("{} of {} upload workers panicked", panicked, concurrency),
+                None,
+            ));
+        }
+
+        let mut results = results.into_inner().unwrap_or_else(PoisonError::into_inner);
+        results.sort_by_key(|(index, _, _)| *index);
+        for (_, path, result) in results {
+            if options.detect_duplicates {
+                if let Ok(UploadResult { status, .. }) = &result {
+                    if status == "AlreadyStored" {
+                        report.duplicates.push(path.clone());
+                    }
+                }
+            }
+            report.files.push(UploadedFile { path, result });
+        }
+        Ok(report)
+    }
+
     /// Search for Entities in Orthanc
     pub fn search<T: Entity>(&self, query: HashMap<String, String>) -> Result<Vec<T>> {
         let kind = T::kind();
@@ -845,13 +2366,166 @@ impl Client {
             level: kind,
             query,
             expand: Some(true),
+            labels: None,
+            labels_constraint: None,
+        };
+        self.find(search)
+    }
+
+    /// Search for Entities in Orthanc, filtering them by their labels
+    ///
+    /// ```no_run
+    /// # use orthanc::{entity::Study, models::LabelsConstraint, Client};
+    /// # use std::collections::HashMap;
+    /// # let client = Client::new("http://localhost:8042");
+    /// let studies: Vec<Study> = client
+    ///     .search_with_labels(HashMap::new(), &["reviewed"], LabelsConstraint::None)
+    ///     .unwrap();
+    /// ```
+    pub fn search_with_labels<T: Entity>(
+        &self,
+        query: HashMap<String, String>,
+        labels: &[&str],
+        constraint: LabelsConstraint,
+    ) -> Result<Vec<T>> {
+        self.require(Feature::Labels)?;
+        let search = Search {
+            level: T::kind(),
+            query,
+            expand: Some(true),
+            labels: Some(labels.iter().map(|l| l.to_string()).collect()),
+            labels_constraint: Some(constraint),
         };
-        let resp = self.post("tools/find", Some(serde_json::to_value(search)?))?;
+        self.find(search)
+    }
+
+    fn find<T: Entity>(&self, search: Search) -> Result<Vec<T>> {
+        let resp = self.http_post("tools/find", Some(serde_json::to_value(search)?))?;
         let json: Vec<T> = serde_json::from_slice(&resp)?;
         Ok(json)
     }
 }
 
+fn echo_request(timeout: Option<u32>) -> Value {
+    let mut data = HashMap::new();
+    if let Some(to) = timeout {
+        data.insert("Timeout", to);
+    }
+    json!(data)
+}
+
+// State of an export to a directory
+struct Export<'a> {
+    dir: &'a Path,
+    layout: &'a ExportLayout,
+    report: ExportReport,
+    // Paths of the files of the export, so far
+    paths: HashSet<PathBuf>,
+}
+
+// An entity, loaded to plan a deletion
+#[derive(Debug)]
+struct DeletionNode {
+    kind: EntityKind,
+    parent: Option<String>,
+    // IDs of the child entities (e.g. series of a study)
+    children: Vec<String>,
+    is_stable: bool,
+    // Size of the file of an instance
+    file_size: u64,
+}
+
+impl DeletionNode {
+    fn new(
+        kind: &EntityKind,
+        parent: Option<String>,
+        children: Vec<String>,
+        is_stable: bool,
+        file_size: u64,
+    ) -> DeletionNode {
+        DeletionNode {
+            kind: kind.clone(),
+            parent,
+            children,
+            is_stable,
+            file_size,
+        }
+    }
+
+    // Kind and ID of the parent entity
+    fn parent(&self) -> Option<(EntityKind, String)> {
+        let kind = match self.kind {
+            EntityKind::Study => EntityKind::Patient,
+            EntityKind::Series => EntityKind::Study,
+            EntityKind::Instance => EntityKind::Series,
+            _ => return None,
+        };
+        self.parent.clone().map(|p| (kind, p))
+    }
+}
+
+// Append an ID to the file name, keeping the extension
+fn with_id(path: &Path, id: &str) -> PathBuf {
+    let mut name = path.file_stem().unwrap_or_default().to_owned();
+    name.push(format!("_{}", id));
+    if let Some(e) = path.extension() {
+        name.push(".");
+        name.push(e);
+    }
+    path.with_file_name(name)
+}
+
+// Returns the size of the file after writing
+fn write_response(
+    mut resp: reqwest::blocking::Response,
+    path: &Path,
+    append: bool,
+) -> Result<u64> {
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(append)
+        .truncate(!append)
+        .open(path)?;
+    resp.copy_to(&mut file)?;
+    file.flush()?;
+    Ok(file.metadata()?.len())
+}
+
+// A file of a wrong size is not going to get better by resuming, so it is removed
+fn check_download_size(path: &Path, size: u64, expected: u64) -> Result<()> {
+    if size != expected {
+        fs::remove_file(path)?;
+        return Err(Error::new(
+            &format!("Size mismatch: expected {} bytes, got {}", expected, size),
+            None,
+        ));
+    }
+    Ok(())
+}
+
+// The file is hashed in chunks, so that it is never loaded into memory as a whole
+fn file_md5(path: &Path) -> Result<String> {
+    let mut context = md5::Context::new();
+    std::io::copy(&mut fs::File::open(path)?, &mut context)?;
+    Ok(format!("{:x}", context.compute()))
+}
+
+// Symbolic links to directories are not followed, so that a link loop can not recurse forever
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        let path = entry.path();
+        if file_type.is_dir() {
+            collect_files(&path, files)?;
+        } else if file_type.is_file() || (file_type.is_symlink() && path.is_file()) {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -888,7 +2562,7 @@ mod tests {
             .create_on(&mock_server);
 
         let cl = Client::new(url).auth("foo", "bar");
-        let resp = cl.get("foo").unwrap();
+        let resp = cl.http_get("foo").unwrap();
 
         assert_eq!(resp, "bar");
         assert_eq!(m.times_called(), 1);
@@ -909,7 +2583,7 @@ mod tests {
 
         let cl = Client::new(url).auth("foo", "bar");
         let mut writer: Vec<u8> = vec![];
-        cl.get_stream("foo", &mut writer).unwrap();
+        cl.http_get_stream("foo", &mut writer).unwrap();
 
         assert_eq!(&writer, &b"bar");
         assert_eq!(m.times_called(), 1);
@@ -931,7 +2605,7 @@ mod tests {
             .create_on(&mock_server);
 
         let cl = Client::new(url).auth("foo", "bar");
-        let resp = cl.post("foo", Some(serde_json::json!("bar"))).unwrap();
+        let resp = cl.http_post("foo", Some(serde_json::json!("bar"))).unwrap();
 
         assert_eq!(resp, "baz");
         assert_eq!(m.times_called(), 1);
@@ -952,7 +2626,7 @@ mod tests {
             .create_on(&mock_server);
 
         let cl = Client::new(url).auth("foo", "bar");
-        let resp = cl.post("foo", None).unwrap();
+        let resp = cl.http_post("foo", None).unwrap();
 
         assert_eq!(resp, "baz");
         assert_eq!(m.times_called(), 1);
@@ -975,7 +2649,7 @@ mod tests {
 
         let cl = Client::new(url).auth("foo", "bar");
         let mut writer: Vec<u8> = vec![];
-        cl.post_receive_stream("foo", serde_json::json!("bar"), &mut writer)
+        cl.http_post_receive_stream("foo", serde_json::json!("bar"), &mut writer)
             .unwrap();
 
         assert_eq!(&writer, &b"baz");
@@ -998,7 +2672,7 @@ mod tests {
             .create_on(&mock_server);
 
         let cl = Client::new(url).auth("foo", "bar");
-        let resp = cl.post_bytes("foo", "bar".as_bytes()).unwrap();
+        let resp = cl.http_post_bytes("foo", "bar".as_bytes()).unwrap();
 
         assert_eq!(resp, "baz");
         assert_eq!(m.times_called(), 1);
@@ -1017,7 +2691,7 @@ mod tests {
             .create_on(&mock_server);
 
         let cl = Client::new(url).auth("foo", "bar");
-        let resp = cl.delete("foo").unwrap();
+        let resp = cl.http_delete("foo").unwrap();
 
         assert_eq!(resp, "");
         assert_eq!(m.times_called(), 1);
@@ -1049,13 +2723,13 @@ mod tests {
             .create_on(&mock_server);
 
         let cl = Client::new(url);
-        let resp = cl.get("foo");
+        let resp = cl.http_get("foo");
 
         assert_eq!(
             resp.unwrap_err(),
             Error {
                 message: "API error: 400 Bad Request".to_string(),
-                details: Some(ApiError {
+                details: Some(Box::new(ApiError {
                     method: "POST".to_string(),
                     uri: "/instances".to_string(),
                     message: "Bad file format".to_string(),
@@ -1066,7 +2740,8 @@ mod tests {
                     http_error: "Bad Request".to_string(),
                     orthanc_status: 15,
                     orthanc_error: "Bad file format".to_string(),
-                },),
+                }),),
+                kind: None,
             },
         );
         assert_eq!(m.times_called(), 1);
@@ -1099,13 +2774,13 @@ mod tests {
 
         let cl = Client::new(url);
         let mut writer: Vec<u8> = vec![];
-        let resp = cl.get_stream("foo", &mut writer);
+        let resp = cl.http_get_stream("foo", &mut writer);
 
         assert_eq!(
             resp.unwrap_err(),
             Error {
                 message: "API error: 400 Bad Request".to_string(),
-                details: Some(ApiError {
+                details: Some(Box::new(ApiError {
                     method: "POST".to_string(),
                     uri: "/instances".to_string(),
                     message: "Bad file format".to_string(),
@@ -1116,7 +2791,8 @@ mod tests {
                     http_error: "Bad Request".to_string(),
                     orthanc_status: 15,
                     orthanc_error: "Bad file format".to_string(),
-                },),
+                }),),
+                kind: None,
             },
         );
         assert_eq!(m.times_called(), 1);
@@ -1135,13 +2811,14 @@ mod tests {
 
         let cl = Client::new(url);
         let mut writer: Vec<u8> = vec![];
-        let resp = cl.get_stream("foo", &mut writer);
+        let resp = cl.http_get_stream("foo", &mut writer);
 
         assert_eq!(
             resp.unwrap_err(),
             Error {
                 message: "API error: 400 Bad Request".to_string(),
                 details: None,
+                kind: None,
             },
         );
         assert_eq!(m.times_called(), 1);
@@ -1173,13 +2850,13 @@ mod tests {
             .create_on(&mock_server);
 
         let cl = Client::new(url);
-        let resp = cl.post("foo", Some(serde_json::json!("bar")));
+        let resp = cl.http_post("foo", Some(serde_json::json!("bar")));
 
         assert_eq!(
             resp.unwrap_err(),
             Error {
                 message: "API error: 400 Bad Request".to_string(),
-                details: Some(ApiError {
+                details: Some(Box::new(ApiError {
                     method: "POST".to_string(),
                     uri: "/instances".to_string(),
                     message: "Bad file format".to_string(),
@@ -1190,7 +2867,8 @@ mod tests {
                     http_error: "Bad Request".to_string(),
                     orthanc_status: 15,
                     orthanc_error: "Bad file format".to_string(),
-                },),
+                }),),
+                kind: None,
             },
         );
         assert_eq!(m.times_called(), 1);
@@ -1223,13 +2901,14 @@ mod tests {
 
         let cl = Client::new(url);
         let mut writer: Vec<u8> = vec![];
-        let resp = cl.post_receive_stream("foo", serde_json::json!("bar"), &mut writer);
+        let resp =
+            cl.http_post_receive_stream("foo", serde_json::json!("bar"), &mut writer);
 
         assert_eq!(
             resp.unwrap_err(),
             Error {
                 message: "API error: 400 Bad Request".to_string(),
-                details: Some(ApiError {
+                details: Some(Box::new(ApiError {
                     method: "POST".to_string(),
                     uri: "/instances".to_string(),
                     message: "Bad file format".to_string(),
@@ -1240,7 +2919,8 @@ mod tests {
                     http_error: "Bad Request".to_string(),
                     orthanc_status: 15,
                     orthanc_error: "Bad file format".to_string(),
-                },),
+                }),),
+                kind: None,
             },
         );
         assert_eq!(m.times_called(), 1);
@@ -1259,13 +2939,15 @@ mod tests {
 
         let cl = Client::new(url);
         let mut writer: Vec<u8> = vec![];
-        let resp = cl.post_receive_stream("foo", serde_json::json!("bar"), &mut writer);
+        let resp =
+            cl.http_post_receive_stream("foo", serde_json::json!("bar"), &mut writer);
 
         assert_eq!(
             resp.unwrap_err(),
             Error {
                 message: "API error: 400 Bad Request".to_string(),
                 details: None,
+                kind: None,
             },
         );
         assert_eq!(m.times_called(), 1);
@@ -1297,13 +2979,13 @@ mod tests {
             .create_on(&mock_server);
 
         let cl = Client::new(url);
-        let resp = cl.post_bytes("foo", &[13, 42, 17]);
+        let resp = cl.http_post_bytes("foo", &[13, 42, 17]);
 
         assert_eq!(
             resp.unwrap_err(),
             Error {
                 message: "API error: 400 Bad Request".to_string(),
-                details: Some(ApiError {
+                details: Some(Box::new(ApiError {
                     method: "POST".to_string(),
                     uri: "/instances".to_string(),
                     message: "Bad file format".to_string(),
@@ -1314,7 +2996,8 @@ mod tests {
                     http_error: "Bad Request".to_string(),
                     orthanc_status: 15,
                     orthanc_error: "Bad file format".to_string(),
-                },),
+                }),),
+                kind: None,
             },
         );
         assert_eq!(m.times_called(), 1);
@@ -1336,7 +3019,7 @@ mod tests {
             .create_on(&mock_server);
 
         let cl = Client::new(url).auth("foo", "bar");
-        let resp = cl.put("foo", serde_json::json!("bar")).unwrap();
+        let resp = cl.http_put("foo", serde_json::json!("bar")).unwrap();
 
         assert_eq!(resp, "baz");
         assert_eq!(m.times_called(), 1);
@@ -1368,13 +3051,13 @@ mod tests {
             .create_on(&mock_server);
 
         let cl = Client::new(url);
-        let resp = cl.put("foo", serde_json::json!("bar"));
+        let resp = cl.http_put("foo", serde_json::json!("bar"));
 
         assert_eq!(
             resp.unwrap_err(),
             Error {
                 message: "API error: 400 Bad Request".to_string(),
-                details: Some(ApiError {
+                details: Some(Box::new(ApiError {
                     method: "POST".to_string(),
                     uri: "/instances".to_string(),
                     message: "Bad file format".to_string(),
@@ -1385,7 +3068,8 @@ mod tests {
                     http_error: "Bad Request".to_string(),
                     orthanc_status: 15,
                     orthanc_error: "Bad file format".to_string(),
-                },),
+                }),),
+                kind: None,
             },
         );
         assert_eq!(m.times_called(), 1);
@@ -1417,13 +3101,13 @@ mod tests {
             .create_on(&mock_server);
 
         let cl = Client::new(url);
-        let resp = cl.delete("foo");
+        let resp = cl.http_delete("foo");
 
         assert_eq!(
             resp.unwrap_err(),
             Error {
                 message: "API error: 400 Bad Request".to_string(),
-                details: Some(ApiError {
+                details: Some(Box::new(ApiError {
                     method: "POST".to_string(),
                     uri: "/instances".to_string(),
                     message: "Bad file format".to_string(),
@@ -1434,7 +3118,8 @@ mod tests {
                     http_error: "Bad Request".to_string(),
                     orthanc_status: 15,
                     orthanc_error: "Bad file format".to_string(),
-                },),
+                }),),
+                kind: None,
             },
         );
         assert_eq!(m.times_called(), 1);
@@ -1452,7 +3137,7 @@ mod tests {
             .create_on(&mock_server);
 
         let cl = Client::new(url);
-        let resp = cl.get("foo");
+        let resp = cl.http_get("foo");
 
         assert!(resp.is_err());
         assert_eq!(
@@ -1460,6 +3145,7 @@ mod tests {
             Error {
                 message: "API error: 404 Not Found".to_string(),
                 details: None,
+                kind: None,
             },
         );
         assert_eq!(m.times_called(), 1);
@@ -1479,7 +3165,7 @@ mod tests {
             .create_on(&mock_server);
 
         let cl = Client::new(url).auth("foo", "bar");
-        let resp = cl.list("foos").unwrap();
+        let resp = cl.list_ids("foos").unwrap();
 
         assert_eq!(resp, vec!["bar", "baz", "qux"]);
         assert_eq!(m.times_called(), 1);
@@ -1497,6 +3183,7 @@ mod tests {
                 replace: Some(hashmap! {"Tag1".to_string() => "value1".to_string()}),
                 remove: Some(vec!["Tag2".to_string()]),
                 force: None,
+                ..Default::default()
             })
             .return_status(200)
             .return_body(
@@ -1513,13 +3200,14 @@ mod tests {
 
         let cl = Client::new(url);
         let resp = cl
-            .modify(
+            .modify_entity(
                 "studies",
                 "foo",
                 Modification {
                     replace: Some(hashmap! {"Tag1".to_string() => "value1".to_string()}),
                     remove: Some(vec!["Tag2".to_string()]),
                     force: None,
+                    ..Default::default()
                 },
             )
             .unwrap();
@@ -1550,6 +3238,7 @@ mod tests {
                 keep_private_tags: None,
                 dicom_version: None,
                 force: None,
+                ..Default::default()
             })
             .return_status(200)
             .return_body(
@@ -1566,7 +3255,7 @@ mod tests {
 
         let cl = Client::new(url);
         let resp = cl
-            .anonymize(
+            .anonymize_entity(
                 "studies",
                 "foo",
                 Some(Anonymization {
@@ -1575,6 +3264,7 @@ mod tests {
                     keep_private_tags: None,
                     dicom_version: None,
                     force: None,
+                    ..Default::default()
                 }),
             )
             .unwrap();
@@ -0,0 +1,394 @@
+//! Local parsing and validation of DICOM files
+//!
+//! Available with the `dicom` feature. Allows checking a DICOM file before it is sent to Orthanc,
+//! and predicting the IDs Orthanc is going to assign to it.
+//!
+//! ```no_run
+//! # use orthanc::dicom::DicomFile;
+//! # use std::fs;
+//! let data = fs::read("/tmp/instance.dcm").unwrap();
+//! let file = DicomFile::parse(&data).unwrap();
+//! println!("{}", file.orthanc_ids().instance);
+//! ```
+use crate::{Error, ErrorKind};
+use dicom_object::{from_reader, DefaultDicomObject, Error as DicomError, Tag};
+use std::error;
+use std::fmt;
+
+const PREAMBLE_LENGTH: usize = 128;
+const DICM_MAGIC_CODE: &[u8] = b"DICM";
+
+const SOP_CLASS_UID: (&str, Tag) = ("SOPClassUID", Tag(0x0008, 0x0016));
+const SOP_INSTANCE_UID: (&str, Tag) = ("SOPInstanceUID", Tag(0x0008, 0x0018));
+const PATIENT_ID: (&str, Tag) = ("PatientID", Tag(0x0010, 0x0020));
+const STUDY_INSTANCE_UID: (&str, Tag) = ("StudyInstanceUID", Tag(0x0020, 0x000D));
+const SERIES_INSTANCE_UID: (&str, Tag) = ("SeriesInstanceUID", Tag(0x0020, 0x000E));
+
+/// Reason a DICOM file was rejected by the local validation
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ValidationError {
+    /// The file is too short to contain the 128-byte preamble and the `DICM` magic code
+    MissingPreamble,
+    /// The `DICM` magic code is not found after the preamble
+    NotDicom,
+    /// The file meta information header could not be parsed
+    InvalidMetaHeader(String),
+    /// The transfer syntax of the file is not supported
+    UnsupportedTransferSyntax(String),
+    /// The data set could not be parsed
+    InvalidDataSet(String),
+    /// A tag required by Orthanc is missing or empty
+    MissingTag(String),
+    /// The SOP Instance UID of the data set does not match the one in the meta header. Only
+    /// checked by [`DicomFile::parse_strict`]
+    SopInstanceUidMismatch { meta: String, dataset: String },
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ValidationError::MissingPreamble => write!(f, "DICOM preamble is missing"),
+            ValidationError::NotDicom => write!(f, "DICM magic code is missing"),
+            ValidationError::InvalidMetaHeader(e) => {
+                write!(f, "Invalid file meta information header: {}", e)
+            }
+            ValidationError::UnsupportedTransferSyntax(ts) => {
+                write!(f, "Unsupported transfer syntax: {}", ts)
+            }
+            ValidationError::InvalidDataSet(e) => write!(f, "Invalid data set: {}", e),
+            ValidationError::MissingTag(tag) => write!(f, "Missing tag: {}", tag),
+            ValidationError::SopInstanceUidMismatch { meta, dataset } => write!(
+                f,
+                "SOP Instance UID mismatch: {} in meta header, {} in data set",
+                meta, dataset
+            ),
+        }
+    }
+}
+
+impl error::Error for ValidationError {}
+
+impl From<ValidationError> for Error {
+    fn from(e: ValidationError) -> Self {
+        Error {
+            kind: Some(ErrorKind::InvalidDicom(e.clone())),
+            ..Error::new(&format!("Invalid DICOM file: {}", e), None)
+        }
+    }
+}
+
+/// IDs Orthanc assigns to the entities of a DICOM instance
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct OrthancIds {
+    pub patient: String,
+    pub study: String,
+    pub series: String,
+    pub instance: String,
+}
+
+/// Main identifiers of a locally parsed DICOM file
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct DicomFile {
+    pub transfer_syntax: String,
+    pub sop_class_uid: String,
+    pub sop_instance_uid: String,
+    pub patient_id: String,
+    pub study_instance_uid: String,
+    pub series_instance_uid: String,
+    /// `MediaStorageSOPInstanceUID` of the file meta information header
+    pub media_storage_sop_instance_uid: String,
+}
+
+impl DicomFile {
+    /// Parse and validate the contents of a DICOM file (Part 10 format, including the preamble)
+    ///
+    /// The checks are stricter than the ones of Orthanc: besides the problems that make Orthanc
+    /// reject the file, files using a transfer syntax that dicom-rs can't parse and files without a
+    /// `SOPClassUID` are reported as invalid, although Orthanc accepts them.
+    pub fn parse(data: &[u8]) -> Result<DicomFile, ValidationError> {
+        if data.len() < PREAMBLE_LENGTH + DICM_MAGIC_CODE.len() {
+            return Err(ValidationError::MissingPreamble);
+        }
+        if &data[PREAMBLE_LENGTH..PREAMBLE_LENGTH + DICM_MAGIC_CODE.len()]
+            != DICM_MAGIC_CODE
+        {
+            return Err(ValidationError::NotDicom);
+        }
+
+        let obj = from_reader(&data[PREAMBLE_LENGTH..]).map_err(|e| match e {
+            DicomError::ParseMetaDataSet { source } => {
+                ValidationError::InvalidMetaHeader(source.to_string())
+            }
+            DicomError::UnsupportedTransferSyntax { uid, .. } => {
+                ValidationError::UnsupportedTransferSyntax(trim(&uid))
+            }
+            e => ValidationError::InvalidDataSet(e.to_string()),
+        })?;
+
+        let meta = obj.meta();
+        let file = DicomFile {
+            transfer_syntax: trim(&meta.transfer_syntax),
+            sop_class_uid: required(&obj, SOP_CLASS_UID)?,
+            sop_instance_uid: required(&obj, SOP_INSTANCE_UID)?,
+            // Orthanc accepts instances without PatientID
+            patient_id: optional(&obj, PATIENT_ID.1),
+            study_instance_uid: required(&obj, STUDY_INSTANCE_UID)?,
+            series_instance_uid: required(&obj, SERIES_INSTANCE_UID)?,
+            media_storage_sop_instance_uid: trim(&meta.media_storage_sop_instance_uid),
+        };
+        Ok(file)
+    }
+
+    /// Same as [`DicomFile::parse`], but also rejects the files, the meta header of which is
+    /// inconsistent with the data set
+    ///
+    /// Orthanc accepts such files, using the SOP Instance UID of the data set.
+    pub fn parse_strict(data: &[u8]) -> Result<DicomFile, ValidationError> {
+        let file = DicomFile::parse(data)?;
+        if file.media_storage_sop_instance_uid != file.sop_instance_uid {
+            return Err(ValidationError::SopInstanceUidMismatch {
+                meta: file.media_storage_sop_instance_uid,
+                dataset: file.sop_instance_uid,
+            });
+        }
+        Ok(file)
+    }
+
+    /// Predict the IDs Orthanc is going to assign to the entities of the file
+    ///
+    /// Orthanc IDs are SHA-1 hashes of the DICOM identifiers of the entity and its parents
+    pub fn orthanc_ids(&self) -> OrthancIds {
+        let patient = self.patient_id.clone();
+        let study = format!("{}|{}", patient, self.study_instance_uid);
+        let series = format!("{}|{}", study, self.series_instance_uid);
+        let instance = format!("{}|{}", series, self.sop_instance_uid);
+        OrthancIds {
+            patient: orthanc_hash(&patient),
+            study: orthanc_hash(&study),
+            series: orthanc_hash(&series),
+            instance: orthanc_hash(&instance),
+        }
+    }
+}
+
+fn required(obj: &DefaultDicomObject, tag: (&str, Tag)) -> Result<String, ValidationError> {
+    let value = optional(obj, tag.1);
+    if value.is_empty() {
+        return Err(ValidationError::MissingTag(tag.0.to_string()));
+    }
+    Ok(value)
+}
+
+fn optional(obj: &DefaultDicomObject, tag: Tag) -> String {
+    obj.element(tag)
+        .ok()
+        .and_then(|e| e.to_str().ok().map(|v| trim(&v)))
+        .unwrap_or_default()
+}
+
+// String values are padded to an even length with either spaces or NULs
+fn trim(value: &str) -> String {
+    value
+        .trim_matches(|c: char| c == '\0' || c.is_whitespace())
+        .to_string()
+}
+
+fn orthanc_hash(value: &str) -> String {
+    let hex = sha1_smol::Sha1::from(value).digest().to_string();
+    hex.as_bytes()
+        .chunks(8)
+        .map(|c| String::from_utf8_lossy(c).into_owned())
+        .collect::<Vec<String>>()
+        .join("-")
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    const EXPLICIT_VR_LITTLE_ENDIAN: &str = "1.2.840.10008.1.2.1";
+
+    fn element(group: u16, elem: u16, vr: &[u8; 2], value: &[u8]) -> Vec<u8> {
+        let mut value = value.to_vec();
+        if value.len() % 2 == 1 {
+            value.push(if vr == b"UI" { 0 } else { b' ' });
+        }
+        let mut data = vec![];
+        data.extend(&group.to_le_bytes());
+        data.extend(&elem.to_le_bytes());
+        data.extend(vr);
+        if vr == b"OB" {
+            data.extend(&[0, 0]);
+            data.extend(&(value.len() as u32).to_le_bytes());
+        } else {
+            data.extend(&(value.len() as u16).to_le_bytes());
+        }
+        data.extend(value);
+        data
+    }
+
+    /// Build a minimal DICOM file, with the tags Orthanc requires
+    pub(crate) fn dicom_file(
+        transfer_syntax: &str,
+        patient_id: &str,
+        sop_instance_uid: &str,
+    ) -> Vec<u8> {
+        let mut meta = vec![];
+        meta.extend(element(0x0002, 0x0001, b"OB", &[0, 1]));
+        meta.extend(element(0x0002, 0x0002, b"UI", b"1.2.840.10008.5.1.4.1.1.4"));
+        meta.extend(element(0x0002, 0x0003, b"UI", sop_instance_uid.as_bytes()));
+        meta.extend(element(0x0002, 0x0010, b"UI", transfer_syntax.as_bytes()));
+        meta.extend(element(0x0002, 0x0012, b"UI", b"1.2.3.4"));
+
+        let mut data = vec![0; PREAMBLE_LENGTH];
+        data.extend(DICM_MAGIC_CODE);
+        data.extend(element(
+            0x0002,
+            0x0000,
+            b"UL",
+            &(meta.len() as u32).to_le_bytes(),
+        ));
+        data.extend(meta);
+        data.extend(element(0x0008, 0x0016, b"UI", b"1.2.840.10008.5.1.4.1.1.4"));
+        data.extend(element(0x0008, 0x0018, b"UI", sop_instance_uid.as_bytes()));
+        data.extend(element(0x0010, 0x0020, b"LO", patient_id.as_bytes()));
+        data.extend(element(0x0020, 0x000D, b"UI", b"1.2.3.4.5"));
+        data.extend(element(0x0020, 0x000E, b"UI", b"1.2.3.4.5.6"));
+        data
+    }
+
+    #[test]
+    fn test_parse() {
+        let data = dicom_file(EXPLICIT_VR_LITTLE_ENDIAN, "patient_1", "1.2.3.4.5.6.7");
+        assert_eq!(
+            DicomFile::parse(&data).unwrap(),
+            DicomFile {
+                transfer_syntax: EXPLICIT_VR_LITTLE_ENDIAN.to_string(),
+                sop_class_uid: "1.2.840.10008.5.1.4.1.1.4".to_string(),
+                sop_instance_uid: "1.2.3.4.5.6.7".to_string(),
+                patient_id: "patient_1".to_string(),
+                study_instance_uid: "1.2.3.4.5".to_string(),
+                series_instance_uid: "1.2.3.4.5.6".to_string(),
+                media_storage_sop_instance_uid: "1.2.3.4.5.6.7".to_string(),
+            }
+        );
+        assert_eq!(
+            DicomFile::parse_strict(&data).unwrap(),
+            DicomFile::parse(&data).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_parse_sop_instance_uid_mismatch() {
+        let mut data = dicom_file(EXPLICIT_VR_LITTLE_ENDIAN, "patient_1", "1.2.3.4.5.6.7");
+        // The first occurrence of the UID is in the meta header
+        let uid = b"1.2.3.4.5.6.7";
+        let pos = data.windows(uid.len()).position(|w| w == uid).unwrap();
+        data[pos + uid.len() - 1] = b'8';
+
+        let file = DicomFile::parse(&data).unwrap();
+        assert_eq!(file.sop_instance_uid, "1.2.3.4.5.6.7");
+        assert_eq!(file.media_storage_sop_instance_uid, "1.2.3.4.5.6.8");
+        assert_eq!(
+            DicomFile::parse_strict(&data).unwrap_err(),
+            ValidationError::SopInstanceUidMismatch {
+                meta: "1.2.3.4.5.6.8".to_string(),
+                dataset: "1.2.3.4.5.6.7".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_missing_preamble() {
+        assert_eq!(
+            DicomFile::parse(b"DICM").unwrap_err(),
+            ValidationError::MissingPreamble
+        );
+    }
+
+    #[test]
+    fn test_parse_not_dicom() {
+        assert_eq!(
+            DicomFile::parse(&[0; 256]).unwrap_err(),
+            ValidationError::NotDicom
+        );
+    }
+
+    #[test]
+    fn test_parse_invalid_meta_header() {
+        let mut data = vec![0; PREAMBLE_LENGTH];
+        data.extend(DICM_MAGIC_CODE);
+        data.extend(&[0x08, 0x00, 0x16, 0x00]);
+        assert!(matches!(
+            DicomFile::parse(&data).unwrap_err(),
+            ValidationError::InvalidMetaHeader(_)
+        ));
+    }
+
+    #[test]
+    fn test_parse_unsupported_transfer_syntax() {
+        let data = dicom_file("1.2.3.4.5.6.7.8", "patient_1", "1.2.3.4.5.6.7");
+        assert_eq!(
+            DicomFile::parse(&data).unwrap_err(),
+            ValidationError::UnsupportedTransferSyntax("1.2.3.4.5.6.7.8".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_missing_tag() {
+        let data = dicom_file(EXPLICIT_VR_LITTLE_ENDIAN, "patient_1", "");
+        assert_eq!(
+            DicomFile::parse(&data).unwrap_err(),
+            ValidationError::MissingTag("SOPInstanceUID".to_string())
+        );
+    }
+
+    #[test]
+    fn test_orthanc_ids() {
+        let file = DicomFile {
+            transfer_syntax: EXPLICIT_VR_LITTLE_ENDIAN.to_string(),
+            sop_class_uid: "1.2.840.10008.5.1.4.1.1.4".to_string(),
+            sop_instance_uid: "1.3.46.670589.11.1.5.0.3724.2011072815265975004".to_string(),
+            patient_id: "patient_2".to_string(),
+            study_instance_uid: "1.3.46.670589.11.1.5.0.6560.2011072814060507000"
+                .to_string(),
+            series_instance_uid: "1.3.46.670589.11.1.5.0.3724.2011072815265926000"
+                .to_string(),
+            media_storage_sop_instance_uid:
+                "1.3.46.670589.11.1.5.0.3724.2011072815265975004".to_string(),
+        };
+        assert_eq!(
+            file.orthanc_ids(),
+            OrthancIds {
+                patient: "3693b9d5-8b0e2a80-2cf45dda-d19e7c22-8749103c".to_string(),
+                study: "ab7a6e26-18072a37-5f2a2210-8a7f0823-f2fa9119".to_string(),
+                series: "dc0e4c94-39f97dc4-b2c25ae2-a423cb85-d363880c".to_string(),
+                instance: "c0680933-cc6b1e9d-f1bbb796-f155e4dc-3f321656".to_string(),
+            }
+        );
+
+        // Orthanc assigns this ID to all the patients without PatientID
+        let file = DicomFile {
+            patient_id: "".to_string(),
+            ..file
+        };
+        assert_eq!(
+            file.orthanc_ids().patient,
+            "da39a3ee-5e6b4b0d-3255bfef-95601890-afd80709"
+        );
+    }
+
+    #[test]
+    fn test_validation_error_into_error() {
+        assert_eq!(
+            Error::from(ValidationError::MissingTag("PatientID".to_string())),
+            Error {
+                message: "Invalid DICOM file: Missing tag: PatientID".to_string(),
+                details: None,
+                kind: Some(ErrorKind::InvalidDicom(ValidationError::MissingTag(
+                    "PatientID".to_string()
+                ))),
+            }
+        );
+    }
+}
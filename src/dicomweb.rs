@@ -0,0 +1,1160 @@
+//! DICOMweb client (QIDO-RS, WADO-RS, STOW-RS)
+//!
+//! Available with the `dicomweb` feature. Talks to the DICOMweb plugin of Orthanc, reusing the
+//! connection and the credentials of the [`Client`], so the plugin has to be loaded by the
+//! server.
+//!
+//! ```no_run
+//! # use orthanc::{dicomweb::QidoQuery, Client};
+//! let client = Client::new("http://localhost:8042");
+//! let dicomweb = client.dicomweb();
+//! let query = QidoQuery::default().filter("PatientID", "C137").limit(10);
+//! for study in dicomweb.search_studies(&query).unwrap() {
+//!     println!("{:?}", study.study_instance_uid());
+//! }
+//! ```
+use crate::models::{CreatedJob, HttpHeaders};
+use crate::raw::{RawClient, RawResponse};
+use crate::{Client, Error, Result};
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use serde_with::skip_serializing_none;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Default root of the DICOMweb API of Orthanc
+pub const DEFAULT_ROOT: &str = "dicom-web";
+
+const DICOM_JSON: &str = "application/dicom+json";
+const MULTIPART_DICOM: &str = r#"multipart/related; type="application/dicom""#;
+const MULTIPART_OCTET_STREAM: &str =
+    r#"multipart/related; type="application/octet-stream""#;
+
+const PATIENT_ID: &str = "00100020";
+const STUDY_INSTANCE_UID: &str = "0020000D";
+const SERIES_INSTANCE_UID: &str = "0020000E";
+const SOP_INSTANCE_UID: &str = "00080018";
+const RETRIEVE_URL: &str = "00081190";
+const FAILED_SOP_SEQUENCE: &str = "00081198";
+const REFERENCED_SOP_SEQUENCE: &str = "00081199";
+
+/// Size of the chunks, multipart responses are read in
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Attribute of a DICOM JSON data set (DICOM PS3.18, Annex F)
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct DicomJsonAttribute {
+    pub vr: String,
+    #[serde(rename = "Value")]
+    pub value: Option<Vec<Value>>,
+    #[serde(rename = "InlineBinary")]
+    pub inline_binary: Option<String>,
+    #[serde(rename = "BulkDataURI")]
+    pub bulk_data_uri: Option<String>,
+}
+
+impl DicomJsonAttribute {
+    /// The values of the attribute as strings
+    ///
+    /// Person names are represented by their alphabetic component. Sequence items are skipped.
+    pub fn strings(&self) -> Vec<String> {
+        let values = match &self.value {
+            Some(v) => v,
+            None => return vec![],
+        };
+        values
+            .iter()
+            .filter_map(|v| match v {
+                Value::String(s) => Some(s.clone()),
+                Value::Number(n) => Some(n.to_string()),
+                Value::Object(o) if self.vr == "PN" => o
+                    .get("Alphabetic")
+                    .and_then(|a| a.as_str())
+                    .map(|a| a.to_string()),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// DICOM JSON data set, keyed by tags in the `GGGGEEEE` format (e.g. `0020000D`)
+#[derive(Serialize, Deserialize, Debug, Clone, Default, Eq, PartialEq)]
+#[serde(transparent)]
+pub struct DicomJson(pub BTreeMap<String, DicomJsonAttribute>);
+
+impl DicomJson {
+    /// Get an attribute by its tag. The hexadecimal digits of the tag are case-insensitive
+    pub fn attribute(&self, tag: &str) -> Option<&DicomJsonAttribute> {
+        self.0.get(tag).or_else(|| {
+            self.0
+                .iter()
+                .find(|(k, _)| k.eq_ignore_ascii_case(tag))
+                .map(|(_, v)| v)
+        })
+    }
+
+    /// The first value of an attribute as a string (see [`DicomJsonAttribute::strings`])
+    pub fn string(&self, tag: &str) -> Option<String> {
+        self.attribute(tag)
+            .and_then(|a| a.strings().into_iter().next())
+    }
+
+    /// The items of a sequence attribute
+    pub fn sequence(&self, tag: &str) -> Result<Vec<DicomJson>> {
+        match self.attribute(tag).and_then(|a| a.value.clone()) {
+            Some(v) => Ok(serde_json::from_value(Value::Array(v))?),
+            None => Ok(vec![]),
+        }
+    }
+
+    /// `PatientID`
+    pub fn patient_id(&self) -> Option<String> {
+        self.string(PATIENT_ID)
+    }
+
+    /// `StudyInstanceUID`
+    pub fn study_instance_uid(&self) -> Option<String> {
+        self.string(STUDY_INSTANCE_UID)
+    }
+
+    /// `SeriesInstanceUID`
+    pub fn series_instance_uid(&self) -> Option<String> {
+        self.string(SERIES_INSTANCE_UID)
+    }
+
+    /// `SOPInstanceUID`
+    pub fn sop_instance_uid(&self) -> Option<String> {
+        self.string(SOP_INSTANCE_UID)
+    }
+}
+
+/// QIDO-RS search query
+///
+/// Filters are matched by the attribute keywords (e.g. `PatientID`) or tags (e.g.
+/// `00100020`), supporting the `*` and `?` wildcards.
+///
+/// ```
+/// # use orthanc::dicomweb::QidoQuery;
+/// let query = QidoQuery::default()
+///     .filter("PatientName", "Rick*")
+///     .include_field("StudyDescription")
+///     .limit(10);
+/// ```
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+pub struct QidoQuery {
+    pub filters: BTreeMap<String, String>,
+    pub include_fields: Vec<String>,
+    pub fuzzy_matching: Option<bool>,
+    pub limit: Option<u32>,
+    pub offset: Option<u32>,
+}
+
+impl QidoQuery {
+    /// Match an attribute against a value
+    pub fn filter(mut self, attribute: &str, value: &str) -> Self {
+        self.filters
+            .insert(attribute.to_string(), value.to_string());
+        self
+    }
+
+    /// Include an attribute in the results, in addition to the default ones
+    pub fn include_field(mut self, attribute: &str) -> Self {
+        self.include_fields.push(attribute.to_string());
+        self
+    }
+
+    /// Enable fuzzy matching of person names
+    pub fn fuzzy_matching(mut self, value: bool) -> Self {
+        self.fuzzy_matching = Some(value);
+        self
+    }
+
+    /// Maximum number of results
+    pub fn limit(mut self, value: u32) -> Self {
+        self.limit = Some(value);
+        self
+    }
+
+    /// Number of results to skip
+    pub fn offset(mut self, value: u32) -> Self {
+        self.offset = Some(value);
+        self
+    }
+
+    /// The query parameters
+    fn arguments(&self) -> Vec<(String, String)> {
+        let mut arguments: Vec<(String, String)> = self
+            .filters
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+        if !self.include_fields.is_empty() {
+            arguments.push(("includefield".to_string(), self.include_fields.join(",")));
+        }
+        if let Some(f) = self.fuzzy_matching {
+            arguments.push(("fuzzymatching".to_string(), f.to_string()));
+        }
+        if let Some(l) = self.limit {
+            arguments.push(("limit".to_string(), l.to_string()));
+        }
+        if let Some(o) = self.offset {
+            arguments.push(("offset".to_string(), o.to_string()));
+        }
+        arguments
+    }
+
+    fn query_string(&self) -> String {
+        // Any URL does, only the encoded query is taken
+        let mut url = reqwest::Url::parse("http://localhost/").unwrap();
+        url.query_pairs_mut().extend_pairs(self.arguments());
+        url.query().unwrap_or("").to_string()
+    }
+}
+
+/// Remote DICOMweb server, configured in the DICOMweb plugin
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Eq, PartialEq)]
+#[serde(rename_all = "PascalCase")]
+pub struct DicomWebServer {
+    pub url: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub http_headers: Option<HttpHeaders>,
+    pub user_properties: Option<HashMap<String, Value>>,
+    pub has_delete: Option<bool>,
+    pub chunked_transfers: Option<bool>,
+    pub has_wado_rs_universal_transfer_syntax: Option<bool>,
+}
+
+/// Study (or some of its series) to retrieve from a remote DICOMweb server
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Eq, PartialEq)]
+#[serde(rename_all = "PascalCase")]
+pub struct RemoteResource {
+    /// `StudyInstanceUID` of the study
+    pub study: String,
+    /// `SeriesInstanceUID`s of the series of the study. All series are retrieved if [`None`]
+    pub series: Option<Vec<String>>,
+}
+
+/// Result of a STOW-RS request
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct StowResult {
+    /// URL of the study the instances were stored in
+    pub retrieve_url: Option<String>,
+    /// Instances, stored by the server (`ReferencedSOPSequence`)
+    pub referenced: Vec<DicomJson>,
+    /// Instances, the server failed to store (`FailedSOPSequence`)
+    pub failed: Vec<DicomJson>,
+}
+
+/// DICOMweb client, obtained with [`Client::dicomweb`]
+#[derive(Debug, Clone)]
+pub struct DicomWebClient<'a> {
+    raw: RawClient<'a>,
+    root: String,
+}
+
+impl<'a> DicomWebClient<'a> {
+    pub(crate) fn new(client: &'a Client) -> DicomWebClient<'a> {
+        DicomWebClient {
+            raw: client.raw(),
+            root: DEFAULT_ROOT.to_string(),
+        }
+    }
+
+    /// Use a root of the DICOMweb API, other than the default `dicom-web` (the `Root` option
+    /// of the plugin configuration)
+    pub fn with_root(mut self, root: &str) -> Self {
+        self.root = root.trim_matches('/').to_string();
+        self
+    }
+
+    ////////// QIDO-RS //////////
+
+    /// Search for studies
+    pub fn search_studies(&self, query: &QidoQuery) -> Result<Vec<DicomJson>> {
+        self.search("studies", query)
+    }
+
+    /// Search for series
+    pub fn search_series(&self, query: &QidoQuery) -> Result<Vec<DicomJson>> {
+        self.search("series", query)
+    }
+
+    /// Search for the series of a study
+    pub fn search_study_series(
+        &self,
+        study: &str,
+        query: &QidoQuery,
+    ) -> Result<Vec<DicomJson>> {
+        self.search(&format!("studies/{}/series", study), query)
+    }
+
+    /// Search for instances
+    pub fn search_instances(&self, query: &QidoQuery) -> Result<Vec<DicomJson>> {
+        self.search("instances", query)
+    }
+
+    /// Search for the instances of a study
+    pub fn search_study_instances(
+        &self,
+        study: &str,
+        query: &QidoQuery,
+    ) -> Result<Vec<DicomJson>> {
+        self.search(&format!("studies/{}/instances", study), query)
+    }
+
+    /// Search for the instances of a series
+    pub fn search_series_instances(
+        &self,
+        study: &str,
+        series: &str,
+        query: &QidoQuery,
+    ) -> Result<Vec<DicomJson>> {
+        self.search(
+            &format!("studies/{}/series/{}/instances", study, series),
+            query,
+        )
+    }
+
+    ////////// WADO-RS //////////
+
+    /// Retrieve the DICOM files of all instances of a study
+    ///
+    /// All files are held in memory. Use [`DicomWebClient::retrieve_study_to`] for large
+    /// studies.
+    pub fn retrieve_study(&self, study: &str) -> Result<Vec<Bytes>> {
+        self.retrieve(&format!("studies/{}", study), MULTIPART_DICOM)
+    }
+
+    /// Retrieve the DICOM files of all instances of a study, streaming each of them into the
+    /// writer, returned by `writer` for the index of the file
+    ///
+    /// Only a small buffer is held in memory. Returns the number of files retrieved.
+    ///
+    /// ```no_run
+    /// # use orthanc::Client;
+    /// # use std::fs::File;
+    /// # let client = Client::new("http://localhost:8042");
+    /// let count = client
+    ///     .dicomweb()
+    ///     .retrieve_study_to("1.2.3", |i| File::create(format!("/tmp/{}.dcm", i)))
+    ///     .unwrap();
+    /// ```
+    pub fn retrieve_study_to<W, F>(&self, study: &str, writer: F) -> Result<usize>
+    where
+        W: Write,
+        F: FnMut(usize) -> io::Result<W>,
+    {
+        self.retrieve_to(&format!("studies/{}", study), MULTIPART_DICOM, writer)
+    }
+
+    /// Retrieve the DICOM files of all instances of a series
+    ///
+    /// All files are held in memory. Use [`DicomWebClient::retrieve_series_to`] for large
+    /// series.
+    pub fn retrieve_series(&self, study: &str, series: &str) -> Result<Vec<Bytes>> {
+        self.retrieve(
+            &format!("studies/{}/series/{}", study, series),
+            MULTIPART_DICOM,
+        )
+    }
+
+    /// Retrieve the DICOM files of all instances of a series, streaming each of them into the
+    /// writer, returned by `writer` for the index of the file
+    ///
+    /// See [`DicomWebClient::retrieve_study_to`].
+    pub fn retrieve_series_to<W, F>(
+        &self,
+        study: &str,
+        series: &str,
+        writer: F,
+    ) -> Result<usize>
+    where
+        W: Write,
+        F: FnMut(usize) -> io::Result<W>,
+    {
+        self.retrieve_to(
+            &format!("studies/{}/series/{}", study, series),
+            MULTIPART_DICOM,
+            writer,
+        )
+    }
+
+    /// Retrieve the DICOM file of an instance
+    pub fn retrieve_instance(
+        &self,
+        study: &str,
+        series: &str,
+        instance: &str,
+    ) -> Result<Bytes> {
+        let path = format!("studies/{}/series/{}/instances/{}", study, series, instance);
+        self.retrieve(&path, MULTIPART_DICOM)?
+            .into_iter()
+            .next()
+            .ok_or_else(|| {
+                Error::new(&format!("Instance {} not retrieved", instance), None)
+            })
+    }
+
+    /// Retrieve the pixel data of frames of an instance. Frame numbers start with 1
+    pub fn retrieve_frames(
+        &self,
+        study: &str,
+        series: &str,
+        instance: &str,
+        frames: &[u32],
+    ) -> Result<Vec<Bytes>> {
+        let frames: Vec<String> = frames.iter().map(|f| f.to_string()).collect();
+        let path = format!(
+            "studies/{}/series/{}/instances/{}/frames/{}",
+            study,
+            series,
+            instance,
+            frames.join(",")
+        );
+        self.retrieve(&path, MULTIPART_OCTET_STREAM)
+    }
+
+    /// Retrieve the metadata of all instances of a study
+    pub fn study_metadata(&self, study: &str) -> Result<Vec<DicomJson>> {
+        self.get_json(&format!("studies/{}/metadata", study))
+    }
+
+    /// Retrieve the metadata of all instances of a series
+    pub fn series_metadata(&self, study: &str, series: &str) -> Result<Vec<DicomJson>> {
+        self.get_json(&format!("studies/{}/series/{}/metadata", study, series))
+    }
+
+    /// Retrieve the metadata of an instance
+    pub fn instance_metadata(
+        &self,
+        study: &str,
+        series: &str,
+        instance: &str,
+    ) -> Result<DicomJson> {
+        let path = format!(
+            "studies/{}/series/{}/instances/{}/metadata",
+            study, series, instance
+        );
+        self.get_json::<Vec<DicomJson>>(&path)?
+            .into_iter()
+            .next()
+            .ok_or_else(|| {
+                Error::new(&format!("No metadata of instance {}", instance), None)
+            })
+    }
+
+    /// Retrieve bulk data, referenced by a `BulkDataURI` of the metadata
+    pub fn retrieve_bulk_data(&self, uri: &str) -> Result<Vec<Bytes>> {
+        // Orthanc returns absolute URIs, while the requests are sent relative to the server
+        let root = format!("/{}/", self.root);
+        let path = match uri.find(&root) {
+            Some(i) => &uri[i + 1..],
+            None => uri.trim_start_matches('/'),
+        };
+        collect_parts(self.multipart(path, MULTIPART_OCTET_STREAM)?)
+    }
+
+    ////////// STOW-RS //////////
+
+    /// Store DICOM files
+    ///
+    /// Use [`DicomWebClient::store_files`] to store files, that are not in memory yet.
+    pub fn store(&self, instances: &[&[u8]]) -> Result<StowResult> {
+        self.stow("studies", StowBody::from_data(instances))
+    }
+
+    /// Store DICOM files, that belong to a study. Files of other studies are rejected
+    pub fn store_to_study(&self, study: &str, instances: &[&[u8]]) -> Result<StowResult> {
+        self.stow(
+            &format!("studies/{}", study),
+            StowBody::from_data(instances),
+        )
+    }
+
+    /// Store DICOM files from the disk
+    ///
+    /// The files are streamed into the request, one after another, so only a small buffer is
+    /// held in memory.
+    ///
+    /// ```no_run
+    /// # use orthanc::Client;
+    /// # let client = Client::new("http://localhost:8042");
+    /// let result = client
+    ///     .dicomweb()
+    ///     .store_files(&["/tmp/foo.dcm", "/tmp/bar.dcm"])
+    ///     .unwrap();
+    /// ```
+    pub fn store_files<P: AsRef<Path>>(&self, paths: &[P]) -> Result<StowResult> {
+        self.stow("studies", StowBody::from_files(paths)?)
+    }
+
+    /// Store DICOM files from the disk, that belong to a study. Files of other studies are
+    /// rejected
+    ///
+    /// See [`DicomWebClient::store_files`].
+    pub fn store_files_to_study<P: AsRef<Path>>(
+        &self,
+        study: &str,
+        paths: &[P],
+    ) -> Result<StowResult> {
+        self.stow(&format!("studies/{}", study), StowBody::from_files(paths)?)
+    }
+
+    ////////// Remote servers //////////
+
+    /// List remote DICOMweb servers
+    pub fn servers(&self) -> Result<Vec<String>> {
+        self.raw.get_json(&format!("{}/servers", self.root))
+    }
+
+    /// List all remote DICOMweb servers in an expanded format
+    pub fn servers_expanded(&self) -> Result<HashMap<String, DicomWebServer>> {
+        self.raw.get_json(&format!("{}/servers?expand", self.root))
+    }
+
+    /// Create a remote DICOMweb server
+    ///
+    /// Returns an error if the server has HTTP headers with unknown values (see
+    /// [`HttpHeaders`])
+    pub fn create_server(&self, name: &str, server: DicomWebServer) -> Result<()> {
+        self.put_server(name, server)
+    }
+
+    /// Modify a remote DICOMweb server
+    ///
+    /// Returns an error if the server has HTTP headers with unknown values (see
+    /// [`HttpHeaders`]), which is the case for a server, obtained from
+    /// [`DicomWebClient::servers_expanded`], that has HTTP headers configured.
+    pub fn modify_server(&self, name: &str, server: DicomWebServer) -> Result<()> {
+        self.put_server(name, server)
+    }
+
+    fn put_server(&self, name: &str, server: DicomWebServer) -> Result<()> {
+        if let Some(h) = &server.http_headers {
+            h.require_values(&format!("DICOMweb server {}", name))?;
+        }
+        self.raw
+            .put(
+                &format!("{}/servers/{}", self.root, name),
+                serde_json::to_value(server)?,
+            )
+            .map(|_| ())
+    }
+
+    /// Delete a remote DICOMweb server
+    pub fn delete_server(&self, name: &str) -> Result<()> {
+        self.raw
+            .delete(&format!("{}/servers/{}", self.root, name))
+            .map(|_| ())
+    }
+
+    /// Send local entities to a remote DICOMweb server (STOW-RS)
+    ///
+    /// `ids` are Orthanc IDs of patients, studies, series or instances. Returns the job that
+    /// sends the entities.
+    pub fn store_to_server(&self, name: &str, ids: &[&str]) -> Result<CreatedJob> {
+        self.raw.post_json(
+            &format!("{}/servers/{}/stow", self.root, name),
+            json!({"Resources": ids, "Synchronous": false}),
+        )
+    }
+
+    /// Retrieve studies from a remote DICOMweb server into Orthanc (WADO-RS)
+    ///
+    /// Returns the job that retrieves the studies.
+    pub fn retrieve_from_server(
+        &self,
+        name: &str,
+        resources: &[RemoteResource],
+    ) -> Result<CreatedJob> {
+        self.raw.post_json(
+            &format!("{}/servers/{}/retrieve", self.root, name),
+            json!({"Resources": resources, "Synchronous": false}),
+        )
+    }
+
+    /// Search a remote DICOMweb server (QIDO-RS)
+    ///
+    /// `uri` is the QIDO-RS path on the remote server (e.g. `/studies`)
+    pub fn search_server(
+        &self,
+        name: &str,
+        uri: &str,
+        query: &QidoQuery,
+    ) -> Result<Vec<DicomJson>> {
+        let arguments: HashMap<String, String> = query.arguments().into_iter().collect();
+        let resp = self.raw.post(
+            &format!("{}/servers/{}/qido", self.root, name),
+            Some(json!({"Uri": uri, "Arguments": arguments})),
+        )?;
+        if resp.is_empty() {
+            return Ok(vec![]);
+        }
+        Ok(serde_json::from_slice(&resp)?)
+    }
+
+    /// Send a GET request to a remote DICOMweb server, returning the response body
+    ///
+    /// `uri` is the path on the remote server (e.g. `/studies/1.2.3/metadata`)
+    pub fn get_from_server(&self, name: &str, uri: &str) -> Result<Bytes> {
+        self.raw.post(
+            &format!("{}/servers/{}/get", self.root, name),
+            Some(json!({ "Uri": uri })),
+        )
+    }
+
+    fn search(&self, path: &str, query: &QidoQuery) -> Result<Vec<DicomJson>> {
+        let query_string = query.query_string();
+        let path = match query_string.is_empty() {
+            true => path.to_string(),
+            false => format!("{}?{}", path, query_string),
+        };
+        self.get_json(&path)
+    }
+
+    fn get_json<T: serde::de::DeserializeOwned + Default>(&self, path: &str) -> Result<T> {
+        let resp = self.raw.get_with_headers(
+            &format!("{}/{}", self.root, path),
+            &[("Accept", DICOM_JSON)],
+        )?;
+        // No content, in case nothing is found
+        if resp.body.is_empty() {
+            return Ok(T::default());
+        }
+        Ok(serde_json::from_slice(&resp.body)?)
+    }
+
+    fn retrieve(&self, path: &str, accept: &str) -> Result<Vec<Bytes>> {
+        collect_parts(self.multipart(&format!("{}/{}", self.root, path), accept)?)
+    }
+
+    fn retrieve_to<W, F>(&self, path: &str, accept: &str, mut writer: F) -> Result<usize>
+    where
+        W: Write,
+        F: FnMut(usize) -> io::Result<W>,
+    {
+        let mut parts = self.multipart(&format!("{}/{}", self.root, path), accept)?;
+        let mut count = 0;
+        while parts.next_part()? {
+            parts.copy_part(&mut writer(count)?)?;
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    // Send a GET request, reading the parts of the multipart response as they arrive
+    fn multipart(
+        &self,
+        path: &str,
+        accept: &str,
+    ) -> Result<MultipartReader<reqwest::blocking::Response>> {
+        let resp = self.raw.client().http_request(
+            reqwest::Method::GET,
+            path,
+            &[("Accept", accept)],
+            None,
+        )?;
+        let content_type = resp
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.to_string());
+        let boundary = multipart_boundary(content_type.as_deref())?;
+        Ok(MultipartReader::new(resp, &boundary))
+    }
+
+    fn stow(&self, path: &str, body: StowBody) -> Result<StowResult> {
+        let content_type = format!("{}; boundary={}", MULTIPART_DICOM, body.boundary);
+        let length = body.len();
+        let resp = self.raw.client().http_request(
+            reqwest::Method::POST,
+            &format!("{}/{}", self.root, path),
+            &[("Content-Type", &content_type), ("Accept", DICOM_JSON)],
+            Some(reqwest::blocking::Body::sized(body, length)),
+        )?;
+        let resp = RawResponse::read(resp)?;
+        let json: DicomJson = serde_json::from_slice(&resp.body)?;
+        Ok(StowResult {
+            retrieve_url: json.string(RETRIEVE_URL),
+            referenced: json.sequence(REFERENCED_SOP_SEQUENCE)?,
+            failed: json.sequence(FAILED_SOP_SEQUENCE)?,
+        })
+    }
+}
+
+/// The value of a parameter of a header (e.g. `boundary` of `Content-Type`)
+fn header_parameter(header: &str, name: &str) -> Option<String> {
+    header.split(';').skip(1).find_map(|p| {
+        let mut kv = p.splitn(2, '=');
+        match (kv.next(), kv.next()) {
+            (Some(k), Some(v)) if k.trim().eq_ignore_ascii_case(name) => {
+                Some(v.trim().trim_matches('"').to_string())
+            }
+            _ => None,
+        }
+    })
+}
+
+fn find(haystack: &[u8], needle: &[u8], from: usize) -> Option<usize> {
+    if from > haystack.len() {
+        return None;
+    }
+    haystack[from..]
+        .windows(needle.len())
+        .position(|w| w == needle)
+        .map(|p| p + from)
+}
+
+/// The boundary of a `multipart/related` response, given its content type
+fn multipart_boundary(content_type: Option<&str>) -> Result<String> {
+    let content_type = content_type.unwrap_or_default();
+    match header_parameter(content_type, "boundary") {
+        Some(b) if content_type.starts_with("multipart/") => Ok(b),
+        _ => Err(Error::new(
+            &format!("Expected a multipart response, got {}", content_type),
+            None,
+        )),
+    }
+}
+
+/// The bodies of all parts of a `multipart/related` body, held in memory
+fn collect_parts<R: Read>(mut parts: MultipartReader<R>) -> Result<Vec<Bytes>> {
+    let mut result = vec![];
+    while parts.next_part()? {
+        let mut data = vec![];
+        parts.copy_part(&mut data)?;
+        result.push(Bytes::from(data));
+    }
+    Ok(result)
+}
+
+fn invalid_multipart() -> Error {
+    Error::new("Invalid multipart response", None)
+}
+
+/// Reader of the parts of a `multipart/related` body, one after another
+///
+/// Only the data, that may hold a delimiter, is buffered, so that parts of any size can be
+/// copied without holding them in memory.
+struct MultipartReader<R> {
+    reader: R,
+    delimiter: Vec<u8>,
+    buffer: Vec<u8>,
+    finished: bool,
+}
+
+impl<R: Read> MultipartReader<R> {
+    fn new(reader: R, boundary: &str) -> MultipartReader<R> {
+        MultipartReader {
+            reader,
+            delimiter: format!("--{}", boundary).into_bytes(),
+            buffer: vec![],
+            finished: false,
+        }
+    }
+
+    /// Move to the body of the next part, returning `false` if there are no more parts
+    fn next_part(&mut self) -> Result<bool> {
+        if self.finished {
+            return Ok(false);
+        }
+        // The first delimiter may be preceded by a preamble
+        let delimiter = self.delimiter.clone();
+        if !self.skip_past(&delimiter)? {
+            self.finished = true;
+            return Ok(false);
+        }
+        self.read_part_headers()
+    }
+
+    /// Copy the body of the current part into a writer
+    fn copy_part<W: Write>(&mut self, writer: &mut W) -> Result<()> {
+        let end = [b"\r\n", self.delimiter.as_slice()].concat();
+        loop {
+            if let Some(p) = find(&self.buffer, &end, 0) {
+                writer.write_all(&self.buffer[..p])?;
+                // Keep the delimiter, so that `next_part` finds it
+                self.buffer.drain(..p + 2);
+                return Ok(());
+            }
+            // The end of the buffer may be the start of the delimiter
+            let safe = self.buffer.len().saturating_sub(end.len() - 1);
+            writer.write_all(&self.buffer[..safe])?;
+            self.buffer.drain(..safe);
+            if !self.fill()? {
+                return Err(invalid_multipart());
+            }
+        }
+    }
+
+    // Skip the rest of the delimiter line and the part headers, that follow it
+    fn read_part_headers(&mut self) -> Result<bool> {
+        if !self.fill_to(2)? {
+            return Err(invalid_multipart());
+        }
+        // The closing delimiter
+        if self.buffer.starts_with(b"--") {
+            self.finished = true;
+            return Ok(false);
+        }
+        if !self.skip_past(b"\r\n")? || !self.fill_to(2)? {
+            return Err(invalid_multipart());
+        }
+        // The part headers are followed by an empty line
+        if self.buffer.starts_with(b"\r\n") {
+            self.buffer.drain(..2);
+        } else if !self.skip_past(b"\r\n\r\n")? {
+            return Err(invalid_multipart());
+        }
+        Ok(true)
+    }
+
+    // Drop the data up to and including the needle, returning `false` if it is not found
+    fn skip_past(&mut self, needle: &[u8]) -> Result<bool> {
+        loop {
+            if let Some(p) = find(&self.buffer, needle, 0) {
+                self.buffer.drain(..p + needle.len());
+                return Ok(true);
+            }
+            let safe = self.buffer.len().saturating_sub(needle.len() - 1);
+            self.buffer.drain(..safe);
+            if !self.fill()? {
+                return Ok(false);
+            }
+        }
+    }
+
+    // Read until the buffer holds `length` bytes, returning `false` if the body ends before
+    fn fill_to(&mut self, length: usize) -> Result<bool> {
+        while self.buffer.len() < length {
+            if !self.fill()? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    // Read the next chunk into the buffer, returning `false` at the end of the body
+    fn fill(&mut self) -> Result<bool> {
+        let length = self.buffer.len();
+        self.buffer.resize(length + CHUNK_SIZE, 0);
+        let read = loop {
+            match self.reader.read(&mut self.buffer[length..]) {
+                Ok(n) => break Ok(n),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => break Err(e),
+            }
+        };
+        self.buffer.truncate(length + *read.as_ref().unwrap_or(&0));
+        Ok(read? > 0)
+    }
+}
+
+/// Instance, sent in a STOW-RS request
+enum StowInstance {
+    Data(Vec<u8>),
+    File(PathBuf, u64),
+}
+
+impl StowInstance {
+    fn len(&self) -> u64 {
+        match self {
+            StowInstance::Data(d) => d.len() as u64,
+            StowInstance::File(_, l) => *l,
+        }
+    }
+
+    fn open(self) -> io::Result<Box<dyn Read + Send>> {
+        match self {
+            StowInstance::Data(d) => Ok(Box::new(io::Cursor::new(d))),
+            StowInstance::File(p, _) => Ok(Box::new(fs::File::open(p)?)),
+        }
+    }
+}
+
+/// `multipart/related` body of a STOW-RS request
+///
+/// The instances are read one after another, as the body is sent. Files are only opened once
+/// they are reached.
+struct StowBody {
+    boundary: String,
+    instances: std::vec::IntoIter<StowInstance>,
+    current: Option<Box<dyn Read + Send>>,
+    closed: bool,
+}
+
+impl StowBody {
+    fn new(instances: Vec<StowInstance>) -> StowBody {
+        // The instances are not read in advance, so the boundary can not be derived from them
+        let seed = format!("{:?} {}", SystemTime::now(), instances.len());
+        StowBody {
+            boundary: format!("orthanc-rs-{:x}", md5::compute(seed)),
+            instances: instances.into_iter(),
+            current: None,
+            closed: false,
+        }
+    }
+
+    fn from_data(instances: &[&[u8]]) -> StowBody {
+        StowBody::new(
+            instances
+                .iter()
+                .map(|i| StowInstance::Data(i.to_vec()))
+                .collect(),
+        )
+    }
+
+    fn from_files<P: AsRef<Path>>(paths: &[P]) -> Result<StowBody> {
+        let mut instances = vec![];
+        for p in paths {
+            let length = fs::metadata(p)?.len();
+            instances.push(StowInstance::File(p.as_ref().to_path_buf(), length));
+        }
+        Ok(StowBody::new(instances))
+    }
+
+    fn part_header(&self) -> String {
+        format!(
+            "--{}\r\nContent-Type: application/dicom\r\n\r\n",
+            self.boundary
+        )
+    }
+
+    fn closing_delimiter(&self) -> String {
+        format!("--{}--\r\n", self.boundary)
+    }
+
+    /// Length of the body, that has not been read yet
+    fn len(&self) -> u64 {
+        let parts: u64 = self
+            .instances
+            .as_slice()
+            .iter()
+            .map(|i| self.part_header().len() as u64 + i.len() + 2)
+            .sum();
+        parts + self.closing_delimiter().len() as u64
+    }
+}
+
+impl Read for StowBody {
+    // Fills the buffer across the parts, so that the small ones (e.g. the part headers) are not
+    // sent as separate chunks
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut filled = 0;
+        while filled < buf.len() {
+            if let Some(r) = &mut self.current {
+                let n = r.read(&mut buf[filled..])?;
+                if n > 0 {
+                    filled += n;
+                    continue;
+                }
+            }
+            self.current = match self.instances.next() {
+                Some(i) => Some(Box::new(
+                    io::Cursor::new(self.part_header())
+                        .chain(i.open()?)
+                        .chain(&b"\r\n"[..]),
+                )),
+                None if !self.closed => {
+                    self.closed = true;
+                    Some(Box::new(io::Cursor::new(self.closing_delimiter())))
+                }
+                None => break,
+            };
+        }
+        Ok(filled)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn parse_multipart(content_type: &str, body: &[u8]) -> Result<Vec<Bytes>> {
+        let boundary = multipart_boundary(Some(content_type))?;
+        collect_parts(MultipartReader::new(body, &boundary))
+    }
+
+    #[test]
+    fn test_header_parameter() {
+        let header = r#"multipart/related; type="application/dicom"; boundary=foo"#;
+        assert_eq!(
+            header_parameter(header, "type"),
+            Some("application/dicom".to_string())
+        );
+        assert_eq!(
+            header_parameter(header, "boundary"),
+            Some("foo".to_string())
+        );
+        assert_eq!(header_parameter(header, "start"), None);
+    }
+
+    #[test]
+    fn test_parse_multipart() {
+        let parts = parse_multipart(
+            r#"multipart/related; type="application/octet-stream"; boundary="foo""#,
+            b"preamble\r\n--foo\r\nContent-Type: application/octet-stream\r\n\
+              Content-Location: bar\r\n\r\n\
+              \x00\x01\r\n\x02\r\n\
+              --foo\r\nContent-Type: application/octet-stream\r\n\r\n\
+              baz\r\n\
+              --foo--\r\n",
+        );
+        assert_eq!(
+            parts.unwrap(),
+            vec![
+                Bytes::from_static(b"\x00\x01\r\n\x02"),
+                Bytes::from_static(b"baz")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_multipart_no_headers() {
+        let parts = parse_multipart(
+            "multipart/related; boundary=foo",
+            b"--foo\r\n\r\nbar\r\n--foo--",
+        );
+        assert_eq!(parts.unwrap(), vec![Bytes::from_static(b"bar")]);
+    }
+
+    #[test]
+    fn test_parse_multipart_empty() {
+        let parts = parse_multipart("multipart/related; boundary=foo", b"");
+        assert_eq!(parts.unwrap(), Vec::<Bytes>::new());
+    }
+
+    #[test]
+    fn test_parse_multipart_large() {
+        // Parts, larger than the chunks, with delimiters split between the chunks
+        let first = vec![b'x'; CHUNK_SIZE * 2 + 3];
+        let second = vec![b'-'; CHUNK_SIZE - 5];
+        let mut body = b"--foo\r\n\r\n".to_vec();
+        body.extend(&first);
+        body.extend(b"\r\n--foo\r\n\r\n");
+        body.extend(&second);
+        body.extend(b"\r\n--foo--\r\n");
+        let parts = parse_multipart("multipart/related; boundary=foo", &body);
+        assert_eq!(
+            parts.unwrap(),
+            vec![Bytes::from(first), Bytes::from(second)]
+        );
+    }
+
+    #[test]
+    fn test_parse_multipart_invalid() {
+        let parts = parse_multipart(
+            "multipart/related; boundary=foo",
+            b"--foo\r\nContent-Type: application/dicom\r\n\r\nbar",
+        );
+        assert_eq!(
+            parts.unwrap_err(),
+            Error::new("Invalid multipart response", None)
+        );
+        let parts = parse_multipart("multipart/related; boundary=foo", b"--foo\r\nbar");
+        assert_eq!(
+            parts.unwrap_err(),
+            Error::new("Invalid multipart response", None)
+        );
+        assert_eq!(
+            parse_multipart("application/dicom", b"bar").unwrap_err(),
+            Error::new("Expected a multipart response, got application/dicom", None)
+        );
+    }
+
+    #[test]
+    fn test_stow_body() {
+        let mut body = StowBody::from_data(&[b"foo", b"bar"]);
+        let boundary = body.boundary.clone();
+        let length = body.len();
+        let mut data = vec![];
+        body.read_to_end(&mut data).unwrap();
+        assert_eq!(
+            String::from_utf8(data.clone()).unwrap(),
+            format!(
+                "--{0}\r\nContent-Type: application/dicom\r\n\r\nfoo\r\n\
+                 --{0}\r\nContent-Type: application/dicom\r\n\r\nbar\r\n\
+                 --{0}--\r\n",
+                boundary
+            )
+        );
+        assert_eq!(data.len() as u64, length);
+
+        let body = StowBody::from_data(&[]);
+        assert_eq!(body.len(), body.closing_delimiter().len() as u64);
+    }
+
+    #[test]
+    fn test_stow_body_files() {
+        let path =
+            std::env::temp_dir().join(format!("orthanc_stow_{}", std::process::id()));
+        fs::write(&path, b"foo").unwrap();
+        let mut body = StowBody::from_files(&[&path, &path]).unwrap();
+        let length = body.len();
+        let mut data = vec![];
+        body.read_to_end(&mut data).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(data.len() as u64, length);
+        assert_eq!(
+            collect_parts(MultipartReader::new(&data[..], &body.boundary)).unwrap(),
+            vec!["foo", "foo"]
+        );
+
+        let missing = std::env::temp_dir().join("orthanc_stow_missing");
+        assert!(StowBody::from_files(&[missing]).is_err());
+    }
+
+    #[test]
+    fn test_qido_query_string() {
+        assert_eq!(QidoQuery::default().query_string(), "");
+        let query = QidoQuery::default()
+            .filter("PatientName", "Rick Sanchez*")
+            .filter("00100020", "C137")
+            .include_field("StudyDescription")
+            .include_field("00081030")
+            .fuzzy_matching(true)
+            .limit(10)
+            .offset(20);
+        assert_eq!(
+            query.query_string(),
+            "00100020=C137&PatientName=Rick+Sanchez*&includefield=StudyDescription%2C00081030\
+             &fuzzymatching=true&limit=10&offset=20"
+        );
+    }
+
+    #[test]
+    fn test_dicom_json() {
+        let json: DicomJson = serde_json::from_value(json!({
+            "00100010": {"vr": "PN", "Value": [{"Alphabetic": "Sanchez^Rick"}]},
+            "00100020": {"vr": "LO", "Value": ["C137"]},
+            "00201208": {"vr": "IS", "Value": [42]},
+            "0020000d": {"vr": "UI", "Value": ["1.2.3"]},
+            "7FE00010": {"vr": "OB", "BulkDataURI": "http://localhost/bulk"},
+            "00081199": {"vr": "SQ", "Value": [{"00080018": {"vr": "UI", "Value": ["4.5.6"]}}]},
+        }))
+        .unwrap();
+        assert_eq!(json.string("00100010"), Some("Sanchez^Rick".to_string()));
+        assert_eq!(json.patient_id(), Some("C137".to_string()));
+        assert_eq!(json.string("00201208"), Some("42".to_string()));
+        assert_eq!(json.study_instance_uid(), Some("1.2.3".to_string()));
+        assert_eq!(json.string("7fe00010"), None);
+        assert_eq!(
+            json.attribute("7FE00010").unwrap().bulk_data_uri,
+            Some("http://localhost/bulk".to_string())
+        );
+        assert_eq!(json.string("00080020"), None);
+        let items = json.sequence("00081199").unwrap();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].sop_instance_uid(), Some("4.5.6".to_string()));
+        assert_eq!(json.sequence("00081198").unwrap(), vec![]);
+    }
+}
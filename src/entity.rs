@@ -1,6 +1,7 @@
 use crate::Error;
 use chrono::NaiveDateTime;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
 use std::collections::HashMap;
 use std::convert::TryFrom;
 use std::str;
@@ -8,13 +9,72 @@ use std::str;
 /// Orthanc entity kinds (types).
 ///
 /// Orthanc operates with 4 entity kinds, which correspond to the ones, available in DICOM.
-/// In descending hierarchical order: Patient, Study, Series, Instance
-#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+/// In descending hierarchical order: Patient, Study, Series, Instance. Kinds, unknown to this
+/// crate (e.g. introduced by a future version of Orthanc), are deserialized as `Unknown`, keeping
+/// the name reported by Orthanc.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+#[non_exhaustive]
 pub enum EntityKind {
     Patient,
     Study,
     Series,
     Instance,
+    Unknown(String),
+}
+
+impl EntityKind {
+    /// Name of the entity kind, as used by Orthanc (e.g. `Study`)
+    pub fn as_str(&self) -> &str {
+        match self {
+            EntityKind::Patient => "Patient",
+            EntityKind::Study => "Study",
+            EntityKind::Series => "Series",
+            EntityKind::Instance => "Instance",
+            EntityKind::Unknown(s) => s,
+        }
+    }
+
+    /// Name of the API resource of the entity kind (e.g. `studies`)
+    pub(crate) fn resource(&self) -> Result<&'static str, Error> {
+        match self {
+            EntityKind::Patient => Ok("patients"),
+            EntityKind::Study => Ok("studies"),
+            EntityKind::Series => Ok("series"),
+            EntityKind::Instance => Ok("instances"),
+            EntityKind::Unknown(_) => Err(self.unsupported()),
+        }
+    }
+
+    /// Error, returned by the operations on the kinds, unknown to this crate
+    pub(crate) fn unsupported(&self) -> Error {
+        Error::new(&format!("Unsupported entity kind: {}", self.as_str()), None)
+    }
+}
+
+impl From<&str> for EntityKind {
+    fn from(s: &str) -> EntityKind {
+        match s {
+            "Patient" => EntityKind::Patient,
+            "Study" => EntityKind::Study,
+            "Series" => EntityKind::Series,
+            "Instance" => EntityKind::Instance,
+            s => EntityKind::Unknown(s.to_string()),
+        }
+    }
+}
+
+impl Serialize for EntityKind {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for EntityKind {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<EntityKind, D::Error> {
+        Ok(EntityKind::from(
+            String::deserialize(deserializer)?.as_str(),
+        ))
+    }
 }
 
 impl TryFrom<bytes::Bytes> for EntityKind {
@@ -107,6 +167,14 @@ pub struct Patient {
     pub entity: EntityKind,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub anonymized_from: Option<String>,
+    /// Whether the patient is protected from recycling. `false` if not reported by Orthanc
+    #[serde(rename = "IsProtected", default)]
+    pub protected: bool,
+    #[serde(default)]
+    pub labels: Vec<String>,
+    /// Fields, unknown to this crate (e.g. introduced by a future version of Orthanc)
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
 }
 
 impl Entity for Patient {
@@ -158,6 +226,11 @@ pub struct Study {
     pub entity: EntityKind,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub anonymized_from: Option<String>,
+    #[serde(default)]
+    pub labels: Vec<String>,
+    /// Fields, unknown to this crate (e.g. introduced by a future version of Orthanc)
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
 }
 
 impl Entity for Study {
@@ -223,6 +296,11 @@ pub struct Series {
     pub entity: EntityKind,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub anonymized_from: Option<String>,
+    #[serde(default)]
+    pub labels: Vec<String>,
+    /// Fields, unknown to this crate (e.g. introduced by a future version of Orthanc)
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
 }
 
 impl Entity for Series {
@@ -282,6 +360,11 @@ pub struct Instance {
     pub entity: EntityKind,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub anonymized_from: Option<String>,
+    #[serde(default)]
+    pub labels: Vec<String>,
+    /// Fields, unknown to this crate (e.g. introduced by a future version of Orthanc)
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
 }
 
 impl Entity for Instance {
@@ -320,6 +403,80 @@ impl Entity for Instance {
     }
 }
 
+/// A patient with all of its studies, series and instances
+#[derive(Debug, Eq, PartialEq)]
+pub struct ResourceTree {
+    pub patient: Patient,
+    /// In the order of [`Patient::studies`]
+    pub studies: Vec<StudyTree>,
+}
+
+/// A study with all of its series and instances
+#[derive(Debug, Eq, PartialEq)]
+pub struct StudyTree {
+    pub study: Study,
+    /// In the order of [`Study::series`]
+    pub series: Vec<SeriesTree>,
+}
+
+/// A series with all of its instances
+#[derive(Debug, Eq, PartialEq)]
+pub struct SeriesTree {
+    pub series: Series,
+    /// In the order of [`Series::instances`]
+    pub instances: Vec<Instance>,
+}
+
+impl ResourceTree {
+    /// Assemble the tree from flat lists of entities
+    ///
+    /// Entities that do not belong to the patient are ignored.
+    pub fn new(
+        patient: Patient,
+        studies: Vec<Study>,
+        series: Vec<Series>,
+        instances: Vec<Instance>,
+    ) -> ResourceTree {
+        let mut studies: HashMap<String, Study> =
+            studies.into_iter().map(|s| (s.id.clone(), s)).collect();
+        let mut series: HashMap<String, Series> =
+            series.into_iter().map(|s| (s.id.clone(), s)).collect();
+        let mut instances: HashMap<String, Instance> =
+            instances.into_iter().map(|i| (i.id.clone(), i)).collect();
+
+        let studies = patient
+            .studies
+            .iter()
+            .filter_map(|id| studies.remove(id))
+            .map(|study| StudyTree {
+                series: study
+                    .series
+                    .iter()
+                    .filter_map(|id| series.remove(id))
+                    .map(|series| SeriesTree {
+                        instances: series
+                            .instances
+                            .iter()
+                            .filter_map(|id| instances.remove(id))
+                            .collect(),
+                        series,
+                    })
+                    .collect(),
+                study,
+            })
+            .collect();
+        ResourceTree { patient, studies }
+    }
+
+    /// Iterate over all instances of the tree
+    pub fn instances(&self) -> impl Iterator<Item = &Instance> {
+        self.studies
+            .iter()
+            .flat_map(|s| s.series.iter())
+            .flat_map(|s| s.instances.iter())
+    }
+}
+
 mod datetime_format {
     use chrono::NaiveDateTime;
     use serde::{self, Deserialize, Deserializer, Serializer};
@@ -373,6 +530,18 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_entity_kind_resource() {
+        assert_eq!(EntityKind::Patient.resource().unwrap(), "patients");
+        assert_eq!(EntityKind::Instance.resource().unwrap(), "instances");
+        assert_eq!(
+            EntityKind::Unknown("Frame".to_string())
+                .resource()
+                .unwrap_err(),
+            Error::new("Unsupported entity kind: Frame", None)
+        );
+    }
+
     #[test]
     fn test_entity_trait_patient() {
         assert_eq!(Patient::kind(), EntityKind::Patient);
@@ -380,13 +549,19 @@ mod tests {
         let patient = Patient {
             id: "f88cbd3f-a00dfc59-9ca1ac2d-7ce9851a-40e5b493".to_string(),
             is_stable: true,
-            last_update: NaiveDate::from_ymd(2020, 1, 1).and_hms(15, 46, 17),
+            last_update: NaiveDate::from_ymd_opt(2020, 1, 1)
+                .unwrap()
+                .and_hms_opt(15, 46, 17)
+                .unwrap(),
             main_dicom_tags: hashmap! {
                 "PatientName".to_string() => "Rick Sanchez".to_string(),
             },
             studies: ["e8cafcbe-caf08c39-6e205f15-18554bb8-b3f9ef04".to_string()].to_vec(),
             entity: EntityKind::Patient,
             anonymized_from: None,
+            protected: false,
+            labels: vec![],
+            extra: HashMap::new(),
         };
 
         assert_eq!(patient.id(), "f88cbd3f-a00dfc59-9ca1ac2d-7ce9851a-40e5b493");
@@ -411,7 +586,10 @@ mod tests {
         let study = Study {
             id: "63bf5d42-b5382159-01971752-e0ceea3d-399bbca5".to_string(),
             is_stable: true,
-            last_update: NaiveDate::from_ymd(2020, 8, 30).and_hms(19, 11, 09),
+            last_update: NaiveDate::from_ymd_opt(2020, 8, 30)
+                .unwrap()
+                .and_hms_opt(19, 11, 9)
+                .unwrap(),
             main_dicom_tags: hashmap! {
                 "AccessionNumber".to_string() => "foobar".to_string(),
             },
@@ -426,6 +604,8 @@ mod tests {
             .to_vec(),
             entity: EntityKind::Study,
             anonymized_from: None,
+            labels: vec![],
+            extra: HashMap::new(),
         };
 
         assert_eq!(study.id(), "63bf5d42-b5382159-01971752-e0ceea3d-399bbca5");
@@ -458,7 +638,10 @@ mod tests {
             id: "cd00fffc-db25be29-0c6da430-c56796a5-ba06933c".to_string(),
             status: "Unknown".to_string(),
             is_stable: true,
-            last_update: NaiveDate::from_ymd(2020, 8, 30).and_hms(19, 11, 09),
+            last_update: NaiveDate::from_ymd_opt(2020, 8, 30)
+                .unwrap()
+                .and_hms_opt(19, 11, 9)
+                .unwrap(),
             main_dicom_tags: hashmap! {
                 "BodyPartExamined".to_string() => "ABDOMEN".to_string(),
             },
@@ -471,6 +654,8 @@ mod tests {
             .to_vec(),
             entity: EntityKind::Series,
             anonymized_from: None,
+            labels: vec![],
+            extra: HashMap::new(),
         };
 
         assert_eq!(series.id(), "cd00fffc-db25be29-0c6da430-c56796a5-ba06933c");
@@ -510,6 +695,8 @@ mod tests {
             modified_from: Some("22c54cb6-28302a69-3ff454a3-676b98f4-b84cd80a".to_string()),
             entity: EntityKind::Instance,
             anonymized_from: None,
+            labels: vec![],
+            extra: HashMap::new(),
         };
 
         assert_eq!(
@@ -533,4 +720,100 @@ mod tests {
         assert_eq!(instance.parent_kind_name(), Some("Series".to_string()));
         assert_eq!(instance.children_kind_name(), None);
     }
+
+    fn tree_entities() -> (Patient, Vec<Study>, Vec<Series>, Vec<Instance>) {
+        let last_update = NaiveDate::from_ymd_opt(2020, 1, 1)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap();
+        let patient = Patient {
+            id: "p".to_string(),
+            is_stable: true,
+            last_update,
+            main_dicom_tags: HashMap::new(),
+            studies: vec!["st2".to_string(), "st1".to_string()],
+            entity: EntityKind::Patient,
+            anonymized_from: None,
+            protected: false,
+            labels: vec![],
+            extra: HashMap::new(),
+        };
+        let study = |id: &str, series: &[&str]| Study {
+            id: id.to_string(),
+            is_stable: true,
+            last_update,
+            main_dicom_tags: HashMap::new(),
+            parent_patient: "p".to_string(),
+            patient_main_dicom_tags: HashMap::new(),
+            series: series.iter().map(|s| s.to_string()).collect(),
+            entity: EntityKind::Study,
+            anonymized_from: None,
+            labels: vec![],
+            extra: HashMap::new(),
+        };
+        let series = |id: &str, study: &str, instances: &[&str]| Series {
+            id: id.to_string(),
+            status: "Unknown".to_string(),
+            is_stable: true,
+            last_update,
+            main_dicom_tags: HashMap::new(),
+            parent_study: study.to_string(),
+            expected_number_of_instances: None,
+            instances: instances.iter().map(|s| s.to_string()).collect(),
+            entity: EntityKind::Series,
+            anonymized_from: None,
+            labels: vec![],
+            extra: HashMap::new(),
+        };
+        let instance = |id: &str, series: &str| Instance {
+            id: id.to_string(),
+            main_dicom_tags: HashMap::new(),
+            parent_series: series.to_string(),
+            index_in_series: None,
+            file_uuid: "f".to_string(),
+            file_size: 1,
+            modified_from: None,
+            entity: EntityKind::Instance,
+            anonymized_from: None,
+            labels: vec![],
+            extra: HashMap::new(),
+        };
+        (
+            patient,
+            vec![study("st1", &["se1"]), study("st2", &["se2", "se3"])],
+            vec![
+                series("se3", "st2", &["i3"]),
+                series("se1", "st1", &["i1"]),
+                series("se2", "st2", &["i2", "i4"]),
+            ],
+            vec![
+                instance("i4", "se2"),
+                instance("i1", "se1"),
+                instance("i2", "se2"),
+                instance("i3", "se3"),
+            ],
+        )
+    }
+
+    #[test]
+    fn test_resource_tree() {
+        let (patient, studies, series, instances) = tree_entities();
+        let tree = ResourceTree::new(patient, studies, series, instances);
+
+        assert_eq!(tree.patient.id, "p");
+        let mut paths = vec![];
+        for st in &tree.studies {
+            for se in &st.series {
+                for i in &se.instances {
+                    paths.push(format!("{}/{}/{}", st.study.id, se.series.id, i.id));
+                }
+            }
+        }
+        assert_eq!(
+            paths,
+            vec!["st2/se2/i2", "st2/se2/i4", "st2/se3/i3", "st1/se1/i1"]
+        );
+        let instances: Vec<&str> = tree.instances().map(|i| i.id.as_str()).collect();
+        assert_eq!(instances, vec!["i2", "i4", "i3", "i1"]);
+    }
 }
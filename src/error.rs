@@ -1,5 +1,8 @@
+use crate::models::Feature;
+use semver::Version;
 use serde::{Deserialize, Serialize};
 use std::fmt;
+use std::io;
 use std::str;
 
 /// Structure of Orthanc's API error
@@ -16,12 +19,39 @@ pub struct ApiError {
     pub orthanc_error: String,
 }
 
+/// A feature, requested from an Orthanc server, that is too old to support it
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct UnsupportedByServer {
+    pub feature: Feature,
+    /// The first version of Orthanc, that supports the feature
+    pub required: Version,
+    /// The version of the Orthanc server
+    pub actual: Version,
+}
+
+/// Kind of an error, for the errors that callers may want to handle, rather than only report
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// A DICOM file failed the local validation, and was not sent
+    #[cfg(feature = "dicom")]
+    InvalidDicom(crate::dicom::ValidationError),
+    /// The server is too old to support a feature, the request was not sent
+    UnsupportedByServer(UnsupportedByServer),
+}
+
 /// Error type
+///
+/// Requests, that the server is too old to support, are not sent, and fail with an error that can
+/// be told apart with [`Error::unsupported`].
 #[derive(Debug, Eq, PartialEq)]
 pub struct Error {
     pub message: String,
     // TODO: This is pretty ugly
-    pub details: Option<ApiError>,
+    /// The error, reported by Orthanc. Boxed, as it is much larger than the rest of the error
+    pub details: Option<Box<ApiError>>,
+    /// What went wrong, if the error is of a kind, that can be handled
+    pub kind: Option<ErrorKind>,
 }
 
 impl fmt::Display for Error {
@@ -34,7 +64,36 @@ impl Error {
     pub(crate) fn new(msg: &str, api_error: Option<ApiError>) -> Error {
         Error {
             message: msg.to_string(),
-            details: api_error,
+            details: api_error.map(Box::new),
+            kind: None,
+        }
+    }
+
+    /// The reason a DICOM file was rejected, if it failed the local validation
+    #[cfg(feature = "dicom")]
+    pub fn validation_error(&self) -> Option<&crate::dicom::ValidationError> {
+        match &self.kind {
+            Some(ErrorKind::InvalidDicom(e)) => Some(e),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn unsupported_by_server(unsupported: UnsupportedByServer) -> Error {
+        Error {
+            message: format!(
+                "{} requires Orthanc {} or newer, the server runs Orthanc {}",
+                unsupported.feature, unsupported.required, unsupported.actual
+            ),
+            details: None,
+            kind: Some(ErrorKind::UnsupportedByServer(unsupported)),
+        }
+    }
+
+    /// The feature, the server is too old to support, if the request was not sent because of it
+    pub fn unsupported(&self) -> Option<&UnsupportedByServer> {
+        match &self.kind {
+            Some(ErrorKind::UnsupportedByServer(u)) => Some(u),
+            _ => None,
         }
     }
 }
@@ -57,6 +116,19 @@ impl From<str::Utf8Error> for Error {
     }
 }
 
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::new(&e.to_string(), None)
+    }
+}
+
+#[cfg(feature = "zip")]
+impl From<zip::result::ZipError> for Error {
+    fn from(e: zip::result::ZipError) -> Self {
+        Error::new(&e.to_string(), None)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -68,7 +140,7 @@ mod tests {
     fn test_error_formatting() {
         let error = Error {
             message: "400".to_string(),
-            details: Some(ApiError {
+            details: Some(Box::new(ApiError {
                 method: "POST".to_string(),
                 uri: "/instances".to_string(),
                 message: "Bad file format".to_string(),
@@ -79,7 +151,8 @@ mod tests {
                 http_error: "Bad Request".to_string(),
                 orthanc_status: 15,
                 orthanc_error: "Bad file format".to_string(),
-            }),
+            })),
+            kind: None,
         };
 
         // TODO: Any way to make the formatting nicer?
@@ -108,6 +181,7 @@ mod tests {
             Error {
                 message: "expected ident at line 1 column 2".to_string(),
                 details: None,
+                kind: None,
             },
         )
     }
@@ -128,10 +202,40 @@ mod tests {
             Error {
                 message: expected_err.to_string(),
                 details: None,
+                kind: None,
             },
         );
     }
 
+    #[test]
+    fn test_error_unsupported() {
+        let unsupported = UnsupportedByServer {
+            feature: Feature::BulkTools,
+            required: Version::new(1, 9, 4),
+            actual: Version::new(1, 9, 1),
+        };
+        let error = Error::unsupported_by_server(unsupported.clone());
+        assert_eq!(
+            error.message,
+            "Bulk tools requires Orthanc 1.9.4 or newer, the server runs Orthanc 1.9.1"
+        );
+        assert_eq!(error.unsupported(), Some(&unsupported));
+        assert_eq!(
+            error.kind,
+            Some(ErrorKind::UnsupportedByServer(unsupported))
+        );
+        assert_eq!(Error::new("foo", None).unsupported(), None);
+        // Only the kind of the error counts, not its message
+        assert_eq!(
+            Error::new(
+                "Labels requires Orthanc 1.12.0 or newer, the server runs Orthanc 1.9.1",
+                None
+            )
+            .unsupported(),
+            None
+        );
+    }
+
     #[test]
     fn test_error_from_utf8() {
         let sparkle_heart = vec![0, 159, 146, 150];
@@ -142,6 +246,7 @@ mod tests {
             Error {
                 message: "invalid utf-8 sequence of 1 bytes from index 1".to_string(),
                 details: None,
+                kind: None,
             }
         );
     }
@@ -62,15 +62,58 @@
 //! let data = fs::read("/tmp/instance.dcm").unwrap();
 //! client.upload(&data).unwrap();
 //! ```
+//!
+//! With the `dicom` feature enabled, the file can be validated locally before the upload:
+//!
+//! ```ini
+//! [dependencies]
+//! orthanc = { version = "0.8.0", features = ["dicom"] }
+//! ```
+//!
+//! ```rust
+//! let data = fs::read("/tmp/instance.dcm").unwrap();
+//! client.upload_validated(&data).unwrap();
+//! ```
+//!
+//! With the `zip` feature enabled, ZIP archives of DICOM files can be uploaded with
+//! `Client::upload_zip`.
+//!
+//! With the `dicomweb` feature enabled, the client can talk DICOMweb to the DICOMweb plugin of
+//! Orthanc:
+//!
+//! ```no_run
+//! # #[cfg(feature = "dicomweb")]
+//! # {
+//! # use orthanc::{dicomweb::QidoQuery, Client};
+//! # let client = Client::new("http://localhost:8042");
+//! let query = QidoQuery::default().filter("PatientID", "C137");
+//! let studies = client.dicomweb().search_studies(&query).unwrap();
+//! # }
+//! ```
+//!
+//! With the `worklists` feature enabled, the modality worklists, served by the worklists plugin
+//! of Orthanc, can be managed with `Client::worklists`, and with the `transfers` feature, entities
+//! can be sent to the peers with the transfers accelerator plugin.
 
 pub use client::Client;
-pub use error::{ApiError, Error};
+pub use error::{ApiError, Error, ErrorKind};
 use std::result;
 
 pub mod client;
+#[cfg(feature = "dicom")]
+pub mod dicom;
+#[cfg(feature = "dicomweb")]
+pub mod dicomweb;
 pub mod entity;
 pub mod error;
 pub mod models;
+pub mod pseudonymize;
+pub mod query;
+pub mod raw;
+#[cfg(feature = "transfers")]
+pub mod transfers;
 mod utils;
+#[cfg(feature = "worklists")]
+pub mod worklists;
 
 type Result<T> = result::Result<T, Error>;
@@ -1,9 +1,24 @@
 use crate::entity::EntityKind;
-use serde::{Deserialize, Serialize};
+use crate::error::UnsupportedByServer;
+use crate::Error;
+use chrono::NaiveDate;
+use semver::Version;
+use serde::de::{MapAccess, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
 use serde_with::skip_serializing_none;
 use std::collections::HashMap;
+use std::fmt;
+use std::path::PathBuf;
+use std::time::Duration;
+
+const DICOM_DATE_FORMAT: &str = "%Y%m%d";
 
 /// System
+///
+/// Fields, that are only reported by recent versions of Orthanc, are optional. Fields, unknown
+/// to this crate, are collected in `extra`.
+#[skip_serializing_none]
 #[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
 #[serde(rename_all = "PascalCase")]
 pub struct System {
@@ -11,13 +26,153 @@ pub struct System {
     pub version: String,
     pub api_version: u8,
     pub database_version: u8,
+    #[serialize_always]
     pub database_backend_plugin: Option<String>,
     pub dicom_aet: String,
     pub dicom_port: u16,
     pub http_port: u16,
     pub is_http_server_secure: bool,
     pub plugins_enabled: bool,
+    #[serialize_always]
     pub storage_area_plugin: Option<String>,
+    pub check_revisions: Option<bool>,
+    pub database_server_identifier: Option<String>,
+    pub ingest_transcoding: Option<String>,
+    /// Maximum storage size in MB (`0` means unlimited)
+    pub maximum_storage_size: Option<u64>,
+    /// Maximum number of patients (`0` means unlimited)
+    pub maximum_patient_count: Option<u64>,
+    pub maximum_storage_mode: Option<String>,
+    pub overwrite_instances: Option<bool>,
+    pub storage_compression: Option<bool>,
+    /// Main DICOM tags, stored in the database, per resource level
+    pub main_dicom_tags: Option<HashMap<String, Value>>,
+    /// User-defined metadata, mapping the names to the indices
+    pub user_metadata: Option<HashMap<String, u16>>,
+    pub capabilities: Option<SystemCapabilities>,
+    pub read_only: Option<bool>,
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+/// Capabilities, reported by the server in the system info
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+#[serde(rename_all = "PascalCase")]
+pub struct SystemCapabilities {
+    pub has_extended_changes: Option<bool>,
+    pub has_extended_find: Option<bool>,
+    pub has_labels: Option<bool>,
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+/// Plugin, loaded by the server
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+#[serde(rename_all = "PascalCase")]
+pub struct Plugin {
+    #[serde(rename = "ID")]
+    pub id: String,
+    pub version: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// URI of the web interface of the plugin, if it has one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub root_uri: Option<String>,
+    /// Whether the plugin extends Orthanc Explorer
+    #[serde(default)]
+    pub extends_orthanc_explorer: bool,
+}
+
+/// Features of Orthanc, that are only supported by its recent versions
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum Feature {
+    /// Transcoding of instances (`Transcode` options)
+    Transcoding,
+    /// Bulk modification, anonymization and deletion (`/tools/bulk-*`)
+    BulkTools,
+    /// Labels of entities
+    Labels,
+}
+
+impl Feature {
+    /// The first version of Orthanc, that supports the feature
+    pub fn required_version(&self) -> Version {
+        match self {
+            Feature::Transcoding => Version::new(1, 7, 0),
+            Feature::BulkTools => Version::new(1, 9, 4),
+            Feature::Labels => Version::new(1, 12, 0),
+        }
+    }
+}
+
+impl fmt::Display for Feature {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Feature::Transcoding => write!(f, "Transcoding"),
+            Feature::BulkTools => write!(f, "Bulk tools"),
+            Feature::Labels => write!(f, "Labels"),
+        }
+    }
+}
+
+/// Capabilities of an Orthanc server, derived from its system information
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Capabilities {
+    /// Version of Orthanc. [`None`] if it is not a release version (e.g. `mainline`)
+    pub version: Option<Version>,
+    pub api_version: u8,
+    /// Whether the server supports labels, as reported by recent versions of Orthanc. Labels
+    /// also depend on the database backend, not only on the version
+    pub has_labels: Option<bool>,
+}
+
+impl Capabilities {
+    /// Derive the capabilities from the system info of the server
+    pub fn from_system(system: &System) -> Capabilities {
+        // Orthanc releases are versioned as `major.minor.patch`, but be lenient with `1.12`
+        let version = Version::parse(&system.version)
+            .or_else(|_| Version::parse(&format!("{}.0", system.version)))
+            .ok();
+        Capabilities {
+            version,
+            api_version: system.api_version,
+            has_labels: system.capabilities.as_ref().and_then(|c| c.has_labels),
+        }
+    }
+
+    /// Whether the server supports a feature
+    ///
+    /// Labels are supported if the server reports so, regardless of its version. Otherwise,
+    /// servers of an unknown version (built from source) are assumed to support all features.
+    pub fn supports(&self, feature: Feature) -> bool {
+        if let (Feature::Labels, Some(has_labels)) = (feature, self.has_labels) {
+            return has_labels;
+        }
+        match &self.version {
+            Some(v) => *v >= feature.required_version(),
+            None => true,
+        }
+    }
+
+    pub(crate) fn require(&self, feature: Feature) -> Result<(), Error> {
+        if self.supports(feature) {
+            return Ok(());
+        }
+        match &self.version {
+            Some(v) if *v < feature.required_version() => {
+                Err(Error::unsupported_by_server(UnsupportedByServer {
+                    feature,
+                    required: feature.required_version(),
+                    actual: v.clone(),
+                }))
+            }
+            _ => Err(Error::new(
+                &format!("{} is not supported by the server", feature),
+                None,
+            )),
+        }
+    }
 }
 
 /// Modality
@@ -54,18 +209,124 @@ pub struct Peer {
     pub url: String,
     pub username: Option<String>,
     pub password: Option<String>,
-    // https://bugs.orthanc-server.com/show_bug.cgi?id=191
-    // TODO: Make a custom serializer/deserializer that would deal with differing types
-    #[serde(skip_deserializing)]
-    pub http_headers: Option<HashMap<String, String>>,
+    pub http_headers: Option<HttpHeaders>,
     pub certificate_file: Option<String>,
     pub certificate_key_file: Option<String>,
     pub certificate_key_password: Option<String>,
 }
 
+/// HTTP headers of a peer
+///
+/// Orthanc accepts the headers as a map of names to values, but only reports the names back
+/// (see [bug 191](https://bugs.orthanc-server.com/show_bug.cgi?id=191)). Both shapes are
+/// accepted when deserializing.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum HttpHeaders {
+    /// Names of the headers, as reported by Orthanc
+    Names(Vec<String>),
+    /// Names and values of the headers
+    Values(HashMap<String, String>),
+}
+
+impl HttpHeaders {
+    /// Names of the headers
+    pub fn names(&self) -> Vec<&str> {
+        match self {
+            HttpHeaders::Names(n) => n.iter().map(AsRef::as_ref).collect(),
+            HttpHeaders::Values(v) => v.keys().map(AsRef::as_ref).collect(),
+        }
+    }
+
+    /// Whether the values of the headers are known
+    ///
+    /// Headers, that only have their names known, can not be sent back to Orthanc
+    pub fn has_values(&self) -> bool {
+        match self {
+            HttpHeaders::Names(n) => n.is_empty(),
+            HttpHeaders::Values(_) => true,
+        }
+    }
+
+    /// Refuse to write back the headers of `owner` (e.g. a peer) with unknown values
+    pub(crate) fn require_values(&self, owner: &str) -> Result<(), Error> {
+        match self.has_values() {
+            true => Ok(()),
+            false => Err(Error::new(
+                &format!(
+                    "Values of HTTP headers of {} are unknown: {}",
+                    owner,
+                    self.names().join(", ")
+                ),
+                None,
+            )),
+        }
+    }
+}
+
+impl Serialize for HttpHeaders {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            HttpHeaders::Names(n) if n.is_empty() => {
+                serializer.collect_map(HashMap::<&str, &str>::new())
+            }
+            HttpHeaders::Names(n) => serializer.collect_seq(n),
+            HttpHeaders::Values(v) => serializer.collect_map(v),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for HttpHeaders {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<HttpHeaders, D::Error> {
+        struct HttpHeadersVisitor;
+
+        impl<'de> Visitor<'de> for HttpHeadersVisitor {
+            type Value = HttpHeaders;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter
+                    .write_str("a list of header names or a map of header names to values")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(
+                self,
+                mut seq: A,
+            ) -> Result<HttpHeaders, A::Error> {
+                let mut names = vec![];
+                while let Some(name) = seq.next_element()? {
+                    names.push(name);
+                }
+                Ok(HttpHeaders::Names(names))
+            }
+
+            fn visit_map<A: MapAccess<'de>>(
+                self,
+                mut map: A,
+            ) -> Result<HttpHeaders, A::Error> {
+                let mut values = HashMap::new();
+                while let Some((name, value)) = map.next_entry()? {
+                    values.insert(name, value);
+                }
+                Ok(HttpHeaders::Values(values))
+            }
+        }
+
+        deserializer.deserialize_any(HttpHeadersVisitor)
+    }
+}
+
 /// Anonymization request body
+///
+/// Can be built with chained setters, or started from one of the predefined profiles:
+///
+/// ```
+/// # use orthanc::models::Anonymization;
+/// let anonymization = Anonymization::basic_profile()
+///     .replace("PatientName", "Anonymous")
+///     .keep("StudyDescription")
+///     .keep_private_tags(false);
+/// ```
 #[skip_serializing_none]
-#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Eq, PartialEq)]
 pub struct Anonymization {
     #[serde(rename(serialize = "Replace"))]
     pub replace: Option<HashMap<String, String>>,
@@ -77,11 +338,165 @@ pub struct Anonymization {
     pub dicom_version: Option<String>,
     #[serde(rename(serialize = "Force"))]
     pub force: Option<bool>,
+    #[serde(rename(serialize = "Remove"))]
+    pub remove: Option<Vec<String>>,
+    #[serde(rename(serialize = "KeepSource"))]
+    pub keep_source: Option<bool>,
+    #[serde(rename(serialize = "Transcode"))]
+    pub transcode: Option<String>,
+    #[serde(rename(serialize = "PrivateCreator"))]
+    pub private_creator: Option<String>,
+    #[serde(rename(serialize = "Permissive"))]
+    pub permissive: Option<bool>,
+}
+
+/// Tags, that identify the study, the series and the instances, and that are kept by
+/// [`Anonymization::keep_uids_profile`]
+const UID_TAGS: [&str; 4] = [
+    "StudyInstanceUID",
+    "SeriesInstanceUID",
+    "SOPInstanceUID",
+    "FrameOfReferenceUID",
+];
+
+/// Descriptive tags kept by [`Anonymization::research_profile`]
+const RESEARCH_TAGS: [&str; 5] = [
+    "StudyDescription",
+    "SeriesDescription",
+    "PatientSex",
+    "PatientAge",
+    "BodyPartExamined",
+];
+
+impl Anonymization {
+    /// Basic application level confidentiality profile of DICOM PS 3.15 (2017c)
+    pub fn basic_profile() -> Anonymization {
+        Anonymization::default().dicom_version("2017c")
+    }
+
+    /// Basic profile, that keeps the study, series and instance UIDs
+    ///
+    /// Allows anonymizing the same study several times while keeping its identity. Orthanc
+    /// requires `Force` to keep the UIDs.
+    pub fn keep_uids_profile() -> Anonymization {
+        UID_TAGS
+            .iter()
+            .fold(Anonymization::basic_profile(), |a, t| a.keep(t))
+            .force(true)
+    }
+
+    /// Basic profile, that keeps descriptive tags useful for research
+    ///
+    /// Dates are removed by the basic profile. Use [`Anonymization::replace_dates`] to keep them
+    /// relative to each other.
+    pub fn research_profile() -> Anonymization {
+        RESEARCH_TAGS
+            .iter()
+            .fold(Anonymization::basic_profile(), |a, t| a.keep(t))
+    }
+
+    /// Replace the dates of `tags`, moved by `days`
+    ///
+    /// Every tag of `tags`, whose name ends with `Date` and whose value is a valid DICOM date, is
+    /// replaced with a fixed value: its value moved by `days`. The other tags are ignored. `tags`
+    /// are usually the main DICOM tags of the anonymized entity and its parents.
+    ///
+    /// The same value is written into every instance of the anonymized entity. Dates that differ
+    /// between the instances (e.g. the `SeriesDate` of the series of a study) are not shifted
+    /// instance by instance, so such entities have to be anonymized one by one.
+    pub fn replace_dates(self, days: i64, tags: &HashMap<String, String>) -> Self {
+        tags.iter()
+            .filter(|(t, _)| t.ends_with("Date"))
+            .filter_map(|(t, v)| {
+                NaiveDate::parse_from_str(v.trim(), DICOM_DATE_FORMAT)
+                    .ok()
+                    .map(|d| (t, d))
+            })
+            .fold(self, |a, (t, d)| {
+                let replaced = d + chrono::Duration::days(days);
+                a.replace(t, &replaced.format(DICOM_DATE_FORMAT).to_string())
+            })
+    }
+
+    /// Replace the value of a tag
+    pub fn replace(mut self, tag: &str, value: &str) -> Self {
+        self.replace
+            .get_or_insert_with(HashMap::new)
+            .insert(tag.to_string(), value.to_string());
+        self
+    }
+
+    /// Keep the value of a tag (or a path within a sequence, e.g.
+    /// `ReferencedImageSequence[0].ReferencedSOPInstanceUID`)
+    pub fn keep(mut self, tag: &str) -> Self {
+        self.keep.get_or_insert_with(Vec::new).push(tag.to_string());
+        self
+    }
+
+    /// Remove a tag
+    pub fn remove(mut self, tag: &str) -> Self {
+        self.remove
+            .get_or_insert_with(Vec::new)
+            .push(tag.to_string());
+        self
+    }
+
+    /// Keep private tags
+    pub fn keep_private_tags(mut self, value: bool) -> Self {
+        self.keep_private_tags = Some(value);
+        self
+    }
+
+    /// Version of the DICOM standard, the anonymization profile is taken from
+    pub fn dicom_version(mut self, value: &str) -> Self {
+        self.dicom_version = Some(value.to_string());
+        self
+    }
+
+    /// Allow modifying the tags, that are normally protected (e.g. UIDs)
+    pub fn force(mut self, value: bool) -> Self {
+        self.force = Some(value);
+        self
+    }
+
+    /// Keep the original entity after it has been anonymized
+    pub fn keep_source(mut self, value: bool) -> Self {
+        self.keep_source = Some(value);
+        self
+    }
+
+    /// Transcode the anonymized instances to a transfer syntax
+    pub fn transcode(mut self, transfer_syntax: &str) -> Self {
+        self.transcode = Some(transfer_syntax.to_string());
+        self
+    }
+
+    /// Private creator to use for the private tags in `Replace`
+    pub fn private_creator(mut self, value: &str) -> Self {
+        self.private_creator = Some(value.to_string());
+        self
+    }
+
+    /// Ignore the errors on individual instances
+    pub fn permissive(mut self, value: bool) -> Self {
+        self.permissive = Some(value);
+        self
+    }
 }
 
 /// Modification request body
+///
+/// Can be built with chained setters:
+///
+/// ```
+/// # use orthanc::models::Modification;
+/// let modification = Modification::default()
+///     .replace("PatientID", "C137")
+///     .remove("PatientBirthDate")
+///     .remove_private_tags(true);
+/// ```
 #[skip_serializing_none]
-#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Eq, PartialEq)]
 pub struct Modification {
     #[serde(rename(serialize = "Replace"))]
     pub replace: Option<HashMap<String, String>>,
@@ -89,6 +504,78 @@ pub struct Modification {
     pub remove: Option<Vec<String>>,
     #[serde(rename(serialize = "Force"))]
     pub force: Option<bool>,
+    #[serde(rename(serialize = "Keep"))]
+    pub keep: Option<Vec<String>>,
+    #[serde(rename(serialize = "RemovePrivateTags"))]
+    pub remove_private_tags: Option<bool>,
+    #[serde(rename(serialize = "KeepSource"))]
+    pub keep_source: Option<bool>,
+    #[serde(rename(serialize = "Transcode"))]
+    pub transcode: Option<String>,
+    #[serde(rename(serialize = "PrivateCreator"))]
+    pub private_creator: Option<String>,
+    #[serde(rename(serialize = "Permissive"))]
+    pub permissive: Option<bool>,
+}
+
+impl Modification {
+    /// Replace the value of a tag
+    pub fn replace(mut self, tag: &str, value: &str) -> Self {
+        self.replace
+            .get_or_insert_with(HashMap::new)
+            .insert(tag.to_string(), value.to_string());
+        self
+    }
+
+    /// Remove a tag (or a path within a sequence)
+    pub fn remove(mut self, tag: &str) -> Self {
+        self.remove
+            .get_or_insert_with(Vec::new)
+            .push(tag.to_string());
+        self
+    }
+
+    /// Allow modifying the tags, that are normally protected (e.g. UIDs)
+    pub fn force(mut self, value: bool) -> Self {
+        self.force = Some(value);
+        self
+    }
+
+    /// Keep the value of a tag, that would otherwise be regenerated (e.g. `SOPInstanceUID`)
+    pub fn keep(mut self, tag: &str) -> Self {
+        self.keep.get_or_insert_with(Vec::new).push(tag.to_string());
+        self
+    }
+
+    /// Remove all private tags
+    pub fn remove_private_tags(mut self, value: bool) -> Self {
+        self.remove_private_tags = Some(value);
+        self
+    }
+
+    /// Keep the original entity after it has been modified
+    pub fn keep_source(mut self, value: bool) -> Self {
+        self.keep_source = Some(value);
+        self
+    }
+
+    /// Transcode the modified instances to a transfer syntax
+    pub fn transcode(mut self, transfer_syntax: &str) -> Self {
+        self.transcode = Some(transfer_syntax.to_string());
+        self
+    }
+
+    /// Private creator to use for the private tags in `Replace`
+    pub fn private_creator(mut self, value: &str) -> Self {
+        self.private_creator = Some(value.to_string());
+        self
+    }
+
+    /// Ignore the errors on individual instances
+    pub fn permissive(mut self, value: bool) -> Self {
+        self.permissive = Some(value);
+        self
+    }
 }
 
 /// Ancestor of an entity
@@ -118,6 +605,32 @@ pub struct RemainingAncestor {
     pub remaining_ancestor: Option<Ancestor>,
 }
 
+/// Options of a deletion request (see [`Client::delete_with_options`] and
+/// [`Client::bulk_delete_with_options`])
+///
+/// [`Client::delete_with_options`]: crate::Client::delete_with_options
+/// [`Client::bulk_delete_with_options`]: crate::Client::bulk_delete_with_options
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+pub struct DeleteOptions {
+    /// Only report what would be deleted, without deleting anything
+    pub dry_run: bool,
+    /// Refuse to delete entities that are not stable yet (i.e. may still receive instances)
+    pub require_stable: bool,
+}
+
+/// Report of a deletion request
+///
+/// In a dry run, describes what would have been deleted.
+#[derive(Debug, Default, Eq, PartialEq)]
+pub struct DeletionReport {
+    /// IDs of the deleted instances
+    pub instances: Vec<String>,
+    /// Total size of the files of the deleted instances, in bytes
+    pub size: u64,
+    /// Remaining ancestors of the deleted entities, keyed by the IDs of the entities
+    pub remaining_ancestors: HashMap<String, RemainingAncestor>,
+}
+
 /// Request body of an Orthanc search request
 #[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
 #[serde(rename_all = "PascalCase")]
@@ -125,6 +638,21 @@ pub struct Search {
     pub level: EntityKind,
     pub query: HashMap<String, String>,
     pub expand: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub labels: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub labels_constraint: Option<LabelsConstraint>,
+}
+
+/// How the labels of a search request are matched against the labels of the entities
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Eq, PartialEq)]
+pub enum LabelsConstraint {
+    /// The entity has all the labels
+    All,
+    /// The entity has at least one of the labels
+    Any,
+    /// The entity has none of the labels
+    None,
 }
 
 /// Modality C-MOVE request body
@@ -168,6 +696,110 @@ pub struct UploadResult {
     pub parent_series: String,
 }
 
+/// Information about an attachment of an entity (e.g. the DICOM file of an instance)
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+#[serde(rename_all = "PascalCase")]
+pub struct AttachmentInfo {
+    pub uuid: String,
+    pub content_type: u16,
+    #[serde(rename = "CompressedMD5")]
+    pub compressed_md5: String,
+    pub compressed_size: u64,
+    #[serde(rename = "UncompressedMD5")]
+    pub uncompressed_md5: String,
+    pub uncompressed_size: u64,
+}
+
+/// Layout of the directory tree created by [`Client::export_to_dir`]
+///
+/// Each field is a template of a path component. `{Tag}` placeholders are replaced with the
+/// values of the main DICOM tags of the entity (e.g. `{PatientName}` or `{SeriesNumber}`).
+/// Placeholders of absent tags are replaced with an empty string. If none of the placeholders of
+/// a component has a value, or if the component ends up empty, `.` or `..`, the Orthanc ID of
+/// the entity is used instead (followed by `.dcm` for instances). Instances, that end up with
+/// the same file name, are told apart by appending their Orthanc IDs to the name.
+///
+/// [`Client::export_to_dir`]: crate::Client::export_to_dir
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ExportLayout {
+    pub patient: String,
+    pub study: String,
+    pub series: String,
+    pub instance: String,
+}
+
+impl Default for ExportLayout {
+    fn default() -> Self {
+        ExportLayout {
+            patient: "{PatientID}".to_string(),
+            study: "{StudyInstanceUID}".to_string(),
+            series: "{SeriesInstanceUID}".to_string(),
+            instance: "{SOPInstanceUID}.dcm".to_string(),
+        }
+    }
+}
+
+/// Report of an export to a directory
+#[derive(Debug, Default, Eq, PartialEq)]
+pub struct ExportReport {
+    /// Files that were downloaded
+    pub exported: Vec<PathBuf>,
+    /// Files that were already present, with the expected size
+    pub skipped: Vec<PathBuf>,
+}
+
+/// Options of a bulk upload (see [`Client::upload_dir`] and `Client::upload_zip`)
+///
+/// [`Client::upload_dir`]: crate::Client::upload_dir
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct UploadOptions {
+    /// Maximum number of files uploaded in parallel
+    pub concurrency: usize,
+    /// Report the files that are already stored in Orthanc as duplicates
+    pub detect_duplicates: bool,
+}
+
+impl Default for UploadOptions {
+    fn default() -> Self {
+        UploadOptions {
+            concurrency: 4,
+            detect_duplicates: false,
+        }
+    }
+}
+
+/// Outcome of uploading a single file during a bulk upload
+#[derive(Debug, Eq, PartialEq)]
+pub struct UploadedFile {
+    /// Path of the file (relative to the root of the archive for ZIP uploads)
+    pub path: String,
+    pub result: Result<UploadResult, Error>,
+}
+
+/// Report of a bulk upload
+#[derive(Debug, Default, Eq, PartialEq)]
+pub struct UploadReport {
+    /// Files that were sent to Orthanc, in the order they were found
+    pub files: Vec<UploadedFile>,
+    /// Files that were not sent to Orthanc, because they are not DICOM
+    pub skipped: Vec<String>,
+    /// Files that were already stored in Orthanc (only populated with
+    /// [`UploadOptions::detect_duplicates`])
+    pub duplicates: Vec<String>,
+}
+
+impl UploadReport {
+    /// Files that were successfully uploaded
+    pub fn succeeded(&self) -> impl Iterator<Item = &UploadedFile> {
+        self.files.iter().filter(|f| f.result.is_ok())
+    }
+
+    /// Files that failed to upload
+    pub fn failed(&self) -> impl Iterator<Item = &UploadedFile> {
+        self.files.iter().filter(|f| f.result.is_err())
+    }
+}
+
 /// Result of a C-STORE DICOM request (sending entities to a modality)
 #[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
 #[serde(rename_all = "PascalCase")]
@@ -183,6 +815,45 @@ pub struct ModalityStoreResult {
 #[deprecated(note = "Renamed to ModalityStoreResult", since = "0.8.0")]
 pub type StoreResult = ModalityStoreResult;
 
+/// Outcome of a C-ECHO request
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub enum EchoStatus {
+    /// The modality responded to the C-ECHO request
+    Success,
+    /// The modality did not respond in time
+    TimedOut,
+    /// The modality rejected the DICOM association (e.g. the AET of Orthanc is not allowed).
+    /// Contains the details reported by Orthanc
+    Rejected(String),
+    /// The host name of the modality could not be resolved. Contains the details reported by
+    /// Orthanc
+    DnsFailure(String),
+    /// The host of the modality refused the connection (e.g. nothing listens on the port).
+    /// Contains the details reported by Orthanc
+    ConnectionRefused(String),
+    /// The DICOM association with the modality failed for any other reason. Contains the
+    /// details reported by Orthanc, if any
+    NetworkFailure(Option<String>),
+    /// The request failed for any other reason. Contains the details reported by Orthanc, if any
+    Failed(Option<String>),
+}
+
+/// Detailed result of a C-ECHO request
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct EchoReport {
+    pub modality: String,
+    pub remote_aet: Option<String>,
+    pub latency: Duration,
+    pub status: EchoStatus,
+}
+
+impl EchoReport {
+    /// Whether the modality responded to the C-ECHO request
+    pub fn is_success(&self) -> bool {
+        self.status == EchoStatus::Success
+    }
+}
+
 /// Result of a C-FIND DICOM request (searching for entities in a modality)
 #[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
 #[serde(rename_all = "PascalCase")]
@@ -192,6 +863,167 @@ pub struct ModalityFindResult {
     pub path: String,
 }
 
+/// Method used to retrieve the answers of a C-FIND query from a remote modality
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub enum RetrieveMethod {
+    #[serde(rename = "C-MOVE")]
+    Move,
+    #[serde(rename = "C-GET")]
+    Get,
+}
+
+/// Options of a query retrieve request
+///
+/// `target_aet` defaults to the AET of the Orthanc server itself. `retrieve_method` is only
+/// supported by recent Orthanc versions; older ones always use C-MOVE.
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Default, Eq, PartialEq)]
+#[serde(rename_all = "PascalCase")]
+pub struct RetrieveOptions {
+    pub target_aet: Option<String>,
+    pub timeout: Option<u32>,
+    pub retrieve_method: Option<RetrieveMethod>,
+}
+
+/// Answer to a C-FIND query
+///
+/// Contains the DICOM tags, returned by the remote modality, in a simplified format (tag name to
+/// tag value).
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+#[serde(rename_all = "PascalCase")]
+pub struct QueryAnswer {
+    #[serde(rename = "ID")]
+    pub id: String,
+    pub tags: HashMap<String, String>,
+}
+
+impl QueryAnswer {
+    /// Get the value of a DICOM tag of the answer
+    pub fn tag(&self, tag: &str) -> Option<&str> {
+        self.tags.get(tag).map(AsRef::as_ref)
+    }
+
+    /// The level of the answer, derived from the `QueryRetrieveLevel` tag
+    pub fn level(&self) -> Option<EntityKind> {
+        match self.tag("QueryRetrieveLevel")? {
+            "PATIENT" => Some(EntityKind::Patient),
+            "STUDY" => Some(EntityKind::Study),
+            "SERIES" => Some(EntityKind::Series),
+            "IMAGE" | "INSTANCE" => Some(EntityKind::Instance),
+            _ => None,
+        }
+    }
+
+    /// `PatientID` tag of the answer
+    pub fn patient_id(&self) -> Option<&str> {
+        self.tag("PatientID")
+    }
+
+    /// `StudyInstanceUID` tag of the answer
+    pub fn study_instance_uid(&self) -> Option<&str> {
+        self.tag("StudyInstanceUID")
+    }
+
+    /// `SeriesInstanceUID` tag of the answer
+    pub fn series_instance_uid(&self) -> Option<&str> {
+        self.tag("SeriesInstanceUID")
+    }
+
+    /// `SOPInstanceUID` tag of the answer
+    pub fn sop_instance_uid(&self) -> Option<&str> {
+        self.tag("SOPInstanceUID")
+    }
+}
+
+/// State of an Orthanc job
+///
+/// States, unknown to this crate, are deserialized as `Unknown`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum JobState {
+    Pending,
+    Running,
+    Success,
+    Failure,
+    Paused,
+    Retry,
+    Unknown(String),
+}
+
+impl JobState {
+    /// Name of the state, as used by Orthanc (e.g. `Running`)
+    pub fn as_str(&self) -> &str {
+        match self {
+            JobState::Pending => "Pending",
+            JobState::Running => "Running",
+            JobState::Success => "Success",
+            JobState::Failure => "Failure",
+            JobState::Paused => "Paused",
+            JobState::Retry => "Retry",
+            JobState::Unknown(s) => s,
+        }
+    }
+}
+
+impl From<&str> for JobState {
+    fn from(s: &str) -> JobState {
+        match s {
+            "Pending" => JobState::Pending,
+            "Running" => JobState::Running,
+            "Success" => JobState::Success,
+            "Failure" => JobState::Failure,
+            "Paused" => JobState::Paused,
+            "Retry" => JobState::Retry,
+            s => JobState::Unknown(s.to_string()),
+        }
+    }
+}
+
+impl Serialize for JobState {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for JobState {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<JobState, D::Error> {
+        Ok(JobState::from(String::deserialize(deserializer)?.as_str()))
+    }
+}
+
+/// Orthanc job
+///
+/// Jobs are created by requests that are executed asynchronously (e.g. retrieving query answers,
+/// sending entities to a peer or a modality).
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "PascalCase")]
+pub struct Job {
+    #[serde(rename = "ID")]
+    pub id: String,
+    #[serde(rename = "Type")]
+    pub job_type: String,
+    pub state: JobState,
+    pub progress: u8,
+    pub priority: i32,
+    pub creation_time: String,
+    pub completion_time: Option<String>,
+    pub estimated_time_of_arrival: Option<String>,
+    pub effective_runtime: Option<f64>,
+    pub error_code: i32,
+    pub error_description: String,
+    pub timestamp: String,
+    pub content: Value,
+}
+
+/// Result of a request that created a job
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+#[serde(rename_all = "PascalCase")]
+pub struct CreatedJob {
+    #[serde(rename = "ID")]
+    pub id: String,
+    pub path: String,
+}
+
 /// Result of a peer store request (sending entities to a peer)
 #[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
 #[serde(rename_all = "PascalCase")]
@@ -203,6 +1035,31 @@ pub struct PeerStoreResult {
     pub failed_instances_count: u64,
 }
 
+/// Options of a peer store request
+///
+/// `transcode` is the transfer syntax UID the instances are transcoded to before sending.
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Default, Eq, PartialEq)]
+#[serde(rename_all = "PascalCase")]
+pub struct PeerStoreOptions {
+    pub transcode: Option<String>,
+    pub compress: Option<bool>,
+    pub synchronous: Option<bool>,
+    pub asynchronous: Option<bool>,
+    pub permissive: Option<bool>,
+}
+
+/// Result of a request that can be executed either synchronously or asynchronously
+///
+/// Contains the result of the request if it was executed synchronously, or the job that executes
+/// it otherwise.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+#[serde(untagged)]
+pub enum OperationResult<T> {
+    Completed(T),
+    Scheduled(CreatedJob),
+}
+
 /// Result of a modification or anonymization request
 #[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
 #[serde(rename_all = "PascalCase")]
@@ -216,10 +1073,47 @@ pub struct ModificationResult {
     pub entity: EntityKind,
 }
 
+/// A resource, created by a bulk modification or anonymization request
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+#[serde(rename_all = "PascalCase")]
+pub struct ModifiedResource {
+    #[serde(rename = "ID")]
+    pub id: String,
+    pub path: String,
+    #[serde(rename = "Type")]
+    pub entity: EntityKind,
+}
+
+/// Output of a bulk modification or anonymization job
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub(crate) struct BulkModificationOutput {
+    pub instances_count: u64,
+    pub failed_instances_count: u64,
+    #[serde(default)]
+    pub resources: Vec<ModifiedResource>,
+    /// The requested resources (reported by the recent versions of Orthanc only)
+    #[serde(default)]
+    pub parent_resources: Vec<String>,
+}
+
+/// Result of a bulk modification or anonymization request
+#[derive(Debug, Default, Eq, PartialEq)]
+pub struct BulkModificationResult {
+    pub instances_count: u64,
+    pub failed_instances_count: u64,
+    /// The created resources, keyed by the IDs of the resources they were created from
+    ///
+    /// Contains at least the modified requested resources. The created resources, the origin of
+    /// which is unknown (e.g. the unmodified parents of the requested resources), are left out.
+    pub resources: HashMap<String, ModifiedResource>,
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use maplit::hashmap;
+    use serde_json::json;
 
     #[test]
     fn test_modification_deserialize() {
@@ -241,7 +1135,8 @@ mod tests {
                     hashmap! {"Foo".to_string() => "42".to_string(), "Bar".to_string() => "17".to_string()}
                 ),
                 remove: Some(vec!["Baz".to_string(), "Qux".to_string()]),
-                force: Some(true)
+                force: Some(true),
+                ..Default::default()
             }
         );
 
@@ -251,11 +1146,127 @@ mod tests {
             Modification {
                 replace: None,
                 remove: None,
-                force: None
+                force: None,
+                ..Default::default()
             }
         );
     }
 
+    #[test]
+    fn test_modification_builder() {
+        let m = Modification::default()
+            .replace("PatientID", "C137")
+            .replace("PatientName", "Rick")
+            .remove("PatientBirthDate")
+            .keep("SOPInstanceUID")
+            .remove_private_tags(true)
+            .keep_source(false)
+            .transcode("1.2.840.10008.1.2.1")
+            .private_creator("Foo")
+            .permissive(true)
+            .force(true);
+        assert_eq!(
+            serde_json::to_value(&m).unwrap(),
+            json!({
+                "Replace": {"PatientID": "C137", "PatientName": "Rick"},
+                "Remove": ["PatientBirthDate"],
+                "Keep": ["SOPInstanceUID"],
+                "RemovePrivateTags": true,
+                "KeepSource": false,
+                "Transcode": "1.2.840.10008.1.2.1",
+                "PrivateCreator": "Foo",
+                "Permissive": true,
+                "Force": true
+            })
+        );
+        assert_eq!(
+            serde_json::to_value(Modification::default()).unwrap(),
+            json!({})
+        );
+    }
+
+    #[test]
+    fn test_anonymization_builder() {
+        let a = Anonymization::default()
+            .replace("PatientName", "Anonymous")
+            .keep("StudyDescription")
+            .keep("ReferencedImageSequence[0].ReferencedSOPInstanceUID")
+            .remove("SeriesDescription")
+            .keep_private_tags(true)
+            .dicom_version("2008")
+            .keep_source(true)
+            .transcode("1.2.840.10008.1.2.1")
+            .private_creator("Foo")
+            .permissive(false)
+            .force(false);
+        assert_eq!(
+            serde_json::to_value(&a).unwrap(),
+            json!({
+                "Replace": {"PatientName": "Anonymous"},
+                "Keep": [
+                    "StudyDescription",
+                    "ReferencedImageSequence[0].ReferencedSOPInstanceUID"
+                ],
+                "Remove": ["SeriesDescription"],
+                "KeepPrivateTags": true,
+                "DicomVersion": "2008",
+                "KeepSource": true,
+                "Transcode": "1.2.840.10008.1.2.1",
+                "PrivateCreator": "Foo",
+                "Permissive": false,
+                "Force": false
+            })
+        );
+    }
+
+    #[test]
+    fn test_anonymization_profiles() {
+        assert_eq!(
+            serde_json::to_value(Anonymization::basic_profile()).unwrap(),
+            json!({"DicomVersion": "2017c"})
+        );
+        assert_eq!(
+            serde_json::to_value(Anonymization::keep_uids_profile()).unwrap(),
+            json!({
+                "DicomVersion": "2017c",
+                "Keep": [
+                    "StudyInstanceUID",
+                    "SeriesInstanceUID",
+                    "SOPInstanceUID",
+                    "FrameOfReferenceUID"
+                ],
+                "Force": true
+            })
+        );
+
+        let tags = hashmap! {
+            "StudyDate".to_string() => "20200301".to_string(),
+            "PatientBirthDate".to_string() => "19440101 ".to_string(),
+            "SeriesDate".to_string() => "".to_string(),
+            "StudyTime".to_string() => "120000".to_string(),
+        };
+        assert_eq!(
+            serde_json::to_value(
+                Anonymization::research_profile().replace_dates(-2, &tags)
+            )
+            .unwrap(),
+            json!({
+                "DicomVersion": "2017c",
+                "Keep": [
+                    "StudyDescription",
+                    "SeriesDescription",
+                    "PatientSex",
+                    "PatientAge",
+                    "BodyPartExamined"
+                ],
+                "Replace": {
+                    "StudyDate": "20200228",
+                    "PatientBirthDate": "19431230"
+                }
+            })
+        );
+    }
+
     #[test]
     fn test_anonymization_deserialize() {
         let json = r#"
@@ -280,7 +1291,8 @@ mod tests {
                 keep: Some(vec!["Baz".to_string(), "Qux".to_string()]),
                 keep_private_tags: Some(true),
                 dicom_version: Some("42.17".to_string()),
-                force: Some(true)
+                force: Some(true),
+                ..Default::default()
             }
         );
         let a2: Anonymization = serde_json::from_str("{}").unwrap();
@@ -291,7 +1303,8 @@ mod tests {
                 keep: None,
                 keep_private_tags: None,
                 dicom_version: None,
-                force: None
+                force: None,
+                ..Default::default()
             }
         );
     }
@@ -318,11 +1331,145 @@ mod tests {
                 url: "http://orthanc_peer:8029/".to_string(),
                 username: Some("orthanc".to_string()),
                 password: None, // empty for security reasons
-                http_headers: None,
+                http_headers: Some(HttpHeaders::Names(vec![
+                    "Bar".to_string(),
+                    "Foo".to_string()
+                ])),
                 certificate_file: None,
                 certificate_key_file: None,
                 certificate_key_password: None,
             },
         );
     }
+
+    #[test]
+    fn test_peer_deserialize_http_headers_values() {
+        let json = r#"
+            {
+                "HttpHeaders": {
+                    "Foo": "foo"
+                },
+                "Url": "http://orthanc_peer:8029/"
+            }
+        "#;
+
+        let p: Peer = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            p.http_headers,
+            Some(HttpHeaders::Values(
+                hashmap! {"Foo".to_string() => "foo".to_string()}
+            ))
+        );
+    }
+
+    #[test]
+    fn test_http_headers_serialize() {
+        assert_eq!(
+            serde_json::to_value(HttpHeaders::Values(
+                hashmap! {"Foo".to_string() => "foo".to_string()}
+            ))
+            .unwrap(),
+            serde_json::json!({"Foo": "foo"})
+        );
+        assert_eq!(
+            serde_json::to_value(HttpHeaders::Names(vec!["Foo".to_string()])).unwrap(),
+            serde_json::json!(["Foo"])
+        );
+        assert_eq!(
+            serde_json::to_value(HttpHeaders::Names(vec![])).unwrap(),
+            serde_json::json!({})
+        );
+    }
+
+    #[test]
+    fn test_http_headers_has_values() {
+        assert!(HttpHeaders::Values(hashmap! {}).has_values());
+        assert!(HttpHeaders::Names(vec![]).has_values());
+        assert!(!HttpHeaders::Names(vec!["Foo".to_string()]).has_values());
+    }
+
+    #[test]
+    fn test_capabilities_from_system() {
+        let system = |version: &str| -> System {
+            serde_json::from_value(json!({
+                "Name": "Orthanc",
+                "Version": version,
+                "ApiVersion": 9,
+                "DatabaseVersion": 6,
+                "DicomAet": "ORTHANC",
+                "DicomPort": 4242,
+                "HttpPort": 8042,
+                "IsHttpServerSecure": true,
+                "PluginsEnabled": true,
+            }))
+            .unwrap()
+        };
+
+        let capabilities = Capabilities::from_system(&system("1.9.4"));
+        assert_eq!(capabilities.version, Some(Version::new(1, 9, 4)));
+        assert_eq!(capabilities.api_version, 9);
+        assert!(capabilities.supports(Feature::Transcoding));
+        assert!(capabilities.supports(Feature::BulkTools));
+        assert!(!capabilities.supports(Feature::Labels));
+        assert!(capabilities.require(Feature::BulkTools).is_ok());
+        assert_eq!(
+            capabilities
+                .require(Feature::Labels)
+                .unwrap_err()
+                .unsupported(),
+            Some(&UnsupportedByServer {
+                feature: Feature::Labels,
+                required: Version::new(1, 12, 0),
+                actual: Version::new(1, 9, 4),
+            })
+        );
+
+        let capabilities = Capabilities::from_system(&system("1.12"));
+        assert_eq!(capabilities.version, Some(Version::new(1, 12, 0)));
+        assert!(capabilities.supports(Feature::Labels));
+
+        let capabilities = Capabilities::from_system(&system("mainline"));
+        assert_eq!(capabilities.version, None);
+        assert!(capabilities.require(Feature::Labels).is_ok());
+
+        // A database backend without labels
+        let mut system = system("1.12.5");
+        system.capabilities = Some(SystemCapabilities {
+            has_extended_changes: None,
+            has_extended_find: None,
+            has_labels: Some(false),
+            extra: HashMap::new(),
+        });
+        let capabilities = Capabilities::from_system(&system);
+        assert_eq!(capabilities.has_labels, Some(false));
+        assert!(!capabilities.supports(Feature::Labels));
+        let error = capabilities.require(Feature::Labels).unwrap_err();
+        assert_eq!(error.message, "Labels is not supported by the server");
+        assert_eq!(error.unsupported(), None);
+
+        system.version = "1.9.1".to_string();
+        system.capabilities.as_mut().unwrap().has_labels = Some(true);
+        assert!(Capabilities::from_system(&system).supports(Feature::Labels));
+    }
+
+    #[test]
+    fn test_system_serialization() {
+        let system: System = serde_json::from_value(json!({
+            "Name": "Orthanc",
+            "Version": "1.12.5",
+            "ApiVersion": 24,
+            "DatabaseVersion": 6,
+            "DicomAet": "ORTHANC",
+            "DicomPort": 4242,
+            "HttpPort": 8042,
+            "IsHttpServerSecure": true,
+            "PluginsEnabled": true,
+            "Capabilities": {"HasLabels": true},
+        }))
+        .unwrap();
+        let json = serde_json::to_value(&system).unwrap();
+        assert_eq!(json["DatabaseBackendPlugin"], Value::Null);
+        assert!(json.get("ReadOnly").is_none());
+        assert_eq!(json["Capabilities"], json!({"HasLabels": true}));
+    }
 }
@@ -0,0 +1,342 @@
+//! Consistent pseudonymization of entities across anonymization requests
+//!
+//! Every anonymization request makes Orthanc generate new random identifiers, so anonymizing
+//! the studies of a patient one by one yields as many anonymized patients. A [`Pseudonymizer`]
+//! keeps track of the pseudonyms it has assigned to the original `PatientID` and UIDs in a
+//! [`MappingStore`], and makes Orthanc reuse them.
+use crate::entity::*;
+use crate::models::{Anonymization, ModificationResult};
+use crate::{Client, Result};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Pseudonyms, grouped by DICOM tag, and keyed by the original value of the tag
+pub type Mapping = BTreeMap<String, BTreeMap<String, String>>;
+
+/// Storage of the pseudonyms, assigned by a [`Pseudonymizer`]
+pub trait MappingStore {
+    /// Get the pseudonym of the `original` value of `tag`
+    fn get(&self, tag: &str, original: &str) -> Result<Option<String>>;
+
+    /// Record the pseudonym of the `original` value of `tag`
+    fn insert(&mut self, tag: &str, original: &str, pseudonym: &str) -> Result<()>;
+
+    /// All pseudonyms, recorded in the store
+    fn mapping(&self) -> Result<Mapping>;
+}
+
+/// Mapping store, that keeps the pseudonyms in memory
+#[derive(Debug, Default, Clone)]
+pub struct MemoryStore {
+    mapping: Mapping,
+}
+
+impl MemoryStore {
+    /// Create an empty store
+    pub fn new() -> MemoryStore {
+        MemoryStore::default()
+    }
+
+    /// Create a store, pre-populated with `mapping` (e.g. loaded from a previous export)
+    pub fn from_mapping(mapping: Mapping) -> MemoryStore {
+        MemoryStore { mapping }
+    }
+}
+
+impl MappingStore for MemoryStore {
+    fn get(&self, tag: &str, original: &str) -> Result<Option<String>> {
+        Ok(self.mapping.get(tag).and_then(|m| m.get(original)).cloned())
+    }
+
+    fn insert(&mut self, tag: &str, original: &str, pseudonym: &str) -> Result<()> {
+        self.mapping
+            .entry(tag.to_string())
+            .or_default()
+            .insert(original.to_string(), pseudonym.to_string());
+        Ok(())
+    }
+
+    fn mapping(&self) -> Result<Mapping> {
+        Ok(self.mapping.clone())
+    }
+}
+
+/// Mapping store, that persists the pseudonyms in a JSON file
+///
+/// The file has the same format as the one written by [`Pseudonymizer::export_mapping`], and is
+/// rewritten every time a new pseudonym is recorded.
+#[derive(Debug)]
+pub struct JsonFileStore {
+    path: PathBuf,
+    store: MemoryStore,
+}
+
+impl JsonFileStore {
+    /// Open the store at `path`, loading the pseudonyms from it if the file exists
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<JsonFileStore> {
+        let path = path.as_ref().to_path_buf();
+        let mapping = match path.exists() {
+            true => serde_json::from_slice(&fs::read(&path)?)?,
+            false => Mapping::new(),
+        };
+        Ok(JsonFileStore {
+            path,
+            store: MemoryStore::from_mapping(mapping),
+        })
+    }
+
+    /// Path of the file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn save(&self) -> Result<()> {
+        let tmp_path = PathBuf::from(format!("{}.tmp", self.path.display()));
+        fs::write(&tmp_path, serde_json::to_vec_pretty(&self.store.mapping)?)?;
+        fs::rename(&tmp_path, &self.path)?;
+        Ok(())
+    }
+}
+
+impl MappingStore for JsonFileStore {
+    fn get(&self, tag: &str, original: &str) -> Result<Option<String>> {
+        self.store.get(tag, original)
+    }
+
+    fn insert(&mut self, tag: &str, original: &str, pseudonym: &str) -> Result<()> {
+        self.store.insert(tag, original, pseudonym)?;
+        self.save()
+    }
+
+    fn mapping(&self) -> Result<Mapping> {
+        self.store.mapping()
+    }
+}
+
+/// Anonymizes entities, consistently replacing their identifiers with pseudonyms
+///
+/// The `PatientID` of the anonymized entity, and the UIDs of the entity and of its parents
+/// (e.g. `StudyInstanceUID` and `SeriesInstanceUID` for a series) are replaced with the
+/// pseudonyms recorded in the store. New pseudonyms are generated by Orthanc the first time an
+/// original value is encountered. Anonymizing the studies of a patient separately thus results
+/// in a single anonymized patient, and anonymizing the series of a study separately results in
+/// a single anonymized study. UIDs of the children of the anonymized entity (e.g.
+/// `SOPInstanceUID` of the instances of a series) are still generated by Orthanc anew on each
+/// request.
+///
+/// ```no_run
+/// # use orthanc::{entity::Study, pseudonymize::{JsonFileStore, Pseudonymizer}, Client};
+/// # let client = Client::new("http://localhost:8042");
+/// let store = JsonFileStore::open("/secure/mapping.json").unwrap();
+/// let mut pseudonymizer = Pseudonymizer::new(&client, store);
+/// let studies = &[
+///     "e34c7a50-e7a94a8d-c0ae8e30-2a1ed4b8-3bf8b6f0",
+///     "63bf5d42-b5382159-01971752-e0ceea3d-399bbca5",
+/// ];
+/// for id in studies {
+///     pseudonymizer.anonymize::<Study>(id, None).unwrap();
+/// }
+/// ```
+#[derive(Debug)]
+pub struct Pseudonymizer<'a, S: MappingStore> {
+    client: &'a Client,
+    store: S,
+}
+
+impl<'a, S: MappingStore> Pseudonymizer<'a, S> {
+    /// Create a pseudonymizer, that records the pseudonyms in `store`
+    pub fn new(client: &'a Client, store: S) -> Pseudonymizer<'a, S> {
+        Pseudonymizer { client, store }
+    }
+
+    /// The mapping store
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// Consume the pseudonymizer, returning the mapping store
+    pub fn into_store(self) -> S {
+        self.store
+    }
+
+    /// Get the pseudonym of the `original` value of the identifier of an entity kind
+    /// (`PatientID` for patients, `StudyInstanceUID` for studies etc.), generating a new one if
+    /// none has been recorded yet
+    pub fn pseudonym(&mut self, kind: EntityKind, original: &str) -> Result<String> {
+        let tag = identifier_tag(&kind)?;
+        if let Some(p) = self.store.get(tag, original)? {
+            return Ok(p);
+        }
+        let pseudonym = self.client.generate_uid(kind)?;
+        self.store.insert(tag, original, &pseudonym)?;
+        Ok(pseudonym)
+    }
+
+    /// Anonymize an entity, replacing its identifiers with pseudonyms
+    ///
+    /// The pseudonyms are added to the `Replace` tags of `anonymization`, which is forced, as
+    /// Orthanc requires that for replacing UIDs.
+    pub fn anonymize<T: Entity>(
+        &mut self,
+        id: &str,
+        anonymization: Option<Anonymization>,
+    ) -> Result<ModificationResult> {
+        let mut anonymization = anonymization.unwrap_or_default().force(true);
+        for (kind, original) in self.identifiers(T::kind(), id)? {
+            let tag = identifier_tag(&kind)?;
+            let pseudonym = self.pseudonym(kind, &original)?;
+            anonymization = anonymization.replace(tag, &pseudonym);
+        }
+        self.client.anonymize::<T>(id, Some(anonymization))
+    }
+
+    /// All pseudonyms, recorded in the store
+    pub fn mapping(&self) -> Result<Mapping> {
+        self.store.mapping()
+    }
+
+    /// Export all pseudonyms as JSON, e.g. for re-identification
+    ///
+    /// The JSON object has tag names as keys, and objects mapping the original values to the
+    /// pseudonyms as values:
+    ///
+    /// ```json
+    /// {"PatientID": {"C137": "fd2d2c33-1d3b4c38-51ee95d0-9b8e27f1-e1ad0b47"}}
+    /// ```
+    pub fn export_mapping<W: Write>(&self, writer: W) -> Result<()> {
+        serde_json::to_writer_pretty(writer, &self.mapping()?)?;
+        Ok(())
+    }
+
+    /// Original identifiers of an entity and of its parents
+    fn identifiers(&self, kind: EntityKind, id: &str) -> Result<Vec<(EntityKind, String)>> {
+        let mut identifiers = vec![];
+        let study = match kind {
+            EntityKind::Patient => {
+                let patient = self.client.patient(id)?;
+                push_identifier(&mut identifiers, EntityKind::Patient, &patient);
+                return Ok(identifiers);
+            }
+            EntityKind::Study => self.client.study(id)?,
+            EntityKind::Series => {
+                let series = self.client.series(id)?;
+                push_identifier(&mut identifiers, EntityKind::Series, &series);
+                self.client.study(&series.parent_study)?
+            }
+            EntityKind::Instance => {
+                let instance = self.client.instance(id)?;
+                push_identifier(&mut identifiers, EntityKind::Instance, &instance);
+                let series = self.client.series(&instance.parent_series)?;
+                push_identifier(&mut identifiers, EntityKind::Series, &series);
+                self.client.study(&series.parent_study)?
+            }
+            k => return Err(k.unsupported()),
+        };
+        push_identifier(&mut identifiers, EntityKind::Study, &study);
+        push_identifier(&mut identifiers, EntityKind::Patient, &study);
+        Ok(identifiers)
+    }
+}
+
+/// The DICOM tag, that identifies an entity of the given kind
+fn identifier_tag(kind: &EntityKind) -> Result<&'static str> {
+    match kind {
+        EntityKind::Patient => Ok("PatientID"),
+        EntityKind::Study => Ok("StudyInstanceUID"),
+        EntityKind::Series => Ok("SeriesInstanceUID"),
+        EntityKind::Instance => Ok("SOPInstanceUID"),
+        k => Err(k.unsupported()),
+    }
+}
+
+fn push_identifier<T: Entity>(
+    identifiers: &mut Vec<(EntityKind, String)>,
+    kind: EntityKind,
+    entity: &T,
+) {
+    let value = identifier_tag(&kind)
+        .ok()
+        .and_then(|t| entity.main_dicom_tag(t));
+    if let Some(v) = value {
+        if !v.is_empty() {
+            identifiers.push((kind, v.to_string()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Error;
+    use maplit::btreemap;
+    use std::env;
+
+    #[test]
+    fn test_memory_store() {
+        let mut store = MemoryStore::new();
+        assert_eq!(store.get("PatientID", "C137").unwrap(), None);
+        store.insert("PatientID", "C137", "foo").unwrap();
+        store.insert("StudyInstanceUID", "1.2.3", "4.5.6").unwrap();
+        store.insert("PatientID", "C138", "bar").unwrap();
+        assert_eq!(
+            store.get("PatientID", "C137").unwrap(),
+            Some("foo".to_string())
+        );
+        assert_eq!(store.get("StudyInstanceUID", "C137").unwrap(), None);
+        assert_eq!(
+            store.mapping().unwrap(),
+            btreemap! {
+                "PatientID".to_string() => btreemap! {
+                    "C137".to_string() => "foo".to_string(),
+                    "C138".to_string() => "bar".to_string(),
+                },
+                "StudyInstanceUID".to_string() => btreemap! {
+                    "1.2.3".to_string() => "4.5.6".to_string(),
+                },
+            }
+        );
+    }
+
+    #[test]
+    fn test_json_file_store() {
+        let path =
+            env::temp_dir().join(format!("orthanc-mapping-{}.json", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let mut store = JsonFileStore::open(&path).unwrap();
+        assert_eq!(store.mapping().unwrap(), Mapping::new());
+        store.insert("PatientID", "C137", "foo").unwrap();
+        assert!(path.exists());
+
+        let store = JsonFileStore::open(&path).unwrap();
+        assert_eq!(
+            store.get("PatientID", "C137").unwrap(),
+            Some("foo".to_string())
+        );
+        assert_eq!(store.path(), path);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_identifier_tag() {
+        assert_eq!(identifier_tag(&EntityKind::Patient).unwrap(), "PatientID");
+        assert_eq!(
+            identifier_tag(&EntityKind::Study).unwrap(),
+            "StudyInstanceUID"
+        );
+        assert_eq!(
+            identifier_tag(&EntityKind::Series).unwrap(),
+            "SeriesInstanceUID"
+        );
+        assert_eq!(
+            identifier_tag(&EntityKind::Instance).unwrap(),
+            "SOPInstanceUID"
+        );
+        assert_eq!(
+            identifier_tag(&EntityKind::Unknown("Foo".to_string())).unwrap_err(),
+            Error::new("Unsupported entity kind: Foo", None)
+        );
+    }
+}
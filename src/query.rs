@@ -0,0 +1,190 @@
+use crate::entity::EntityKind;
+use crate::models::*;
+use crate::{Client, Result};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+/// A C-FIND query, previously issued against a remote modality
+///
+/// Wraps Orthanc's `/queries/{id}` resource. Answers of the query can be inspected, used for
+/// drilling down the DICOM hierarchy (e.g. querying the series of a study answer), or retrieved
+/// into Orthanc (or another modality) with C-MOVE or C-GET.
+///
+/// ```no_run
+/// # use orthanc::{entity::EntityKind, models::RetrieveOptions, Client};
+/// # use std::{collections::HashMap, time::Duration};
+/// # let client = Client::new("http://localhost:8042");
+/// # let query = HashMap::new();
+/// let query = client.modality_query("modality", EntityKind::Study, query, None).unwrap();
+/// for answer in query.answers().unwrap() {
+///     println!("{:?}", answer.study_instance_uid());
+/// }
+/// let series_query = query.query_series("0", HashMap::new()).unwrap();
+/// let job = series_query.retrieve(&RetrieveOptions::default()).unwrap();
+/// let timeout = Duration::from_secs(600);
+/// client
+///     .wait_for_job(&job.id, Duration::from_secs(1), timeout, |j| println!("{}%", j.progress))
+///     .unwrap();
+/// ```
+#[derive(Debug)]
+pub struct RemoteQuery<'a> {
+    client: &'a Client,
+    id: String,
+}
+
+impl<'a> RemoteQuery<'a> {
+    pub(crate) fn new(client: &'a Client, id: &str) -> RemoteQuery<'a> {
+        RemoteQuery {
+            client,
+            id: id.to_string(),
+        }
+    }
+
+    /// The ID of the query
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// The level of the query
+    pub fn level(&self) -> Result<EntityKind> {
+        self.client.query_level(&self.id)
+    }
+
+    /// The modality the query was issued against
+    pub fn modality(&self) -> Result<String> {
+        self.client.query_modality(&self.id)
+    }
+
+    /// The DICOM tags the query was issued with
+    pub fn query(&self) -> Result<Value> {
+        self.client.query_query(&self.id)
+    }
+
+    /// List the IDs of the query answers
+    pub fn answer_ids(&self) -> Result<Vec<String>> {
+        self.client.query_answers(&self.id)
+    }
+
+    /// Get all answers of the query
+    pub fn answers(&self) -> Result<Vec<QueryAnswer>> {
+        let resp = self
+            .client
+            .http_get(&format!("queries/{}/answers?expand&simplify", self.id))?;
+        let json: Vec<HashMap<String, Value>> = serde_json::from_slice(&resp)?;
+        Ok(json
+            .into_iter()
+            .enumerate()
+            .map(|(i, tags)| answer(&i.to_string(), tags))
+            .collect())
+    }
+
+    /// Get a single answer of the query
+    pub fn answer(&self, answer_id: &str) -> Result<QueryAnswer> {
+        let resp = self.client.http_get(&format!(
+            "queries/{}/answers/{}/content?simplify",
+            self.id, answer_id
+        ))?;
+        let json: HashMap<String, Value> = serde_json::from_slice(&resp)?;
+        Ok(answer(answer_id, json))
+    }
+
+    /// Query the studies of a patient-level answer
+    pub fn query_studies(
+        &self,
+        answer_id: &str,
+        query: HashMap<String, String>,
+    ) -> Result<RemoteQuery<'a>> {
+        self.drill_down(answer_id, "query-studies", query)
+    }
+
+    /// Query the series of a study-level answer
+    pub fn query_series(
+        &self,
+        answer_id: &str,
+        query: HashMap<String, String>,
+    ) -> Result<RemoteQuery<'a>> {
+        self.drill_down(answer_id, "query-series", query)
+    }
+
+    /// Query the instances of a series-level answer
+    pub fn query_instances(
+        &self,
+        answer_id: &str,
+        query: HashMap<String, String>,
+    ) -> Result<RemoteQuery<'a>> {
+        self.drill_down(answer_id, "query-instances", query)
+    }
+
+    /// Retrieve all answers of the query
+    ///
+    /// The retrieval is executed asynchronously. Use [`Client::wait_for_job`] to track its
+    /// progress.
+    pub fn retrieve(&self, options: &RetrieveOptions) -> Result<CreatedJob> {
+        self.start_retrieve(&format!("queries/{}/retrieve", self.id), options)
+    }
+
+    /// Retrieve a single answer of the query
+    ///
+    /// The retrieval is executed asynchronously. Use [`Client::wait_for_job`] to track its
+    /// progress.
+    pub fn retrieve_answer(
+        &self,
+        answer_id: &str,
+        options: &RetrieveOptions,
+    ) -> Result<CreatedJob> {
+        self.start_retrieve(
+            &format!("queries/{}/answers/{}/retrieve", self.id, answer_id),
+            options,
+        )
+    }
+
+    /// Retrieve the selected answers of the query
+    ///
+    /// A separate job is created for each answer, in the order of `answer_ids`
+    pub fn retrieve_answers(
+        &self,
+        answer_ids: &[&str],
+        options: &RetrieveOptions,
+    ) -> Result<Vec<CreatedJob>> {
+        answer_ids
+            .iter()
+            .map(|a| self.retrieve_answer(a, options))
+            .collect()
+    }
+
+    fn drill_down(
+        &self,
+        answer_id: &str,
+        action: &str,
+        query: HashMap<String, String>,
+    ) -> Result<RemoteQuery<'a>> {
+        let resp = self.client.http_post(
+            &format!("queries/{}/answers/{}/{}", self.id, answer_id, action),
+            Some(json!({ "Query": query })),
+        )?;
+        let json: ModalityFindResult = serde_json::from_slice(&resp)?;
+        Ok(RemoteQuery::new(self.client, &json.id))
+    }
+
+    fn start_retrieve(&self, path: &str, options: &RetrieveOptions) -> Result<CreatedJob> {
+        let mut data = serde_json::to_value(options)?;
+        data["Asynchronous"] = json!(true);
+        let resp = self.client.http_post(path, Some(data))?;
+        let json: CreatedJob = serde_json::from_slice(&resp)?;
+        Ok(json)
+    }
+}
+
+fn answer(id: &str, tags: HashMap<String, Value>) -> QueryAnswer {
+    QueryAnswer {
+        id: id.to_string(),
+        // Sequences are not part of C-FIND answers' main tags, only keep plain values
+        tags: tags
+            .into_iter()
+            .filter_map(|(k, v)| match v {
+                Value::String(s) => Some((k, s)),
+                _ => None,
+            })
+            .collect(),
+    }
+}
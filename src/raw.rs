@@ -0,0 +1,122 @@
+//! Raw access to the Orthanc REST API
+//!
+//! [`RawClient`] is the extension point for the endpoints, not covered by [`Client`] itself,
+//! most notably the ones, provided by Orthanc plugins. It sends requests with the address and
+//! the credentials of the client, and turns the error responses into [`Error`](crate::Error)s
+//! the same way the client does.
+use crate::{Client, Result};
+use bytes::Bytes;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+/// Client for arbitrary Orthanc API endpoints, obtained with [`Client::raw`]
+///
+/// Paths are relative to the address of the server, without the leading slash (e.g.
+/// `dicom-web/studies`).
+///
+/// ```no_run
+/// # use orthanc::Client;
+/// let client = Client::new("http://localhost:8042");
+/// let servers: Vec<String> = client.raw().get_json("dicom-web/servers").unwrap();
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct RawClient<'a> {
+    client: &'a Client,
+}
+
+impl<'a> RawClient<'a> {
+    pub(crate) fn new(client: &'a Client) -> RawClient<'a> {
+        RawClient { client }
+    }
+
+    /// The client, the requests are sent with
+    pub fn client(&self) -> &'a Client {
+        self.client
+    }
+
+    /// Send a GET request, returning the response body
+    pub fn get(&self, path: &str) -> Result<Bytes> {
+        self.client.http_get(path)
+    }
+
+    /// Send a GET request, deserializing the response body from JSON
+    pub fn get_json<T: DeserializeOwned>(&self, path: &str) -> Result<T> {
+        let resp = self.get(path)?;
+        Ok(serde_json::from_slice(&resp)?)
+    }
+
+    /// Send a POST request with an optional JSON body, returning the response body
+    pub fn post(&self, path: &str, data: Option<Value>) -> Result<Bytes> {
+        self.client.http_post(path, data)
+    }
+
+    /// Send a POST request with a JSON body, deserializing the response body from JSON
+    pub fn post_json<T: DeserializeOwned>(&self, path: &str, data: Value) -> Result<T> {
+        let resp = self.post(path, Some(data))?;
+        Ok(serde_json::from_slice(&resp)?)
+    }
+
+    /// Send a POST request with a raw body, returning the response body
+    pub fn post_bytes(&self, path: &str, data: &[u8]) -> Result<Bytes> {
+        self.client.http_post_bytes(path, data)
+    }
+
+    /// Send a PUT request with a JSON body, returning the response body
+    pub fn put(&self, path: &str, data: Value) -> Result<Bytes> {
+        self.client.http_put(path, data)
+    }
+
+    /// Send a DELETE request, returning the response body
+    pub fn delete(&self, path: &str) -> Result<Bytes> {
+        self.client.http_delete(path)
+    }
+
+    /// Send a GET request with custom headers (e.g. `Accept`)
+    pub fn get_with_headers(
+        &self,
+        path: &str,
+        headers: &[(&str, &str)],
+    ) -> Result<RawResponse> {
+        let resp = self
+            .client
+            .http_request(reqwest::Method::GET, path, headers, None)?;
+        RawResponse::read(resp)
+    }
+
+    /// Send a POST request with custom headers (e.g. `Content-Type`) and a raw body
+    pub fn post_with_headers(
+        &self,
+        path: &str,
+        headers: &[(&str, &str)],
+        body: Vec<u8>,
+    ) -> Result<RawResponse> {
+        let resp = self.client.http_request(
+            reqwest::Method::POST,
+            path,
+            headers,
+            Some(body.into()),
+        )?;
+        RawResponse::read(resp)
+    }
+}
+
+/// Response to a request, sent with [`RawClient`], along with its content type
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct RawResponse {
+    pub content_type: Option<String>,
+    pub body: Bytes,
+}
+
+impl RawResponse {
+    pub(crate) fn read(resp: reqwest::blocking::Response) -> Result<RawResponse> {
+        let content_type = resp
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.to_string());
+        Ok(RawResponse {
+            content_type,
+            body: resp.bytes()?,
+        })
+    }
+}
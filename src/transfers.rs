@@ -0,0 +1,187 @@
+//! Models of the transfers accelerator plugin
+//!
+//! Available with the `transfers` feature. The plugin sends entities between Orthanc peers in
+//! large compressed buckets, over several HTTP connections, which is much faster than
+//! [`Client::peer_store`](crate::Client::peer_store) for large studies. It has to be loaded
+//! both by the server and by the peer.
+//!
+//! ```no_run
+//! # use orthanc::{entity::EntityKind, transfers::*, Client};
+//! # use std::time::Duration;
+//! let client = Client::new("http://localhost:8042");
+//! let study = "63bf5d42-b5382159-01971752-e0ceea3d-399bbca5";
+//! let resources = [TransferResource::new(EntityKind::Study, study)];
+//! let options = TransferOptions::default().compression(Compression::Gzip);
+//! let job = client.transfer_send("remote", &resources, &options).unwrap();
+//! let timeout = Duration::from_secs(3600);
+//! client
+//!     .wait_for_job(&job.id, Duration::from_secs(1), timeout, |j| println!("{}%", j.progress))
+//!     .unwrap();
+//! ```
+use crate::entity::EntityKind;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_with::skip_serializing_none;
+
+/// An entity to transfer
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+#[serde(rename_all = "PascalCase")]
+pub struct TransferResource {
+    pub level: EntityKind,
+    #[serde(rename = "ID")]
+    pub id: String,
+}
+
+impl TransferResource {
+    /// The entity of kind `level` with Orthanc ID `id`
+    pub fn new(level: EntityKind, id: &str) -> TransferResource {
+        TransferResource {
+            level,
+            id: id.to_string(),
+        }
+    }
+}
+
+/// Compression of the transferred buckets
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    None,
+    Gzip,
+}
+
+/// Options of a transfer
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Eq, PartialEq)]
+#[serde(rename_all = "PascalCase")]
+pub struct TransferOptions {
+    pub compression: Option<Compression>,
+    /// Priority of the transfer job
+    pub priority: Option<i32>,
+}
+
+impl TransferOptions {
+    /// Compress the transferred buckets
+    pub fn compression(mut self, value: Compression) -> Self {
+        self.compression = Some(value);
+        self
+    }
+
+    /// Set the priority of the transfer job
+    pub fn priority(mut self, value: i32) -> Self {
+        self.priority = Some(value);
+        self
+    }
+}
+
+/// Availability of the transfers plugin on a peer
+///
+/// Statuses, unknown to this crate, are deserialized as `Unknown`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum PeerTransfers {
+    /// The peer has the plugin
+    Installed,
+    /// The peer has the plugin, and is able to send entities to the server (push mode)
+    Bidirectional,
+    /// The peer does not have the plugin, or cannot be reached
+    Disabled,
+    Unknown(String),
+}
+
+impl PeerTransfers {
+    /// Whether the peer has the plugin
+    pub fn is_available(&self) -> bool {
+        matches!(
+            self,
+            PeerTransfers::Installed | PeerTransfers::Bidirectional
+        )
+    }
+
+    /// Status, as reported by Orthanc (e.g. `installed`)
+    pub fn as_str(&self) -> &str {
+        match self {
+            PeerTransfers::Installed => "installed",
+            PeerTransfers::Bidirectional => "bidirectional",
+            PeerTransfers::Disabled => "disabled",
+            PeerTransfers::Unknown(s) => s,
+        }
+    }
+}
+
+impl From<&str> for PeerTransfers {
+    fn from(s: &str) -> PeerTransfers {
+        match s {
+            "installed" => PeerTransfers::Installed,
+            "bidirectional" => PeerTransfers::Bidirectional,
+            "disabled" => PeerTransfers::Disabled,
+            s => PeerTransfers::Unknown(s.to_string()),
+        }
+    }
+}
+
+impl Serialize for PeerTransfers {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for PeerTransfers {
+    fn deserialize<D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<PeerTransfers, D::Error> {
+        Ok(PeerTransfers::from(
+            String::deserialize(deserializer)?.as_str(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_transfer_options_serialize() {
+        assert_eq!(
+            serde_json::to_value(TransferOptions::default()).unwrap(),
+            json!({})
+        );
+        assert_eq!(
+            serde_json::to_value(
+                TransferOptions::default()
+                    .compression(Compression::Gzip)
+                    .priority(10)
+            )
+            .unwrap(),
+            json!({"Compression": "gzip", "Priority": 10})
+        );
+        assert_eq!(
+            serde_json::to_value(TransferResource::new(EntityKind::Series, "foo")).unwrap(),
+            json!({"Level": "Series", "ID": "foo"})
+        );
+    }
+
+    #[test]
+    fn test_peer_transfers_deserialize() {
+        let peers: Vec<PeerTransfers> = serde_json::from_value(json!([
+            "installed",
+            "bidirectional",
+            "disabled",
+            "foo"
+        ]))
+        .unwrap();
+        assert_eq!(
+            peers,
+            vec![
+                PeerTransfers::Installed,
+                PeerTransfers::Bidirectional,
+                PeerTransfers::Disabled,
+                PeerTransfers::Unknown("foo".to_string()),
+            ]
+        );
+        assert_eq!(
+            peers.iter().map(|p| p.is_available()).collect::<Vec<_>>(),
+            vec![true, true, false, false]
+        );
+    }
+}
@@ -1,5 +1,9 @@
+use crate::error::ApiError;
+use crate::models::EchoStatus;
 use crate::{Error, Result};
 use bytes::Bytes;
+use std::collections::HashMap;
+use std::io::Read;
 
 pub(crate) fn check_http_error(status: reqwest::StatusCode, body: Bytes) -> Result<Bytes> {
     if status >= reqwest::StatusCode::BAD_REQUEST {
@@ -12,10 +16,141 @@ pub(crate) fn check_http_error(status: reqwest::StatusCode, body: Bytes) -> Resu
     Ok(body)
 }
 
+/// Render a path component template, replacing `{Tag}` placeholders with tag values
+///
+/// Path separators in the values are replaced, so that a value can not create extra directories.
+/// Falls back to `default` if none of the placeholders has a value, or if the result is empty or
+/// refers to the current or the parent directory (`.` or `..`).
+pub(crate) fn render_template(
+    template: &str,
+    tags: &HashMap<String, String>,
+    default: &str,
+) -> String {
+    let mut result = String::new();
+    let mut rest = template;
+    let mut placeholders = 0;
+    let mut values = 0;
+    while let Some(start) = rest.find('{') {
+        let end = match rest[start..].find('}') {
+            Some(e) => start + e,
+            None => break,
+        };
+        result.push_str(&rest[..start]);
+        placeholders += 1;
+        if let Some(v) = tags.get(&rest[start + 1..end]).map(|v| v.trim()) {
+            if !v.is_empty() {
+                values += 1;
+            }
+            result.push_str(&v.replace(['/', '\\', '\0'], "_"));
+        }
+        rest = &rest[end + 1..];
+    }
+    result.push_str(rest);
+    let result = result.trim();
+    if (placeholders > 0 && values == 0) || ["", ".", ".."].contains(&result) {
+        return default.to_string();
+    }
+    result.to_string()
+}
+
+/// Length of the DICOM preamble, followed by the `DICM` magic code
+const DICOM_PREFIX_LENGTH: usize = 132;
+
+/// Whether the data looks like a DICOM file (has the preamble and the `DICM` magic code)
+pub(crate) fn is_dicom(data: &[u8]) -> bool {
+    data.len() >= DICOM_PREFIX_LENGTH && &data[128..DICOM_PREFIX_LENGTH] == b"DICM"
+}
+
+/// Read a DICOM file, returning `None` if it is not one
+///
+/// Only the preamble is read from the files that are not DICOM.
+pub(crate) fn read_dicom<R: Read>(mut reader: R) -> Result<Option<Vec<u8>>> {
+    let mut data = vec![];
+    reader
+        .by_ref()
+        .take(DICOM_PREFIX_LENGTH as u64)
+        .read_to_end(&mut data)?;
+    if !is_dicom(&data) {
+        return Ok(None);
+    }
+    reader.read_to_end(&mut data)?;
+    Ok(Some(data))
+}
+
+/// Maximum length of a label, accepted by Orthanc
+const MAX_LABEL_LENGTH: usize = 64;
+
+/// Check that a label only contains the characters, accepted by Orthanc
+///
+/// Labels are sent as a part of the URL path, so this also keeps them from escaping it.
+pub(crate) fn check_label(label: &str) -> Result<()> {
+    let valid = !label.is_empty()
+        && label.len() <= MAX_LABEL_LENGTH
+        && label
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if !valid {
+        return Err(Error::new(&format!("Invalid label: {}", label), None));
+    }
+    Ok(())
+}
+
+/// Orthanc error code of a failure in the DICOM network protocol (e.g. a rejected association)
+const ORTHANC_NETWORK_PROTOCOL: u16 = 9;
+/// Orthanc error code of a timeout
+const ORTHANC_TIMEOUT: u16 = 16;
+
+/// Parts of the details of the network protocol errors, reported by Orthanc (through DCMTK),
+/// in lower case
+const ECHO_REJECTED: &[&str] = &["association rejected"];
+const ECHO_DNS_FAILURE: &[&str] = &[
+    "unknown host",
+    "name or service not known",
+    "name resolution",
+    "nodename nor servname",
+];
+const ECHO_CONNECTION_REFUSED: &[&str] = &["connection refused"];
+const ECHO_TIMED_OUT: &[&str] = &["timed out", "timeout"];
+
+/// Classify a failed C-ECHO request by the error reported by Orthanc
+///
+/// Orthanc reports all failures of the association with the same error code, so they are told
+/// apart by their details.
+pub(crate) fn echo_status(error: Option<ApiError>) -> EchoStatus {
+    let error = match error {
+        Some(e) => e,
+        None => return EchoStatus::Failed(None),
+    };
+    if error.orthanc_status == ORTHANC_TIMEOUT {
+        return EchoStatus::TimedOut;
+    }
+    if error.orthanc_status != ORTHANC_NETWORK_PROTOCOL {
+        return EchoStatus::Failed(error.details);
+    }
+    let details = match error.details {
+        Some(d) => d,
+        None => return EchoStatus::NetworkFailure(None),
+    };
+    let lower = details.to_lowercase();
+    let matches = |markers: &[&str]| markers.iter().any(|m| lower.contains(m));
+    if matches(ECHO_REJECTED) {
+        EchoStatus::Rejected(details)
+    } else if matches(ECHO_DNS_FAILURE) {
+        EchoStatus::DnsFailure(details)
+    } else if matches(ECHO_CONNECTION_REFUSED) {
+        EchoStatus::ConnectionRefused(details)
+    } else if matches(ECHO_TIMED_OUT) {
+        EchoStatus::TimedOut
+    } else {
+        EchoStatus::NetworkFailure(Some(details))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::ApiError;
+    use maplit::hashmap;
 
     #[test]
     fn test_check_http_error_ok() {
@@ -47,7 +182,7 @@ mod tests {
             res.unwrap_err(),
             Error {
                 message: "API error: 400 Bad Request".to_string(),
-                details: Some(ApiError {
+                details: Some(Box::new(ApiError {
                     method: "POST".to_string(),
                     uri: "/instances".to_string(),
                     message: "Bad file format".to_string(),
@@ -58,7 +193,8 @@ mod tests {
                     http_error: "Bad Request".to_string(),
                     orthanc_status: 15,
                     orthanc_error: "Bad file format".to_string(),
-                },),
+                }),),
+                kind: None,
             },
         );
     }
@@ -70,11 +206,84 @@ mod tests {
             res.unwrap_err(),
             Error {
                 message: "API error: 401 Unauthorized".to_string(),
-                details: None
+                details: None,
+                kind: None,
             },
         );
     }
 
+    #[test]
+    fn test_echo_status() {
+        let error =
+            |orthanc_status: u16, orthanc_error: &str, details: Option<&str>| ApiError {
+                method: "POST".to_string(),
+                uri: "/modalities/dino/echo".to_string(),
+                message: orthanc_error.to_string(),
+                details: details.map(String::from),
+                http_status: 500,
+                http_error: "Internal Server Error".to_string(),
+                orthanc_status,
+                orthanc_error: orthanc_error.to_string(),
+            };
+        let network = |details: &str| {
+            echo_status(Some(error(
+                9,
+                "Error in the network protocol",
+                Some(details),
+            )))
+        };
+        assert_eq!(
+            network("DicomAssociation - connecting to AET \"DINO\": Association Rejected"),
+            EchoStatus::Rejected(
+                "DicomAssociation - connecting to AET \"DINO\": Association Rejected"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            network("Failed to establish association (0006:0317 Unknown host: dino)"),
+            EchoStatus::DnsFailure(
+                "Failed to establish association (0006:0317 Unknown host: dino)"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            network("TCP Initialization Error: Name or service not known"),
+            EchoStatus::DnsFailure(
+                "TCP Initialization Error: Name or service not known".to_string()
+            )
+        );
+        assert_eq!(
+            network("Failed to establish association (TCP Initialization Error: Connection refused)"),
+            EchoStatus::ConnectionRefused(
+                "Failed to establish association (TCP Initialization Error: Connection refused)"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            network("TCP Initialization Error: Operation timed out"),
+            EchoStatus::TimedOut
+        );
+        assert_eq!(
+            network("DicomAssociation - Peer aborted Association"),
+            EchoStatus::NetworkFailure(Some(
+                "DicomAssociation - Peer aborted Association".to_string()
+            ))
+        );
+        assert_eq!(
+            echo_status(Some(error(9, "Error in the network protocol", None))),
+            EchoStatus::NetworkFailure(None)
+        );
+        assert_eq!(
+            echo_status(Some(error(16, "Timeout", Some("DUL network read timeout")))),
+            EchoStatus::TimedOut
+        );
+        assert_eq!(
+            echo_status(Some(error(2, "Not implemented yet", Some("Foo")))),
+            EchoStatus::Failed(Some("Foo".to_string()))
+        );
+        assert_eq!(echo_status(None), EchoStatus::Failed(None));
+    }
+
     // TODO: Firgure out how to handle this
     #[test]
     fn test_check_http_error_error_random_body() {
@@ -86,8 +295,74 @@ mod tests {
             res.unwrap_err(),
             Error {
                 message: "expected ident at line 1 column 2".to_string(),
-                details: None
+                details: None,
+                kind: None,
             },
         );
     }
+
+    #[test]
+    fn test_is_dicom() {
+        let mut data = vec![0; 128];
+        assert!(!is_dicom(&data));
+        data.extend(b"DICM");
+        assert!(is_dicom(&data));
+        data[128] = b'X';
+        assert!(!is_dicom(&data));
+        assert!(!is_dicom(b"DICM"));
+    }
+
+    #[test]
+    fn test_read_dicom() {
+        let mut data = vec![0; 128];
+        data.extend(b"DICMfoo");
+        assert_eq!(read_dicom(&data[..]).unwrap(), Some(data.clone()));
+        assert_eq!(read_dicom(&b"foo"[..]).unwrap(), None);
+
+        // Nothing is read past the preamble of a file that is not DICOM
+        let mut reader = std::io::Cursor::new(vec![0; 256]);
+        assert_eq!(read_dicom(&mut reader).unwrap(), None);
+        assert_eq!(reader.position(), 132);
+    }
+
+    #[test]
+    fn test_check_label() {
+        assert!(check_label("foo_Bar-42").is_ok());
+        assert!(check_label(&"a".repeat(64)).is_ok());
+        assert!(check_label(&"a".repeat(65)).is_err());
+        assert!(check_label("").is_err());
+        assert!(check_label("foo bar").is_err());
+        assert!(check_label("../foo").is_err());
+        assert!(check_label("foo?bar").is_err());
+        assert!(check_label("föö").is_err());
+    }
+
+    #[test]
+    fn test_render_template() {
+        let tags = hashmap! {
+            "PatientID".to_string() => "foo".to_string(),
+            "PatientName".to_string() => "Bar/Baz ".to_string(),
+        };
+        assert_eq!(
+            render_template("{PatientID} {PatientName}", &tags, "id"),
+            "foo Bar_Baz"
+        );
+        assert_eq!(render_template("{PatientID}.dcm", &tags, "id"), "foo.dcm");
+        assert_eq!(render_template("{Modality}", &tags, "id"), "id");
+        assert_eq!(render_template("{Modality}.dcm", &tags, "id.dcm"), "id.dcm");
+        assert_eq!(
+            render_template("{PatientID}{Modality}.dcm", &tags, "id.dcm"),
+            "foo.dcm"
+        );
+        assert_eq!(render_template("images", &tags, "id"), "images");
+        assert_eq!(render_template("{PatientID", &tags, "id"), "{PatientID");
+
+        let tags = hashmap! {
+            "PatientID".to_string() => "..".to_string(),
+            "PatientName".to_string() => ".".to_string(),
+        };
+        assert_eq!(render_template("{PatientID}", &tags, "id"), "id");
+        assert_eq!(render_template(" {PatientName} ", &tags, "id"), "id");
+        assert_eq!(render_template("{PatientID}.dcm", &tags, "id"), "...dcm");
+    }
 }
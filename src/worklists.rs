@@ -0,0 +1,83 @@
+//! Client of the worklists plugin
+//!
+//! Available with the `worklists` feature. Manages the modality worklists, that the worklists
+//! plugin of Orthanc serves to the modalities over DICOM (C-FIND), through the REST API of the
+//! plugin (Orthanc 1.12.9 or newer). The plugin has to be loaded by the server.
+//!
+//! ```no_run
+//! # use orthanc::Client;
+//! # use serde_json::json;
+//! # use std::collections::HashMap;
+//! let client = Client::new("http://localhost:8042");
+//! let mut tags = HashMap::new();
+//! tags.insert("PatientID".to_string(), json!("C137"));
+//! tags.insert("PatientName".to_string(), json!("Sanchez^Rick"));
+//! let worklist = client.worklists().create(&tags).unwrap();
+//! for w in client.worklists().list().unwrap() {
+//!     println!("{}: {:?}", w.id, w.tags.get("PatientID"));
+//! }
+//! client.worklists().delete(&worklist.id).unwrap();
+//! ```
+use crate::raw::RawClient;
+use crate::{Client, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+/// A worklist, served by the plugin
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+#[serde(rename_all = "PascalCase")]
+pub struct Worklist {
+    #[serde(rename = "ID")]
+    pub id: String,
+    /// DICOM tags of the worklist, keyed by their names (e.g. `PatientID`)
+    #[serde(default)]
+    pub tags: HashMap<String, Value>,
+    /// Fields, unknown to this crate (e.g. introduced by a future version of the plugin)
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+/// A worklist, created by [`WorklistsClient::create`]
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+#[serde(rename_all = "PascalCase")]
+pub struct CreatedWorklist {
+    #[serde(rename = "ID")]
+    pub id: String,
+    pub path: String,
+}
+
+/// Client of the worklists plugin, obtained with [`Client::worklists`]
+#[derive(Debug, Clone, Copy)]
+pub struct WorklistsClient<'a> {
+    raw: RawClient<'a>,
+}
+
+impl<'a> WorklistsClient<'a> {
+    pub(crate) fn new(client: &'a Client) -> WorklistsClient<'a> {
+        WorklistsClient { raw: client.raw() }
+    }
+
+    /// List all worklists
+    pub fn list(&self) -> Result<Vec<Worklist>> {
+        self.raw.get_json("worklists")
+    }
+
+    /// Get a worklist by its ID
+    pub fn get(&self, id: &str) -> Result<Worklist> {
+        self.raw.get_json(&format!("worklists/{}", id))
+    }
+
+    /// Create a worklist from its DICOM tags, keyed by their names (e.g. `PatientID`)
+    ///
+    /// Sequences (e.g. `ScheduledProcedureStepSequence`) are given as arrays of objects.
+    pub fn create(&self, tags: &HashMap<String, Value>) -> Result<CreatedWorklist> {
+        self.raw
+            .post_json("worklists/create", json!({ "Tags": tags }))
+    }
+
+    /// Delete a worklist
+    pub fn delete(&self, id: &str) -> Result<()> {
+        self.raw.delete(&format!("worklists/{}", id)).map(|_| ())
+    }
+}
@@ -1,10 +1,44 @@
 use chrono::NaiveDate;
-use httpmock::{Method, Mock, MockServer};
+use httpmock::{Method, Mock, MockRef, MockServer};
 use maplit::hashmap;
 use orthanc::entity::*;
+use orthanc::error::UnsupportedByServer;
 use orthanc::models::*;
-use orthanc::{ApiError, Client, Error};
-use serde_json::Value;
+use orthanc::pseudonymize::{MemoryStore, Pseudonymizer};
+use orthanc::{ApiError, Client, Error, ErrorKind};
+use semver::Version;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::fs;
+use std::io::Cursor;
+use std::str;
+use std::time::Duration;
+
+fn mock_system<'a>(mock_server: &'a MockServer, version: &str) -> MockRef<'a> {
+    Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/system")
+        .return_status(200)
+        .return_body(&format!(
+            r#"
+                {{
+                    "ApiVersion": 9,
+                    "DatabaseBackendPlugin": null,
+                    "DatabaseVersion": 6,
+                    "DicomAet": "ORTHANC",
+                    "DicomPort": 4242,
+                    "HttpPort": 8042,
+                    "IsHttpServerSecure": true,
+                    "Name": "Orthanc",
+                    "PluginsEnabled": true,
+                    "StorageAreaPlugin": null,
+                    "Version": "{}"
+                }}
+            "#,
+            version
+        ))
+        .create_on(mock_server)
+}
 
 #[test]
 fn test_get_system_info() {
@@ -52,11 +86,251 @@ fn test_get_system_info() {
             is_http_server_secure: true,
             plugins_enabled: true,
             storage_area_plugin: None,
+            check_revisions: None,
+            database_server_identifier: None,
+            ingest_transcoding: None,
+            maximum_storage_size: None,
+            maximum_patient_count: None,
+            maximum_storage_mode: None,
+            overwrite_instances: None,
+            storage_compression: None,
+            main_dicom_tags: None,
+            user_metadata: None,
+            capabilities: None,
+            read_only: None,
+            extra: HashMap::new(),
+        }
+    );
+    assert_eq!(m.times_called(), 1);
+}
+
+#[test]
+fn test_get_system_info_extended() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+
+    let body = r#"
+        {
+            "ApiVersion": 22,
+            "Capabilities": {
+                "HasExtendedChanges": true,
+                "HasExtendedFind": true,
+                "HasFooBar": false
+            },
+            "CheckRevisions": false,
+            "DatabaseBackendPlugin": "/usr/share/orthanc/plugins/libOrthancPostgreSQLIndex.so",
+            "DatabaseServerIdentifier": "Orthanc",
+            "DatabaseVersion": 6,
+            "DicomAet": "ORTHANC",
+            "DicomPort": 4242,
+            "HttpPort": 8042,
+            "IngestTranscoding": "1.2.840.10008.1.2.4.90",
+            "IsHttpServerSecure": true,
+            "MainDicomTags": {
+                "Patient": "0x0010,0x0010;0x0010,0x0020"
+            },
+            "MaximumPatientCount": 0,
+            "MaximumStorageMode": "Recycle",
+            "MaximumStorageSize": 1024,
+            "Name": "Orthanc",
+            "OverwriteInstances": false,
+            "PluginsEnabled": true,
+            "ReadOnly": false,
+            "StorageAreaPlugin": null,
+            "StorageCompression": false,
+            "UserMetadata": {
+                "Foo": 1024
+            },
+            "Version": "1.12.5",
+            "Foo": ["bar"]
+        }
+    "#;
+    let m = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/system")
+        .return_status(200)
+        .return_header("Content-Type", "application/json")
+        .return_body(body)
+        .create_on(&mock_server);
+
+    let cl = Client::new(url);
+    let system = cl.system().unwrap();
+
+    assert_eq!(system.check_revisions, Some(false));
+    assert_eq!(
+        system.ingest_transcoding,
+        Some("1.2.840.10008.1.2.4.90".to_string())
+    );
+    assert_eq!(system.maximum_storage_size, Some(1024));
+    assert_eq!(system.maximum_patient_count, Some(0));
+    assert_eq!(system.maximum_storage_mode, Some("Recycle".to_string()));
+    assert_eq!(system.overwrite_instances, Some(false));
+    assert_eq!(
+        system.user_metadata,
+        Some(hashmap! {"Foo".to_string() => 1024})
+    );
+    assert_eq!(system.read_only, Some(false));
+    let capabilities = system.capabilities.as_ref().unwrap();
+    assert_eq!(capabilities.has_extended_find, Some(true));
+    assert_eq!(capabilities.has_labels, None);
+    assert_eq!(
+        capabilities.extra,
+        hashmap! {"HasFooBar".to_string() => json!(false)}
+    );
+    assert_eq!(system.extra, hashmap! {"Foo".to_string() => json!(["bar"])});
+    // Nothing is lost in a round trip
+    assert_eq!(
+        serde_json::to_value(&system).unwrap(),
+        serde_json::from_str::<Value>(body).unwrap()
+    );
+    assert_eq!(m.times_called(), 1);
+}
+
+#[test]
+fn test_plugins() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+
+    let m = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/plugins")
+        .return_status(200)
+        .return_header("Content-Type", "application/json")
+        .return_body(r#"["dicom-web", "explorer.js"]"#)
+        .create_on(&mock_server);
+
+    let cl = Client::new(url);
+    let plugins = cl.plugins().unwrap();
+
+    assert_eq!(plugins, ["dicom-web", "explorer.js"]);
+    assert_eq!(m.times_called(), 1);
+}
+
+#[test]
+fn test_plugin() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+
+    let m = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/plugins/dicom-web")
+        .return_status(200)
+        .return_header("Content-Type", "application/json")
+        .return_body(
+            r#"
+                {
+                    "Description": "Implementation of DICOMweb (QIDO-RS, STOW-RS and WADO-RS) and WADO-URI.",
+                    "ExtendsOrthancExplorer": true,
+                    "ID": "dicom-web",
+                    "RootUri": "../dicom-web/app/client/index.html",
+                    "Version": "1.6"
+                }
+            "#,
+        )
+        .create_on(&mock_server);
+
+    let cl = Client::new(url);
+    let plugin = cl.plugin("dicom-web").unwrap();
+
+    assert_eq!(
+        plugin,
+        Plugin {
+            id: "dicom-web".to_string(),
+            version: "1.6".to_string(),
+            description: Some(
+                "Implementation of DICOMweb (QIDO-RS, STOW-RS and WADO-RS) and WADO-URI."
+                    .to_string()
+            ),
+            root_uri: Some("../dicom-web/app/client/index.html".to_string()),
+            extends_orthanc_explorer: true,
         }
     );
     assert_eq!(m.times_called(), 1);
 }
 
+#[test]
+fn test_raw() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+
+    let m1 = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/dicom-web/servers")
+        .return_status(200)
+        .return_body(r#"["foo"]"#)
+        .create_on(&mock_server);
+    let m2 = Mock::new()
+        .expect_method(Method::POST)
+        .expect_path("/worklists/create")
+        .expect_json_body(&json!({"Tags": {"PatientID": "bar"}}))
+        .return_status(200)
+        .return_body(r#"{"ID": "baz"}"#)
+        .create_on(&mock_server);
+    let m3 = Mock::new()
+        .expect_method(Method::PUT)
+        .expect_path("/dicom-web/servers/foo")
+        .expect_json_body(&json!({"Url": "http://foo/dicom-web"}))
+        .return_status(200)
+        .create_on(&mock_server);
+    let m4 = Mock::new()
+        .expect_method(Method::DELETE)
+        .expect_path("/dicom-web/servers/foo")
+        .return_status(200)
+        .create_on(&mock_server);
+    let m5 = Mock::new()
+        .expect_method(Method::POST)
+        .expect_path("/transfers/pull")
+        .expect_body("qux")
+        .return_status(200)
+        .return_body("quux")
+        .create_on(&mock_server);
+    let m6 = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/foo")
+        .return_status(404)
+        .return_body(
+            r#"
+                {
+                    "HttpError": "Not Found",
+                    "HttpStatus": 404,
+                    "Message": "Unknown resource",
+                    "Method": "GET",
+                    "OrthancError": "Unknown resource",
+                    "OrthancStatus": 17,
+                    "Uri": "/foo"
+                }
+            "#,
+        )
+        .create_on(&mock_server);
+
+    let cl = Client::new(url);
+    let raw = cl.raw();
+    let servers: Vec<String> = raw.get_json("dicom-web/servers").unwrap();
+    assert_eq!(servers, ["foo"]);
+    let created: Value = raw
+        .post_json("worklists/create", json!({"Tags": {"PatientID": "bar"}}))
+        .unwrap();
+    assert_eq!(created, json!({"ID": "baz"}));
+    raw.put(
+        "dicom-web/servers/foo",
+        json!({"Url": "http://foo/dicom-web"}),
+    )
+    .unwrap();
+    raw.delete("dicom-web/servers/foo").unwrap();
+    assert_eq!(raw.post_bytes("transfers/pull", b"qux").unwrap(), "quux");
+    assert_eq!(
+        raw.get("foo").unwrap_err().details.unwrap().http_status,
+        404
+    );
+
+    assert_eq!(m1.times_called(), 1);
+    assert_eq!(m2.times_called(), 1);
+    assert_eq!(m3.times_called(), 1);
+    assert_eq!(m4.times_called(), 1);
+    assert_eq!(m5.times_called(), 1);
+    assert_eq!(m6.times_called(), 1);
+}
+
 #[test]
 fn test_list_modalities() {
     let mock_server = MockServer::start();
@@ -310,7 +584,10 @@ fn test_list_peers_expanded() {
                 url: "http://orthanc_peer:8029/".to_string(),
                 username: Some("orthanc".to_string()),
                 password: None, // empty for security reasons
-                http_headers: None,
+                http_headers: Some(HttpHeaders::Names(vec![
+                    "Bar".to_string(),
+                    "Foo".to_string()
+                ])),
                 certificate_file: None,
                 certificate_key_file: None,
                 certificate_key_password: None,
@@ -319,7 +596,7 @@ fn test_list_peers_expanded() {
                 url: "http://orthanc_peer:8092/".to_string(),
                 username: None,
                 password: None,
-                http_headers: None,
+                http_headers: Some(HttpHeaders::Names(vec![])),
                 certificate_file: Some("foo".to_string()),
                 certificate_key_file: Some("bar".to_string()),
                 certificate_key_password: None,
@@ -388,7 +665,10 @@ fn test_list_patients_expanded() {
             Patient {
                 id: "f88cbd3f-a00dfc59-9ca1ac2d-7ce9851a-40e5b493".to_string(),
                 is_stable: true,
-                last_update: NaiveDate::from_ymd(2020, 1, 1).and_hms(15, 46, 17),
+                last_update: NaiveDate::from_ymd_opt(2020, 1, 1)
+                    .unwrap()
+                    .and_hms_opt(15, 46, 17)
+                    .unwrap(),
                 main_dicom_tags: hashmap! {
                     "OtherPatientIDs".to_string() => "".to_string(),
                     "PatientBirthDate".to_string() => "19670101".to_string(),
@@ -399,12 +679,18 @@ fn test_list_patients_expanded() {
                 studies: ["e8cafcbe-caf08c39-6e205f15-18554bb8-b3f9ef04".to_string()]
                     .to_vec(),
                 entity: EntityKind::Patient,
-                anonymized_from: None
+                anonymized_from: None,
+                protected: false,
+                labels: vec![],
+                extra: HashMap::new(),
             },
             Patient {
                 id: "7e43f8d3-e50280e6-470079e9-02241af1-d286bdbe".to_string(),
                 is_stable: true,
-                last_update: NaiveDate::from_ymd(2020, 8, 26).and_hms(17, 45, 31),
+                last_update: NaiveDate::from_ymd_opt(2020, 8, 26)
+                    .unwrap()
+                    .and_hms_opt(17, 45, 31)
+                    .unwrap(),
                 main_dicom_tags: hashmap! {
                     "OtherPatientIDs".to_string() => "".to_string(),
                     "PatientBirthDate".to_string() => "19440101".to_string(),
@@ -414,7 +700,10 @@ fn test_list_patients_expanded() {
                 studies: ["63bf5d42-b5382159-01971752-e0ceea3d-399bbca5".to_string()]
                     .to_vec(),
                 entity: EntityKind::Patient,
-                anonymized_from: None
+                anonymized_from: None,
+                protected: false,
+                labels: vec![],
+                extra: HashMap::new(),
             },
         ]
     );
@@ -499,7 +788,10 @@ fn test_list_studies_expanded() {
             Study {
                 id: "63bf5d42-b5382159-01971752-e0ceea3d-399bbca5".to_string(),
                 is_stable: true,
-                last_update: NaiveDate::from_ymd(2020, 8, 30).and_hms(19, 11, 09),
+                last_update: NaiveDate::from_ymd_opt(2020, 8, 30)
+                    .unwrap()
+                    .and_hms_opt(19, 11, 9)
+                    .unwrap(),
                 main_dicom_tags: hashmap! {
                     "AccessionNumber".to_string() => "foobar".to_string(),
                     "StudyDate".to_string() => "20110101".to_string(),
@@ -521,12 +813,17 @@ fn test_list_studies_expanded() {
                 ]
                 .to_vec(),
                 entity: EntityKind::Study,
-                anonymized_from: None
+                anonymized_from: None,
+                labels: vec![],
+                extra: HashMap::new(),
             },
             Study {
                 id: "e8cafcbe-caf08c39-6e205f15-18554bb8-b3f9ef04".to_string(),
                 is_stable: true,
-                last_update: NaiveDate::from_ymd(2020, 9, 1).and_hms(18, 52, 11),
+                last_update: NaiveDate::from_ymd_opt(2020, 9, 1)
+                    .unwrap()
+                    .and_hms_opt(18, 52, 11)
+                    .unwrap(),
                 main_dicom_tags: hashmap! {
                     "AccessionNumber".to_string() => "bazqux".to_string(),
                     "StudyDate".to_string() => "20120101".to_string(),
@@ -548,7 +845,9 @@ fn test_list_studies_expanded() {
                 ]
                 .to_vec(),
                 entity: EntityKind::Study,
-                anonymized_from: None
+                anonymized_from: None,
+                labels: vec![],
+                extra: HashMap::new(),
             },
         ]
     );
@@ -629,7 +928,10 @@ fn test_list_seies_expanded() {
                 id: "cd00fffc-db25be29-0c6da430-c56796a5-ba06933c".to_string(),
                 status: "Unknown".to_string(),
                 is_stable: true,
-                last_update: NaiveDate::from_ymd(2020, 8, 30).and_hms(19, 11, 09),
+                last_update: NaiveDate::from_ymd_opt(2020, 8, 30)
+                    .unwrap()
+                    .and_hms_opt(19, 11, 9)
+                    .unwrap(),
                 main_dicom_tags: hashmap! {
                     "BodyPartExamined".to_string() => "ABDOMEN".to_string(),
                     "Modality".to_string() => "MR".to_string(),
@@ -649,13 +951,18 @@ fn test_list_seies_expanded() {
                 ]
                 .to_vec(),
                 entity: EntityKind::Series,
-                anonymized_from: None
+                anonymized_from: None,
+                labels: vec![],
+                extra: HashMap::new(),
             },
             Series {
                 id: "2ab7dbe7-f1a18a78-86145443-18a8ff93-0b65f2b2".to_string(),
                 status: "Unknown".to_string(),
                 is_stable: true,
-                last_update: NaiveDate::from_ymd(2020, 8, 30).and_hms(19, 11, 09),
+                last_update: NaiveDate::from_ymd_opt(2020, 8, 30)
+                    .unwrap()
+                    .and_hms_opt(19, 11, 9)
+                    .unwrap(),
                 main_dicom_tags: hashmap! {
                     "BodyPartExamined".to_string() => "HEAD".to_string(),
                     "Modality".to_string() => "CT".to_string(),
@@ -674,7 +981,9 @@ fn test_list_seies_expanded() {
                 ]
                 .to_vec(),
                 entity: EntityKind::Series,
-                anonymized_from: None
+                anonymized_from: None,
+                labels: vec![],
+                extra: HashMap::new(),
             },
         ]
     );
@@ -756,7 +1065,9 @@ fn test_list_instances_expanded() {
                     "22c54cb6-28302a69-3ff454a3-676b98f4-b84cd80a".to_string()
                 ),
                 entity: EntityKind::Instance,
-                anonymized_from: None
+                anonymized_from: None,
+                labels: vec![],
+                extra: HashMap::new(),
             },
             Instance {
                 id: "286a251e-46571bd6-0e14ab9a-1baadddc-d0146ea0".to_string(),
@@ -774,7 +1085,9 @@ fn test_list_instances_expanded() {
                 file_size: 381642,
                 modified_from: None,
                 entity: EntityKind::Instance,
-                anonymized_from: None
+                anonymized_from: None,
+                labels: vec![],
+                extra: HashMap::new(),
             },
         ]
     );
@@ -821,7 +1134,10 @@ fn test_get_patient() {
         Patient {
             id: "f88cbd3f-a00dfc59-9ca1ac2d-7ce9851a-40e5b493".to_string(),
             is_stable: true,
-            last_update: NaiveDate::from_ymd(2020, 1, 1).and_hms(15, 46, 17),
+            last_update: NaiveDate::from_ymd_opt(2020, 1, 1)
+                .unwrap()
+                .and_hms_opt(15, 46, 17)
+                .unwrap(),
             main_dicom_tags: hashmap! {
                 "OtherPatientIDs".to_string() => "".to_string(),
                 "PatientBirthDate".to_string() => "19670101".to_string(),
@@ -831,7 +1147,10 @@ fn test_get_patient() {
             },
             studies: ["e8cafcbe-caf08c39-6e205f15-18554bb8-b3f9ef04".to_string()].to_vec(),
             entity: EntityKind::Patient,
-            anonymized_from: None
+            anonymized_from: None,
+            protected: false,
+            labels: vec![],
+            extra: HashMap::new(),
         },
     );
     assert_eq!(m.times_called(), 1);
@@ -886,7 +1205,10 @@ fn test_get_study() {
         Study {
             id: "63bf5d42-b5382159-01971752-e0ceea3d-399bbca5".to_string(),
             is_stable: true,
-            last_update: NaiveDate::from_ymd(2020, 8, 30).and_hms(19, 11, 09),
+            last_update: NaiveDate::from_ymd_opt(2020, 8, 30)
+                .unwrap()
+                .and_hms_opt(19, 11, 9)
+                .unwrap(),
             main_dicom_tags: hashmap! {
                 "AccessionNumber".to_string() => "foobar".to_string(),
                 "StudyDate".to_string() => "20110101".to_string(),
@@ -908,7 +1230,9 @@ fn test_get_study() {
             ]
             .to_vec(),
             entity: EntityKind::Study,
-            anonymized_from: None
+            anonymized_from: None,
+            labels: vec![],
+            extra: HashMap::new(),
         },
     );
     assert_eq!(m.times_called(), 1);
@@ -968,7 +1292,9 @@ fn test_get_instance() {
             file_size: 139402,
             modified_from: Some("22c54cb6-28302a69-3ff454a3-676b98f4-b84cd80a".to_string()),
             entity: EntityKind::Instance,
-            anonymized_from: None
+            anonymized_from: None,
+            labels: vec![],
+            extra: HashMap::new(),
         }
     );
     assert_eq!(m.times_called(), 1);
@@ -1022,7 +1348,10 @@ fn test_get_series() {
             id: "cd00fffc-db25be29-0c6da430-c56796a5-ba06933c".to_string(),
             status: "Unknown".to_string(),
             is_stable: true,
-            last_update: NaiveDate::from_ymd(2020, 8, 30).and_hms(19, 11, 09),
+            last_update: NaiveDate::from_ymd_opt(2020, 8, 30)
+                .unwrap()
+                .and_hms_opt(19, 11, 9)
+                .unwrap(),
             main_dicom_tags: hashmap! {
                 "BodyPartExamined".to_string() => "ABDOMEN".to_string(),
                 "Modality".to_string() => "MR".to_string(),
@@ -1042,7 +1371,9 @@ fn test_get_series() {
             ]
             .to_vec(),
             entity: EntityKind::Series,
-            anonymized_from: None
+            anonymized_from: None,
+            labels: vec![],
+            extra: HashMap::new(),
         },
     );
     assert_eq!(m.times_called(), 1);
@@ -1266,6 +1597,7 @@ fn test_get_instance_dicom() {
 }
 
 #[test]
+#[allow(deprecated)]
 fn test_modality_store() {
     let mock_server = MockServer::start();
     let url = mock_server.url("");
@@ -1357,6 +1689,143 @@ fn test_peer_store() {
     assert_eq!(m.times_called(), 1);
 }
 
+#[test]
+fn test_peer_store_with_options() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+    let system = mock_system(&mock_server, "1.12.0");
+
+    let m = Mock::new()
+        .expect_method(Method::POST)
+        .expect_path("/peers/foobar/store")
+        .expect_json_body(&serde_json::json!({
+            "Resources": ["bar", "baz"],
+            "Transcode": "1.2.840.10008.1.2.1",
+            "Compress": true,
+            "Synchronous": true
+        }))
+        .return_status(200)
+        .return_header("Content-Type", "application/json")
+        .return_body(
+            r#"
+                    {
+                       "Description" : "REST API",
+                       "FailedInstancesCount" : 0,
+                       "InstancesCount" : 42,
+                       "ParentResources" : [ "bar", "baz" ],
+                       "Peer": [ "foobar" ]
+                    }
+                "#,
+        )
+        .create_on(&mock_server);
+
+    let cl = Client::new(url);
+    let resp = cl
+        .peer_store_with_options(
+            "foobar",
+            &["bar", "baz"],
+            &PeerStoreOptions {
+                transcode: Some("1.2.840.10008.1.2.1".to_string()),
+                compress: Some(true),
+                synchronous: Some(true),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+    assert_eq!(
+        resp,
+        OperationResult::Completed(PeerStoreResult {
+            description: "REST API".to_string(),
+            peer: vec!["foobar".to_string()],
+            parent_resources: vec!["bar".to_string(), "baz".to_string()],
+            instances_count: 42,
+            failed_instances_count: 0
+        })
+    );
+    assert_eq!(m.times_called(), 1);
+    assert_eq!(system.times_called(), 1);
+}
+
+#[test]
+fn test_peer_store_with_options_asynchronous() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+
+    let m = Mock::new()
+        .expect_method(Method::POST)
+        .expect_path("/peers/foobar/store")
+        .expect_json_body(&serde_json::json!({
+            "Resources": ["bar"],
+            "Asynchronous": true,
+            "Permissive": true
+        }))
+        .return_status(200)
+        .return_header("Content-Type", "application/json")
+        .return_body(r#"{"ID": "qux", "Path": "/jobs/qux"}"#)
+        .create_on(&mock_server);
+
+    let cl = Client::new(url);
+    let resp = cl
+        .peer_store_with_options(
+            "foobar",
+            &["bar"],
+            &PeerStoreOptions {
+                asynchronous: Some(true),
+                permissive: Some(true),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+    assert_eq!(
+        resp,
+        OperationResult::Scheduled(CreatedJob {
+            id: "qux".to_string(),
+            path: "/jobs/qux".to_string()
+        })
+    );
+    assert_eq!(m.times_called(), 1);
+}
+
+#[test]
+fn test_peer_system() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+
+    let m = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/peers/foobar/system")
+        .return_status(200)
+        .return_header("Content-Type", "application/json")
+        .return_body(
+            r#"
+                    {
+                        "ApiVersion": 9,
+                        "DatabaseBackendPlugin": null,
+                        "DatabaseVersion": 6,
+                        "DicomAet": "ORTHANC",
+                        "DicomPort": 4242,
+                        "HttpPort": 8042,
+                        "IsHttpServerSecure": false,
+                        "Name": "Peer",
+                        "PluginsEnabled": false,
+                        "StorageAreaPlugin": null,
+                        "Version": "1.9.1"
+                    }
+                "#,
+        )
+        .create_on(&mock_server);
+
+    let cl = Client::new(url);
+    let system = cl.peer_system("foobar").unwrap();
+
+    assert_eq!(system.name, "Peer");
+    assert_eq!(system.version, "1.9.1");
+    assert_eq!(system.api_version, 9);
+    assert_eq!(m.times_called(), 1);
+}
+
 #[test]
 fn test_modify_patient() {
     let mock_server = MockServer::start();
@@ -1369,6 +1838,7 @@ fn test_modify_patient() {
             replace: Some(hashmap! {"Tag1".to_string() => "value1".to_string()}),
             remove: Some(vec!["Tag2".to_string()]),
             force: Some(true),
+            ..Default::default()
         })
         .return_status(200)
         .return_body(
@@ -1391,6 +1861,7 @@ fn test_modify_patient() {
                 replace: Some(hashmap! {"Tag1".to_string() => "value1".to_string()}),
                 remove: Some(vec!["Tag2".to_string()]),
                 force: Some(true),
+                ..Default::default()
             },
         )
         .unwrap();
@@ -1419,6 +1890,7 @@ fn test_modify_study() {
             replace: Some(hashmap! {"Tag1".to_string() => "value1".to_string()}),
             remove: Some(vec!["Tag2".to_string()]),
             force: None,
+            ..Default::default()
         })
         .return_status(200)
         .return_body(
@@ -1441,6 +1913,7 @@ fn test_modify_study() {
                 replace: Some(hashmap! {"Tag1".to_string() => "value1".to_string()}),
                 remove: Some(vec!["Tag2".to_string()]),
                 force: None,
+                ..Default::default()
             },
         )
         .unwrap();
@@ -1469,6 +1942,7 @@ fn test_modify_series() {
             replace: Some(hashmap! {"Tag1".to_string() => "value1".to_string()}),
             remove: Some(vec!["Tag2".to_string()]),
             force: None,
+            ..Default::default()
         })
         .return_status(200)
         .return_body(
@@ -1491,6 +1965,7 @@ fn test_modify_series() {
                 replace: Some(hashmap! {"Tag1".to_string() => "value1".to_string()}),
                 remove: Some(vec!["Tag2".to_string()]),
                 force: None,
+                ..Default::default()
             },
         )
         .unwrap();
@@ -1519,6 +1994,7 @@ fn test_modify_instance() {
             replace: Some(hashmap! {"Tag1".to_string() => "value1".to_string()}),
             remove: Some(vec!["Tag2".to_string()]),
             force: None,
+            ..Default::default()
         })
         .return_status(200)
         .return_body("foobar")
@@ -1533,6 +2009,7 @@ fn test_modify_instance() {
             replace: Some(hashmap! {"Tag1".to_string() => "value1".to_string()}),
             remove: Some(vec!["Tag2".to_string()]),
             force: None,
+            ..Default::default()
         },
         &mut writer,
     )
@@ -1543,73 +2020,302 @@ fn test_modify_instance() {
 }
 
 #[test]
-fn test_anonymize_patient() {
+fn test_store_modified_instance() {
     let mock_server = MockServer::start();
     let url = mock_server.url("");
 
-    let m = Mock::new()
+    let m1 = Mock::new()
         .expect_method(Method::POST)
-        .expect_path("/patients/foo/anonymize")
-        .expect_json_body(&Anonymization {
-            replace: Some(hashmap! {"Tag1".to_string() => "value1".to_string()}),
-            keep: Some(vec!["Tag2".to_string(), "Tag3".to_string()]),
-            keep_private_tags: None,
-            dicom_version: None,
-            force: None,
-        })
+        .expect_path("/instances/quux/modify")
+        .expect_json_body(&Modification::default().replace("InstitutionName", "Foo"))
         .return_status(200)
-        .return_body(
-            r#"
-                    {
-                        "ID": "86a3054b-32bb888a-e5f42e28-4b2e82d2-b1d7e14c",
-                        "Path": "/patients/86a3054b-32bb888a-e5f42e28-4b2e82d2-b1d7e14c",
-                        "PatientID": "86a3054b-32bb888a-e5f42e28-4b2e82d2-b1d7e14c",
-                        "Type": "Patient"
-                    }
-                "#,
-        )
+        .return_body("foobar")
+        .create_on(&mock_server);
+    let m2 = Mock::new()
+        .expect_method(Method::POST)
+        .expect_path("/instances")
+        .expect_body("foobar")
+        .return_status(200)
+        .return_body(UPLOAD_RESULT)
+        .create_on(&mock_server);
+    let m3 = Mock::new()
+        .expect_method(Method::DELETE)
+        .expect_path("/instances/quux")
+        .return_status(200)
+        .return_body(r#"{"RemainingAncestor": null}"#)
         .create_on(&mock_server);
 
     let cl = Client::new(url);
     let resp = cl
-        .anonymize_patient(
-            "foo",
-            Some(Anonymization {
-                replace: Some(hashmap! {"Tag1".to_string() => "value1".to_string()}),
-                keep: Some(vec!["Tag2".to_string(), "Tag3".to_string()]),
-                keep_private_tags: None,
-                dicom_version: None,
-                force: None,
-            }),
+        .store_modified_instance(
+            "quux",
+            Modification::default().replace("InstitutionName", "Foo"),
+            true,
         )
         .unwrap();
 
     assert_eq!(
         resp,
         ModificationResult {
-            id: "86a3054b-32bb888a-e5f42e28-4b2e82d2-b1d7e14c".to_string(),
-            patient_id: "86a3054b-32bb888a-e5f42e28-4b2e82d2-b1d7e14c".to_string(),
-            path: "/patients/86a3054b-32bb888a-e5f42e28-4b2e82d2-b1d7e14c".to_string(),
-            entity: EntityKind::Patient,
+            id: "foo".to_string(),
+            patient_id: "bar".to_string(),
+            path: "/instances/foo".to_string(),
+            entity: EntityKind::Instance,
         }
     );
-    assert_eq!(m.times_called(), 1);
+    assert_eq!(m1.times_called(), 1);
+    assert_eq!(m2.times_called(), 1);
+    assert_eq!(m3.times_called(), 1);
 }
 
+// With `OverwriteInstances` enabled, the copy replaces the original
 #[test]
-fn test_anonymize_study() {
+fn test_store_modified_instance_same_id() {
     let mock_server = MockServer::start();
     let url = mock_server.url("");
 
-    let m = Mock::new()
+    let m1 = Mock::new()
         .expect_method(Method::POST)
-        .expect_path("/studies/foo/anonymize")
-        .expect_json_body(&Anonymization {
-            replace: Some(hashmap! {"Tag1".to_string() => "value1".to_string()}),
-            keep: Some(vec!["Tag2".to_string(), "Tag3".to_string()]),
-            keep_private_tags: Some(true),
-            dicom_version: None,
+        .expect_path("/instances/foo/modify")
+        .return_status(200)
+        .return_body("foobar")
+        .create_on(&mock_server);
+    let m2 = Mock::new()
+        .expect_method(Method::POST)
+        .expect_path("/instances")
+        .expect_body("foobar")
+        .return_status(200)
+        .return_body(UPLOAD_RESULT)
+        .create_on(&mock_server);
+    let m3 = Mock::new()
+        .expect_method(Method::DELETE)
+        .expect_path("/instances/foo")
+        .return_status(200)
+        .return_body(r#"{"RemainingAncestor": null}"#)
+        .create_on(&mock_server);
+
+    let cl = Client::new(url);
+    let resp = cl
+        .store_modified_instance(
+            "foo",
+            Modification::default().keep("SOPInstanceUID"),
+            true,
+        )
+        .unwrap();
+
+    assert_eq!(resp.id, "foo");
+    assert_eq!(m1.times_called(), 1);
+    assert_eq!(m2.times_called(), 1);
+    assert_eq!(m3.times_called(), 0);
+}
+
+#[test]
+fn test_store_modified_instance_already_stored() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+
+    let m1 = Mock::new()
+        .expect_method(Method::POST)
+        .expect_path("/instances/foo/modify")
+        .return_status(200)
+        .return_body("foobar")
+        .create_on(&mock_server);
+    let m2 = Mock::new()
+        .expect_method(Method::POST)
+        .expect_path("/instances")
+        .expect_body("foobar")
+        .return_status(200)
+        .return_body(&UPLOAD_RESULT.replace("Success", "AlreadyStored"))
+        .create_on(&mock_server);
+    let m3 = Mock::new()
+        .expect_method(Method::DELETE)
+        .expect_path("/instances/foo")
+        .return_status(200)
+        .return_body(r#"{"RemainingAncestor": null}"#)
+        .create_on(&mock_server);
+
+    let cl = Client::new(url);
+    let resp = cl.modify::<Instance>("foo", Modification::default().keep("SOPInstanceUID"));
+
+    assert_eq!(
+        resp.unwrap_err(),
+        Error {
+            message:
+                "The modified copy of instance foo was not stored: it keeps the UIDs of \
+                      the original, and Orthanc does not overwrite instances"
+                    .to_string(),
+            details: None,
+            kind: None,
+        }
+    );
+    assert_eq!(m1.times_called(), 1);
+    assert_eq!(m2.times_called(), 1);
+    assert_eq!(m3.times_called(), 0);
+}
+
+#[test]
+fn test_store_anonymized_instance() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+
+    let m1 = Mock::new()
+        .expect_method(Method::POST)
+        .expect_path("/instances/quux/anonymize")
+        .expect_json_body(&Anonymization::basic_profile())
+        .return_status(200)
+        .return_body("foobar")
+        .create_on(&mock_server);
+    let m2 = Mock::new()
+        .expect_method(Method::POST)
+        .expect_path("/instances")
+        .expect_body("foobar")
+        .return_status(200)
+        .return_body(UPLOAD_RESULT)
+        .create_on(&mock_server);
+    let m3 = Mock::new()
+        .expect_method(Method::DELETE)
+        .expect_path("/instances/quux")
+        .return_status(200)
+        .return_body(r#"{"RemainingAncestor": null}"#)
+        .create_on(&mock_server);
+
+    let cl = Client::new(url);
+    let resp = cl
+        .store_anonymized_instance("quux", Some(Anonymization::basic_profile()), false)
+        .unwrap();
+
+    assert_eq!(resp.id, "foo");
+    assert_eq!(m1.times_called(), 1);
+    assert_eq!(m2.times_called(), 1);
+    assert_eq!(m3.times_called(), 0);
+}
+
+#[test]
+fn test_store_modified_instance_error() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+
+    let m1 = Mock::new()
+        .expect_method(Method::POST)
+        .expect_path("/instances/quux/modify")
+        .return_status(400)
+        .return_body(
+            r#"
+                {
+                    "Details" : "Cannot modify an instance",
+                    "HttpError" : "Bad Request",
+                    "HttpStatus" : 400,
+                    "Message" : "Bad request",
+                    "Method" : "POST",
+                    "OrthancError" : "Bad request",
+                    "OrthancStatus" : 8,
+                    "Uri" : "/instances/quux/modify"
+                }
+            "#,
+        )
+        .create_on(&mock_server);
+    let m2 = Mock::new()
+        .expect_method(Method::POST)
+        .expect_path("/instances")
+        .return_status(200)
+        .return_body(UPLOAD_RESULT)
+        .create_on(&mock_server);
+
+    let cl = Client::new(url);
+    let resp = cl.store_modified_instance("quux", Modification::default(), true);
+
+    assert_eq!(
+        resp.unwrap_err(),
+        Error {
+            message: "API error: 400 Bad Request".to_string(),
+            details: Some(Box::new(ApiError {
+                method: "POST".to_string(),
+                uri: "/instances/quux/modify".to_string(),
+                message: "Bad request".to_string(),
+                details: Some("Cannot modify an instance".to_string()),
+                http_status: 400,
+                http_error: "Bad Request".to_string(),
+                orthanc_status: 8,
+                orthanc_error: "Bad request".to_string(),
+            }),),
+            kind: None,
+        },
+    );
+    assert_eq!(m1.times_called(), 1);
+    assert_eq!(m2.times_called(), 0);
+}
+
+#[test]
+fn test_anonymize_patient() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+
+    let m = Mock::new()
+        .expect_method(Method::POST)
+        .expect_path("/patients/foo/anonymize")
+        .expect_json_body(&Anonymization {
+            replace: Some(hashmap! {"Tag1".to_string() => "value1".to_string()}),
+            keep: Some(vec!["Tag2".to_string(), "Tag3".to_string()]),
+            keep_private_tags: None,
+            dicom_version: None,
+            force: None,
+            ..Default::default()
+        })
+        .return_status(200)
+        .return_body(
+            r#"
+                    {
+                        "ID": "86a3054b-32bb888a-e5f42e28-4b2e82d2-b1d7e14c",
+                        "Path": "/patients/86a3054b-32bb888a-e5f42e28-4b2e82d2-b1d7e14c",
+                        "PatientID": "86a3054b-32bb888a-e5f42e28-4b2e82d2-b1d7e14c",
+                        "Type": "Patient"
+                    }
+                "#,
+        )
+        .create_on(&mock_server);
+
+    let cl = Client::new(url);
+    let resp = cl
+        .anonymize_patient(
+            "foo",
+            Some(Anonymization {
+                replace: Some(hashmap! {"Tag1".to_string() => "value1".to_string()}),
+                keep: Some(vec!["Tag2".to_string(), "Tag3".to_string()]),
+                keep_private_tags: None,
+                dicom_version: None,
+                force: None,
+                ..Default::default()
+            }),
+        )
+        .unwrap();
+
+    assert_eq!(
+        resp,
+        ModificationResult {
+            id: "86a3054b-32bb888a-e5f42e28-4b2e82d2-b1d7e14c".to_string(),
+            patient_id: "86a3054b-32bb888a-e5f42e28-4b2e82d2-b1d7e14c".to_string(),
+            path: "/patients/86a3054b-32bb888a-e5f42e28-4b2e82d2-b1d7e14c".to_string(),
+            entity: EntityKind::Patient,
+        }
+    );
+    assert_eq!(m.times_called(), 1);
+}
+
+#[test]
+fn test_anonymize_study() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+
+    let m = Mock::new()
+        .expect_method(Method::POST)
+        .expect_path("/studies/foo/anonymize")
+        .expect_json_body(&Anonymization {
+            replace: Some(hashmap! {"Tag1".to_string() => "value1".to_string()}),
+            keep: Some(vec!["Tag2".to_string(), "Tag3".to_string()]),
+            keep_private_tags: Some(true),
+            dicom_version: None,
             force: None,
+            ..Default::default()
         })
         .return_status(200)
         .return_body(
@@ -1634,6 +2340,7 @@ fn test_anonymize_study() {
                 keep_private_tags: Some(true),
                 dicom_version: None,
                 force: None,
+                ..Default::default()
             }),
         )
         .unwrap();
@@ -1664,6 +2371,7 @@ fn test_anonymize_series() {
             keep_private_tags: Some(false),
             dicom_version: None,
             force: None,
+            ..Default::default()
         })
         .return_status(200)
         .return_body(
@@ -1688,6 +2396,7 @@ fn test_anonymize_series() {
                 keep_private_tags: Some(false),
                 dicom_version: None,
                 force: None,
+                ..Default::default()
             }),
         )
         .unwrap();
@@ -1718,6 +2427,7 @@ fn test_anonymize_instance() {
             keep_private_tags: None,
             dicom_version: None,
             force: None,
+            ..Default::default()
         })
         .return_status(200)
         .return_body("foobar")
@@ -1733,6 +2443,7 @@ fn test_anonymize_instance() {
             keep_private_tags: None,
             dicom_version: None,
             force: None,
+            ..Default::default()
         }),
         &mut writer,
     )
@@ -1743,629 +2454,3319 @@ fn test_anonymize_instance() {
 }
 
 #[test]
-fn test_delete_patient() {
+fn test_get_generic() {
     let mock_server = MockServer::start();
     let url = mock_server.url("");
 
     let m = Mock::new()
-        .expect_method(Method::DELETE)
-        .expect_path("/patients/foo")
+        .expect_method(Method::GET)
+        .expect_path("/series/foo")
         .return_status(200)
-        .return_body(r#"{"RemainingAncestor": null}"#)
+        .return_body(
+            r#"
+                    {
+                        "ExpectedNumberOfInstances": null,
+                        "ID": "foo",
+                        "Instances": ["qux"],
+                        "IsStable": false,
+                        "LastUpdate": "20200830T191109",
+                        "MainDicomTags": {
+                            "Modality": "MR"
+                        },
+                        "ParentStudy": "bar",
+                        "Status": "Unknown",
+                        "Type": "Series"
+                    }
+                "#,
+        )
         .create_on(&mock_server);
 
     let cl = Client::new(url);
-    let resp = cl.delete_patient("foo").unwrap();
+    let series: Series = cl.get("foo").unwrap();
 
     assert_eq!(
-        resp,
-        RemainingAncestor {
-            remaining_ancestor: None
+        series,
+        Series {
+            id: "foo".to_string(),
+            status: "Unknown".to_string(),
+            is_stable: false,
+            last_update: NaiveDate::from_ymd_opt(2020, 8, 30)
+                .unwrap()
+                .and_hms_opt(19, 11, 9)
+                .unwrap(),
+            main_dicom_tags: hashmap! {"Modality".to_string() => "MR".to_string()},
+            parent_study: "bar".to_string(),
+            expected_number_of_instances: None,
+            instances: vec!["qux".to_string()],
+            entity: EntityKind::Series,
+            anonymized_from: None,
+            labels: vec![],
+            extra: HashMap::new(),
         }
     );
     assert_eq!(m.times_called(), 1);
 }
 
 #[test]
-fn test_delete_study() {
+fn test_list_generic() {
     let mock_server = MockServer::start();
     let url = mock_server.url("");
 
-    let m = Mock::new()
-        .expect_method(Method::DELETE)
-        .expect_path("/studies/foo")
+    let m1 = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/instances")
+        .return_status(200)
+        .return_body(r#"["foo", "bar"]"#)
+        .create_on(&mock_server);
+    let m2 = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/patients")
+        .expect_query_param_exists("expand")
         .return_status(200)
         .return_body(
             r#"
-                    {
-                        "RemainingAncestor": {
-                            "ID": "bar",
-                            "Path": "/patients/bar",
+                    [
+                        {
+                            "ID": "foo",
+                            "IsStable": true,
+                            "LastUpdate": "20200830T191109",
+                            "MainDicomTags": {},
+                            "Studies": [],
                             "Type": "Patient"
                         }
-                    }
+                    ]
                 "#,
         )
         .create_on(&mock_server);
 
     let cl = Client::new(url);
-    let resp = cl.delete_study("foo").unwrap();
-
-    assert_eq!(
-        resp,
-        RemainingAncestor {
-            remaining_ancestor: Some(Ancestor {
-                id: "bar".to_string(),
-                path: "/patients/bar".to_string(),
-                entity: EntityKind::Patient,
-            })
-        }
-    );
-    assert_eq!(m.times_called(), 1);
+    assert_eq!(cl.list::<Instance>().unwrap(), vec!["foo", "bar"]);
+    let patients = cl.list_expanded::<Patient>().unwrap();
+    assert_eq!(patients.len(), 1);
+    assert_eq!(patients[0].id, "foo");
+    assert_eq!(m1.times_called(), 1);
+    assert_eq!(m2.times_called(), 1);
 }
 
 #[test]
-fn test_delete_series() {
+fn test_delete_generic() {
     let mock_server = MockServer::start();
     let url = mock_server.url("");
 
     let m = Mock::new()
         .expect_method(Method::DELETE)
-        .expect_path("/series/foo")
+        .expect_path("/studies/foo")
         .return_status(200)
-        .return_body(
-            r#"
-                    {
-                        "RemainingAncestor": {
-                            "ID": "bar",
-                            "Path": "/studies/bar",
-                            "Type": "Study"
-                        }
-                    }
-                "#,
-        )
+        .return_body(r#"{"RemainingAncestor": null}"#)
         .create_on(&mock_server);
 
     let cl = Client::new(url);
-    let resp = cl.delete_series("foo").unwrap();
+    let resp = cl.delete::<Study>("foo").unwrap();
 
     assert_eq!(
         resp,
         RemainingAncestor {
-            remaining_ancestor: Some(Ancestor {
-                id: "bar".to_string(),
-                path: "/studies/bar".to_string(),
-                entity: EntityKind::Study,
-            })
+            remaining_ancestor: None
         }
     );
     assert_eq!(m.times_called(), 1);
 }
 
 #[test]
-fn test_delete_instance() {
+fn test_modify_generic() {
     let mock_server = MockServer::start();
     let url = mock_server.url("");
 
     let m = Mock::new()
-        .expect_method(Method::DELETE)
-        .expect_path("/instances/foo")
+        .expect_method(Method::POST)
+        .expect_path("/series/foo/modify")
+        .expect_json_body(&Modification {
+            replace: None,
+            remove: Some(vec!["PatientName".to_string()]),
+            force: None,
+            ..Default::default()
+        })
         .return_status(200)
         .return_body(
             r#"
                     {
-                        "RemainingAncestor": {
-                            "ID": "bar",
-                            "Path": "/series/bar",
-                            "Type": "Series"
-                        }
+                        "ID": "bar",
+                        "Path": "/series/bar",
+                        "PatientID": "baz",
+                        "Type": "Series"
                     }
                 "#,
         )
         .create_on(&mock_server);
 
     let cl = Client::new(url);
-    let resp = cl.delete_instance("foo").unwrap();
+    let resp = cl
+        .modify::<Series>(
+            "foo",
+            Modification {
+                replace: None,
+                remove: Some(vec!["PatientName".to_string()]),
+                force: None,
+                ..Default::default()
+            },
+        )
+        .unwrap();
 
     assert_eq!(
         resp,
-        RemainingAncestor {
-            remaining_ancestor: Some(Ancestor {
-                id: "bar".to_string(),
-                path: "/series/bar".to_string(),
-                entity: EntityKind::Series,
-            })
+        ModificationResult {
+            id: "bar".to_string(),
+            patient_id: "baz".to_string(),
+            path: "/series/bar".to_string(),
+            entity: EntityKind::Series,
         }
     );
     assert_eq!(m.times_called(), 1);
 }
 
 #[test]
-fn test_modality_echo() {
+fn test_anonymize_generic_instance() {
     let mock_server = MockServer::start();
     let url = mock_server.url("");
 
-    let m = Mock::new()
+    let m1 = Mock::new()
         .expect_method(Method::POST)
-        .expect_path("/modalities/foo/echo")
+        .expect_path("/instances/foo/anonymize")
         .return_status(200)
-        .return_body("{}")
+        .return_body("quux")
+        .create_on(&mock_server);
+    let m2 = Mock::new()
+        .expect_method(Method::POST)
+        .expect_path("/instances")
+        .expect_body("quux")
+        .return_status(200)
+        .return_body(UPLOAD_RESULT)
         .create_on(&mock_server);
 
     let cl = Client::new(url);
-    assert_eq!(cl.modality_echo("foo", None).unwrap(), ());
-    assert_eq!(cl.echo("foo", None).unwrap(), ());
-    assert_eq!(m.times_called(), 2);
+    let resp = cl.anonymize::<Instance>("foo", None).unwrap();
+
+    assert_eq!(
+        resp,
+        ModificationResult {
+            id: "foo".to_string(),
+            patient_id: "bar".to_string(),
+            path: "/instances/foo".to_string(),
+            entity: EntityKind::Instance,
+        }
+    );
+    assert_eq!(m1.times_called(), 1);
+    assert_eq!(m2.times_called(), 1);
 }
 
 #[test]
-fn test_echo_with_timeout() {
+fn test_download_generic() {
     let mock_server = MockServer::start();
     let url = mock_server.url("");
 
-    let m = Mock::new()
-        .expect_method(Method::POST)
-        .expect_path("/modalities/foo/echo")
-        .expect_json_body(&hashmap! {"Timeout" => 42})
+    let m1 = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/instances/foo/file")
         .return_status(200)
-        .return_body("{}")
+        .return_body("foo")
+        .create_on(&mock_server);
+    let m2 = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/patients/bar/archive")
+        .return_status(200)
+        .return_body("bar")
         .create_on(&mock_server);
 
     let cl = Client::new(url);
-    assert_eq!(cl.modality_echo("foo", Some(42)).unwrap(), ());
-    assert_eq!(cl.echo("foo", Some(42)).unwrap(), ());
-    assert_eq!(m.times_called(), 2);
+    let mut writer: Vec<u8> = vec![];
+    cl.download::<Instance, _>("foo", &mut writer).unwrap();
+    assert_eq!(&writer, b"foo");
+
+    let mut writer: Vec<u8> = vec![];
+    cl.download::<Patient, _>("bar", &mut writer).unwrap();
+    assert_eq!(&writer, b"bar");
+
+    assert_eq!(m1.times_called(), 1);
+    assert_eq!(m2.times_called(), 1);
 }
 
+const PATIENT_FOO: &str = r#"
+    {
+        "ID": "foo",
+        "IsStable": true,
+        "LastUpdate": "20200830T191109",
+        "MainDicomTags": {"PatientID": "c137"},
+        "Studies": ["bar"],
+        "Type": "Patient"
+    }
+"#;
+
+const STUDY_BAR: &str = r#"
+    {
+        "ID": "bar",
+        "IsStable": true,
+        "LastUpdate": "20200830T191109",
+        "MainDicomTags": {},
+        "ParentPatient": "foo",
+        "PatientMainDicomTags": {"PatientID": "c137"},
+        "Series": ["baz"],
+        "Type": "Study"
+    }
+"#;
+
+const SERIES_BAZ: &str = r#"
+    {
+        "ExpectedNumberOfInstances": null,
+        "ID": "baz",
+        "Instances": ["qux"],
+        "IsStable": true,
+        "LastUpdate": "20200830T191109",
+        "MainDicomTags": {},
+        "ParentStudy": "bar",
+        "Status": "Unknown",
+        "Type": "Series"
+    }
+"#;
+
+const INSTANCE_QUX: &str = r#"
+    {
+        "FileSize": 42,
+        "FileUuid": "d8c5eff3-986c-4fe4-b06e-7e52b2a4238e",
+        "ID": "qux",
+        "IndexInSeries": 1,
+        "MainDicomTags": {},
+        "ParentSeries": "baz",
+        "Type": "Instance"
+    }
+"#;
+
 #[test]
-fn test_echo_failed() {
+fn test_series_patient() {
     let mock_server = MockServer::start();
     let url = mock_server.url("");
 
     let m = Mock::new()
-        .expect_method(Method::POST)
-        .expect_path("/modalities/foo/echo")
-        .return_status(500)
+        .expect_method(Method::GET)
+        .expect_path("/series/baz/patient")
+        .return_status(200)
+        .return_body(PATIENT_FOO)
         .create_on(&mock_server);
 
     let cl = Client::new(url);
-    assert_eq!(
-        cl.modality_echo("foo", None).unwrap_err(),
-        Error {
-            message: "API error: 500 Internal Server Error".to_string(),
-            details: None
-        }
-    );
-    assert_eq!(
-        cl.echo("foo", None).unwrap_err(),
-        Error {
-            message: "API error: 500 Internal Server Error".to_string(),
-            details: None
-        }
-    );
-    assert_eq!(m.times_called(), 2);
+    let patient = cl.series_patient("baz").unwrap();
+
+    assert_eq!(patient.id, "foo");
+    assert_eq!(patient.studies, vec!["bar"]);
+    assert_eq!(m.times_called(), 1);
 }
 
 #[test]
-fn test_upload_dicom() {
+fn test_study_instances() {
     let mock_server = MockServer::start();
     let url = mock_server.url("");
 
     let m = Mock::new()
-        .expect_method(Method::POST)
-        .expect_path("/instances")
-        .expect_body("quux")
+        .expect_method(Method::GET)
+        .expect_path("/studies/bar/instances")
         .return_status(200)
-        .return_body(
-            r#"
-                    {
-                        "ID": "foo",
-                        "ParentPatient": "bar",
-                        "ParentSeries": "baz",
-                        "ParentStudy": "qux",
-                        "Path": "/instances/foo",
-                        "Status": "Success"
-                    }
-                "#,
-        )
+        .return_body(&format!("[{}]", INSTANCE_QUX))
         .create_on(&mock_server);
 
     let cl = Client::new(url);
-    let resp = cl.upload("quux".as_bytes()).unwrap();
+    let instances = cl.study_instances("bar").unwrap();
 
-    assert_eq!(
-        resp,
-        UploadResult {
-            id: "foo".to_string(),
-            status: "Success".to_string(),
-            path: "/instances/foo".to_string(),
-            parent_patient: "bar".to_string(),
-            parent_study: "qux".to_string(),
-            parent_series: "baz".to_string(),
-        }
-    );
+    assert_eq!(instances.len(), 1);
+    assert_eq!(instances[0].id, "qux");
+    assert_eq!(instances[0].file_size, 42);
     assert_eq!(m.times_called(), 1);
 }
 
-// The following 2 tests are exactly the same except one calls `create_modality`,
-// the other one calls `modify_modality`.
 #[test]
-fn test_create_modality() {
+fn test_resource_tree() {
     let mock_server = MockServer::start();
     let url = mock_server.url("");
 
-    let m = Mock::new()
-        .expect_method(Method::PUT)
-        .expect_path("/modalities/bazqux")
-        .expect_json_body(&Modality {
-            aet: "foobar".to_string(),
-            host: "localhost".to_string(),
-            port: 11113,
-            manufacturer: None,
-            allow_c_echo: None,
-            allow_c_find: None,
-            allow_c_get: None,
-            allow_c_move: None,
-            allow_c_store: None,
-            allow_n_action: None,
-            allow_n_event_report: None,
-            allow_transcoding: None,
-        })
+    let m1 = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/patients/foo")
         .return_status(200)
-        .return_body("")
+        .return_body(PATIENT_FOO)
+        .create_on(&mock_server);
+    let m2 = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/patients/foo/studies")
+        .return_status(200)
+        .return_body(&format!("[{}]", STUDY_BAR))
+        .create_on(&mock_server);
+    let m3 = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/patients/foo/series")
+        .return_status(200)
+        .return_body(&format!("[{}]", SERIES_BAZ))
+        .create_on(&mock_server);
+    let m4 = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/patients/foo/instances")
+        .return_status(200)
+        .return_body(&format!("[{}]", INSTANCE_QUX))
         .create_on(&mock_server);
 
     let cl = Client::new(url);
-    let modality = Modality {
-        aet: "foobar".to_string(),
-        host: "localhost".to_string(),
-        port: 11113,
-        manufacturer: None,
-        allow_c_echo: None,
-        allow_c_find: None,
-        allow_c_get: None,
-        allow_c_move: None,
-        allow_c_store: None,
-        allow_n_action: None,
-        allow_n_event_report: None,
-        allow_transcoding: None,
-    };
-    let resp = cl.create_modality("bazqux", modality).unwrap();
-
-    assert_eq!(resp, ());
-    assert_eq!(m.times_called(), 1);
+    let tree = cl.resource_tree("foo").unwrap();
+
+    assert_eq!(tree.patient.id, "foo");
+    assert_eq!(tree.studies.len(), 1);
+    assert_eq!(tree.studies[0].study.id, "bar");
+    assert_eq!(tree.studies[0].series.len(), 1);
+    assert_eq!(tree.studies[0].series[0].series.id, "baz");
+    let instances: Vec<&str> = tree.instances().map(|i| i.id.as_str()).collect();
+    assert_eq!(instances, vec!["qux"]);
+    assert_eq!(m1.times_called(), 1);
+    assert_eq!(m2.times_called(), 1);
+    assert_eq!(m3.times_called(), 1);
+    assert_eq!(m4.times_called(), 1);
 }
 
 #[test]
-fn test_modify_modality() {
+fn test_capabilities() {
     let mock_server = MockServer::start();
     let url = mock_server.url("");
-
-    let m = Mock::new()
-        .expect_method(Method::PUT)
-        .expect_path("/modalities/bazqux")
-        .expect_json_body(&Modality {
-            aet: "foobar".to_string(),
-            host: "localhost".to_string(),
-            port: 11113,
-            manufacturer: None,
-            allow_c_echo: None,
-            allow_c_find: None,
-            allow_c_get: None,
-            allow_c_move: None,
-            allow_c_store: None,
-            allow_n_action: None,
-            allow_n_event_report: None,
-            allow_transcoding: None,
-        })
-        .return_status(200)
-        .return_body("")
-        .create_on(&mock_server);
+    let system = mock_system(&mock_server, "1.9.1");
 
     let cl = Client::new(url);
-    let modality = Modality {
-        aet: "foobar".to_string(),
-        host: "localhost".to_string(),
-        port: 11113,
-        manufacturer: None,
-        allow_c_echo: None,
-        allow_c_find: None,
-        allow_c_get: None,
-        allow_c_move: None,
-        allow_c_store: None,
-        allow_n_action: None,
-        allow_n_event_report: None,
-        allow_transcoding: None,
-    };
-    let resp = cl.modify_modality("bazqux", modality).unwrap();
+    let capabilities = cl.capabilities().unwrap();
+    assert_eq!(
+        capabilities,
+        Capabilities {
+            version: Some(Version::new(1, 9, 1)),
+            api_version: 9,
+            has_labels: None,
+        }
+    );
+    assert!(capabilities.supports(Feature::Transcoding));
+    assert!(!capabilities.supports(Feature::BulkTools));
+    assert!(!capabilities.supports(Feature::Labels));
+    assert_eq!(cl.capabilities().unwrap(), capabilities);
 
-    assert_eq!(resp, ());
-    assert_eq!(m.times_called(), 1);
+    assert_eq!(system.times_called(), 1);
 }
 
 #[test]
-fn test_delete_modality() {
+fn test_capabilities_mainline() {
     let mock_server = MockServer::start();
     let url = mock_server.url("");
+    let system = mock_system(&mock_server, "mainline");
+
+    let cl = Client::new(url);
+    let capabilities = cl.capabilities().unwrap();
+    assert_eq!(capabilities.version, None);
+    assert!(capabilities.supports(Feature::Labels));
+
+    assert_eq!(system.times_called(), 1);
+}
 
+#[test]
+fn test_capabilities_refresh() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+    let mut system = mock_system(&mock_server, "1.9.1");
     let m = Mock::new()
-        .expect_method(Method::DELETE)
-        .expect_path("/modalities/bazqux")
+        .expect_method(Method::GET)
+        .expect_path("/tools/labels")
         .return_status(200)
-        .return_body("")
+        .return_body(r#"["foo"]"#)
         .create_on(&mock_server);
 
     let cl = Client::new(url);
-    let resp = cl.delete_modality("bazqux").unwrap();
+    assert!(cl.all_labels().unwrap_err().unsupported().is_some());
+    assert_eq!(system.times_called(), 1);
+    system.delete();
+
+    // The server has been upgraded, but the capabilities are cached
+    let system = mock_system(&mock_server, "1.12.0");
+    assert!(cl.all_labels().unwrap_err().unsupported().is_some());
+    assert_eq!(system.times_called(), 0);
+
+    let capabilities = cl.refresh_capabilities().unwrap();
+    assert_eq!(capabilities.version, Some(Version::new(1, 12, 0)));
+    assert_eq!(cl.all_labels().unwrap(), vec!["foo"]);
 
-    assert_eq!(resp, ());
+    assert_eq!(system.times_called(), 1);
     assert_eq!(m.times_called(), 1);
 }
 
-// The following 2 tests are exactly the same except one calls `create_peer`,
-// the other one calls `modify_peer`.
 #[test]
-fn test_create_peer() {
+fn test_capabilities_unknown() {
     let mock_server = MockServer::start();
     let url = mock_server.url("");
-
+    let system = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/system")
+        .return_status(403)
+        .create_on(&mock_server);
     let m = Mock::new()
-        .expect_method(Method::PUT)
-        .expect_path("/peers/bazqux")
-        .expect_json_body(
-            &Peer {
-                url: "http://orthanc_peer:8029".to_string(),
-                username: Some("orthanc".to_string()),
-                password: Some("orthanc".to_string()),
-                http_headers: Some(
-                    hashmap! {"Foo".to_string() => "foo".to_string(), "Bar".to_string() => "bar".to_string()},
-                ),
-                certificate_file: None,
-                certificate_key_file: None,
-                certificate_key_password: None,
-        })
+        .expect_method(Method::GET)
+        .expect_path("/tools/labels")
         .return_status(200)
-        .return_body("")
+        .return_body(r#"["foo"]"#)
         .create_on(&mock_server);
 
     let cl = Client::new(url);
-    let peer = Peer {
-        url: "http://orthanc_peer:8029".to_string(),
-        username: Some("orthanc".to_string()),
-        password: Some("orthanc".to_string()),
-        http_headers: Some(
-            hashmap! {"Foo".to_string() => "foo".to_string(), "Bar".to_string() => "bar".to_string()},
-        ),
-        certificate_file: None,
-        certificate_key_file: None,
-        certificate_key_password: None,
-    };
-    let resp = cl.create_peer("bazqux", peer).unwrap();
+    assert!(cl.capabilities().is_err());
+    // The request is sent anyway, and the system information is requested again next time
+    assert_eq!(cl.all_labels().unwrap(), vec!["foo"]);
+    assert_eq!(cl.all_labels().unwrap(), vec!["foo"]);
 
-    assert_eq!(resp, ());
-    assert_eq!(m.times_called(), 1);
+    assert_eq!(system.times_called(), 3);
+    assert_eq!(m.times_called(), 2);
 }
 
 #[test]
-fn test_modify_peer() {
+fn test_unsupported_by_server() {
     let mock_server = MockServer::start();
     let url = mock_server.url("");
-
+    let system = mock_system(&mock_server, "1.9.1");
     let m = Mock::new()
-        .expect_method(Method::PUT)
-        .expect_path("/peers/bazqux")
-        .expect_json_body(
-            &Peer {
-                url: "http://orthanc_peer:8029".to_string(),
-                username: Some("orthanc".to_string()),
-                password: Some("orthanc".to_string()),
-                http_headers: Some(
-                    hashmap! {"Foo".to_string() => "foo".to_string(), "Bar".to_string() => "bar".to_string()},
-                ),
-                certificate_file: None,
-                certificate_key_file: None,
-                certificate_key_password: None,
+        .expect_method(Method::GET)
+        .expect_path("/tools/labels")
+        .return_status(404)
+        .create_on(&mock_server);
+
+    let cl = Client::new(url);
+    let err = cl.all_labels().unwrap_err();
+    assert_eq!(
+        err,
+        Error {
+            message:
+                "Labels requires Orthanc 1.12.0 or newer, the server runs Orthanc 1.9.1"
+                    .to_string(),
+            details: None,
+            kind: Some(ErrorKind::UnsupportedByServer(UnsupportedByServer {
+                feature: Feature::Labels,
+                required: Version::new(1, 12, 0),
+                actual: Version::new(1, 9, 1),
+            })),
+        }
+    );
+    assert_eq!(
+        err.unsupported(),
+        Some(&UnsupportedByServer {
+            feature: Feature::Labels,
+            required: Version::new(1, 12, 0),
+            actual: Version::new(1, 9, 1),
         })
+    );
+    let err = cl.bulk_delete(&["foo"]).unwrap_err();
+    assert_eq!(err.unsupported().unwrap().feature, Feature::BulkTools);
+
+    assert_eq!(system.times_called(), 1);
+    assert_eq!(m.times_called(), 0);
+}
+
+#[test]
+fn test_transcoding_unsupported_by_server() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+    let system = mock_system(&mock_server, "1.6.1");
+    let m1 = Mock::new()
+        .expect_method(Method::POST)
+        .expect_path("/studies/foo/modify")
         .return_status(200)
-        .return_body("")
+        .return_body(
+            r#"
+                {
+                    "ID": "bar",
+                    "Path": "/studies/bar",
+                    "PatientID": "baz",
+                    "Type": "Study"
+                }
+            "#,
+        )
         .create_on(&mock_server);
 
     let cl = Client::new(url);
-    let peer = Peer {
-        url: "http://orthanc_peer:8029".to_string(),
-        username: Some("orthanc".to_string()),
-        password: Some("orthanc".to_string()),
-        http_headers: Some(
-            hashmap! {"Foo".to_string() => "foo".to_string(), "Bar".to_string() => "bar".to_string()},
-        ),
-        certificate_file: None,
-        certificate_key_file: None,
-        certificate_key_password: None,
-    };
-    let resp = cl.modify_peer("bazqux", peer).unwrap();
+    let err = cl
+        .modify_study(
+            "foo",
+            Modification::default().transcode("1.2.840.10008.1.2.1"),
+        )
+        .unwrap_err();
+    assert_eq!(
+        err.unsupported(),
+        Some(&UnsupportedByServer {
+            feature: Feature::Transcoding,
+            required: Version::new(1, 7, 0),
+            actual: Version::new(1, 6, 1),
+        })
+    );
+    // Requests, that do not transcode, are not checked
+    cl.modify_study("foo", Modification::default().remove("PatientName"))
+        .unwrap();
 
-    assert_eq!(resp, ());
-    assert_eq!(m.times_called(), 1);
+    assert_eq!(system.times_called(), 1);
+    assert_eq!(m1.times_called(), 1);
 }
 
 #[test]
-fn test_delete_peer() {
+fn test_generate_uid() {
     let mock_server = MockServer::start();
     let url = mock_server.url("");
 
     let m = Mock::new()
-        .expect_method(Method::DELETE)
-        .expect_path("/peers/bazqux")
+        .expect_method(Method::GET)
+        .expect_path("/tools/generate-uid")
+        .expect_query_param("level", "series")
         .return_status(200)
-        .return_body("")
+        .return_body("1.2.276.0.7230010.3.1.3.1234\n")
         .create_on(&mock_server);
 
     let cl = Client::new(url);
-    let resp = cl.delete_peer("bazqux").unwrap();
+    let uid = cl.generate_uid(EntityKind::Series).unwrap();
 
-    assert_eq!(resp, ());
+    assert_eq!(uid, "1.2.276.0.7230010.3.1.3.1234");
     assert_eq!(m.times_called(), 1);
 }
 
 #[test]
-fn test_get_dicom_tag_value_patient() {
-    let patient = Patient {
-        id: "f88cbd3f-a00dfc59-9ca1ac2d-7ce9851a-40e5b493".to_string(),
-        is_stable: true,
-        last_update: NaiveDate::from_ymd(2020, 1, 1).and_hms(15, 46, 17),
-        main_dicom_tags: hashmap! {
-            "OtherPatientIDs".to_string() => "".to_string(),
-            "PatientBirthDate".to_string() => "19670101".to_string(),
-            "PatientID".to_string() => "123456789".to_string(),
-            "PatientName".to_string() => "Rick Sanchez".to_string(),
-            "PatientSex".to_string() => "M".to_string()
-        },
-        studies: ["e8cafcbe-caf08c39-6e205f15-18554bb8-b3f9ef04".to_string()].to_vec(),
-        entity: EntityKind::Patient,
-        anonymized_from: None,
-    };
-    assert_eq!(patient.main_dicom_tag("PatientID"), Some("123456789"));
-    assert_eq!(patient.main_dicom_tag("FooBar"), None);
+fn test_pseudonymizer_anonymize() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+
+    let m1 = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/studies/bar")
+        .return_status(200)
+        .return_body(
+            r#"
+                {
+                    "ID": "bar",
+                    "IsStable": true,
+                    "LastUpdate": "20200830T191109",
+                    "MainDicomTags": {"StudyInstanceUID": "1.2.3"},
+                    "ParentPatient": "foo",
+                    "PatientMainDicomTags": {"PatientID": "c137"},
+                    "Series": ["baz"],
+                    "Type": "Study"
+                }
+            "#,
+        )
+        .create_on(&mock_server);
+    let m2 = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/tools/generate-uid")
+        .expect_query_param("level", "patient")
+        .return_status(200)
+        .return_body("b6a4e6c1-5e6c6d1f-0b8ef4ec-3b3e1e85-c5a7b1e2")
+        .create_on(&mock_server);
+    let m3 = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/tools/generate-uid")
+        .expect_query_param("level", "study")
+        .return_status(200)
+        .return_body("1.2.276.0.7230010.3.1.2.42")
+        .create_on(&mock_server);
+    let m4 = Mock::new()
+        .expect_method(Method::POST)
+        .expect_path("/studies/bar/anonymize")
+        .expect_json_body(
+            &Anonymization::default()
+                .keep("StudyDescription")
+                .force(true)
+                .replace("StudyInstanceUID", "1.2.276.0.7230010.3.1.2.42")
+                .replace("PatientID", "b6a4e6c1-5e6c6d1f-0b8ef4ec-3b3e1e85-c5a7b1e2"),
+        )
+        .return_status(200)
+        .return_body(
+            r#"
+                {
+                    "ID": "86a3054b-32bb888a-e5f42e28-4b2e82d2-b1d7e14c",
+                    "Path": "/studies/86a3054b-32bb888a-e5f42e28-4b2e82d2-b1d7e14c",
+                    "PatientID": "ed3fa7ba-9e1b7bd8-1cdb2b8b-f0b1d8e8-0c1ab1c3",
+                    "Type": "Study"
+                }
+            "#,
+        )
+        .create_on(&mock_server);
+
+    let cl = Client::new(url);
+    let mut pseudonymizer = Pseudonymizer::new(&cl, MemoryStore::new());
+    for _ in 0..2 {
+        let anonymization = Anonymization::default().keep("StudyDescription");
+        let resp = pseudonymizer
+            .anonymize::<Study>("bar", Some(anonymization))
+            .unwrap();
+        assert_eq!(resp.id, "86a3054b-32bb888a-e5f42e28-4b2e82d2-b1d7e14c");
+    }
+
+    let mut exported = vec![];
+    pseudonymizer.export_mapping(&mut exported).unwrap();
+    let exported: Value = serde_json::from_slice(&exported).unwrap();
+    assert_eq!(
+        exported,
+        serde_json::json!({
+            "PatientID": {"c137": "b6a4e6c1-5e6c6d1f-0b8ef4ec-3b3e1e85-c5a7b1e2"},
+            "StudyInstanceUID": {"1.2.3": "1.2.276.0.7230010.3.1.2.42"}
+        })
+    );
+    assert_eq!(m1.times_called(), 2);
+    assert_eq!(m2.times_called(), 1);
+    assert_eq!(m3.times_called(), 1);
+    assert_eq!(m4.times_called(), 2);
 }
 
-#[test]
-fn test_get_dicom_tag_value_study() {
-    let study = Study {
-        id: "63bf5d42-b5382159-01971752-e0ceea3d-399bbca5".to_string(),
-        is_stable: true,
-        last_update: NaiveDate::from_ymd(2020, 8, 30).and_hms(19, 11, 09),
-        main_dicom_tags: hashmap! {
-            "AccessionNumber".to_string() => "foobar".to_string(),
-            "StudyDate".to_string() => "20110101".to_string(),
-            "StudyDescription".to_string() => "Brain".to_string(),
-            "StudyID".to_string() => "1742".to_string(),
-            "StudyInstanceUID".to_string() => "1.2.3.4.5.6789".to_string(),
-            "StudyTime".to_string() => "084707".to_string()
-        },
-        parent_patient: "7e43f8d3-e50280e6-470079e9-02241af1-d286bdbe".to_string(),
-        patient_main_dicom_tags: hashmap! {
-            "PatientBirthDate".to_string() => "19440101".to_string(),
-            "PatientID".to_string() => "c137".to_string(),
-            "PatientName".to_string() => "Rick Sanchez".to_string(),
-            "PatientSex".to_string() => "M".to_string(),
-        },
-        series: [
-            "cd00fffc-db25be29-0c6da430-c56796a5-ba06933c".to_string(),
-            "2ab7dbe7-f1a18a78-86145443-18a8ff93-0b65f2b2".to_string(),
+const BULK_MODIFICATION_OUTPUT: &str = r#"
+    {
+        "Description": "REST API",
+        "FailedInstancesCount": 0,
+        "InstancesCount": 3,
+        "IsAnonymization": false,
+        "Resources": [
+            {"ID": "bar-modified", "Path": "/studies/bar-modified", "Type": "Study"},
+            {"ID": "qux-modified", "Path": "/instances/qux-modified", "Type": "Instance"},
+            {"ID": "baz-modified", "Path": "/series/baz-modified", "Type": "Series"}
         ]
-        .to_vec(),
-        entity: EntityKind::Study,
-        anonymized_from: None,
-    };
-    assert_eq!(study.main_dicom_tag("StudyID"), Some("1742"));
-    assert_eq!(study.main_dicom_tag("PatientID"), Some("c137"));
-    assert_eq!(study.main_dicom_tag("FooBar"), None);
-}
+    }
+"#;
 
 #[test]
-fn test_get_dicom_tag_value_series() {
-    let series = Series {
-        id: "cd00fffc-db25be29-0c6da430-c56796a5-ba06933c".to_string(),
-        status: "Unknown".to_string(),
-        is_stable: true,
-        last_update: NaiveDate::from_ymd(2020, 8, 30).and_hms(19, 11, 09),
-        main_dicom_tags: hashmap! {
-            "BodyPartExamined".to_string() => "ABDOMEN".to_string(),
-            "Modality".to_string() => "MR".to_string(),
-            "ProtocolName".to_string() => "TCP".to_string(),
-            "SeriesDate".to_string() => "20110101".to_string(),
-            "SeriesInstanceUID".to_string() => "1.2.3.4.5.6789".to_string(),
-            "SeriesNumber".to_string() => "1101".to_string(),
-            "SeriesTime".to_string() => "091313.93".to_string(),
+fn test_bulk_modify() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+    let system = mock_system(&mock_server, "1.12.0");
 
-        },
-        parent_study: "63bf5d42-b5382159-01971752-e0ceea3d-399bbca5".to_string(),
-        expected_number_of_instances: Some(17),
-        instances: [
-            "556530b5-de7c487b-110b9d0e-12cfdbb9-f06b546e".to_string(),
-            "c46605db-836489fa-cb55fbbc-13c8a913-b0bad6ac".to_string(),
-            "9b63498d-cae4f25e-f52206b2-cbb4dc0e-dc55c788".to_string(),
-        ]
-        .to_vec(),
-        entity: EntityKind::Series,
-        anonymized_from: None,
-    };
-    assert_eq!(series.main_dicom_tag("SeriesNumber"), Some("1101"));
-    assert_eq!(series.main_dicom_tag("FooBar"), None);
+    let m1 = Mock::new()
+        .expect_method(Method::POST)
+        .expect_path("/tools/bulk-modify")
+        .expect_json_body(&serde_json::json!({
+            "Replace": {"InstitutionName": "Foo"},
+            "Resources": ["bar", "baz"],
+            "Asynchronous": false
+        }))
+        .return_status(200)
+        .return_body(BULK_MODIFICATION_OUTPUT)
+        .create_on(&mock_server);
+    let m2 = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/studies/bar-modified")
+        .return_status(200)
+        .return_body(r#"{"ID": "bar-modified", "ModifiedFrom": "bar"}"#)
+        .create_on(&mock_server);
+    let m3 = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/series/baz-modified")
+        .return_status(200)
+        .return_body(r#"{"ID": "baz-modified", "ModifiedFrom": "baz"}"#)
+        .create_on(&mock_server);
+    let m4 = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/instances/qux-modified")
+        .return_status(200)
+        .return_body(r#"{"ID": "qux-modified", "ModifiedFrom": "qux"}"#)
+        .create_on(&mock_server);
+
+    let cl = Client::new(url);
+    let result = cl
+        .bulk_modify(
+            &["bar", "baz"],
+            Modification::default().replace("InstitutionName", "Foo"),
+        )
+        .unwrap();
+
+    assert_eq!(
+        result,
+        BulkModificationResult {
+            instances_count: 3,
+            failed_instances_count: 0,
+            resources: hashmap! {
+                "bar".to_string() => ModifiedResource {
+                    id: "bar-modified".to_string(),
+                    path: "/studies/bar-modified".to_string(),
+                    entity: EntityKind::Study,
+                },
+                "baz".to_string() => ModifiedResource {
+                    id: "baz-modified".to_string(),
+                    path: "/series/baz-modified".to_string(),
+                    entity: EntityKind::Series,
+                },
+            },
+        }
+    );
+    assert_eq!(m1.times_called(), 1);
+    assert_eq!(m2.times_called(), 1);
+    assert_eq!(m3.times_called(), 1);
+    // All the requested resources are found before the instances are looked up
+    assert_eq!(m4.times_called(), 0);
+    assert_eq!(system.times_called(), 1);
 }
 
 #[test]
-fn test_get_dicom_tag_value_instance() {
-    let instance = Instance {
-        id: "29fa4d9d-51a69d1d-70e2b29a-fd824316-50850d0c".to_string(),
-        main_dicom_tags: hashmap! {
-            "ImageOrientationPatient".to_string() => "1\\0\\0\\0\\1\\0".to_string(),
-            "ImagePositionPatient".to_string() => "-17\\42\\13".to_string(),
-            "InstanceCreationDate".to_string() => "20130326".to_string(),
-            "InstanceCreationTime".to_string() => "135901".to_string(),
-            "InstanceNumber".to_string() => "13".to_string(),
-            "SOPInstanceUID".to_string() => "1.2.3.4.5.6789".to_string(),
-        },
-        parent_series: "82081568-b6f8f4e6-ced76876-6504da25-ed0dfe03".to_string(),
-        index_in_series: Some(13),
-        file_uuid: "d8c5eff3-986c-4fe4-b06e-7e52b2a4238e".to_string(),
-        file_size: 139402,
-        modified_from: Some("22c54cb6-28302a69-3ff454a3-676b98f4-b84cd80a".to_string()),
-        entity: EntityKind::Instance,
-        anonymized_from: None,
-    };
-    assert_eq!(instance.main_dicom_tag("InstanceNumber"), Some("13"));
-    assert_eq!(instance.main_dicom_tag("FooBar"), None);
+fn test_bulk_anonymize_unknown_origin() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+    let system = mock_system(&mock_server, "1.12.0");
+
+    let m1 = Mock::new()
+        .expect_method(Method::POST)
+        .expect_path("/tools/bulk-anonymize")
+        .expect_json_body(&serde_json::json!({
+            "Resources": ["bar", "baz"],
+            "Asynchronous": false
+        }))
+        .return_status(200)
+        .return_body(BULK_MODIFICATION_OUTPUT)
+        .create_on(&mock_server);
+    let m2 = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/studies/bar-modified")
+        .return_status(200)
+        .return_body(r#"{"ID": "bar-modified"}"#)
+        .create_on(&mock_server);
+    let m3 = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/series/baz-modified")
+        .return_status(200)
+        .return_body(r#"{"ID": "baz-modified", "AnonymizedFrom": "baz"}"#)
+        .create_on(&mock_server);
+    let m4 = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/instances/qux-modified")
+        .return_status(200)
+        .return_body(r#"{"ID": "qux-modified", "AnonymizedFrom": "qux"}"#)
+        .create_on(&mock_server);
+
+    let cl = Client::new(url);
+    let result = cl.bulk_anonymize(&["bar", "baz"], None).unwrap();
+
+    // The resource of unknown origin is skipped, all the others are looked up
+    assert_eq!(result.instances_count, 3);
+    assert_eq!(result.resources.len(), 1);
+    assert_eq!(result.resources["baz"].id, "baz-modified");
+    assert_eq!(m1.times_called(), 1);
+    assert_eq!(m2.times_called(), 1);
+    assert_eq!(m3.times_called(), 1);
+    assert_eq!(m4.times_called(), 1);
+    assert_eq!(system.times_called(), 1);
 }
 
 #[test]
-fn test_search_patient_level() {
+fn test_bulk_anonymize_async() {
     let mock_server = MockServer::start();
     let url = mock_server.url("");
+    let system = mock_system(&mock_server, "1.12.0");
 
-    let m = Mock::new()
+    let m1 = Mock::new()
         .expect_method(Method::POST)
-        .expect_path("/tools/find")
-        .expect_json_body(&Search {
-            level: EntityKind::Patient,
-            query: hashmap! {"PatientID".to_string() => "foobar".to_string()},
-            expand: Some(true),
-        })
+        .expect_path("/tools/bulk-anonymize")
+        .expect_json_body(&serde_json::json!({
+            "DicomVersion": "2017c",
+            "Resources": ["bar"],
+            "Asynchronous": true
+        }))
         .return_status(200)
-        .return_header("Content-Type", "application/json")
-        .return_body(
+        .return_body(r#"{"ID": "foo", "Path": "/jobs/foo"}"#)
+        .create_on(&mock_server);
+    let m2 = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/jobs/foo")
+        .return_status(200)
+        .return_body(&format!(
             r#"
-                    [
-                        {
-                            "ID": "f88cbd3f-a00dfc59-9ca1ac2d-7ce9851a-40e5b493",
-                            "IsStable": true,
-                            "LastUpdate": "20200101T154617",
-                            "MainDicomTags": {
-                                "OtherPatientIDs": "",
-                                "PatientBirthDate": "19670101",
-                                "PatientID": "123456789",
-                                "PatientName": "Rick Sanchez",
-                                "PatientSex": "M"
-                            },
-                            "Studies": [
-                                "e8cafcbe-caf08c39-6e205f15-18554bb8-b3f9ef04"
-                            ],
-                            "Type": "Patient"
-                        },
+                {{
+                    "Content": {},
+                    "CreationTime": "20210310T143217.398000",
+                    "ErrorCode": 0,
+                    "ErrorDescription": "Success",
+                    "ID": "foo",
+                    "Priority": 0,
+                    "Progress": 100,
+                    "State": "Success",
+                    "Timestamp": "20210310T143218.106722",
+                    "Type": "ResourceModification"
+                }}
+            "#,
+            BULK_MODIFICATION_OUTPUT.replace(
+                r#""IsAnonymization": false,"#,
+                r#""IsAnonymization": false, "ParentResources": ["bar"],"#
+            )
+        ))
+        .create_on(&mock_server);
+    let m3 = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/studies/bar-modified")
+        .return_status(200)
+        .return_body(r#"{"ID": "bar-modified", "AnonymizedFrom": "bar"}"#)
+        .create_on(&mock_server);
+    let m4 = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/series/baz-modified")
+        .return_status(200)
+        .return_body(r#"{"ID": "baz-modified", "AnonymizedFrom": "baz"}"#)
+        .create_on(&mock_server);
+
+    let cl = Client::new(url);
+    let job = cl
+        .bulk_anonymize_async(&["bar"], Some(Anonymization::basic_profile()))
+        .unwrap();
+    assert_eq!(
+        job,
+        CreatedJob {
+            id: "foo".to_string(),
+            path: "/jobs/foo".to_string(),
+        }
+    );
+    let job = cl.job(&job.id).unwrap();
+    let result = cl.bulk_modification_result(&job).unwrap();
+
+    assert_eq!(result.instances_count, 3);
+    assert_eq!(result.resources.len(), 1);
+    assert_eq!(result.resources["bar"].id, "bar-modified");
+    assert_eq!(m1.times_called(), 1);
+    assert_eq!(m2.times_called(), 1);
+    assert_eq!(m3.times_called(), 1);
+    assert_eq!(m4.times_called(), 0);
+    assert_eq!(system.times_called(), 1);
+}
+
+const UNKNOWN_RESOURCE: &str = r#"
+    {
+        "HttpError": "Not Found",
+        "HttpStatus": 404,
+        "Message": "Unknown resource",
+        "Method": "GET",
+        "OrthancError": "Unknown resource",
+        "OrthancStatus": 17,
+        "Uri": "/foo"
+    }
+"#;
+
+#[test]
+fn test_delete_with_options_dry_run() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+
+    let m1 = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/series/baz")
+        .return_status(200)
+        .return_body(SERIES_BAZ)
+        .create_on(&mock_server);
+    let m2 = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/studies/bar")
+        .return_status(200)
+        .return_body(STUDY_BAR)
+        .create_on(&mock_server);
+    let m3 = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/patients/foo")
+        .return_status(200)
+        .return_body(PATIENT_FOO)
+        .create_on(&mock_server);
+    let m4 = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/series/baz/instances")
+        .return_status(200)
+        .return_body(&format!("[{}]", INSTANCE_QUX))
+        .create_on(&mock_server);
+    let m5 = Mock::new()
+        .expect_method(Method::DELETE)
+        .expect_path("/series/baz")
+        .return_status(200)
+        .return_body(r#"{"RemainingAncestor": null}"#)
+        .create_on(&mock_server);
+
+    let cl = Client::new(url);
+    let options = DeleteOptions {
+        dry_run: true,
+        require_stable: true,
+    };
+    let report = cl.delete_with_options::<Series>("baz", &options).unwrap();
+
+    // The only series of the only study of the patient
+    assert_eq!(
+        report,
+        DeletionReport {
+            instances: vec!["qux".to_string()],
+            size: 42,
+            remaining_ancestors: hashmap! {
+                "baz".to_string() => RemainingAncestor {
+                    remaining_ancestor: None
+                }
+            },
+        }
+    );
+    assert_eq!(m1.times_called(), 1);
+    assert_eq!(m2.times_called(), 1);
+    assert_eq!(m3.times_called(), 1);
+    assert_eq!(m4.times_called(), 1);
+    assert_eq!(m5.times_called(), 0);
+}
+
+#[test]
+fn test_delete_with_options_unstable() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+
+    let m1 = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/instances/qux")
+        .return_status(200)
+        .return_body(INSTANCE_QUX)
+        .create_on(&mock_server);
+    let m2 = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/series/baz")
+        .return_status(200)
+        .return_body(&SERIES_BAZ.replace(r#""IsStable": true"#, r#""IsStable": false"#))
+        .create_on(&mock_server);
+    let m3 = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/studies/bar")
+        .return_status(200)
+        .return_body(STUDY_BAR)
+        .create_on(&mock_server);
+    let m4 = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/patients/foo")
+        .return_status(200)
+        .return_body(PATIENT_FOO)
+        .create_on(&mock_server);
+    let m5 = Mock::new()
+        .expect_method(Method::DELETE)
+        .expect_path("/instances/qux")
+        .return_status(200)
+        .return_body(r#"{"RemainingAncestor": null}"#)
+        .create_on(&mock_server);
+
+    let cl = Client::new(url);
+    let options = DeleteOptions {
+        dry_run: false,
+        require_stable: true,
+    };
+    let resp = cl.delete_with_options::<Instance>("qux", &options);
+
+    assert_eq!(
+        resp.unwrap_err(),
+        Error {
+            message: "Refusing to delete unstable Instance qux".to_string(),
+            details: None,
+            kind: None,
+        }
+    );
+    assert_eq!(m1.times_called(), 1);
+    assert_eq!(m2.times_called(), 1);
+    assert_eq!(m3.times_called(), 1);
+    assert_eq!(m4.times_called(), 1);
+    assert_eq!(m5.times_called(), 0);
+}
+
+#[test]
+fn test_bulk_delete() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+    let system = mock_system(&mock_server, "1.12.0");
+
+    let m = Mock::new()
+        .expect_method(Method::POST)
+        .expect_path("/tools/bulk-delete")
+        .expect_json_body(&serde_json::json!({"Resources": ["foo", "bar"]}))
+        .return_status(200)
+        .return_body("{}")
+        .create_on(&mock_server);
+
+    let cl = Client::new(url);
+    cl.bulk_delete(&["foo", "bar"]).unwrap();
+
+    assert_eq!(m.times_called(), 1);
+    assert_eq!(system.times_called(), 1);
+}
+
+#[test]
+fn test_bulk_delete_with_options() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+
+    let m1 = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/instances/qux")
+        .return_status(200)
+        .return_body(INSTANCE_QUX)
+        .create_on(&mock_server);
+    let m2 = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/series/baz")
+        .return_status(200)
+        .return_body(&SERIES_BAZ.replace(r#"["qux"]"#, r#"["qux", "quux"]"#))
+        .create_on(&mock_server);
+    let m3 = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/studies/bar")
+        .return_status(200)
+        .return_body(STUDY_BAR)
+        .create_on(&mock_server);
+    let m4 = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/patients/foo")
+        .return_status(200)
+        .return_body(PATIENT_FOO)
+        .create_on(&mock_server);
+    let m5 = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/studies/bar/instances")
+        .return_status(200)
+        .return_body(&format!(
+            "[{}, {}]",
+            INSTANCE_QUX,
+            INSTANCE_QUX.replace(r#""qux""#, r#""quux""#)
+        ))
+        .create_on(&mock_server);
+    let m6 = Mock::new()
+        .expect_method(Method::DELETE)
+        .expect_path("/studies/bar")
+        .return_status(200)
+        .return_body(r#"{"RemainingAncestor": null}"#)
+        .create_on(&mock_server);
+    let m7 = Mock::new()
+        .expect_method(Method::DELETE)
+        .expect_path("/instances/qux")
+        .return_status(200)
+        .return_body(r#"{"RemainingAncestor": null}"#)
+        .create_on(&mock_server);
+
+    let cl = Client::new(url);
+    let report = cl
+        .bulk_delete_with_options(
+            &[(EntityKind::Instance, "qux"), (EntityKind::Study, "bar")],
+            &DeleteOptions::default(),
+        )
+        .unwrap();
+
+    // The instance is deleted along with its study
+    assert_eq!(
+        report,
+        DeletionReport {
+            instances: vec!["qux".to_string(), "quux".to_string()],
+            size: 84,
+            remaining_ancestors: hashmap! {
+                "qux".to_string() => RemainingAncestor {
+                    remaining_ancestor: None
+                },
+                "bar".to_string() => RemainingAncestor {
+                    remaining_ancestor: None
+                },
+            },
+        }
+    );
+    assert_eq!(m1.times_called(), 1);
+    assert_eq!(m2.times_called(), 1);
+    assert_eq!(m3.times_called(), 1);
+    assert_eq!(m4.times_called(), 1);
+    assert_eq!(m5.times_called(), 1);
+    assert_eq!(m6.times_called(), 1);
+    assert_eq!(m7.times_called(), 0);
+}
+
+#[test]
+fn test_bulk_delete_with_options_remaining_ancestor() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+
+    let m1 = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/instances/qux")
+        .return_status(200)
+        .return_body(INSTANCE_QUX)
+        .create_on(&mock_server);
+    let m2 = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/series/baz")
+        .return_status(200)
+        .return_body(&SERIES_BAZ.replace(r#"["qux"]"#, r#"["qux", "quux"]"#))
+        .create_on(&mock_server);
+    let m3 = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/studies/bar")
+        .return_status(200)
+        .return_body(STUDY_BAR)
+        .create_on(&mock_server);
+    let m4 = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/patients/foo")
+        .return_status(200)
+        .return_body(PATIENT_FOO)
+        .create_on(&mock_server);
+    let m5 = Mock::new()
+        .expect_method(Method::DELETE)
+        .expect_path("/instances/qux")
+        .return_status(200)
+        .return_body(
+            r#"
+            {
+                "RemainingAncestor": {
+                    "ID": "baz",
+                    "Path": "/series/baz",
+                    "Type": "Series"
+                }
+            }
+        "#,
+        )
+        .create_on(&mock_server);
+
+    let cl = Client::new(url);
+    let entities = &[(EntityKind::Instance, "qux")];
+    let options = DeleteOptions {
+        dry_run: true,
+        require_stable: false,
+    };
+    let planned = cl.bulk_delete_with_options(entities, &options).unwrap();
+    let report = cl
+        .bulk_delete_with_options(entities, &DeleteOptions::default())
+        .unwrap();
+
+    // The series keeps its other instance
+    let expected = DeletionReport {
+        instances: vec!["qux".to_string()],
+        size: 42,
+        remaining_ancestors: hashmap! {
+            "qux".to_string() => RemainingAncestor {
+                remaining_ancestor: Some(Ancestor {
+                    id: "baz".to_string(),
+                    path: "/series/baz".to_string(),
+                    entity: EntityKind::Series,
+                })
+            }
+        },
+    };
+    assert_eq!(planned, expected);
+    assert_eq!(report, expected);
+    assert_eq!(m1.times_called(), 2);
+    assert_eq!(m2.times_called(), 2);
+    assert_eq!(m3.times_called(), 2);
+    assert_eq!(m4.times_called(), 2);
+    assert_eq!(m5.times_called(), 1);
+}
+
+#[test]
+fn test_bulk_delete_with_options_unknown_entity() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+
+    let m = Mock::new()
+        .expect_method(Method::GET)
+        .return_status(404)
+        .return_body(UNKNOWN_RESOURCE)
+        .create_on(&mock_server);
+
+    let cl = Client::new(url);
+    let resp = cl
+        .bulk_delete_with_options(&[(EntityKind::Study, "foo")], &DeleteOptions::default());
+
+    assert_eq!(
+        resp.unwrap_err(),
+        Error {
+            message: "API error: 404 Not Found".to_string(),
+            details: Some(serde_json::from_str(UNKNOWN_RESOURCE).unwrap()),
+            kind: None,
+        }
+    );
+    assert_eq!(m.times_called(), 1);
+}
+
+#[test]
+fn test_delete_patient() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+
+    let m = Mock::new()
+        .expect_method(Method::DELETE)
+        .expect_path("/patients/foo")
+        .return_status(200)
+        .return_body(r#"{"RemainingAncestor": null}"#)
+        .create_on(&mock_server);
+
+    let cl = Client::new(url);
+    let resp = cl.delete_patient("foo").unwrap();
+
+    assert_eq!(
+        resp,
+        RemainingAncestor {
+            remaining_ancestor: None
+        }
+    );
+    assert_eq!(m.times_called(), 1);
+}
+
+#[test]
+fn test_protect_patient() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+
+    let m = Mock::new()
+        .expect_method(Method::PUT)
+        .expect_path("/patients/foo/protected")
+        .expect_body("1")
+        .return_status(200)
+        .create_on(&mock_server);
+
+    let cl = Client::new(url);
+    cl.protect_patient("foo").unwrap();
+
+    assert_eq!(m.times_called(), 1);
+}
+
+#[test]
+fn test_unprotect_patient() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+
+    let m = Mock::new()
+        .expect_method(Method::PUT)
+        .expect_path("/patients/foo/protected")
+        .expect_body("0")
+        .return_status(200)
+        .create_on(&mock_server);
+
+    let cl = Client::new(url);
+    cl.unprotect_patient("foo").unwrap();
+
+    assert_eq!(m.times_called(), 1);
+}
+
+#[test]
+fn test_is_patient_protected() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+
+    let m1 = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/patients/foo/protected")
+        .return_status(200)
+        .return_body("1")
+        .create_on(&mock_server);
+    let m2 = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/patients/bar/protected")
+        .return_status(200)
+        .return_body("0")
+        .create_on(&mock_server);
+    let m3 = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/patients/baz/protected")
+        .return_status(200)
+        .return_body("2")
+        .create_on(&mock_server);
+
+    let cl = Client::new(url);
+    assert!(cl.is_patient_protected("foo").unwrap());
+    assert!(!cl.is_patient_protected("bar").unwrap());
+    assert_eq!(
+        cl.is_patient_protected("baz").unwrap_err(),
+        Error {
+            message: "Unknown protection status: 2".to_string(),
+            details: None,
+            kind: None,
+        }
+    );
+    assert_eq!(m1.times_called(), 1);
+    assert_eq!(m2.times_called(), 1);
+    assert_eq!(m3.times_called(), 1);
+}
+
+#[test]
+fn test_get_patient_protected() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+
+    let m = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/patients/foo")
+        .return_status(200)
+        .return_body(&PATIENT_FOO.replace(
+            r#""Type": "Patient""#,
+            r#""Type": "Patient", "IsProtected": true"#,
+        ))
+        .create_on(&mock_server);
+
+    let cl = Client::new(url);
+    let patient = cl.patient("foo").unwrap();
+
+    assert!(patient.protected);
+    assert_eq!(m.times_called(), 1);
+}
+
+#[test]
+fn test_delete_study() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+
+    let m = Mock::new()
+        .expect_method(Method::DELETE)
+        .expect_path("/studies/foo")
+        .return_status(200)
+        .return_body(
+            r#"
+                    {
+                        "RemainingAncestor": {
+                            "ID": "bar",
+                            "Path": "/patients/bar",
+                            "Type": "Patient"
+                        }
+                    }
+                "#,
+        )
+        .create_on(&mock_server);
+
+    let cl = Client::new(url);
+    let resp = cl.delete_study("foo").unwrap();
+
+    assert_eq!(
+        resp,
+        RemainingAncestor {
+            remaining_ancestor: Some(Ancestor {
+                id: "bar".to_string(),
+                path: "/patients/bar".to_string(),
+                entity: EntityKind::Patient,
+            })
+        }
+    );
+    assert_eq!(m.times_called(), 1);
+}
+
+#[test]
+fn test_delete_series() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+
+    let m = Mock::new()
+        .expect_method(Method::DELETE)
+        .expect_path("/series/foo")
+        .return_status(200)
+        .return_body(
+            r#"
+                    {
+                        "RemainingAncestor": {
+                            "ID": "bar",
+                            "Path": "/studies/bar",
+                            "Type": "Study"
+                        }
+                    }
+                "#,
+        )
+        .create_on(&mock_server);
+
+    let cl = Client::new(url);
+    let resp = cl.delete_series("foo").unwrap();
+
+    assert_eq!(
+        resp,
+        RemainingAncestor {
+            remaining_ancestor: Some(Ancestor {
+                id: "bar".to_string(),
+                path: "/studies/bar".to_string(),
+                entity: EntityKind::Study,
+            })
+        }
+    );
+    assert_eq!(m.times_called(), 1);
+}
+
+#[test]
+fn test_delete_instance() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+
+    let m = Mock::new()
+        .expect_method(Method::DELETE)
+        .expect_path("/instances/foo")
+        .return_status(200)
+        .return_body(
+            r#"
+                    {
+                        "RemainingAncestor": {
+                            "ID": "bar",
+                            "Path": "/series/bar",
+                            "Type": "Series"
+                        }
+                    }
+                "#,
+        )
+        .create_on(&mock_server);
+
+    let cl = Client::new(url);
+    let resp = cl.delete_instance("foo").unwrap();
+
+    assert_eq!(
+        resp,
+        RemainingAncestor {
+            remaining_ancestor: Some(Ancestor {
+                id: "bar".to_string(),
+                path: "/series/bar".to_string(),
+                entity: EntityKind::Series,
+            })
+        }
+    );
+    assert_eq!(m.times_called(), 1);
+}
+
+#[test]
+#[allow(deprecated)]
+fn test_modality_echo() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+
+    let m = Mock::new()
+        .expect_method(Method::POST)
+        .expect_path("/modalities/foo/echo")
+        .return_status(200)
+        .return_body("{}")
+        .create_on(&mock_server);
+
+    let cl = Client::new(url);
+    assert_eq!(cl.modality_echo("foo", None).unwrap(), ());
+    assert_eq!(cl.echo("foo", None).unwrap(), ());
+    assert_eq!(m.times_called(), 2);
+}
+
+#[test]
+#[allow(deprecated)]
+fn test_echo_with_timeout() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+
+    let m = Mock::new()
+        .expect_method(Method::POST)
+        .expect_path("/modalities/foo/echo")
+        .expect_json_body(&hashmap! {"Timeout" => 42})
+        .return_status(200)
+        .return_body("{}")
+        .create_on(&mock_server);
+
+    let cl = Client::new(url);
+    assert_eq!(cl.modality_echo("foo", Some(42)).unwrap(), ());
+    assert_eq!(cl.echo("foo", Some(42)).unwrap(), ());
+    assert_eq!(m.times_called(), 2);
+}
+
+#[test]
+#[allow(deprecated)]
+fn test_echo_failed() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+
+    let m = Mock::new()
+        .expect_method(Method::POST)
+        .expect_path("/modalities/foo/echo")
+        .return_status(500)
+        .create_on(&mock_server);
+
+    let cl = Client::new(url);
+    assert_eq!(
+        cl.modality_echo("foo", None).unwrap_err(),
+        Error {
+            message: "API error: 500 Internal Server Error".to_string(),
+            details: None,
+            kind: None,
+        }
+    );
+    assert_eq!(
+        cl.echo("foo", None).unwrap_err(),
+        Error {
+            message: "API error: 500 Internal Server Error".to_string(),
+            details: None,
+            kind: None,
+        }
+    );
+    assert_eq!(m.times_called(), 2);
+}
+
+#[test]
+fn test_modality_echo_report() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+
+    let m1 = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/modalities")
+        .expect_query_param_exists("expand")
+        .return_status(200)
+        .return_header("Content-Type", "application/json")
+        .return_body(r#"{"foo": {"AET": "FOO", "Host": "localhost", "Port": 11114}}"#)
+        .create_on(&mock_server);
+    let m2 = Mock::new()
+        .expect_method(Method::POST)
+        .expect_path("/modalities/foo/echo")
+        .expect_json_body(&serde_json::json!({"Timeout": 42}))
+        .return_status(200)
+        .create_on(&mock_server);
+
+    let cl = Client::new(url);
+    let report = cl.modality_echo_report("foo", Some(42)).unwrap();
+
+    assert_eq!(report.modality, "foo");
+    assert_eq!(report.remote_aet, Some("FOO".to_string()));
+    assert_eq!(report.status, EchoStatus::Success);
+    assert!(report.is_success());
+    assert_eq!(m1.times_called(), 1);
+    assert_eq!(m2.times_called(), 1);
+}
+
+#[test]
+fn test_modality_echo_report_failed() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+
+    let m1 = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/modalities")
+        .expect_query_param_exists("expand")
+        .return_status(200)
+        .return_header("Content-Type", "application/json")
+        .return_body(r#"{"foo": {"AET": "FOO", "Host": "localhost", "Port": 11114}}"#)
+        .create_on(&mock_server);
+    let m2 = Mock::new()
+        .expect_method(Method::POST)
+        .expect_path("/modalities/foo/echo")
+        .return_status(500)
+        .return_body(
+            r#"
+                {
+                    "Details" : "DicomAssociation - connecting to AET \"FOO\": Association Rejected",
+                    "HttpError" : "Internal Server Error",
+                    "HttpStatus" : 500,
+                    "Message" : "Error in the network protocol",
+                    "Method" : "POST",
+                    "OrthancError" : "Error in the network protocol",
+                    "OrthancStatus" : 9,
+                    "Uri" : "/modalities/foo/echo"
+                }
+            "#,
+        )
+        .create_on(&mock_server);
+
+    let cl = Client::new(url);
+    let report = cl.modality_echo_report("foo", None).unwrap();
+
+    assert_eq!(report.remote_aet, Some("FOO".to_string()));
+    assert_eq!(
+        report.status,
+        EchoStatus::Rejected(
+            "DicomAssociation - connecting to AET \"FOO\": Association Rejected"
+                .to_string()
+        )
+    );
+    assert!(!report.is_success());
+    assert_eq!(m1.times_called(), 1);
+    assert_eq!(m2.times_called(), 1);
+}
+
+#[test]
+fn test_modality_echo_report_error() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+
+    let m1 = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/modalities")
+        .expect_query_param_exists("expand")
+        .return_status(200)
+        .return_header("Content-Type", "application/json")
+        .return_body("{}")
+        .create_on(&mock_server);
+    let m2 = Mock::new()
+        .expect_method(Method::POST)
+        .expect_path("/modalities/foo/echo")
+        .return_status(404)
+        .create_on(&mock_server);
+
+    let cl = Client::new(url);
+    assert_eq!(
+        cl.modality_echo_report("foo", None).unwrap_err(),
+        Error {
+            message: "API error: 404 Not Found".to_string(),
+            details: None,
+            kind: None,
+        }
+    );
+    assert_eq!(m1.times_called(), 1);
+    assert_eq!(m2.times_called(), 1);
+}
+
+#[test]
+fn test_check_all_modalities() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+
+    let m1 = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/modalities")
+        .expect_query_param_exists("expand")
+        .return_status(200)
+        .return_header("Content-Type", "application/json")
+        .return_body(
+            r#"
+                {
+                    "foo": {"AET": "FOO", "Host": "localhost", "Port": 11114},
+                    "bar": {"AET": "BAR", "Host": "remotehost", "Port": 11113},
+                    "baz": {"AET": "BAZ", "Host": "remotehost", "Port": 11112}
+                }
+            "#,
+        )
+        .create_on(&mock_server);
+    let m2 = Mock::new()
+        .expect_method(Method::POST)
+        .expect_path("/modalities/foo/echo")
+        .return_status(200)
+        .create_on(&mock_server);
+    let m3 = Mock::new()
+        .expect_method(Method::POST)
+        .expect_path("/modalities/bar/echo")
+        .return_status(500)
+        .create_on(&mock_server);
+    let m4 = Mock::new()
+        .expect_method(Method::POST)
+        .expect_path("/modalities/baz/echo")
+        .return_status(404)
+        .create_on(&mock_server);
+
+    let cl = Client::new(url);
+    let reports = cl.check_all_modalities(None, 2).unwrap();
+
+    assert_eq!(reports.len(), 3);
+    let foo = reports["foo"].as_ref().unwrap();
+    assert_eq!(foo.remote_aet, Some("FOO".to_string()));
+    assert_eq!(foo.status, EchoStatus::Success);
+    let bar = reports["bar"].as_ref().unwrap();
+    assert_eq!(bar.remote_aet, Some("BAR".to_string()));
+    assert_eq!(bar.status, EchoStatus::Failed(None));
+    assert_eq!(
+        reports["baz"].as_ref().unwrap_err(),
+        &Error {
+            message: "API error: 404 Not Found".to_string(),
+            details: None,
+            kind: None,
+        }
+    );
+    assert_eq!(m1.times_called(), 1);
+    assert_eq!(m2.times_called(), 1);
+    assert_eq!(m3.times_called(), 1);
+    assert_eq!(m4.times_called(), 1);
+}
+
+const ATTACHMENT_INFO: &str = r#"
+    {
+        "CompressedMD5": "3858f62230ac3c915f300c664312c63f",
+        "CompressedSize": 6,
+        "ContentType": 1,
+        "UncompressedMD5": "3858f62230ac3c915f300c664312c63f",
+        "UncompressedSize": 6,
+        "Uuid": "a1b2c3d4"
+    }
+"#;
+
+fn download_path(name: &str) -> std::path::PathBuf {
+    std::env::temp_dir().join(format!("orthanc_{}_{}", name, std::process::id()))
+}
+
+#[test]
+fn test_instance_attachment_info() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+
+    let m = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/instances/foo/attachments/dicom/info")
+        .return_status(200)
+        .return_body(ATTACHMENT_INFO)
+        .create_on(&mock_server);
+
+    let cl = Client::new(url);
+    let resp = cl.instance_attachment_info("foo", "dicom").unwrap();
+
+    assert_eq!(
+        resp,
+        AttachmentInfo {
+            uuid: "a1b2c3d4".to_string(),
+            content_type: 1,
+            compressed_md5: "3858f62230ac3c915f300c664312c63f".to_string(),
+            compressed_size: 6,
+            uncompressed_md5: "3858f62230ac3c915f300c664312c63f".to_string(),
+            uncompressed_size: 6,
+        }
+    );
+    assert_eq!(m.times_called(), 1);
+}
+
+#[test]
+fn test_download_instance_to_file() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+
+    let m1 = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/instances/foo/attachments/dicom/info")
+        .return_status(200)
+        .return_body(ATTACHMENT_INFO)
+        .create_on(&mock_server);
+    let m2 = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/instances/foo/file")
+        .return_status(200)
+        .return_body("foobar")
+        .create_on(&mock_server);
+
+    let path = download_path("download_instance");
+    let cl = Client::new(url);
+    cl.download_to_file(EntityKind::Instance, "foo", &path)
+        .unwrap();
+
+    assert_eq!(fs::read_to_string(&path).unwrap(), "foobar");
+    fs::remove_file(&path).unwrap();
+    assert_eq!(m1.times_called(), 1);
+    assert_eq!(m2.times_called(), 1);
+}
+
+#[test]
+fn test_download_instance_to_file_resume() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+
+    let m1 = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/instances/foo/attachments/dicom/info")
+        .return_status(200)
+        .return_body(ATTACHMENT_INFO)
+        .create_on(&mock_server);
+    let m2 = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/instances/foo/file")
+        .expect_header("range", "bytes=3-")
+        .return_status(206)
+        .return_body("bar")
+        .create_on(&mock_server);
+
+    let path = download_path("download_instance_resume");
+    let partial = std::path::PathBuf::from(format!("{}.part", path.display()));
+    fs::write(&partial, "foo").unwrap();
+
+    let cl = Client::new(url);
+    cl.download_to_file(EntityKind::Instance, "foo", &path)
+        .unwrap();
+
+    assert_eq!(fs::read_to_string(&path).unwrap(), "foobar");
+    assert!(!partial.exists());
+    fs::remove_file(&path).unwrap();
+    assert_eq!(m1.times_called(), 1);
+    assert_eq!(m2.times_called(), 1);
+}
+
+#[test]
+fn test_download_instance_to_file_no_md5() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+
+    let m1 = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/instances/foo/attachments/dicom/info")
+        .return_status(200)
+        .return_body(&ATTACHMENT_INFO.replace("3858f62230ac3c915f300c664312c63f", ""))
+        .create_on(&mock_server);
+    let m2 = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/instances/foo/file")
+        .return_status(200)
+        .return_body("foobaz")
+        .create_on(&mock_server);
+
+    let path = download_path("download_instance_no_md5");
+    let cl = Client::new(url);
+    cl.download_to_file(EntityKind::Instance, "foo", &path)
+        .unwrap();
+
+    assert_eq!(fs::read_to_string(&path).unwrap(), "foobaz");
+    fs::remove_file(&path).unwrap();
+    assert_eq!(m1.times_called(), 1);
+    assert_eq!(m2.times_called(), 1);
+}
+
+#[test]
+fn test_download_instance_to_file_md5_mismatch() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+
+    let m1 = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/instances/foo/attachments/dicom/info")
+        .return_status(200)
+        .return_body(ATTACHMENT_INFO)
+        .create_on(&mock_server);
+    let m2 = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/instances/foo/file")
+        .return_status(200)
+        .return_body("foobaz")
+        .create_on(&mock_server);
+
+    let path = download_path("download_instance_md5");
+    let cl = Client::new(url);
+    let resp = cl.download_to_file(EntityKind::Instance, "foo", &path);
+
+    assert_eq!(
+        resp.unwrap_err(),
+        Error {
+            message: format!(
+                "MD5 mismatch: expected 3858f62230ac3c915f300c664312c63f, got {}",
+                "80338e79d2ca9b9c090ebaaa2ef293c7"
+            ),
+            details: None,
+            kind: None,
+        },
+    );
+    assert!(!path.exists());
+    assert!(!std::path::PathBuf::from(format!("{}.part", path.display())).exists());
+    assert_eq!(m1.times_called(), 1);
+    assert_eq!(m2.times_called(), 1);
+}
+
+#[test]
+fn test_download_study_to_file() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+
+    let m = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/studies/foo/archive")
+        .return_status(200)
+        .return_body("foobar")
+        .create_on(&mock_server);
+
+    let path = download_path("download_study");
+    let cl = Client::new(url);
+    cl.download_to_file(EntityKind::Study, "foo", &path)
+        .unwrap();
+
+    assert_eq!(fs::read_to_string(&path).unwrap(), "foobar");
+    fs::remove_file(&path).unwrap();
+    assert_eq!(m.times_called(), 1);
+}
+
+#[test]
+fn test_export_series_to_dir() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+
+    let m1 = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/series/foo")
+        .return_status(200)
+        .return_body(
+            r#"
+                    {
+                        "ExpectedNumberOfInstances": null,
+                        "ID": "foo",
+                        "Instances": ["qux", "quux"],
+                        "IsStable": true,
+                        "LastUpdate": "20200830T191109",
+                        "MainDicomTags": {
+                            "Modality": "MR",
+                            "SeriesNumber": "1101"
+                        },
+                        "ParentStudy": "bar",
+                        "Status": "Unknown",
+                        "Type": "Series"
+                    }
+                "#,
+        )
+        .create_on(&mock_server);
+    let m2 = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/studies/bar")
+        .return_status(200)
+        .return_body(
+            r#"
+                    {
+                        "ID": "bar",
+                        "IsStable": true,
+                        "LastUpdate": "20200830T191109",
+                        "MainDicomTags": {
+                            "StudyDescription": "Knee/Left"
+                        },
+                        "ParentPatient": "baz",
+                        "PatientMainDicomTags": {
+                            "PatientID": "patient_1"
+                        },
+                        "Series": ["foo"],
+                        "Type": "Study"
+                    }
+                "#,
+        )
+        .create_on(&mock_server);
+    let instance = |id: &str, number: &str| {
+        format!(
+            r#"
+                {{
+                    "FileSize": 6,
+                    "FileUuid": "d8c5eff3-986c-4fe4-b06e-7e52b2a4238e",
+                    "ID": "{}",
+                    "IndexInSeries": {},
+                    "MainDicomTags": {{
+                        "InstanceNumber": "{}"
+                    }},
+                    "ParentSeries": "foo",
+                    "Type": "Instance"
+                }}
+            "#,
+            id, number, number
+        )
+    };
+    let m3 = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/series/foo/instances")
+        .return_status(200)
+        .return_body(&format!(
+            "[{}, {}, {}]",
+            instance("qux", "1"),
+            instance("quux", "2"),
+            instance("corge", "1")
+        ))
+        .create_on(&mock_server);
+    let m4 = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/instances/qux/file")
+        .return_status(200)
+        .return_body("foobar")
+        .create_on(&mock_server);
+    let m5 = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/instances/quux/file")
+        .return_status(200)
+        .return_body("foobar")
+        .create_on(&mock_server);
+    let m6 = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/instances/corge/file")
+        .return_status(200)
+        .return_body("bazqux")
+        .create_on(&mock_server);
+    let m7 = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/instances/qux/attachments/dicom/info")
+        .return_status(200)
+        .return_body(ATTACHMENT_INFO)
+        .create_on(&mock_server);
+    let m8 = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/instances/corge/attachments/dicom/info")
+        .return_status(200)
+        .return_body(&ATTACHMENT_INFO.replace(
+            "3858f62230ac3c915f300c664312c63f",
+            "cf212ff9c188c19f17e5342a69f234e9",
+        ))
+        .create_on(&mock_server);
+
+    let dir = std::env::temp_dir().join(format!("orthanc_export_{}", std::process::id()));
+    let series_dir = dir.join("patient_1/Knee_Left/MR 1101");
+    fs::create_dir_all(&series_dir).unwrap();
+    fs::write(series_dir.join("2.dcm"), "barbaz").unwrap();
+
+    let layout = ExportLayout {
+        study: "{StudyDate}{StudyDescription}".to_string(),
+        series: "{Modality} {SeriesNumber}".to_string(),
+        instance: "{InstanceNumber}.dcm".to_string(),
+        ..Default::default()
+    };
+    let cl = Client::new(url);
+    let report = cl
+        .export_to_dir("foo", EntityKind::Series, &dir, &layout)
+        .unwrap();
+
+    assert_eq!(
+        report,
+        ExportReport {
+            exported: vec![series_dir.join("1.dcm"), series_dir.join("1_corge.dcm")],
+            skipped: vec![series_dir.join("2.dcm")],
+        }
+    );
+    assert_eq!(
+        fs::read_to_string(series_dir.join("1.dcm")).unwrap(),
+        "foobar"
+    );
+    assert_eq!(
+        fs::read_to_string(series_dir.join("2.dcm")).unwrap(),
+        "barbaz"
+    );
+    assert_eq!(
+        fs::read_to_string(series_dir.join("1_corge.dcm")).unwrap(),
+        "bazqux"
+    );
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(m1.times_called(), 1);
+    assert_eq!(m2.times_called(), 1);
+    assert_eq!(m3.times_called(), 1);
+    assert_eq!(m4.times_called(), 1);
+    assert_eq!(m5.times_called(), 0);
+    assert_eq!(m6.times_called(), 1);
+    assert_eq!(m7.times_called(), 1);
+    assert_eq!(m8.times_called(), 1);
+}
+
+#[test]
+fn test_export_instance_to_dir_size_mismatch() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+
+    let m1 = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/instances/qux")
+        .return_status(200)
+        .return_body(
+            r#"
+                    {
+                        "FileSize": 6,
+                        "FileUuid": "d8c5eff3-986c-4fe4-b06e-7e52b2a4238e",
+                        "ID": "qux",
+                        "IndexInSeries": 1,
+                        "MainDicomTags": {},
+                        "ParentSeries": "foo",
+                        "Type": "Instance"
+                    }
+                "#,
+        )
+        .create_on(&mock_server);
+    let m2 = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/series/foo")
+        .return_status(200)
+        .return_body(
+            r#"
+                    {
+                        "ExpectedNumberOfInstances": null,
+                        "ID": "foo",
+                        "Instances": ["qux"],
+                        "IsStable": true,
+                        "LastUpdate": "20200830T191109",
+                        "MainDicomTags": {},
+                        "ParentStudy": "bar",
+                        "Status": "Unknown",
+                        "Type": "Series"
+                    }
+                "#,
+        )
+        .create_on(&mock_server);
+    let m3 = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/studies/bar")
+        .return_status(200)
+        .return_body(
+            r#"
+                    {
+                        "ID": "bar",
+                        "IsStable": true,
+                        "LastUpdate": "20200830T191109",
+                        "MainDicomTags": {},
+                        "ParentPatient": "baz",
+                        "PatientMainDicomTags": {},
+                        "Series": ["foo"],
+                        "Type": "Study"
+                    }
+                "#,
+        )
+        .create_on(&mock_server);
+    let m4 = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/instances/qux/attachments/dicom/info")
+        .return_status(200)
+        .return_body(ATTACHMENT_INFO)
+        .create_on(&mock_server);
+    let m5 = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/instances/qux/file")
+        .return_status(200)
+        .return_body("foo")
+        .create_on(&mock_server);
+
+    let dir = download_path("export_mismatch");
+    let cl = Client::new(url);
+    let resp =
+        cl.export_to_dir("qux", EntityKind::Instance, &dir, &ExportLayout::default());
+
+    assert_eq!(
+        resp.unwrap_err(),
+        Error {
+            message: "Size mismatch: expected 6 bytes, got 3".to_string(),
+            details: None,
+            kind: None,
+        }
+    );
+    // Neither the file nor its temporary copy are left behind
+    let series_dir = dir.join("baz/bar/foo");
+    assert!(!series_dir.join("qux.dcm").exists());
+    assert!(!series_dir.join("qux.dcm.part").exists());
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(m1.times_called(), 1);
+    assert_eq!(m2.times_called(), 1);
+    assert_eq!(m3.times_called(), 1);
+    assert_eq!(m4.times_called(), 1);
+    assert_eq!(m5.times_called(), 1);
+}
+
+#[test]
+fn test_upload_dicom() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+
+    let m = Mock::new()
+        .expect_method(Method::POST)
+        .expect_path("/instances")
+        .expect_body("quux")
+        .return_status(200)
+        .return_body(
+            r#"
+                    {
+                        "ID": "foo",
+                        "ParentPatient": "bar",
+                        "ParentSeries": "baz",
+                        "ParentStudy": "qux",
+                        "Path": "/instances/foo",
+                        "Status": "Success"
+                    }
+                "#,
+        )
+        .create_on(&mock_server);
+
+    let cl = Client::new(url);
+    let resp = cl.upload("quux".as_bytes()).unwrap();
+
+    assert_eq!(
+        resp,
+        UploadResult {
+            id: "foo".to_string(),
+            status: "Success".to_string(),
+            path: "/instances/foo".to_string(),
+            parent_patient: "bar".to_string(),
+            parent_study: "qux".to_string(),
+            parent_series: "baz".to_string(),
+        }
+    );
+    assert_eq!(m.times_called(), 1);
+}
+
+fn fake_dicom(content: &str) -> Vec<u8> {
+    let mut data = vec![0; 128];
+    data.extend(b"DICM");
+    data.extend(content.as_bytes());
+    data
+}
+
+const UPLOAD_RESULT: &str = r#"
+    {
+        "ID": "foo",
+        "ParentPatient": "bar",
+        "ParentSeries": "baz",
+        "ParentStudy": "qux",
+        "Path": "/instances/foo",
+        "Status": "Success"
+    }
+"#;
+
+#[test]
+fn test_upload_reader() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+
+    let m = Mock::new()
+        .expect_method(Method::POST)
+        .expect_path("/instances")
+        .expect_header("Content-Length", "4")
+        .expect_body("quux")
+        .return_status(200)
+        .return_body(UPLOAD_RESULT)
+        .create_on(&mock_server);
+
+    let cl = Client::new(url);
+    let resp = cl.upload_reader(Cursor::new("quux"), Some(4)).unwrap();
+
+    assert_eq!(
+        resp,
+        vec![UploadResult {
+            id: "foo".to_string(),
+            status: "Success".to_string(),
+            path: "/instances/foo".to_string(),
+            parent_patient: "bar".to_string(),
+            parent_study: "qux".to_string(),
+            parent_series: "baz".to_string(),
+        }]
+    );
+    assert_eq!(m.times_called(), 1);
+}
+
+#[test]
+fn test_upload_reader_chunked_zip() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+
+    let m = Mock::new()
+        .expect_method(Method::POST)
+        .expect_path("/instances")
+        .expect_header("Transfer-Encoding", "chunked")
+        .expect_body("quux")
+        .return_status(200)
+        .return_body(&format!(
+            "[{}, {}]",
+            UPLOAD_RESULT,
+            UPLOAD_RESULT
+                .replace("foo", "garble")
+                .replace("Success", "AlreadyStored")
+        ))
+        .create_on(&mock_server);
+
+    let cl = Client::new(url);
+    let resp = cl.upload_reader(Cursor::new("quux"), None).unwrap();
+
+    assert_eq!(
+        resp,
+        vec![
+            UploadResult {
+                id: "foo".to_string(),
+                status: "Success".to_string(),
+                path: "/instances/foo".to_string(),
+                parent_patient: "bar".to_string(),
+                parent_study: "qux".to_string(),
+                parent_series: "baz".to_string(),
+            },
+            UploadResult {
+                id: "garble".to_string(),
+                status: "AlreadyStored".to_string(),
+                path: "/instances/garble".to_string(),
+                parent_patient: "bar".to_string(),
+                parent_study: "qux".to_string(),
+                parent_series: "baz".to_string(),
+            },
+        ]
+    );
+    assert_eq!(m.times_called(), 1);
+}
+
+#[test]
+fn test_upload_dir() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+
+    let m = Mock::new()
+        .expect_method(Method::POST)
+        .expect_path("/instances")
+        .return_status(200)
+        .return_body(UPLOAD_RESULT)
+        .create_on(&mock_server);
+
+    let dir =
+        std::env::temp_dir().join(format!("orthanc_upload_dir_{}", std::process::id()));
+    fs::create_dir_all(dir.join("sub")).unwrap();
+    fs::write(dir.join("a.dcm"), fake_dicom("a")).unwrap();
+    fs::write(dir.join("sub/b.dcm"), fake_dicom("b")).unwrap();
+    fs::write(dir.join("readme.txt"), "foo").unwrap();
+    // Links to directories are not followed
+    #[cfg(unix)]
+    std::os::unix::fs::symlink(&dir, dir.join("sub/loop")).unwrap();
+
+    let cl = Client::new(url);
+    let report = cl
+        .upload_dir(
+            &dir,
+            &UploadOptions {
+                concurrency: 2,
+                detect_duplicates: true,
+            },
+        )
+        .unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    let paths: Vec<String> = report.files.iter().map(|f| f.path.clone()).collect();
+    assert_eq!(
+        paths,
+        vec![
+            dir.join("a.dcm").display().to_string(),
+            dir.join("sub/b.dcm").display().to_string(),
+        ]
+    );
+    assert_eq!(report.succeeded().count(), 2);
+    assert_eq!(report.failed().count(), 0);
+    assert_eq!(
+        report.skipped,
+        vec![dir.join("readme.txt").display().to_string()]
+    );
+    assert_eq!(report.duplicates, Vec::<String>::new());
+    assert_eq!(m.times_called(), 2);
+}
+
+#[test]
+fn test_upload_dir_not_found() {
+    let cl = Client::new("http://localhost:8042");
+    let resp = cl.upload_dir("/foo/bar/baz", &UploadOptions::default());
+    assert_eq!(
+        resp.unwrap_err(),
+        Error {
+            message: "No such file or directory (os error 2)".to_string(),
+            details: None,
+            kind: None,
+        },
+    );
+}
+
+#[cfg(feature = "zip")]
+#[test]
+fn test_upload_zip() {
+    use std::io::Write;
+
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+
+    let m1 = Mock::new()
+        .expect_method(Method::POST)
+        .expect_path("/instances")
+        .expect_body(str::from_utf8(&fake_dicom("a")).unwrap())
+        .return_status(200)
+        .return_body(&UPLOAD_RESULT.replace("Success", "AlreadyStored"))
+        .create_on(&mock_server);
+    let m2 = Mock::new()
+        .expect_method(Method::POST)
+        .expect_path("/instances")
+        .expect_body(str::from_utf8(&fake_dicom("b")).unwrap())
+        .return_status(400)
+        .return_body(
+            r#"
+                {
+                    "Details" : "Cannot parse an invalid DICOM file (size: 134 bytes)",
+                    "HttpError" : "Bad Request",
+                    "HttpStatus" : 400,
+                    "Message" : "Bad file format",
+                    "Method" : "POST",
+                    "OrthancError" : "Bad file format",
+                    "OrthancStatus" : 15,
+                    "Uri" : "/instances"
+                }
+            "#,
+        )
+        .create_on(&mock_server);
+
+    let mut archive = zip::ZipWriter::new(Cursor::new(vec![]));
+    let zip_options = zip::write::FileOptions::default();
+    archive.add_directory("foo/", zip_options).unwrap();
+    archive.start_file("foo/a.dcm", zip_options).unwrap();
+    archive.write_all(&fake_dicom("a")).unwrap();
+    archive.start_file("foo/b.dcm", zip_options).unwrap();
+    archive.write_all(&fake_dicom("b")).unwrap();
+    archive.start_file("DICOMDIR.txt", zip_options).unwrap();
+    archive.write_all(b"foo").unwrap();
+    let data = archive.finish().unwrap();
+
+    let cl = Client::new(url);
+    let report = cl
+        .upload_zip(
+            data,
+            &UploadOptions {
+                concurrency: 1,
+                detect_duplicates: true,
+            },
+        )
+        .unwrap();
+
+    assert_eq!(
+        report,
+        UploadReport {
+            files: vec![
+                UploadedFile {
+                    path: "foo/a.dcm".to_string(),
+                    result: Ok(UploadResult {
+                        id: "foo".to_string(),
+                        status: "AlreadyStored".to_string(),
+                        path: "/instances/foo".to_string(),
+                        parent_patient: "bar".to_string(),
+                        parent_study: "qux".to_string(),
+                        parent_series: "baz".to_string(),
+                    }),
+                },
+                UploadedFile {
+                    path: "foo/b.dcm".to_string(),
+                    result: Err(Error {
+                        message: "API error: 400 Bad Request".to_string(),
+                        details: Some(Box::new(ApiError {
+                            method: "POST".to_string(),
+                            uri: "/instances".to_string(),
+                            message: "Bad file format".to_string(),
+                            details: Some(
+                                "Cannot parse an invalid DICOM file (size: 134 bytes)"
+                                    .to_string()
+                            ),
+                            http_status: 400,
+                            http_error: "Bad Request".to_string(),
+                            orthanc_status: 15,
+                            orthanc_error: "Bad file format".to_string(),
+                        })),
+                        kind: None,
+                    }),
+                },
+            ],
+            skipped: vec!["DICOMDIR.txt".to_string()],
+            duplicates: vec!["foo/a.dcm".to_string()],
+        }
+    );
+    assert_eq!(m1.times_called(), 1);
+    assert_eq!(m2.times_called(), 1);
+}
+
+#[cfg(feature = "dicom")]
+#[test]
+fn test_upload_validated_invalid_dicom() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+
+    let m = Mock::new()
+        .expect_method(Method::POST)
+        .expect_path("/instances")
+        .return_status(200)
+        .create_on(&mock_server);
+
+    let cl = Client::new(url);
+    let resp = cl.upload_validated("quux".as_bytes());
+
+    assert_eq!(
+        resp.unwrap_err(),
+        Error {
+            message: "Invalid DICOM file: DICOM preamble is missing".to_string(),
+            details: None,
+            kind: Some(orthanc::ErrorKind::InvalidDicom(
+                orthanc::dicom::ValidationError::MissingPreamble
+            )),
+        },
+    );
+    assert_eq!(m.times_called(), 0);
+}
+
+// The following 2 tests are exactly the same except one calls `create_modality`,
+// the other one calls `modify_modality`.
+#[test]
+fn test_create_modality() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+
+    let m = Mock::new()
+        .expect_method(Method::PUT)
+        .expect_path("/modalities/bazqux")
+        .expect_json_body(&Modality {
+            aet: "foobar".to_string(),
+            host: "localhost".to_string(),
+            port: 11113,
+            manufacturer: None,
+            allow_c_echo: None,
+            allow_c_find: None,
+            allow_c_get: None,
+            allow_c_move: None,
+            allow_c_store: None,
+            allow_n_action: None,
+            allow_n_event_report: None,
+            allow_transcoding: None,
+        })
+        .return_status(200)
+        .return_body("")
+        .create_on(&mock_server);
+
+    let cl = Client::new(url);
+    let modality = Modality {
+        aet: "foobar".to_string(),
+        host: "localhost".to_string(),
+        port: 11113,
+        manufacturer: None,
+        allow_c_echo: None,
+        allow_c_find: None,
+        allow_c_get: None,
+        allow_c_move: None,
+        allow_c_store: None,
+        allow_n_action: None,
+        allow_n_event_report: None,
+        allow_transcoding: None,
+    };
+    cl.create_modality("bazqux", modality).unwrap();
+
+    assert_eq!(m.times_called(), 1);
+}
+
+#[test]
+fn test_modify_modality() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+
+    let m = Mock::new()
+        .expect_method(Method::PUT)
+        .expect_path("/modalities/bazqux")
+        .expect_json_body(&Modality {
+            aet: "foobar".to_string(),
+            host: "localhost".to_string(),
+            port: 11113,
+            manufacturer: None,
+            allow_c_echo: None,
+            allow_c_find: None,
+            allow_c_get: None,
+            allow_c_move: None,
+            allow_c_store: None,
+            allow_n_action: None,
+            allow_n_event_report: None,
+            allow_transcoding: None,
+        })
+        .return_status(200)
+        .return_body("")
+        .create_on(&mock_server);
+
+    let cl = Client::new(url);
+    let modality = Modality {
+        aet: "foobar".to_string(),
+        host: "localhost".to_string(),
+        port: 11113,
+        manufacturer: None,
+        allow_c_echo: None,
+        allow_c_find: None,
+        allow_c_get: None,
+        allow_c_move: None,
+        allow_c_store: None,
+        allow_n_action: None,
+        allow_n_event_report: None,
+        allow_transcoding: None,
+    };
+    cl.modify_modality("bazqux", modality).unwrap();
+
+    assert_eq!(m.times_called(), 1);
+}
+
+#[test]
+fn test_delete_modality() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+
+    let m = Mock::new()
+        .expect_method(Method::DELETE)
+        .expect_path("/modalities/bazqux")
+        .return_status(200)
+        .return_body("")
+        .create_on(&mock_server);
+
+    let cl = Client::new(url);
+    cl.delete_modality("bazqux").unwrap();
+
+    assert_eq!(m.times_called(), 1);
+}
+
+// The following 2 tests are exactly the same except one calls `create_peer`,
+// the other one calls `modify_peer`.
+#[test]
+fn test_create_peer() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+
+    let m = Mock::new()
+        .expect_method(Method::PUT)
+        .expect_path("/peers/bazqux")
+        .expect_json_body(
+            &Peer {
+                url: "http://orthanc_peer:8029".to_string(),
+                username: Some("orthanc".to_string()),
+                password: Some("orthanc".to_string()),
+                http_headers: Some(HttpHeaders::Values(
+                    hashmap! {"Foo".to_string() => "foo".to_string(), "Bar".to_string() => "bar".to_string()},
+                )),
+                certificate_file: None,
+                certificate_key_file: None,
+                certificate_key_password: None,
+        })
+        .return_status(200)
+        .return_body("")
+        .create_on(&mock_server);
+
+    let cl = Client::new(url);
+    let peer = Peer {
+        url: "http://orthanc_peer:8029".to_string(),
+        username: Some("orthanc".to_string()),
+        password: Some("orthanc".to_string()),
+        http_headers: Some(HttpHeaders::Values(
+            hashmap! {"Foo".to_string() => "foo".to_string(), "Bar".to_string() => "bar".to_string()},
+        )),
+        certificate_file: None,
+        certificate_key_file: None,
+        certificate_key_password: None,
+    };
+    cl.create_peer("bazqux", peer).unwrap();
+
+    assert_eq!(m.times_called(), 1);
+}
+
+#[test]
+fn test_modify_peer() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+
+    let m = Mock::new()
+        .expect_method(Method::PUT)
+        .expect_path("/peers/bazqux")
+        .expect_json_body(
+            &Peer {
+                url: "http://orthanc_peer:8029".to_string(),
+                username: Some("orthanc".to_string()),
+                password: Some("orthanc".to_string()),
+                http_headers: Some(HttpHeaders::Values(
+                    hashmap! {"Foo".to_string() => "foo".to_string(), "Bar".to_string() => "bar".to_string()},
+                )),
+                certificate_file: None,
+                certificate_key_file: None,
+                certificate_key_password: None,
+        })
+        .return_status(200)
+        .return_body("")
+        .create_on(&mock_server);
+
+    let cl = Client::new(url);
+    let peer = Peer {
+        url: "http://orthanc_peer:8029".to_string(),
+        username: Some("orthanc".to_string()),
+        password: Some("orthanc".to_string()),
+        http_headers: Some(HttpHeaders::Values(
+            hashmap! {"Foo".to_string() => "foo".to_string(), "Bar".to_string() => "bar".to_string()},
+        )),
+        certificate_file: None,
+        certificate_key_file: None,
+        certificate_key_password: None,
+    };
+    cl.modify_peer("bazqux", peer).unwrap();
+
+    assert_eq!(m.times_called(), 1);
+}
+
+#[test]
+fn test_modify_peer_unknown_http_headers() {
+    let cl = Client::new("http://localhost:8042");
+    let peer = Peer {
+        url: "http://orthanc_peer:8029".to_string(),
+        username: None,
+        password: None,
+        http_headers: Some(HttpHeaders::Names(vec!["Foo".to_string()])),
+        certificate_file: None,
+        certificate_key_file: None,
+        certificate_key_password: None,
+    };
+
+    assert_eq!(
+        cl.modify_peer("bazqux", peer).unwrap_err(),
+        Error {
+            message: "Values of HTTP headers of peer bazqux are unknown: Foo".to_string(),
+            details: None,
+            kind: None,
+        }
+    );
+}
+
+#[test]
+fn test_delete_peer() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+
+    let m = Mock::new()
+        .expect_method(Method::DELETE)
+        .expect_path("/peers/bazqux")
+        .return_status(200)
+        .return_body("")
+        .create_on(&mock_server);
+
+    let cl = Client::new(url);
+    cl.delete_peer("bazqux").unwrap();
+
+    assert_eq!(m.times_called(), 1);
+}
+
+#[test]
+fn test_get_dicom_tag_value_patient() {
+    let patient = Patient {
+        id: "f88cbd3f-a00dfc59-9ca1ac2d-7ce9851a-40e5b493".to_string(),
+        is_stable: true,
+        last_update: NaiveDate::from_ymd_opt(2020, 1, 1)
+            .unwrap()
+            .and_hms_opt(15, 46, 17)
+            .unwrap(),
+        main_dicom_tags: hashmap! {
+            "OtherPatientIDs".to_string() => "".to_string(),
+            "PatientBirthDate".to_string() => "19670101".to_string(),
+            "PatientID".to_string() => "123456789".to_string(),
+            "PatientName".to_string() => "Rick Sanchez".to_string(),
+            "PatientSex".to_string() => "M".to_string()
+        },
+        studies: ["e8cafcbe-caf08c39-6e205f15-18554bb8-b3f9ef04".to_string()].to_vec(),
+        entity: EntityKind::Patient,
+        anonymized_from: None,
+        protected: false,
+        labels: vec![],
+        extra: HashMap::new(),
+    };
+    assert_eq!(patient.main_dicom_tag("PatientID"), Some("123456789"));
+    assert_eq!(patient.main_dicom_tag("FooBar"), None);
+}
+
+#[test]
+fn test_get_dicom_tag_value_study() {
+    let study = Study {
+        id: "63bf5d42-b5382159-01971752-e0ceea3d-399bbca5".to_string(),
+        is_stable: true,
+        last_update: NaiveDate::from_ymd_opt(2020, 8, 30)
+            .unwrap()
+            .and_hms_opt(19, 11, 9)
+            .unwrap(),
+        main_dicom_tags: hashmap! {
+            "AccessionNumber".to_string() => "foobar".to_string(),
+            "StudyDate".to_string() => "20110101".to_string(),
+            "StudyDescription".to_string() => "Brain".to_string(),
+            "StudyID".to_string() => "1742".to_string(),
+            "StudyInstanceUID".to_string() => "1.2.3.4.5.6789".to_string(),
+            "StudyTime".to_string() => "084707".to_string()
+        },
+        parent_patient: "7e43f8d3-e50280e6-470079e9-02241af1-d286bdbe".to_string(),
+        patient_main_dicom_tags: hashmap! {
+            "PatientBirthDate".to_string() => "19440101".to_string(),
+            "PatientID".to_string() => "c137".to_string(),
+            "PatientName".to_string() => "Rick Sanchez".to_string(),
+            "PatientSex".to_string() => "M".to_string(),
+        },
+        series: [
+            "cd00fffc-db25be29-0c6da430-c56796a5-ba06933c".to_string(),
+            "2ab7dbe7-f1a18a78-86145443-18a8ff93-0b65f2b2".to_string(),
+        ]
+        .to_vec(),
+        entity: EntityKind::Study,
+        anonymized_from: None,
+        labels: vec![],
+        extra: HashMap::new(),
+    };
+    assert_eq!(study.main_dicom_tag("StudyID"), Some("1742"));
+    assert_eq!(study.main_dicom_tag("PatientID"), Some("c137"));
+    assert_eq!(study.main_dicom_tag("FooBar"), None);
+}
+
+#[test]
+fn test_get_dicom_tag_value_series() {
+    let series = Series {
+        id: "cd00fffc-db25be29-0c6da430-c56796a5-ba06933c".to_string(),
+        status: "Unknown".to_string(),
+        is_stable: true,
+        last_update: NaiveDate::from_ymd_opt(2020, 8, 30)
+            .unwrap()
+            .and_hms_opt(19, 11, 9)
+            .unwrap(),
+        main_dicom_tags: hashmap! {
+            "BodyPartExamined".to_string() => "ABDOMEN".to_string(),
+            "Modality".to_string() => "MR".to_string(),
+            "ProtocolName".to_string() => "TCP".to_string(),
+            "SeriesDate".to_string() => "20110101".to_string(),
+            "SeriesInstanceUID".to_string() => "1.2.3.4.5.6789".to_string(),
+            "SeriesNumber".to_string() => "1101".to_string(),
+            "SeriesTime".to_string() => "091313.93".to_string(),
+
+        },
+        parent_study: "63bf5d42-b5382159-01971752-e0ceea3d-399bbca5".to_string(),
+        expected_number_of_instances: Some(17),
+        instances: [
+            "556530b5-de7c487b-110b9d0e-12cfdbb9-f06b546e".to_string(),
+            "c46605db-836489fa-cb55fbbc-13c8a913-b0bad6ac".to_string(),
+            "9b63498d-cae4f25e-f52206b2-cbb4dc0e-dc55c788".to_string(),
+        ]
+        .to_vec(),
+        entity: EntityKind::Series,
+        anonymized_from: None,
+        labels: vec![],
+        extra: HashMap::new(),
+    };
+    assert_eq!(series.main_dicom_tag("SeriesNumber"), Some("1101"));
+    assert_eq!(series.main_dicom_tag("FooBar"), None);
+}
+
+#[test]
+fn test_get_dicom_tag_value_instance() {
+    let instance = Instance {
+        id: "29fa4d9d-51a69d1d-70e2b29a-fd824316-50850d0c".to_string(),
+        main_dicom_tags: hashmap! {
+            "ImageOrientationPatient".to_string() => "1\\0\\0\\0\\1\\0".to_string(),
+            "ImagePositionPatient".to_string() => "-17\\42\\13".to_string(),
+            "InstanceCreationDate".to_string() => "20130326".to_string(),
+            "InstanceCreationTime".to_string() => "135901".to_string(),
+            "InstanceNumber".to_string() => "13".to_string(),
+            "SOPInstanceUID".to_string() => "1.2.3.4.5.6789".to_string(),
+        },
+        parent_series: "82081568-b6f8f4e6-ced76876-6504da25-ed0dfe03".to_string(),
+        index_in_series: Some(13),
+        file_uuid: "d8c5eff3-986c-4fe4-b06e-7e52b2a4238e".to_string(),
+        file_size: 139402,
+        modified_from: Some("22c54cb6-28302a69-3ff454a3-676b98f4-b84cd80a".to_string()),
+        entity: EntityKind::Instance,
+        anonymized_from: None,
+        labels: vec![],
+        extra: HashMap::new(),
+    };
+    assert_eq!(instance.main_dicom_tag("InstanceNumber"), Some("13"));
+    assert_eq!(instance.main_dicom_tag("FooBar"), None);
+}
+
+#[test]
+fn test_search_patient_level() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+
+    let m = Mock::new()
+        .expect_method(Method::POST)
+        .expect_path("/tools/find")
+        .expect_json_body(&Search {
+            level: EntityKind::Patient,
+            query: hashmap! {"PatientID".to_string() => "foobar".to_string()},
+            expand: Some(true),
+            labels: None,
+            labels_constraint: None,
+        })
+        .return_status(200)
+        .return_header("Content-Type", "application/json")
+        .return_body(
+            r#"
+                    [
+                        {
+                            "ID": "f88cbd3f-a00dfc59-9ca1ac2d-7ce9851a-40e5b493",
+                            "IsStable": true,
+                            "LastUpdate": "20200101T154617",
+                            "MainDicomTags": {
+                                "OtherPatientIDs": "",
+                                "PatientBirthDate": "19670101",
+                                "PatientID": "123456789",
+                                "PatientName": "Rick Sanchez",
+                                "PatientSex": "M"
+                            },
+                            "Studies": [
+                                "e8cafcbe-caf08c39-6e205f15-18554bb8-b3f9ef04"
+                            ],
+                            "Type": "Patient"
+                        },
+                        {
+                            "ID": "7e43f8d3-e50280e6-470079e9-02241af1-d286bdbe",
+                            "IsStable": true,
+                            "LastUpdate": "20200826T174531",
+                            "MainDicomTags": {
+                                "OtherPatientIDs": "",
+                                "PatientBirthDate": "19440101",
+                                "PatientID": "987654321",
+                                "PatientName": "Morty Smith"
+                            },
+                            "Studies": [
+                                "63bf5d42-b5382159-01971752-e0ceea3d-399bbca5"
+                            ],
+                            "Type": "Patient"
+                        }
+                    ]
+               "#,
+        )
+        .create_on(&mock_server);
+
+    let cl = Client::new(url);
+    let patients: Vec<Patient> = cl
+        .search(hashmap! {"PatientID".to_string() => "foobar".to_string()})
+        .unwrap();
+
+    assert_eq!(
+        patients,
+        [
+            Patient {
+                id: "f88cbd3f-a00dfc59-9ca1ac2d-7ce9851a-40e5b493".to_string(),
+                is_stable: true,
+                last_update: NaiveDate::from_ymd_opt(2020, 1, 1)
+                    .unwrap()
+                    .and_hms_opt(15, 46, 17)
+                    .unwrap(),
+                main_dicom_tags: hashmap! {
+                    "OtherPatientIDs".to_string() => "".to_string(),
+                    "PatientBirthDate".to_string() => "19670101".to_string(),
+                    "PatientID".to_string() => "123456789".to_string(),
+                    "PatientName".to_string() => "Rick Sanchez".to_string(),
+                    "PatientSex".to_string() => "M".to_string()
+                },
+                studies: ["e8cafcbe-caf08c39-6e205f15-18554bb8-b3f9ef04".to_string()]
+                    .to_vec(),
+                entity: EntityKind::Patient,
+                anonymized_from: None,
+                protected: false,
+                labels: vec![],
+                extra: HashMap::new(),
+            },
+            Patient {
+                id: "7e43f8d3-e50280e6-470079e9-02241af1-d286bdbe".to_string(),
+                is_stable: true,
+                last_update: NaiveDate::from_ymd_opt(2020, 8, 26)
+                    .unwrap()
+                    .and_hms_opt(17, 45, 31)
+                    .unwrap(),
+                main_dicom_tags: hashmap! {
+                    "OtherPatientIDs".to_string() => "".to_string(),
+                    "PatientBirthDate".to_string() => "19440101".to_string(),
+                    "PatientID".to_string() => "987654321".to_string(),
+                    "PatientName".to_string() => "Morty Smith".to_string(),
+                },
+                studies: ["63bf5d42-b5382159-01971752-e0ceea3d-399bbca5".to_string()]
+                    .to_vec(),
+                entity: EntityKind::Patient,
+                anonymized_from: None,
+                protected: false,
+                labels: vec![],
+                extra: HashMap::new(),
+            },
+        ]
+    );
+    assert_eq!(m.times_called(), 1);
+}
+
+#[test]
+fn test_search_study_level() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+
+    let m = Mock::new()
+        .expect_method(Method::POST)
+        .expect_path("/tools/find")
+        .expect_json_body(&Search {
+            level: EntityKind::Study,
+            query: hashmap! {"StudyID".to_string() => "foobar".to_string()},
+            expand: Some(true),
+            labels: None,
+            labels_constraint: None,
+        })
+        .return_status(200)
+        .return_header("Content-Type", "application/json")
+        .return_body(
+            r#"
+                    [
+                        {
+                            "ID": "63bf5d42-b5382159-01971752-e0ceea3d-399bbca5",
+                            "IsStable": true,
+                            "LastUpdate": "20200830T191109",
+                            "MainDicomTags": {
+                                "AccessionNumber": "foobar",
+                                "StudyDate": "20110101",
+                                "StudyDescription": "Brain",
+                                "StudyID": "1742",
+                                "StudyInstanceUID": "1.2.3.4.5.6789",
+                                "StudyTime": "084707"
+                            },
+                            "ParentPatient": "7e43f8d3-e50280e6-470079e9-02241af1-d286bdbe",
+                            "PatientMainDicomTags": {
+                                "PatientBirthDate": "19440101",
+                                "PatientID": "c137",
+                                "PatientName": "Rick Sanchez",
+                                "PatientSex": "M"
+                            },
+                            "Series": [
+                                "cd00fffc-db25be29-0c6da430-c56796a5-ba06933c",
+                                "2ab7dbe7-f1a18a78-86145443-18a8ff93-0b65f2b2"
+                            ],
+                            "Type": "Study"
+                        },
+                        {
+                            "ID": "e8cafcbe-caf08c39-6e205f15-18554bb8-b3f9ef04",
+                            "IsStable": true,
+                            "LastUpdate": "20200901T185211",
+                            "MainDicomTags": {
+                                "AccessionNumber": "bazqux",
+                                "StudyDate": "20120101",
+                                "StudyDescription": "Knee",
+                                "StudyID": "1010100",
+                                "StudyInstanceUID": "1.2.3.4.5.67810",
+                                "StudyTime": "130431"
+                            },
+                            "ParentPatient": "f88cbd3f-a00dfc59-9ca1ac2d-7ce9851a-40e5b493",
+                            "PatientMainDicomTags": {
+                                "PatientBirthDate": "19670101",
+                                "PatientID": "4217",
+                                "PatientName": "Summer Smith",
+                                "PatientSex": "F"
+                            },
+                            "Series": [
+                                "222bbd7e-4dfbc5a8-ea58f933-f1747134-0810c7c8",
+                                "54f8778a-75ba559c-db7c7c1a-c1056140-ef74d487"
+                            ],
+                            "Type": "Study"
+                        }
+                    ]
+               "#,
+        )
+        .create_on(&mock_server);
+
+    let cl = Client::new(url);
+    let studies: Vec<Study> = cl
+        .search(hashmap! {"StudyID".to_string() => "foobar".to_string()})
+        .unwrap();
+
+    assert_eq!(
+        studies,
+        [
+            Study {
+                id: "63bf5d42-b5382159-01971752-e0ceea3d-399bbca5".to_string(),
+                is_stable: true,
+                last_update: NaiveDate::from_ymd_opt(2020, 8, 30)
+                    .unwrap()
+                    .and_hms_opt(19, 11, 9)
+                    .unwrap(),
+                main_dicom_tags: hashmap! {
+                    "AccessionNumber".to_string() => "foobar".to_string(),
+                    "StudyDate".to_string() => "20110101".to_string(),
+                    "StudyDescription".to_string() => "Brain".to_string(),
+                    "StudyID".to_string() => "1742".to_string(),
+                    "StudyInstanceUID".to_string() => "1.2.3.4.5.6789".to_string(),
+                    "StudyTime".to_string() => "084707".to_string()
+                },
+                parent_patient: "7e43f8d3-e50280e6-470079e9-02241af1-d286bdbe".to_string(),
+                patient_main_dicom_tags: hashmap! {
+                    "PatientBirthDate".to_string() => "19440101".to_string(),
+                    "PatientID".to_string() => "c137".to_string(),
+                    "PatientName".to_string() => "Rick Sanchez".to_string(),
+                    "PatientSex".to_string() => "M".to_string(),
+                },
+                series: [
+                    "cd00fffc-db25be29-0c6da430-c56796a5-ba06933c".to_string(),
+                    "2ab7dbe7-f1a18a78-86145443-18a8ff93-0b65f2b2".to_string()
+                ]
+                .to_vec(),
+                entity: EntityKind::Study,
+                anonymized_from: None,
+                labels: vec![],
+                extra: HashMap::new(),
+            },
+            Study {
+                id: "e8cafcbe-caf08c39-6e205f15-18554bb8-b3f9ef04".to_string(),
+                is_stable: true,
+                last_update: NaiveDate::from_ymd_opt(2020, 9, 1)
+                    .unwrap()
+                    .and_hms_opt(18, 52, 11)
+                    .unwrap(),
+                main_dicom_tags: hashmap! {
+                    "AccessionNumber".to_string() => "bazqux".to_string(),
+                    "StudyDate".to_string() => "20120101".to_string(),
+                    "StudyDescription".to_string() => "Knee".to_string(),
+                    "StudyID".to_string() => "1010100".to_string(),
+                    "StudyInstanceUID".to_string() => "1.2.3.4.5.67810".to_string(),
+                    "StudyTime".to_string() => "130431".to_string()
+                },
+                parent_patient: "f88cbd3f-a00dfc59-9ca1ac2d-7ce9851a-40e5b493".to_string(),
+                patient_main_dicom_tags: hashmap! {
+                    "PatientBirthDate".to_string() => "19670101".to_string(),
+                    "PatientID".to_string() => "4217".to_string(),
+                    "PatientName".to_string() => "Summer Smith".to_string(),
+                    "PatientSex".to_string() => "F".to_string(),
+                },
+                series: [
+                    "222bbd7e-4dfbc5a8-ea58f933-f1747134-0810c7c8".to_string(),
+                    "54f8778a-75ba559c-db7c7c1a-c1056140-ef74d487".to_string()
+                ]
+                .to_vec(),
+                entity: EntityKind::Study,
+                anonymized_from: None,
+                labels: vec![],
+                extra: HashMap::new(),
+            },
+        ]
+    );
+    assert_eq!(m.times_called(), 1);
+}
+
+#[test]
+fn test_search_series_level() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+
+    let m = Mock::new()
+        .expect_method(Method::POST)
+        .expect_path("/tools/find")
+        .expect_json_body(&Search {
+            level: EntityKind::Series,
+            query: hashmap! {"SeriesID".to_string() => "foobar".to_string()},
+            expand: Some(true),
+            labels: None,
+            labels_constraint: None,
+        })
+        .return_status(200)
+        .return_header("Content-Type", "application/json")
+        .return_body(
+            r#"
+                    [
+                        {
+                            "ExpectedNumberOfInstances": 17,
+                            "ID": "cd00fffc-db25be29-0c6da430-c56796a5-ba06933c",
+                            "Instances": [
+                                "556530b5-de7c487b-110b9d0e-12cfdbb9-f06b546e",
+                                "c46605db-836489fa-cb55fbbc-13c8a913-b0bad6ac",
+                                "9b63498d-cae4f25e-f52206b2-cbb4dc0e-dc55c788"
+                            ],
+                            "IsStable": true,
+                            "LastUpdate": "20200830T191109",
+                            "MainDicomTags": {
+                                "BodyPartExamined": "ABDOMEN",
+                                "Modality": "MR",
+                                "ProtocolName": "TCP",
+                                "SeriesDate": "20110101",
+                                "SeriesInstanceUID": "1.2.3.4.5.6789",
+                                "SeriesNumber": "1101",
+                                "SeriesTime": "091313.93"
+                            },
+                            "ParentStudy": "63bf5d42-b5382159-01971752-e0ceea3d-399bbca5",
+                            "Status": "Unknown",
+                            "Type": "Series"
+                        },
+                        {
+                            "ExpectedNumberOfInstances": null,
+                            "ID": "2ab7dbe7-f1a18a78-86145443-18a8ff93-0b65f2b2",
+                            "Instances": [
+                                "a17e85ca-380bb2dc-d29ea4b7-6e73c10a-ca6ba458",
+                                "1c81e7e8-30642777-ffc2ca41-c7536670-7ad68124"
+                            ],
+                            "IsStable": true,
+                            "LastUpdate": "20200830T191109",
+                            "MainDicomTags": {
+                                "BodyPartExamined": "HEAD",
+                                "Modality": "CT",
+                                "ProtocolName": "UDP",
+                                "SeriesDate": "20110101",
+                                "SeriesInstanceUID": "1.2.3.4.5.67810",
+                                "SeriesNumber": "1102",
+                                "SeriesTime": "091313.93"
+                            },
+                            "ParentStudy": "63bf5d42-b5382159-01971752-e0ceea3d-399bbca5",
+                            "Status": "Unknown",
+                            "Type": "Series"
+                        }
+                    ]
+               "#,
+        )
+        .create_on(&mock_server);
+
+    let cl = Client::new(url);
+    let series: Vec<Series> = cl
+        .search(hashmap! {"SeriesID".to_string() => "foobar".to_string()})
+        .unwrap();
+
+    assert_eq!(
+        series,
+        [
+            Series {
+                id: "cd00fffc-db25be29-0c6da430-c56796a5-ba06933c".to_string(),
+                status: "Unknown".to_string(),
+                is_stable: true,
+                last_update: NaiveDate::from_ymd_opt(2020, 8, 30)
+                    .unwrap()
+                    .and_hms_opt(19, 11, 9)
+                    .unwrap(),
+                main_dicom_tags: hashmap! {
+                    "BodyPartExamined".to_string() => "ABDOMEN".to_string(),
+                    "Modality".to_string() => "MR".to_string(),
+                    "ProtocolName".to_string() => "TCP".to_string(),
+                    "SeriesDate".to_string() => "20110101".to_string(),
+                    "SeriesInstanceUID".to_string() => "1.2.3.4.5.6789".to_string(),
+                    "SeriesNumber".to_string() => "1101".to_string(),
+                    "SeriesTime".to_string() => "091313.93".to_string(),
+
+                },
+                parent_study: "63bf5d42-b5382159-01971752-e0ceea3d-399bbca5".to_string(),
+                expected_number_of_instances: Some(17),
+                instances: [
+                    "556530b5-de7c487b-110b9d0e-12cfdbb9-f06b546e".to_string(),
+                    "c46605db-836489fa-cb55fbbc-13c8a913-b0bad6ac".to_string(),
+                    "9b63498d-cae4f25e-f52206b2-cbb4dc0e-dc55c788".to_string(),
+                ]
+                .to_vec(),
+                entity: EntityKind::Series,
+                anonymized_from: None,
+                labels: vec![],
+                extra: HashMap::new(),
+            },
+            Series {
+                id: "2ab7dbe7-f1a18a78-86145443-18a8ff93-0b65f2b2".to_string(),
+                status: "Unknown".to_string(),
+                is_stable: true,
+                last_update: NaiveDate::from_ymd_opt(2020, 8, 30)
+                    .unwrap()
+                    .and_hms_opt(19, 11, 9)
+                    .unwrap(),
+                main_dicom_tags: hashmap! {
+                    "BodyPartExamined".to_string() => "HEAD".to_string(),
+                    "Modality".to_string() => "CT".to_string(),
+                    "ProtocolName".to_string() => "UDP".to_string(),
+                    "SeriesDate".to_string() => "20110101".to_string(),
+                    "SeriesInstanceUID".to_string() => "1.2.3.4.5.67810".to_string(),
+                    "SeriesNumber".to_string() => "1102".to_string(),
+                    "SeriesTime".to_string() => "091313.93".to_string(),
+
+                },
+                parent_study: "63bf5d42-b5382159-01971752-e0ceea3d-399bbca5".to_string(),
+                expected_number_of_instances: None,
+                instances: [
+                    "a17e85ca-380bb2dc-d29ea4b7-6e73c10a-ca6ba458".to_string(),
+                    "1c81e7e8-30642777-ffc2ca41-c7536670-7ad68124".to_string(),
+                ]
+                .to_vec(),
+                entity: EntityKind::Series,
+                anonymized_from: None,
+                labels: vec![],
+                extra: HashMap::new(),
+            },
+        ]
+    );
+    assert_eq!(m.times_called(), 1);
+}
+
+#[test]
+fn test_search_instance_level() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+
+    let m = Mock::new()
+        .expect_method(Method::POST)
+        .expect_path("/tools/find")
+        .expect_json_body(&Search {
+            level: EntityKind::Instance,
+            query: hashmap! {"InstanceID".to_string() => "foobar".to_string()},
+            expand: Some(true),
+            labels: None,
+            labels_constraint: None,
+        })
+        .return_status(200)
+        .return_header("Content-Type", "application/json")
+        .return_body(
+            r#"
+                    [
                         {
-                            "ID": "7e43f8d3-e50280e6-470079e9-02241af1-d286bdbe",
-                            "IsStable": true,
-                            "LastUpdate": "20200826T174531",
+                            "FileSize": 139402,
+                            "FileUuid": "d8c5eff3-986c-4fe4-b06e-7e52b2a4238e",
+                            "ID": "29fa4d9d-51a69d1d-70e2b29a-fd824316-50850d0c",
+                            "IndexInSeries": 13,
                             "MainDicomTags": {
-                                "OtherPatientIDs": "",
-                                "PatientBirthDate": "19440101",
-                                "PatientID": "987654321",
-                                "PatientName": "Morty Smith"
+                                "ImageOrientationPatient": "1\\0\\0\\0\\1\\0",
+                                "ImagePositionPatient": "-17\\42\\13",
+                                "InstanceCreationDate": "20130326",
+                                "InstanceCreationTime": "135901",
+                                "InstanceNumber": "13",
+                                "SOPInstanceUID": "1.2.3.4.5.6789"
                             },
-                            "Studies": [
-                                "63bf5d42-b5382159-01971752-e0ceea3d-399bbca5"
-                            ],
-                            "Type": "Patient"
+                            "ModifiedFrom": "22c54cb6-28302a69-3ff454a3-676b98f4-b84cd80a",
+                            "ParentSeries": "82081568-b6f8f4e6-ced76876-6504da25-ed0dfe03",
+                            "Type": "Instance"
+                        },
+                        {
+                            "FileSize": 381642,
+                            "FileUuid": "86bbad65-2c98-4cb0-bf77-0ef0243410a4",
+                            "ID": "286a251e-46571bd6-0e14ab9a-1baadddc-d0146ea0",
+                            "MainDicomTags": {
+                                "ImageOrientationPatient": "-1\\0\\0\\0\\1\\0",
+                                "ImagePositionPatient": "-17\\42\\14",
+                                "InstanceCreationDate": "20130326",
+                                "InstanceCreationTime": "135830",
+                                "InstanceNumber": "75",
+                                "SOPInstanceUID": "1.2.3.4.5.67810"
+                            },
+                            "ParentSeries": "a240e0d7-538699a0-7464bb4b-a906f72a-fa3a32c7",
+                            "Type": "Instance"
                         }
                     ]
                "#,
@@ -2373,951 +5774,2103 @@ fn test_search_patient_level() {
         .create_on(&mock_server);
 
     let cl = Client::new(url);
-    let patients: Vec<Patient> = cl
-        .search(hashmap! {"PatientID".to_string() => "foobar".to_string()})
+    let instances: Vec<Instance> = cl
+        .search(hashmap! {"InstanceID".to_string() => "foobar".to_string()})
+        .unwrap();
+
+    assert_eq!(
+        instances,
+        [
+            Instance {
+                id: "29fa4d9d-51a69d1d-70e2b29a-fd824316-50850d0c".to_string(),
+                main_dicom_tags: hashmap! {
+                    "ImageOrientationPatient".to_string() => "1\\0\\0\\0\\1\\0".to_string(),
+                    "ImagePositionPatient".to_string() => "-17\\42\\13".to_string(),
+                    "InstanceCreationDate".to_string() => "20130326".to_string(),
+                    "InstanceCreationTime".to_string() => "135901".to_string(),
+                    "InstanceNumber".to_string() => "13".to_string(),
+                    "SOPInstanceUID".to_string() => "1.2.3.4.5.6789".to_string(),
+                },
+                parent_series: "82081568-b6f8f4e6-ced76876-6504da25-ed0dfe03".to_string(),
+                index_in_series: Some(13),
+                file_uuid: "d8c5eff3-986c-4fe4-b06e-7e52b2a4238e".to_string(),
+                file_size: 139402,
+                modified_from: Some(
+                    "22c54cb6-28302a69-3ff454a3-676b98f4-b84cd80a".to_string()
+                ),
+                entity: EntityKind::Instance,
+                anonymized_from: None,
+                labels: vec![],
+                extra: HashMap::new(),
+            },
+            Instance {
+                id: "286a251e-46571bd6-0e14ab9a-1baadddc-d0146ea0".to_string(),
+                main_dicom_tags: hashmap! {
+                    "ImageOrientationPatient".to_string() => "-1\\0\\0\\0\\1\\0".to_string(),
+                    "ImagePositionPatient".to_string() => "-17\\42\\14".to_string(),
+                    "InstanceCreationDate".to_string() => "20130326".to_string(),
+                    "InstanceCreationTime".to_string() => "135830".to_string(),
+                    "InstanceNumber".to_string() => "75".to_string(),
+                    "SOPInstanceUID".to_string() => "1.2.3.4.5.67810".to_string(),
+                },
+                parent_series: "a240e0d7-538699a0-7464bb4b-a906f72a-fa3a32c7".to_string(),
+                index_in_series: None,
+                file_uuid: "86bbad65-2c98-4cb0-bf77-0ef0243410a4".to_string(),
+                file_size: 381642,
+                modified_from: None,
+                entity: EntityKind::Instance,
+                anonymized_from: None,
+                labels: vec![],
+                extra: HashMap::new(),
+            },
+        ]
+    );
+    assert_eq!(m.times_called(), 1);
+}
+
+#[test]
+fn test_search_error() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+
+    let m = Mock::new()
+        .expect_method(Method::POST)
+        .expect_path("/tools/find")
+        .expect_json_body(&Search {
+            level: EntityKind::Patient,
+            query: hashmap! {"PatientID".to_string() => "foobar".to_string()},
+            expand: Some(true),
+            labels: None,
+            labels_constraint: None,
+        })
+        .return_status(500)
+        .return_header("Content-Type", "application/json")
+        .return_body(
+            r#"
+                    {
+                       "HttpError" : "Internal Server Error",
+                       "HttpStatus" : 500,
+                       "Message" : "Unknown DICOM tag",
+                       "Method" : "POST",
+                       "OrthancError" : "Unknown DICOM tag",
+                       "OrthancStatus" : 27,
+                       "Uri" : "/tools/find"
+                    }
+               "#,
+        )
+        .create_on(&mock_server);
+
+    let cl = Client::new(url);
+    let res: std::result::Result<Vec<Patient>, Error> =
+        cl.search(hashmap! {"PatientID".to_string() => "foobar".to_string()});
+
+    assert_eq!(
+        res.unwrap_err(),
+        Error {
+            message: "API error: 500 Internal Server Error".to_string(),
+            details: Some(Box::new(ApiError {
+                method: "POST".to_string(),
+                uri: "/tools/find".to_string(),
+                message: "Unknown DICOM tag".to_string(),
+                details: None,
+                http_status: 500,
+                http_error: "Internal Server Error".to_string(),
+                orthanc_status: 27,
+                orthanc_error: "Unknown DICOM tag".to_string(),
+            }),),
+            kind: None,
+        },
+    );
+    assert_eq!(m.times_called(), 1);
+}
+
+#[test]
+fn test_search_with_labels() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+    let system = mock_system(&mock_server, "1.12.0");
+
+    let m = Mock::new()
+        .expect_method(Method::POST)
+        .expect_path("/tools/find")
+        .expect_json_body(&serde_json::json!({
+            "Level": "Study",
+            "Query": {},
+            "Expand": true,
+            "Labels": ["foo", "bar"],
+            "LabelsConstraint": "Any"
+        }))
+        .return_status(200)
+        .return_body(&format!(
+            "[{}]",
+            STUDY_BAR.replace(
+                r#""Type": "Study""#,
+                r#""Type": "Study", "Labels": ["foo"]"#
+            )
+        ))
+        .create_on(&mock_server);
+
+    let cl = Client::new(url);
+    let studies: Vec<Study> = cl
+        .search_with_labels(HashMap::new(), &["foo", "bar"], LabelsConstraint::Any)
+        .unwrap();
+
+    assert_eq!(studies.len(), 1);
+    assert_eq!(studies[0].id, "bar");
+    assert_eq!(studies[0].labels, vec!["foo"]);
+    assert_eq!(m.times_called(), 1);
+    assert_eq!(system.times_called(), 1);
+}
+
+#[test]
+fn test_labels() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+    let system = mock_system(&mock_server, "1.12.0");
+
+    let m = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/series/foo/labels")
+        .return_status(200)
+        .return_body(r#"["bar", "baz"]"#)
+        .create_on(&mock_server);
+
+    let cl = Client::new(url);
+    let labels = cl.labels(EntityKind::Series, "foo").unwrap();
+
+    assert_eq!(labels, vec!["bar", "baz"]);
+    assert_eq!(m.times_called(), 1);
+    assert_eq!(system.times_called(), 1);
+}
+
+#[test]
+fn test_add_label() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+    let system = mock_system(&mock_server, "1.12.0");
+
+    let m = Mock::new()
+        .expect_method(Method::PUT)
+        .expect_path("/instances/foo/labels/bar")
+        .return_status(200)
+        .create_on(&mock_server);
+
+    let cl = Client::new(url);
+    cl.add_label(EntityKind::Instance, "foo", "bar").unwrap();
+
+    assert_eq!(m.times_called(), 1);
+    assert_eq!(system.times_called(), 1);
+}
+
+#[test]
+fn test_add_label_invalid() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+
+    let m = Mock::new().return_status(200).create_on(&mock_server);
+
+    let cl = Client::new(url);
+    let resp = cl.add_label(EntityKind::Instance, "foo", "../../patients/bar");
+
+    assert_eq!(
+        resp.unwrap_err(),
+        Error {
+            message: "Invalid label: ../../patients/bar".to_string(),
+            details: None,
+            kind: None,
+        }
+    );
+    let resp = cl.remove_label(EntityKind::Instance, "foo", "bar baz");
+    assert_eq!(resp.unwrap_err().message, "Invalid label: bar baz");
+    assert_eq!(m.times_called(), 0);
+}
+
+#[test]
+fn test_remove_label() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+    let system = mock_system(&mock_server, "1.12.0");
+
+    let m = Mock::new()
+        .expect_method(Method::DELETE)
+        .expect_path("/patients/foo/labels/bar")
+        .return_status(200)
+        .create_on(&mock_server);
+
+    let cl = Client::new(url);
+    cl.remove_label(EntityKind::Patient, "foo", "bar").unwrap();
+
+    assert_eq!(m.times_called(), 1);
+    assert_eq!(system.times_called(), 1);
+}
+
+#[test]
+fn test_all_labels() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+    let system = mock_system(&mock_server, "1.12.0");
+
+    let m = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/tools/labels")
+        .return_status(200)
+        .return_body(r#"["bar", "baz", "foo"]"#)
+        .create_on(&mock_server);
+
+    let cl = Client::new(url);
+    let labels = cl.all_labels().unwrap();
+
+    assert_eq!(labels, vec!["bar", "baz", "foo"]);
+    assert_eq!(m.times_called(), 1);
+    assert_eq!(system.times_called(), 1);
+}
+
+#[test]
+fn test_modality_move() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+
+    let m = Mock::new()
+        .expect_method(Method::POST)
+        .expect_path("/modalities/foo/move")
+        .expect_json_body(&ModalityMove {
+            level: EntityKind::Study,
+            target_aet: Some("MODALITY_TWO".to_string()),
+            resources: vec![hashmap! {
+                "StudyInstanceUID".to_string() => "99.88.77.66.5.4.3.2.1.0".to_string(),
+            }],
+            timeout: None,
+        })
+        .return_status(200)
+        .create_on(&mock_server);
+
+    let cl = Client::new(url);
+    cl.modality_move(
+        "foo",
+        ModalityMove {
+            level: EntityKind::Study,
+            target_aet: Some("MODALITY_TWO".to_string()),
+            resources: vec![hashmap! {
+                "StudyInstanceUID".to_string() => "99.88.77.66.5.4.3.2.1.0".to_string(),
+            }],
+            timeout: None,
+        },
+    )
+    .unwrap();
+
+    assert_eq!(m.times_called(), 1);
+}
+
+#[test]
+fn test_modality_move_error() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+
+    let m = Mock::new()
+        .expect_method(Method::POST)
+        .expect_path("/modalities/foo/move")
+        .expect_json_body(&ModalityMove {
+            level: EntityKind::Study,
+            target_aet: Some("MODALITY_TWO".to_string()),
+            resources: vec![hashmap! {
+                "StudyInstanceUID".to_string() => "99.88.77.66.5.4.3.2.1.0".to_string(),
+            }],
+            timeout: None,
+        })
+        .return_status(500)
+        .return_header("Content-Type", "application/json")
+        .return_body(
+            r#"
+                    {
+                       "HttpError" : "Internal Server Error",
+                       "HttpStatus" : 500,
+                       "Message" : "Boom!",
+                       "Method" : "POST",
+                       "OrthancError" : "Boom!",
+                       "OrthancStatus" : 27,
+                       "Uri" : "/modalities/foo/move"
+                    }
+               "#,
+        )
+        .create_on(&mock_server);
+
+    let cl = Client::new(url);
+    let res = cl.modality_move(
+        "foo",
+        ModalityMove {
+            level: EntityKind::Study,
+            target_aet: Some("MODALITY_TWO".to_string()),
+            resources: vec![hashmap! {
+                "StudyInstanceUID".to_string() => "99.88.77.66.5.4.3.2.1.0".to_string(),
+            }],
+            timeout: None,
+        },
+    );
+
+    assert_eq!(
+        res.unwrap_err(),
+        Error {
+            message: "API error: 500 Internal Server Error".to_string(),
+            details: Some(Box::new(ApiError {
+                method: "POST".to_string(),
+                uri: "/modalities/foo/move".to_string(),
+                message: "Boom!".to_string(),
+                details: None,
+                http_status: 500,
+                http_error: "Internal Server Error".to_string(),
+                orthanc_status: 27,
+                orthanc_error: "Boom!".to_string(),
+            }),),
+            kind: None,
+        },
+    );
+    assert_eq!(m.times_called(), 1);
+}
+
+#[test]
+fn test_modality_find() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+
+    let m = Mock::new()
+        .expect_method(Method::POST)
+        .expect_path("/modalities/them/query")
+        .expect_json_body(&ModalityFind {
+            level: EntityKind::Study,
+            query: hashmap! {
+                "StudyInstanceUID".to_string() => "1.2.3.4.5.6.999".to_string()
+            },
+            normalize: None,
+        })
+        .return_status(200)
+        .return_header("Content-Type", "application/json")
+        .return_body(
+            r#"
+                {
+                    "ID": "1c315256-3eef-4ef6-aa8a-03947cc53513",
+                    "Path": "/queries/1c315256-3eef-4ef6-aa8a-03947cc53513"
+
+                }
+            "#,
+        )
+        .create_on(&mock_server);
+
+    let cl = Client::new(url);
+    assert_eq!(
+        cl.modality_find(
+            "them",
+            EntityKind::Study,
+            hashmap! {
+                "StudyInstanceUID".to_string() => "1.2.3.4.5.6.999".to_string()
+            },
+            None
+        )
+        .unwrap(),
+        ModalityFindResult {
+            id: "1c315256-3eef-4ef6-aa8a-03947cc53513".to_string(),
+            path: "/queries/1c315256-3eef-4ef6-aa8a-03947cc53513".to_string()
+        }
+    );
+
+    assert_eq!(m.times_called(), 1);
+}
+
+#[test]
+fn test_modality_find_error() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+
+    let m = Mock::new()
+        .expect_method(Method::POST)
+        .expect_path("/modalities/them/query")
+        .expect_json_body(&ModalityFind {
+            level: EntityKind::Study,
+            query: hashmap! {
+                "StudyInstanceUID".to_string() => "1.2.3.4.5.6.999".to_string()
+            },
+            normalize: None,
+        })
+        .return_status(500)
+        .return_header("Content-Type", "application/json")
+        .return_body(
+            r#"
+                {
+                   "Details" : "DicomAssociation - C-FIND to AET \"ORTHANC\": Peer aborted Association (or never connected)",
+                   "HttpError" : "Internal Server Error",
+                   "HttpStatus" : 500,
+                   "Message" : "Error in the network protocol",
+                   "Method" : "POST",
+                   "OrthancError" : "Error in the network protocol",
+                   "OrthancStatus" : 9,
+                   "Uri" : "/modalities/them/query"
+                }
+            "#,
+        )
+        .create_on(&mock_server);
+
+    let cl = Client::new(url);
+    assert_eq!(
+        cl.modality_find(
+            "them",
+            EntityKind::Study,
+            hashmap! {
+                "StudyInstanceUID".to_string() => "1.2.3.4.5.6.999".to_string()
+            },
+            None
+        )
+        .unwrap_err(),
+        Error{
+            message: "API error: 500 Internal Server Error".to_string(),
+            details: Some(Box::new(ApiError {
+                method: "POST".to_string(),
+                uri: "/modalities/them/query".to_string(),
+                message: "Error in the network protocol".to_string(),
+                details: Some("DicomAssociation - C-FIND to AET \"ORTHANC\": Peer aborted Association (or never connected)".to_string()),
+                http_status: 500,
+                http_error: "Internal Server Error".to_string(),
+                orthanc_status: 9,
+                orthanc_error: "Error in the network protocol".to_string(),
+
+            })),
+            kind: None,
+        }
+    );
+
+    assert_eq!(m.times_called(), 1);
+}
+
+#[test]
+fn test_list_queries() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+
+    let m = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/queries")
+        .return_status(200)
+        .return_header("Content-Type", "application/json")
+        .return_body(r#"["1c315256-3eef-4ef6-aa8a-03947cc53513", "1c315256-3eef-4ef6-aa8a-03947cc53514"]"#)
+        .create_on(&mock_server);
+
+    let cl = Client::new(url);
+    let queries = cl.queries().unwrap();
+
+    assert_eq!(
+        queries,
+        [
+            "1c315256-3eef-4ef6-aa8a-03947cc53513",
+            "1c315256-3eef-4ef6-aa8a-03947cc53514"
+        ]
+    );
+    assert_eq!(m.times_called(), 1);
+}
+
+#[test]
+fn test_get_query_level() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+
+    let m = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/queries/foo/level")
+        .return_status(200)
+        .return_header("Content-Type", "application/json")
+        .return_body("Instance")
+        .create_on(&mock_server);
+
+    let cl = Client::new(url);
+    assert_eq!(cl.query_level("foo").unwrap(), EntityKind::Instance);
+    assert_eq!(m.times_called(), 1);
+}
+
+#[test]
+fn test_get_query_level_error() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+
+    let m = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/queries/foo/level")
+        .return_status(200)
+        .return_header("Content-Type", "application/json")
+        .return_body("Foobar")
+        .create_on(&mock_server);
+
+    let cl = Client::new(url);
+    assert_eq!(
+        cl.query_level("foo").unwrap_err(),
+        Error {
+            message: "Unknown entity kind: Foobar".to_string(),
+            details: None,
+            kind: None,
+        }
+    );
+    assert_eq!(m.times_called(), 1);
+}
+
+#[test]
+fn test_get_query_modality() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+
+    let m = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/queries/foo/modality")
+        .return_status(200)
+        .return_header("Content-Type", "application/json")
+        .return_body("bar")
+        .create_on(&mock_server);
+
+    let cl = Client::new(url);
+    assert_eq!(cl.query_modality("foo").unwrap(), "bar".to_string());
+    assert_eq!(m.times_called(), 1);
+}
+
+#[test]
+fn test_get_query_query() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+
+    let body = r#"
+        {
+            "0008,0018": {
+                "Name": "SOPInstanceUID",
+                "Type": "String",
+                "Value": "1.3.46.670589.11.1.5.0.10176.2012103017543590042"
+            }
+        }
+    "#;
+
+    let m = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/queries/foo/query")
+        .return_status(200)
+        .return_header("Content-Type", "application/json")
+        .return_body(body)
+        .create_on(&mock_server);
+
+    let cl = Client::new(url);
+
+    let resp = cl.query_query("foo").unwrap();
+    let expected_resp: Value = serde_json::from_str(body).unwrap();
+    assert_eq!(resp, expected_resp);
+    assert_eq!(m.times_called(), 1);
+}
+
+#[test]
+fn test_list_query_answers() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+
+    let m = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/queries/foo/answers")
+        .return_status(200)
+        .return_header("Content-Type", "application/json")
+        .return_body(r#"["0", "1", "42"]"#)
+        .create_on(&mock_server);
+
+    let cl = Client::new(url);
+    let queries = cl.query_answers("foo").unwrap();
+
+    assert_eq!(queries, ["0", "1", "42"]);
+    assert_eq!(m.times_called(), 1);
+}
+
+#[test]
+fn test_get_query_answer() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+
+    let body = r#"
+        {
+            "0008,0018": {
+                "Name": "SOPInstanceUID",
+                "Type": "String",
+                "Value": "1.3.46.670589.11.1.5.0.10176.2012103017543590042"
+            },
+            "0008,0050": {
+                "Name": "AccessionNumber",
+                "Type": "String",
+                "Value": "REMOVED"
+            },
+            "0008,0052": {
+                "Name": "QueryRetrieveLevel",
+                "Type": "String",
+                "Value": "IMAGE"
+            },
+            "0008,0054": {
+                "Name": "RetrieveAETitle",
+                "Type": "String",
+                "Value": "ORTHANC"
+            }
+        }
+    "#;
+
+    let m = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/queries/foo/answers/0/content")
+        .return_status(200)
+        .return_header("Content-Type", "application/json")
+        .return_body(body)
+        .create_on(&mock_server);
+
+    let cl = Client::new(url);
+
+    let resp = cl.query_answer("foo", "0").unwrap();
+    let expected_resp: Value = serde_json::from_str(body).unwrap();
+    assert_eq!(resp, expected_resp);
+    assert_eq!(m.times_called(), 1);
+}
+
+#[test]
+fn test_retrieve_query_answer() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+
+    let m = Mock::new()
+        .expect_method(Method::POST)
+        .expect_path("/queries/foo/answers/0/retrieve")
+        .return_status(200)
+        .return_header("Content-Type", "application/json")
+        .create_on(&mock_server);
+
+    let cl = Client::new(url);
+    cl.retrieve_query_answer("foo", "0", None).unwrap();
+
+    assert_eq!(m.times_called(), 1);
+}
+
+#[test]
+fn test_retrieve_query_answer_with_target_aet() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+
+    let m = Mock::new()
+        .expect_method(Method::POST)
+        .expect_path("/queries/foo/answers/0/retrieve")
+        .expect_body(r#"{"TargetAet":"BAZ"}"#)
+        .return_status(200)
+        .return_header("Content-Type", "application/json")
+        .create_on(&mock_server);
+
+    let cl = Client::new(url);
+    cl.retrieve_query_answer("foo", "0", Some("BAZ")).unwrap();
+
+    assert_eq!(m.times_called(), 1);
+}
+
+#[test]
+fn test_retrieve_query_answers() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+
+    let m = Mock::new()
+        .expect_method(Method::POST)
+        .expect_path("/queries/foo/retrieve")
+        .return_status(200)
+        .return_header("Content-Type", "application/json")
+        .create_on(&mock_server);
+
+    let cl = Client::new(url);
+    cl.retrieve_query_answers("foo", None).unwrap();
+
+    assert_eq!(m.times_called(), 1);
+}
+
+#[test]
+fn test_retrieve_query_answers_with_target_aet() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+
+    let m = Mock::new()
+        .expect_method(Method::POST)
+        .expect_path("/queries/foo/retrieve")
+        .expect_body(r#"{"TargetAet":"BAZ"}"#)
+        .return_status(200)
+        .return_header("Content-Type", "application/json")
+        .create_on(&mock_server);
+
+    let cl = Client::new(url);
+    cl.retrieve_query_answers("foo", Some("BAZ")).unwrap();
+
+    assert_eq!(m.times_called(), 1);
+}
+
+#[test]
+fn test_modality_query() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+
+    let m = Mock::new()
+        .expect_method(Method::POST)
+        .expect_path("/modalities/them/query")
+        .expect_json_body(&ModalityFind {
+            level: EntityKind::Study,
+            query: hashmap! {
+                "StudyInstanceUID".to_string() => "1.2.3.4.5.6.999".to_string()
+            },
+            normalize: None,
+        })
+        .return_status(200)
+        .return_header("Content-Type", "application/json")
+        .return_body(
+            r#"
+                {
+                    "ID": "1c315256-3eef-4ef6-aa8a-03947cc53513",
+                    "Path": "/queries/1c315256-3eef-4ef6-aa8a-03947cc53513"
+                }
+            "#,
+        )
+        .create_on(&mock_server);
+
+    let cl = Client::new(url);
+    let query = cl
+        .modality_query(
+            "them",
+            EntityKind::Study,
+            hashmap! {
+                "StudyInstanceUID".to_string() => "1.2.3.4.5.6.999".to_string()
+            },
+            None,
+        )
         .unwrap();
 
+    assert_eq!(query.id(), "1c315256-3eef-4ef6-aa8a-03947cc53513");
+    assert_eq!(m.times_called(), 1);
+}
+
+#[test]
+fn test_remote_query_answers() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+
+    let m = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/queries/foo/answers")
+        .expect_query_param_exists("expand")
+        .expect_query_param_exists("simplify")
+        .return_status(200)
+        .return_header("Content-Type", "application/json")
+        .return_body(
+            r#"
+                [
+                    {
+                        "PatientID": "patient_1",
+                        "QueryRetrieveLevel": "STUDY",
+                        "StudyInstanceUID": "1.2.3.4.5678.909"
+                    },
+                    {
+                        "PatientID": "patient_2",
+                        "QueryRetrieveLevel": "STUDY",
+                        "ReferencedStudySequence": [],
+                        "StudyInstanceUID": "1.2.3.4.5678.910"
+                    }
+                ]
+            "#,
+        )
+        .create_on(&mock_server);
+
+    let cl = Client::new(url);
+    let answers = cl.remote_query("foo").answers().unwrap();
+
     assert_eq!(
-        patients,
-        [
-            Patient {
-                id: "f88cbd3f-a00dfc59-9ca1ac2d-7ce9851a-40e5b493".to_string(),
-                is_stable: true,
-                last_update: NaiveDate::from_ymd(2020, 1, 1).and_hms(15, 46, 17),
-                main_dicom_tags: hashmap! {
-                    "OtherPatientIDs".to_string() => "".to_string(),
-                    "PatientBirthDate".to_string() => "19670101".to_string(),
-                    "PatientID".to_string() => "123456789".to_string(),
-                    "PatientName".to_string() => "Rick Sanchez".to_string(),
-                    "PatientSex".to_string() => "M".to_string()
+        answers,
+        vec![
+            QueryAnswer {
+                id: "0".to_string(),
+                tags: hashmap! {
+                    "PatientID".to_string() => "patient_1".to_string(),
+                    "QueryRetrieveLevel".to_string() => "STUDY".to_string(),
+                    "StudyInstanceUID".to_string() => "1.2.3.4.5678.909".to_string(),
                 },
-                studies: ["e8cafcbe-caf08c39-6e205f15-18554bb8-b3f9ef04".to_string()]
-                    .to_vec(),
-                entity: EntityKind::Patient,
-                anonymized_from: None
             },
-            Patient {
-                id: "7e43f8d3-e50280e6-470079e9-02241af1-d286bdbe".to_string(),
-                is_stable: true,
-                last_update: NaiveDate::from_ymd(2020, 8, 26).and_hms(17, 45, 31),
-                main_dicom_tags: hashmap! {
-                    "OtherPatientIDs".to_string() => "".to_string(),
-                    "PatientBirthDate".to_string() => "19440101".to_string(),
-                    "PatientID".to_string() => "987654321".to_string(),
-                    "PatientName".to_string() => "Morty Smith".to_string(),
+            QueryAnswer {
+                id: "1".to_string(),
+                tags: hashmap! {
+                    "PatientID".to_string() => "patient_2".to_string(),
+                    "QueryRetrieveLevel".to_string() => "STUDY".to_string(),
+                    "StudyInstanceUID".to_string() => "1.2.3.4.5678.910".to_string(),
                 },
-                studies: ["63bf5d42-b5382159-01971752-e0ceea3d-399bbca5".to_string()]
-                    .to_vec(),
-                entity: EntityKind::Patient,
-                anonymized_from: None
             },
         ]
     );
+    assert_eq!(answers[1].level(), Some(EntityKind::Study));
+    assert_eq!(answers[1].patient_id(), Some("patient_2"));
+    assert_eq!(answers[1].study_instance_uid(), Some("1.2.3.4.5678.910"));
+    assert_eq!(answers[1].series_instance_uid(), None);
     assert_eq!(m.times_called(), 1);
 }
 
 #[test]
-fn test_search_study_level() {
+fn test_remote_query_answer() {
     let mock_server = MockServer::start();
     let url = mock_server.url("");
 
     let m = Mock::new()
-        .expect_method(Method::POST)
-        .expect_path("/tools/find")
-        .expect_json_body(&Search {
-            level: EntityKind::Study,
-            query: hashmap! {"StudyID".to_string() => "foobar".to_string()},
-            expand: Some(true),
-        })
+        .expect_method(Method::GET)
+        .expect_path("/queries/foo/answers/1/content")
+        .expect_query_param_exists("simplify")
         .return_status(200)
         .return_header("Content-Type", "application/json")
         .return_body(
             r#"
-                    [
-                        {
-                            "ID": "63bf5d42-b5382159-01971752-e0ceea3d-399bbca5",
-                            "IsStable": true,
-                            "LastUpdate": "20200830T191109",
-                            "MainDicomTags": {
-                                "AccessionNumber": "foobar",
-                                "StudyDate": "20110101",
-                                "StudyDescription": "Brain",
-                                "StudyID": "1742",
-                                "StudyInstanceUID": "1.2.3.4.5.6789",
-                                "StudyTime": "084707"
-                            },
-                            "ParentPatient": "7e43f8d3-e50280e6-470079e9-02241af1-d286bdbe",
-                            "PatientMainDicomTags": {
-                                "PatientBirthDate": "19440101",
-                                "PatientID": "c137",
-                                "PatientName": "Rick Sanchez",
-                                "PatientSex": "M"
-                            },
-                            "Series": [
-                                "cd00fffc-db25be29-0c6da430-c56796a5-ba06933c",
-                                "2ab7dbe7-f1a18a78-86145443-18a8ff93-0b65f2b2"
-                            ],
-                            "Type": "Study"
-                        },
-                        {
-                            "ID": "e8cafcbe-caf08c39-6e205f15-18554bb8-b3f9ef04",
-                            "IsStable": true,
-                            "LastUpdate": "20200901T185211",
-                            "MainDicomTags": {
-                                "AccessionNumber": "bazqux",
-                                "StudyDate": "20120101",
-                                "StudyDescription": "Knee",
-                                "StudyID": "1010100",
-                                "StudyInstanceUID": "1.2.3.4.5.67810",
-                                "StudyTime": "130431"
-                            },
-                            "ParentPatient": "f88cbd3f-a00dfc59-9ca1ac2d-7ce9851a-40e5b493",
-                            "PatientMainDicomTags": {
-                                "PatientBirthDate": "19670101",
-                                "PatientID": "4217",
-                                "PatientName": "Summer Smith",
-                                "PatientSex": "F"
-                            },
-                            "Series": [
-                                "222bbd7e-4dfbc5a8-ea58f933-f1747134-0810c7c8",
-                                "54f8778a-75ba559c-db7c7c1a-c1056140-ef74d487"
-                            ],
-                            "Type": "Study"
-                        }
-                    ]
-               "#,
+                {
+                    "QueryRetrieveLevel": "IMAGE",
+                    "SOPInstanceUID": "1.2.3.4.5.6789.1742",
+                    "SeriesInstanceUID": "1.2.3.4.5.6789"
+                }
+            "#,
         )
         .create_on(&mock_server);
 
     let cl = Client::new(url);
-    let studies: Vec<Study> = cl
-        .search(hashmap! {"StudyID".to_string() => "foobar".to_string()})
+    let answer = cl.remote_query("foo").answer("1").unwrap();
+
+    assert_eq!(answer.id, "1");
+    assert_eq!(answer.level(), Some(EntityKind::Instance));
+    assert_eq!(answer.series_instance_uid(), Some("1.2.3.4.5.6789"));
+    assert_eq!(answer.sop_instance_uid(), Some("1.2.3.4.5.6789.1742"));
+    assert_eq!(m.times_called(), 1);
+}
+
+#[test]
+fn test_remote_query_drill_down() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+
+    let m1 = Mock::new()
+        .expect_method(Method::POST)
+        .expect_path("/queries/foo/answers/0/query-series")
+        .expect_json_body(&serde_json::json!({
+            "Query": {"Modality": "MR"}
+        }))
+        .return_status(200)
+        .return_header("Content-Type", "application/json")
+        .return_body(r#"{"ID": "bar", "Path": "/queries/bar"}"#)
+        .create_on(&mock_server);
+    let m2 = Mock::new()
+        .expect_method(Method::POST)
+        .expect_path("/queries/bar/answers/1/query-instances")
+        .expect_json_body(&serde_json::json!({"Query": {}}))
+        .return_status(200)
+        .return_header("Content-Type", "application/json")
+        .return_body(r#"{"ID": "baz", "Path": "/queries/baz"}"#)
+        .create_on(&mock_server);
+    let m3 = Mock::new()
+        .expect_method(Method::POST)
+        .expect_path("/queries/qux/answers/2/query-studies")
+        .expect_json_body(&serde_json::json!({"Query": {}}))
+        .return_status(200)
+        .return_header("Content-Type", "application/json")
+        .return_body(r#"{"ID": "quux", "Path": "/queries/quux"}"#)
+        .create_on(&mock_server);
+
+    let cl = Client::new(url);
+    let series_query = cl
+        .remote_query("foo")
+        .query_series("0", hashmap! {"Modality".to_string() => "MR".to_string()})
         .unwrap();
+    assert_eq!(series_query.id(), "bar");
+    let instances_query = series_query.query_instances("1", hashmap! {}).unwrap();
+    assert_eq!(instances_query.id(), "baz");
+    let studies_query = cl
+        .remote_query("qux")
+        .query_studies("2", hashmap! {})
+        .unwrap();
+    assert_eq!(studies_query.id(), "quux");
+
+    assert_eq!(m1.times_called(), 1);
+    assert_eq!(m2.times_called(), 1);
+    assert_eq!(m3.times_called(), 1);
+}
+
+#[test]
+fn test_remote_query_retrieve() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+
+    let m1 = Mock::new()
+        .expect_method(Method::POST)
+        .expect_path("/queries/foo/retrieve")
+        .expect_json_body(&serde_json::json!({"Asynchronous": true}))
+        .return_status(200)
+        .return_header("Content-Type", "application/json")
+        .return_body(r#"{"ID": "job1", "Path": "/jobs/job1"}"#)
+        .create_on(&mock_server);
+    let m2 = Mock::new()
+        .expect_method(Method::POST)
+        .expect_path("/queries/foo/answers/0/retrieve")
+        .expect_json_body(&serde_json::json!({
+            "TargetAet": "BAZ",
+            "RetrieveMethod": "C-GET",
+            "Asynchronous": true
+        }))
+        .return_status(200)
+        .return_header("Content-Type", "application/json")
+        .return_body(r#"{"ID": "job2", "Path": "/jobs/job2"}"#)
+        .create_on(&mock_server);
+    let m3 = Mock::new()
+        .expect_method(Method::POST)
+        .expect_path("/queries/foo/answers/1/retrieve")
+        .expect_json_body(&serde_json::json!({
+            "TargetAet": "BAZ",
+            "RetrieveMethod": "C-GET",
+            "Asynchronous": true
+        }))
+        .return_status(200)
+        .return_header("Content-Type", "application/json")
+        .return_body(r#"{"ID": "job3", "Path": "/jobs/job3"}"#)
+        .create_on(&mock_server);
 
+    let cl = Client::new(url);
+    let query = cl.remote_query("foo");
     assert_eq!(
-        studies,
-        [
-            Study {
-                id: "63bf5d42-b5382159-01971752-e0ceea3d-399bbca5".to_string(),
-                is_stable: true,
-                last_update: NaiveDate::from_ymd(2020, 8, 30).and_hms(19, 11, 09),
-                main_dicom_tags: hashmap! {
-                    "AccessionNumber".to_string() => "foobar".to_string(),
-                    "StudyDate".to_string() => "20110101".to_string(),
-                    "StudyDescription".to_string() => "Brain".to_string(),
-                    "StudyID".to_string() => "1742".to_string(),
-                    "StudyInstanceUID".to_string() => "1.2.3.4.5.6789".to_string(),
-                    "StudyTime".to_string() => "084707".to_string()
-                },
-                parent_patient: "7e43f8d3-e50280e6-470079e9-02241af1-d286bdbe".to_string(),
-                patient_main_dicom_tags: hashmap! {
-                    "PatientBirthDate".to_string() => "19440101".to_string(),
-                    "PatientID".to_string() => "c137".to_string(),
-                    "PatientName".to_string() => "Rick Sanchez".to_string(),
-                    "PatientSex".to_string() => "M".to_string(),
-                },
-                series: [
-                    "cd00fffc-db25be29-0c6da430-c56796a5-ba06933c".to_string(),
-                    "2ab7dbe7-f1a18a78-86145443-18a8ff93-0b65f2b2".to_string()
-                ]
-                .to_vec(),
-                entity: EntityKind::Study,
-                anonymized_from: None
+        query.retrieve(&RetrieveOptions::default()).unwrap(),
+        CreatedJob {
+            id: "job1".to_string(),
+            path: "/jobs/job1".to_string()
+        }
+    );
+    let options = RetrieveOptions {
+        target_aet: Some("BAZ".to_string()),
+        timeout: None,
+        retrieve_method: Some(RetrieveMethod::Get),
+    };
+    assert_eq!(
+        query.retrieve_answers(&["0", "1"], &options).unwrap(),
+        vec![
+            CreatedJob {
+                id: "job2".to_string(),
+                path: "/jobs/job2".to_string()
             },
-            Study {
-                id: "e8cafcbe-caf08c39-6e205f15-18554bb8-b3f9ef04".to_string(),
-                is_stable: true,
-                last_update: NaiveDate::from_ymd(2020, 9, 1).and_hms(18, 52, 11),
-                main_dicom_tags: hashmap! {
-                    "AccessionNumber".to_string() => "bazqux".to_string(),
-                    "StudyDate".to_string() => "20120101".to_string(),
-                    "StudyDescription".to_string() => "Knee".to_string(),
-                    "StudyID".to_string() => "1010100".to_string(),
-                    "StudyInstanceUID".to_string() => "1.2.3.4.5.67810".to_string(),
-                    "StudyTime".to_string() => "130431".to_string()
-                },
-                parent_patient: "f88cbd3f-a00dfc59-9ca1ac2d-7ce9851a-40e5b493".to_string(),
-                patient_main_dicom_tags: hashmap! {
-                    "PatientBirthDate".to_string() => "19670101".to_string(),
-                    "PatientID".to_string() => "4217".to_string(),
-                    "PatientName".to_string() => "Summer Smith".to_string(),
-                    "PatientSex".to_string() => "F".to_string(),
-                },
-                series: [
-                    "222bbd7e-4dfbc5a8-ea58f933-f1747134-0810c7c8".to_string(),
-                    "54f8778a-75ba559c-db7c7c1a-c1056140-ef74d487".to_string()
-                ]
-                .to_vec(),
-                entity: EntityKind::Study,
-                anonymized_from: None
+            CreatedJob {
+                id: "job3".to_string(),
+                path: "/jobs/job3".to_string()
             },
         ]
     );
+
+    assert_eq!(m1.times_called(), 1);
+    assert_eq!(m2.times_called(), 1);
+    assert_eq!(m3.times_called(), 1);
+}
+
+#[test]
+fn test_list_jobs() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+
+    let m = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/jobs")
+        .return_status(200)
+        .return_header("Content-Type", "application/json")
+        .return_body(r#"["foo", "bar"]"#)
+        .create_on(&mock_server);
+
+    let cl = Client::new(url);
+    assert_eq!(cl.jobs().unwrap(), ["foo", "bar"]);
+    assert_eq!(m.times_called(), 1);
+}
+
+#[test]
+fn test_get_job() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+
+    let m = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/jobs/foo")
+        .return_status(200)
+        .return_header("Content-Type", "application/json")
+        .return_body(
+            r#"
+                {
+                    "CompletionTime": "20210310T143217.410469",
+                    "Content": {
+                        "Description": "REST API",
+                        "LocalAet": "ORTHANC",
+                        "RemoteAet": "MODALITY_ONE",
+                        "TargetAet": "ORTHANC"
+                    },
+                    "CreationTime": "20210310T143217.398000",
+                    "EffectiveRuntime": 0.012,
+                    "ErrorCode": 0,
+                    "ErrorDescription": "Success",
+                    "ID": "foo",
+                    "Priority": 0,
+                    "Progress": 100,
+                    "State": "Success",
+                    "Timestamp": "20210310T143218.106722",
+                    "Type": "DicomMoveScu"
+                }
+            "#,
+        )
+        .create_on(&mock_server);
+
+    let cl = Client::new(url);
+    assert_eq!(
+        cl.job("foo").unwrap(),
+        Job {
+            id: "foo".to_string(),
+            job_type: "DicomMoveScu".to_string(),
+            state: JobState::Success,
+            progress: 100,
+            priority: 0,
+            creation_time: "20210310T143217.398000".to_string(),
+            completion_time: Some("20210310T143217.410469".to_string()),
+            estimated_time_of_arrival: None,
+            effective_runtime: Some(0.012),
+            error_code: 0,
+            error_description: "Success".to_string(),
+            timestamp: "20210310T143218.106722".to_string(),
+            content: serde_json::json!({
+                "Description": "REST API",
+                "LocalAet": "ORTHANC",
+                "RemoteAet": "MODALITY_ONE",
+                "TargetAet": "ORTHANC"
+            }),
+        }
+    );
     assert_eq!(m.times_called(), 1);
 }
 
 #[test]
-fn test_search_series_level() {
+fn test_wait_for_job() {
     let mock_server = MockServer::start();
     let url = mock_server.url("");
 
     let m = Mock::new()
-        .expect_method(Method::POST)
-        .expect_path("/tools/find")
-        .expect_json_body(&Search {
-            level: EntityKind::Series,
-            query: hashmap! {"SeriesID".to_string() => "foobar".to_string()},
-            expand: Some(true),
-        })
+        .expect_method(Method::GET)
+        .expect_path("/jobs/foo")
         .return_status(200)
         .return_header("Content-Type", "application/json")
         .return_body(
             r#"
-                    [
-                        {
-                            "ExpectedNumberOfInstances": 17,
-                            "ID": "cd00fffc-db25be29-0c6da430-c56796a5-ba06933c",
-                            "Instances": [
-                                "556530b5-de7c487b-110b9d0e-12cfdbb9-f06b546e",
-                                "c46605db-836489fa-cb55fbbc-13c8a913-b0bad6ac",
-                                "9b63498d-cae4f25e-f52206b2-cbb4dc0e-dc55c788"
-                            ],
-                            "IsStable": true,
-                            "LastUpdate": "20200830T191109",
-                            "MainDicomTags": {
-                                "BodyPartExamined": "ABDOMEN",
-                                "Modality": "MR",
-                                "ProtocolName": "TCP",
-                                "SeriesDate": "20110101",
-                                "SeriesInstanceUID": "1.2.3.4.5.6789",
-                                "SeriesNumber": "1101",
-                                "SeriesTime": "091313.93"
-                            },
-                            "ParentStudy": "63bf5d42-b5382159-01971752-e0ceea3d-399bbca5",
-                            "Status": "Unknown",
-                            "Type": "Series"
-                        },
-                        {
-                            "ExpectedNumberOfInstances": null,
-                            "ID": "2ab7dbe7-f1a18a78-86145443-18a8ff93-0b65f2b2",
-                            "Instances": [
-                                "a17e85ca-380bb2dc-d29ea4b7-6e73c10a-ca6ba458",
-                                "1c81e7e8-30642777-ffc2ca41-c7536670-7ad68124"
-                            ],
-                            "IsStable": true,
-                            "LastUpdate": "20200830T191109",
-                            "MainDicomTags": {
-                                "BodyPartExamined": "HEAD",
-                                "Modality": "CT",
-                                "ProtocolName": "UDP",
-                                "SeriesDate": "20110101",
-                                "SeriesInstanceUID": "1.2.3.4.5.67810",
-                                "SeriesNumber": "1102",
-                                "SeriesTime": "091313.93"
-                            },
-                            "ParentStudy": "63bf5d42-b5382159-01971752-e0ceea3d-399bbca5",
-                            "Status": "Unknown",
-                            "Type": "Series"
-                        }
-                    ]
-               "#,
+                {
+                    "Content": {},
+                    "CreationTime": "20210310T143217.398000",
+                    "ErrorCode": 0,
+                    "ErrorDescription": "Success",
+                    "ID": "foo",
+                    "Priority": 0,
+                    "Progress": 100,
+                    "State": "Success",
+                    "Timestamp": "20210310T143218.106722",
+                    "Type": "DicomMoveScu"
+                }
+            "#,
         )
         .create_on(&mock_server);
 
     let cl = Client::new(url);
-    let series: Vec<Series> = cl
-        .search(hashmap! {"SeriesID".to_string() => "foobar".to_string()})
+    let mut progress = vec![];
+    let job = cl
+        .wait_for_job(
+            "foo",
+            Duration::from_millis(1),
+            Duration::from_secs(1),
+            |j| progress.push(j.progress),
+        )
         .unwrap();
 
-    assert_eq!(
-        series,
-        [
-            Series {
-                id: "cd00fffc-db25be29-0c6da430-c56796a5-ba06933c".to_string(),
-                status: "Unknown".to_string(),
-                is_stable: true,
-                last_update: NaiveDate::from_ymd(2020, 8, 30).and_hms(19, 11, 09),
-                main_dicom_tags: hashmap! {
-                    "BodyPartExamined".to_string() => "ABDOMEN".to_string(),
-                    "Modality".to_string() => "MR".to_string(),
-                    "ProtocolName".to_string() => "TCP".to_string(),
-                    "SeriesDate".to_string() => "20110101".to_string(),
-                    "SeriesInstanceUID".to_string() => "1.2.3.4.5.6789".to_string(),
-                    "SeriesNumber".to_string() => "1101".to_string(),
-                    "SeriesTime".to_string() => "091313.93".to_string(),
+    assert_eq!(job.state, JobState::Success);
+    assert_eq!(progress, [100]);
+    assert_eq!(m.times_called(), 1);
+}
 
-                },
-                parent_study: "63bf5d42-b5382159-01971752-e0ceea3d-399bbca5".to_string(),
-                expected_number_of_instances: Some(17),
-                instances: [
-                    "556530b5-de7c487b-110b9d0e-12cfdbb9-f06b546e".to_string(),
-                    "c46605db-836489fa-cb55fbbc-13c8a913-b0bad6ac".to_string(),
-                    "9b63498d-cae4f25e-f52206b2-cbb4dc0e-dc55c788".to_string(),
-                ]
-                .to_vec(),
-                entity: EntityKind::Series,
-                anonymized_from: None
-            },
-            Series {
-                id: "2ab7dbe7-f1a18a78-86145443-18a8ff93-0b65f2b2".to_string(),
-                status: "Unknown".to_string(),
-                is_stable: true,
-                last_update: NaiveDate::from_ymd(2020, 8, 30).and_hms(19, 11, 09),
-                main_dicom_tags: hashmap! {
-                    "BodyPartExamined".to_string() => "HEAD".to_string(),
-                    "Modality".to_string() => "CT".to_string(),
-                    "ProtocolName".to_string() => "UDP".to_string(),
-                    "SeriesDate".to_string() => "20110101".to_string(),
-                    "SeriesInstanceUID".to_string() => "1.2.3.4.5.67810".to_string(),
-                    "SeriesNumber".to_string() => "1102".to_string(),
-                    "SeriesTime".to_string() => "091313.93".to_string(),
+#[test]
+fn test_wait_for_job_failure() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
 
-                },
-                parent_study: "63bf5d42-b5382159-01971752-e0ceea3d-399bbca5".to_string(),
-                expected_number_of_instances: None,
-                instances: [
-                    "a17e85ca-380bb2dc-d29ea4b7-6e73c10a-ca6ba458".to_string(),
-                    "1c81e7e8-30642777-ffc2ca41-c7536670-7ad68124".to_string(),
-                ]
-                .to_vec(),
-                entity: EntityKind::Series,
-                anonymized_from: None
-            },
-        ]
+    let m = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/jobs/foo")
+        .return_status(200)
+        .return_header("Content-Type", "application/json")
+        .return_body(
+            r#"
+                {
+                    "Content": {},
+                    "CreationTime": "20210310T143217.398000",
+                    "ErrorCode": 9,
+                    "ErrorDescription": "Error in the network protocol",
+                    "ID": "foo",
+                    "Priority": 0,
+                    "Progress": 0,
+                    "State": "Failure",
+                    "Timestamp": "20210310T143218.106722",
+                    "Type": "DicomMoveScu"
+                }
+            "#,
+        )
+        .create_on(&mock_server);
+
+    let cl = Client::new(url);
+    assert_eq!(
+        cl.wait_for_job(
+            "foo",
+            Duration::from_millis(1),
+            Duration::from_secs(1),
+            |_| {}
+        )
+        .unwrap_err(),
+        Error {
+            message: "Job foo failed: Error in the network protocol".to_string(),
+            details: None,
+            kind: None,
+        }
     );
     assert_eq!(m.times_called(), 1);
 }
 
 #[test]
-fn test_search_instance_level() {
+fn test_wait_for_job_timeout() {
     let mock_server = MockServer::start();
     let url = mock_server.url("");
 
     let m = Mock::new()
-        .expect_method(Method::POST)
-        .expect_path("/tools/find")
-        .expect_json_body(&Search {
-            level: EntityKind::Instance,
-            query: hashmap! {"InstanceID".to_string() => "foobar".to_string()},
-            expand: Some(true),
-        })
+        .expect_method(Method::GET)
+        .expect_path("/jobs/foo")
         .return_status(200)
         .return_header("Content-Type", "application/json")
         .return_body(
             r#"
-                    [
-                        {
-                            "FileSize": 139402,
-                            "FileUuid": "d8c5eff3-986c-4fe4-b06e-7e52b2a4238e",
-                            "ID": "29fa4d9d-51a69d1d-70e2b29a-fd824316-50850d0c",
-                            "IndexInSeries": 13,
-                            "MainDicomTags": {
-                                "ImageOrientationPatient": "1\\0\\0\\0\\1\\0",
-                                "ImagePositionPatient": "-17\\42\\13",
-                                "InstanceCreationDate": "20130326",
-                                "InstanceCreationTime": "135901",
-                                "InstanceNumber": "13",
-                                "SOPInstanceUID": "1.2.3.4.5.6789"
-                            },
-                            "ModifiedFrom": "22c54cb6-28302a69-3ff454a3-676b98f4-b84cd80a",
-                            "ParentSeries": "82081568-b6f8f4e6-ced76876-6504da25-ed0dfe03",
-                            "Type": "Instance"
-                        },
-                        {
-                            "FileSize": 381642,
-                            "FileUuid": "86bbad65-2c98-4cb0-bf77-0ef0243410a4",
-                            "ID": "286a251e-46571bd6-0e14ab9a-1baadddc-d0146ea0",
-                            "MainDicomTags": {
-                                "ImageOrientationPatient": "-1\\0\\0\\0\\1\\0",
-                                "ImagePositionPatient": "-17\\42\\14",
-                                "InstanceCreationDate": "20130326",
-                                "InstanceCreationTime": "135830",
-                                "InstanceNumber": "75",
-                                "SOPInstanceUID": "1.2.3.4.5.67810"
-                            },
-                            "ParentSeries": "a240e0d7-538699a0-7464bb4b-a906f72a-fa3a32c7",
-                            "Type": "Instance"
-                        }
-                    ]
-               "#,
+                {
+                    "Content": {},
+                    "CreationTime": "20210310T143217.398000",
+                    "ErrorCode": 0,
+                    "ErrorDescription": "Success",
+                    "ID": "foo",
+                    "Priority": 0,
+                    "Progress": 50,
+                    "State": "Running",
+                    "Timestamp": "20210310T143218.106722",
+                    "Type": "DicomMoveScu"
+                }
+            "#,
         )
         .create_on(&mock_server);
 
     let cl = Client::new(url);
-    let instances: Vec<Instance> = cl
-        .search(hashmap! {"InstanceID".to_string() => "foobar".to_string()})
-        .unwrap();
-
     assert_eq!(
-        instances,
-        [
-            Instance {
-                id: "29fa4d9d-51a69d1d-70e2b29a-fd824316-50850d0c".to_string(),
-                main_dicom_tags: hashmap! {
-                    "ImageOrientationPatient".to_string() => "1\\0\\0\\0\\1\\0".to_string(),
-                    "ImagePositionPatient".to_string() => "-17\\42\\13".to_string(),
-                    "InstanceCreationDate".to_string() => "20130326".to_string(),
-                    "InstanceCreationTime".to_string() => "135901".to_string(),
-                    "InstanceNumber".to_string() => "13".to_string(),
-                    "SOPInstanceUID".to_string() => "1.2.3.4.5.6789".to_string(),
-                },
-                parent_series: "82081568-b6f8f4e6-ced76876-6504da25-ed0dfe03".to_string(),
-                index_in_series: Some(13),
-                file_uuid: "d8c5eff3-986c-4fe4-b06e-7e52b2a4238e".to_string(),
-                file_size: 139402,
-                modified_from: Some(
-                    "22c54cb6-28302a69-3ff454a3-676b98f4-b84cd80a".to_string()
-                ),
-                entity: EntityKind::Instance,
-                anonymized_from: None
-            },
-            Instance {
-                id: "286a251e-46571bd6-0e14ab9a-1baadddc-d0146ea0".to_string(),
-                main_dicom_tags: hashmap! {
-                    "ImageOrientationPatient".to_string() => "-1\\0\\0\\0\\1\\0".to_string(),
-                    "ImagePositionPatient".to_string() => "-17\\42\\14".to_string(),
-                    "InstanceCreationDate".to_string() => "20130326".to_string(),
-                    "InstanceCreationTime".to_string() => "135830".to_string(),
-                    "InstanceNumber".to_string() => "75".to_string(),
-                    "SOPInstanceUID".to_string() => "1.2.3.4.5.67810".to_string(),
-                },
-                parent_series: "a240e0d7-538699a0-7464bb4b-a906f72a-fa3a32c7".to_string(),
-                index_in_series: None,
-                file_uuid: "86bbad65-2c98-4cb0-bf77-0ef0243410a4".to_string(),
-                file_size: 381642,
-                modified_from: None,
-                entity: EntityKind::Instance,
-                anonymized_from: None
-            },
-        ]
+        cl.wait_for_job(
+            "foo",
+            Duration::from_millis(10),
+            Duration::from_millis(25),
+            |_| {}
+        )
+        .unwrap_err(),
+        Error {
+            message: "Job foo did not finish in 25ms, its state is Running".to_string(),
+            details: None,
+            kind: None,
+        }
     );
-    assert_eq!(m.times_called(), 1);
+    assert!(m.times_called() >= 2);
 }
 
 #[test]
-fn test_search_error() {
+fn test_wait_for_job_unknown_state() {
     let mock_server = MockServer::start();
     let url = mock_server.url("");
 
     let m = Mock::new()
-        .expect_method(Method::POST)
-        .expect_path("/tools/find")
-        .expect_json_body(&Search {
-            level: EntityKind::Patient,
-            query: hashmap! {"PatientID".to_string() => "foobar".to_string()},
-            expand: Some(true),
-        })
-        .return_status(500)
+        .expect_method(Method::GET)
+        .expect_path("/jobs/foo")
+        .return_status(200)
         .return_header("Content-Type", "application/json")
         .return_body(
             r#"
-                    {
-                       "HttpError" : "Internal Server Error",
-                       "HttpStatus" : 500,
-                       "Message" : "Unknown DICOM tag",
-                       "Method" : "POST",
-                       "OrthancError" : "Unknown DICOM tag",
-                       "OrthancStatus" : 27,
-                       "Uri" : "/tools/find"
-                    }
-               "#,
+                {
+                    "Content": {},
+                    "CreationTime": "20210310T143217.398000",
+                    "ErrorCode": 0,
+                    "ErrorDescription": "Success",
+                    "ID": "foo",
+                    "Priority": 0,
+                    "Progress": 0,
+                    "State": "Cancelled",
+                    "Timestamp": "20210310T143218.106722",
+                    "Type": "DicomMoveScu"
+                }
+            "#,
         )
         .create_on(&mock_server);
 
     let cl = Client::new(url);
-    let res: std::result::Result<Vec<Patient>, Error> =
-        cl.search(hashmap! {"PatientID".to_string() => "foobar".to_string()});
-
     assert_eq!(
-        res.unwrap_err(),
+        cl.wait_for_job(
+            "foo",
+            Duration::from_millis(1),
+            Duration::from_secs(1),
+            |_| {}
+        )
+        .unwrap_err(),
         Error {
-            message: "API error: 500 Internal Server Error".to_string(),
-            details: Some(ApiError {
-                method: "POST".to_string(),
-                uri: "/tools/find".to_string(),
-                message: "Unknown DICOM tag".to_string(),
-                details: None,
-                http_status: 500,
-                http_error: "Internal Server Error".to_string(),
-                orthanc_status: 27,
-                orthanc_error: "Unknown DICOM tag".to_string(),
-            },),
-        },
+            message: "Job foo is in an unknown state: Cancelled".to_string(),
+            details: None,
+            kind: None,
+        }
     );
     assert_eq!(m.times_called(), 1);
 }
 
+#[cfg(feature = "dicomweb")]
 #[test]
-fn test_modality_move() {
+fn test_dicomweb_search_studies() {
     let mock_server = MockServer::start();
     let url = mock_server.url("");
 
     let m = Mock::new()
-        .expect_method(Method::POST)
-        .expect_path("/modalities/foo/move")
-        .expect_json_body(&ModalityMove {
-            level: EntityKind::Study,
-            target_aet: Some("MODALITY_TWO".to_string()),
-            resources: vec![hashmap! {
-                "StudyInstanceUID".to_string() => "99.88.77.66.5.4.3.2.1.0".to_string(),
-            }],
-            timeout: None,
-        })
+        .expect_method(Method::GET)
+        .expect_path("/dicom-web/studies")
+        .expect_header("Accept", "application/dicom+json")
+        .expect_query_param("PatientID", "C137")
+        .expect_query_param("includefield", "StudyDescription")
+        .expect_query_param("limit", "10")
         .return_status(200)
+        .return_header("Content-Type", "application/dicom+json")
+        .return_body(
+            r#"
+                [
+                    {
+                        "00081030": {"vr": "LO", "Value": ["Foo"]},
+                        "00100010": {"vr": "PN", "Value": [{"Alphabetic": "Sanchez^Rick"}]},
+                        "00100020": {"vr": "LO", "Value": ["C137"]},
+                        "0020000D": {"vr": "UI", "Value": ["1.2.3"]}
+                    }
+                ]
+            "#,
+        )
         .create_on(&mock_server);
 
     let cl = Client::new(url);
-    let res = cl
-        .modality_move(
-            "foo",
-            ModalityMove {
-                level: EntityKind::Study,
-                target_aet: Some("MODALITY_TWO".to_string()),
-                resources: vec![hashmap! {
-                    "StudyInstanceUID".to_string() => "99.88.77.66.5.4.3.2.1.0".to_string(),
-                }],
-                timeout: None,
-            },
-        )
+    let query = orthanc::dicomweb::QidoQuery::default()
+        .filter("PatientID", "C137")
+        .include_field("StudyDescription")
+        .limit(10);
+    let studies = cl.dicomweb().search_studies(&query).unwrap();
+
+    assert_eq!(studies.len(), 1);
+    assert_eq!(studies[0].study_instance_uid(), Some("1.2.3".to_string()));
+    assert_eq!(
+        studies[0].string("00100010"),
+        Some("Sanchez^Rick".to_string())
+    );
+    assert_eq!(studies[0].string("00081030"), Some("Foo".to_string()));
+    assert_eq!(m.times_called(), 1);
+}
+
+#[cfg(feature = "dicomweb")]
+#[test]
+fn test_dicomweb_search_series_instances_no_content() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+
+    let m = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/dicom-web/studies/1.2.3/series/4.5.6/instances")
+        .return_status(204)
+        .create_on(&mock_server);
+
+    let cl = Client::new(url);
+    let instances = cl
+        .dicomweb()
+        .search_series_instances("1.2.3", "4.5.6", &Default::default())
         .unwrap();
 
-    assert_eq!(res, ());
+    assert_eq!(instances, vec![]);
     assert_eq!(m.times_called(), 1);
 }
 
+#[cfg(feature = "dicomweb")]
 #[test]
-fn test_modality_move_error() {
+fn test_dicomweb_retrieve() {
     let mock_server = MockServer::start();
     let url = mock_server.url("");
 
-    let m = Mock::new()
-        .expect_method(Method::POST)
-        .expect_path("/modalities/foo/move")
-        .expect_json_body(&ModalityMove {
-            level: EntityKind::Study,
-            target_aet: Some("MODALITY_TWO".to_string()),
-            resources: vec![hashmap! {
-                "StudyInstanceUID".to_string() => "99.88.77.66.5.4.3.2.1.0".to_string(),
-            }],
-            timeout: None,
-        })
-        .return_status(500)
-        .return_header("Content-Type", "application/json")
+    let m1 = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/dicom-web/studies/1.2.3/series/4.5.6")
+        .expect_header("Accept", r#"multipart/related; type="application/dicom""#)
+        .return_status(200)
+        .return_header(
+            "Content-Type",
+            r#"multipart/related; type="application/dicom"; boundary=foo"#,
+        )
         .return_body(
-            r#"
-                    {
-                       "HttpError" : "Internal Server Error",
-                       "HttpStatus" : 500,
-                       "Message" : "Boom!",
-                       "Method" : "POST",
-                       "OrthancError" : "Boom!",
-                       "OrthancStatus" : 27,
-                       "Uri" : "/modalities/foo/move"
-                    }
-               "#,
+            "--foo\r\nContent-Type: application/dicom\r\n\r\nbar\r\n\
+             --foo\r\nContent-Type: application/dicom\r\n\r\nbaz\r\n--foo--\r\n",
+        )
+        .create_on(&mock_server);
+    let m2 = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/dicom-web/studies/1.2.3/series/4.5.6/instances/7.8.9/frames/1,3")
+        .expect_header(
+            "Accept",
+            r#"multipart/related; type="application/octet-stream""#,
+        )
+        .return_status(200)
+        .return_header(
+            "Content-Type",
+            r#"multipart/related; type="application/octet-stream"; boundary=foo"#,
+        )
+        .return_body(
+            "--foo\r\nContent-Type: application/octet-stream\r\n\r\nqux\r\n\
+             --foo\r\nContent-Type: application/octet-stream\r\n\r\nquux\r\n--foo--\r\n",
+        )
+        .create_on(&mock_server);
+    let m3 = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/dicom-web/studies/1.2.3/series/4.5.6/instances/7.8.9")
+        .return_status(200)
+        .return_header(
+            "Content-Type",
+            r#"multipart/related; type="application/dicom"; boundary=foo"#,
         )
+        .return_body("--foo\r\nContent-Type: application/dicom\r\n\r\nbar\r\n--foo--\r\n")
         .create_on(&mock_server);
 
     let cl = Client::new(url);
-    let res = cl.modality_move(
-        "foo",
-        ModalityMove {
-            level: EntityKind::Study,
-            target_aet: Some("MODALITY_TWO".to_string()),
-            resources: vec![hashmap! {
-                "StudyInstanceUID".to_string() => "99.88.77.66.5.4.3.2.1.0".to_string(),
-            }],
-            timeout: None,
-        },
+    let dicomweb = cl.dicomweb();
+    assert_eq!(
+        dicomweb.retrieve_series("1.2.3", "4.5.6").unwrap(),
+        vec!["bar", "baz"]
     );
-
     assert_eq!(
-        res.unwrap_err(),
-        Error {
-            message: "API error: 500 Internal Server Error".to_string(),
-            details: Some(ApiError {
-                method: "POST".to_string(),
-                uri: "/modalities/foo/move".to_string(),
-                message: "Boom!".to_string(),
-                details: None,
-                http_status: 500,
-                http_error: "Internal Server Error".to_string(),
-                orthanc_status: 27,
-                orthanc_error: "Boom!".to_string(),
-            },),
-        },
+        dicomweb
+            .retrieve_frames("1.2.3", "4.5.6", "7.8.9", &[1, 3])
+            .unwrap(),
+        vec!["qux", "quux"]
     );
-    assert_eq!(m.times_called(), 1);
+    assert_eq!(
+        dicomweb
+            .retrieve_instance("1.2.3", "4.5.6", "7.8.9")
+            .unwrap(),
+        "bar"
+    );
+
+    assert_eq!(m1.times_called(), 1);
+    assert_eq!(m2.times_called(), 1);
+    assert_eq!(m3.times_called(), 1);
 }
 
+#[cfg(feature = "dicomweb")]
 #[test]
-fn test_modality_find() {
+fn test_dicomweb_retrieve_study_to() {
     let mock_server = MockServer::start();
     let url = mock_server.url("");
 
     let m = Mock::new()
-        .expect_method(Method::POST)
-        .expect_path("/modalities/them/query")
-        .expect_json_body(&ModalityFind {
-            level: EntityKind::Study,
-            query: hashmap! {
-                "StudyInstanceUID".to_string() => "1.2.3.4.5.6.999".to_string()
-            },
-            normalize: None,
-        })
+        .expect_method(Method::GET)
+        .expect_path("/dicom-web/studies/1.2.3")
+        .expect_header("Accept", r#"multipart/related; type="application/dicom""#)
         .return_status(200)
-        .return_header("Content-Type", "application/json")
+        .return_header(
+            "Content-Type",
+            r#"multipart/related; type="application/dicom"; boundary=foo"#,
+        )
         .return_body(
-            r#"
-                {
-                    "ID": "1c315256-3eef-4ef6-aa8a-03947cc53513",
-                    "Path": "/queries/1c315256-3eef-4ef6-aa8a-03947cc53513"
-
-                }
-            "#,
+            "--foo\r\nContent-Type: application/dicom\r\n\r\nbar\r\n\
+             --foo\r\nContent-Type: application/dicom\r\n\r\nbaz\r\n--foo--\r\n",
         )
         .create_on(&mock_server);
 
     let cl = Client::new(url);
+    let dir = download_path("dicomweb_study");
+    fs::create_dir_all(&dir).unwrap();
+    let count = cl
+        .dicomweb()
+        .retrieve_study_to("1.2.3", |i| {
+            fs::File::create(dir.join(format!("{}.dcm", i)))
+        })
+        .unwrap();
+    assert_eq!(count, 2);
+    assert_eq!(fs::read(dir.join("0.dcm")).unwrap(), b"bar");
+    assert_eq!(fs::read(dir.join("1.dcm")).unwrap(), b"baz");
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(m.times_called(), 1);
+}
+
+#[cfg(feature = "dicomweb")]
+#[test]
+fn test_dicomweb_retrieve_study_to_error() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+
+    let m = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/dicom-web/studies/1.2.3")
+        .return_status(200)
+        .return_header("Content-Type", "application/dicom")
+        .return_body("bar")
+        .create_on(&mock_server);
+
+    let cl = Client::new(url);
+    let mut files: Vec<Vec<u8>> = vec![];
+    let result = cl.dicomweb().retrieve_study_to("1.2.3", |_| {
+        files.push(vec![]);
+        Ok(vec![])
+    });
     assert_eq!(
-        cl.modality_find(
-            "them",
-            EntityKind::Study,
-            hashmap! {
-                "StudyInstanceUID".to_string() => "1.2.3.4.5.6.999".to_string()
-            },
-            None
-        )
-        .unwrap(),
-        ModalityFindResult {
-            id: "1c315256-3eef-4ef6-aa8a-03947cc53513".to_string(),
-            path: "/queries/1c315256-3eef-4ef6-aa8a-03947cc53513".to_string()
+        result.unwrap_err(),
+        Error {
+            message: "Expected a multipart response, got application/dicom".to_string(),
+            details: None,
+            kind: None,
         }
     );
-
+    assert!(files.is_empty());
     assert_eq!(m.times_called(), 1);
 }
 
+#[cfg(feature = "dicomweb")]
 #[test]
-fn test_modality_find_error() {
+fn test_dicomweb_metadata_bulk_data() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+    let bulk_data_uri = mock_server
+        .url("/dicom-web/studies/1.2.3/series/4.5.6/instances/7.8.9/bulk/7fe00010");
+
+    let m1 = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/dicom-web/studies/1.2.3/series/4.5.6/instances/7.8.9/metadata")
+        .expect_header("Accept", "application/dicom+json")
+        .return_status(200)
+        .return_header("Content-Type", "application/dicom+json")
+        .return_body(&format!(
+            r#"
+                [
+                    {{
+                        "00080018": {{"vr": "UI", "Value": ["7.8.9"]}},
+                        "7FE00010": {{"vr": "OW", "BulkDataURI": "{}"}}
+                    }}
+                ]
+            "#,
+            bulk_data_uri
+        ))
+        .create_on(&mock_server);
+    let m2 = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/dicom-web/studies/1.2.3/series/4.5.6/instances/7.8.9/bulk/7fe00010")
+        .return_status(200)
+        .return_header(
+            "Content-Type",
+            r#"multipart/related; type="application/octet-stream"; boundary=foo"#,
+        )
+        .return_body(
+            "--foo\r\nContent-Type: application/octet-stream\r\n\r\nbar\r\n--foo--",
+        )
+        .create_on(&mock_server);
+
+    let cl = Client::new(url);
+    let dicomweb = cl.dicomweb();
+    let metadata = dicomweb
+        .instance_metadata("1.2.3", "4.5.6", "7.8.9")
+        .unwrap();
+    assert_eq!(metadata.sop_instance_uid(), Some("7.8.9".to_string()));
+    let uri = metadata
+        .attribute("7fe00010")
+        .unwrap()
+        .bulk_data_uri
+        .clone()
+        .unwrap();
+    assert_eq!(dicomweb.retrieve_bulk_data(&uri).unwrap(), vec!["bar"]);
+
+    assert_eq!(m1.times_called(), 1);
+    assert_eq!(m2.times_called(), 1);
+}
+
+// The boundary of a STOW-RS request is random
+#[cfg(feature = "dicomweb")]
+fn stow_body(instances: &[&str]) -> httpmock::Regex {
+    let parts: String = instances
+        .iter()
+        .map(|i| {
+            format!(
+                "--orthanc-rs-[0-9a-f]{{32}}\r\nContent-Type: application/dicom\r\n\r\n{}\r\n",
+                i
+            )
+        })
+        .collect();
+    httpmock::Regex::new(&format!("^{}--orthanc-rs-[0-9a-f]{{32}}--\r\n$", parts)).unwrap()
+}
+
+#[cfg(feature = "dicomweb")]
+#[test]
+fn test_dicomweb_store() {
     let mock_server = MockServer::start();
     let url = mock_server.url("");
 
     let m = Mock::new()
         .expect_method(Method::POST)
-        .expect_path("/modalities/them/query")
-        .expect_json_body(&ModalityFind {
-            level: EntityKind::Study,
-            query: hashmap! {
-                "StudyInstanceUID".to_string() => "1.2.3.4.5.6.999".to_string()
-            },
-            normalize: None,
-        })
-        .return_status(500)
-        .return_header("Content-Type", "application/json")
+        .expect_path("/dicom-web/studies")
+        .expect_header_exists("Content-Type")
+        .expect_header("Content-Length", "223")
+        .expect_body_matches(stow_body(&["foo", "bar"]))
+        .return_status(200)
+        .return_header("Content-Type", "application/dicom+json")
+        .return_body(
+            r#"
+                {
+                    "00081190": {"vr": "UR", "Value": ["http://localhost:8042/dicom-web/studies/1.2.3"]},
+                    "00081198": {
+                        "vr": "SQ",
+                        "Value": [{"00080018": {"vr": "UI", "Value": ["4.5.6"]}}]
+                    },
+                    "00081199": {
+                        "vr": "SQ",
+                        "Value": [{"00080018": {"vr": "UI", "Value": ["7.8.9"]}}]
+                    }
+                }
+            "#,
+        )
+        .create_on(&mock_server);
+
+    let cl = Client::new(url);
+    let result = cl.dicomweb().store(&[b"foo", b"bar"]).unwrap();
+
+    assert_eq!(
+        result.retrieve_url,
+        Some("http://localhost:8042/dicom-web/studies/1.2.3".to_string())
+    );
+    assert_eq!(result.referenced.len(), 1);
+    assert_eq!(
+        result.referenced[0].sop_instance_uid(),
+        Some("7.8.9".to_string())
+    );
+    assert_eq!(
+        result.failed[0].sop_instance_uid(),
+        Some("4.5.6".to_string())
+    );
+    assert_eq!(m.times_called(), 1);
+}
+
+#[cfg(feature = "dicomweb")]
+#[test]
+fn test_dicomweb_store_files() {
+    let mock_server = MockServer::start();
+    let url = mock_server.url("");
+
+    let m = Mock::new()
+        .expect_method(Method::POST)
+        .expect_path("/dicom-web/studies/1.2.3")
+        .expect_header("Content-Length", "223")
+        .expect_body_matches(stow_body(&["foo", "bar"]))
+        .return_status(200)
+        .return_header("Content-Type", "application/dicom+json")
         .return_body(
             r#"
                 {
-                   "Details" : "DicomAssociation - C-FIND to AET \"ORTHANC\": Peer aborted Association (or never connected)",
-                   "HttpError" : "Internal Server Error",
-                   "HttpStatus" : 500,
-                   "Message" : "Error in the network protocol",
-                   "Method" : "POST",
-                   "OrthancError" : "Error in the network protocol",
-                   "OrthancStatus" : 9,
-                   "Uri" : "/modalities/them/query"
+                    "00081199": {
+                        "vr": "SQ",
+                        "Value": [
+                            {"00080018": {"vr": "UI", "Value": ["4.5.6"]}},
+                            {"00080018": {"vr": "UI", "Value": ["7.8.9"]}}
+                        ]
+                    }
                 }
             "#,
         )
         .create_on(&mock_server);
 
-    let cl = Client::new(url);
-    assert_eq!(
-        cl.modality_find(
-            "them",
-            EntityKind::Study,
-            hashmap! {
-                "StudyInstanceUID".to_string() => "1.2.3.4.5.6.999".to_string()
-            },
-            None
-        )
-        .unwrap_err(),
-        Error{
-            message: "API error: 500 Internal Server Error".to_string(),
-            details: Some(ApiError {
-                method: "POST".to_string(),
-                uri: "/modalities/them/query".to_string(),
-                message: "Error in the network protocol".to_string(),
-                details: Some("DicomAssociation - C-FIND to AET \"ORTHANC\": Peer aborted Association (or never connected)".to_string()),
-                http_status: 500,
-                http_error: "Internal Server Error".to_string(),
-                orthanc_status: 9,
-                orthanc_error: "Error in the network protocol".to_string(),
+    let dir = download_path("dicomweb_store");
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("foo.dcm"), "foo").unwrap();
+    fs::write(dir.join("bar.dcm"), "bar").unwrap();
 
-            })
-        }
-    );
+    let cl = Client::new(url);
+    let result = cl
+        .dicomweb()
+        .store_files_to_study("1.2.3", &[dir.join("foo.dcm"), dir.join("bar.dcm")])
+        .unwrap();
+    assert_eq!(result.retrieve_url, None);
+    assert_eq!(result.referenced.len(), 2);
+    assert!(result.failed.is_empty());
 
+    let missing = cl.dicomweb().store_files(&[dir.join("baz.dcm")]);
+    assert!(missing.is_err());
+    fs::remove_dir_all(&dir).unwrap();
     assert_eq!(m.times_called(), 1);
 }
 
+#[cfg(feature = "dicomweb")]
 #[test]
-fn test_list_queries() {
+fn test_dicomweb_servers() {
     let mock_server = MockServer::start();
     let url = mock_server.url("");
 
-    let m = Mock::new()
+    let m1 = Mock::new()
         .expect_method(Method::GET)
-        .expect_path("/queries")
+        .expect_path("/dicom-web/servers")
+        .expect_query_param_exists("expand")
         .return_status(200)
-        .return_header("Content-Type", "application/json")
-        .return_body(r#"["1c315256-3eef-4ef6-aa8a-03947cc53513", "1c315256-3eef-4ef6-aa8a-03947cc53514"]"#)
+        .return_body(
+            r#"
+                {
+                    "foo": {
+                        "HasDelete": true,
+                        "HttpHeaders": ["Foo"],
+                        "Url": "http://foo/dicom-web/",
+                        "Username": "bar"
+                    }
+                }
+            "#,
+        )
+        .create_on(&mock_server);
+    let m2 = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/dicom-web/servers")
+        .return_status(200)
+        .return_body(r#"["foo"]"#)
         .create_on(&mock_server);
 
     let cl = Client::new(url);
-    let queries = cl.queries().unwrap();
-
+    let dicomweb = cl.dicomweb();
+    let servers = dicomweb.servers_expanded().unwrap();
     assert_eq!(
-        queries,
-        [
-            "1c315256-3eef-4ef6-aa8a-03947cc53513",
-            "1c315256-3eef-4ef6-aa8a-03947cc53514"
-        ]
+        servers,
+        hashmap! {
+            "foo".to_string() => orthanc::dicomweb::DicomWebServer {
+                url: "http://foo/dicom-web/".to_string(),
+                username: Some("bar".to_string()),
+                http_headers: Some(HttpHeaders::Names(vec!["Foo".to_string()])),
+                has_delete: Some(true),
+                ..Default::default()
+            },
+        }
     );
-    assert_eq!(m.times_called(), 1);
+    assert_eq!(m1.times_called(), 1);
+    assert_eq!(dicomweb.servers().unwrap(), ["foo"]);
+    assert_eq!(m2.times_called(), 1);
 }
 
+#[cfg(feature = "dicomweb")]
 #[test]
-fn test_get_query_level() {
+fn test_dicomweb_create_modify_delete_server() {
     let mock_server = MockServer::start();
     let url = mock_server.url("");
 
-    let m = Mock::new()
-        .expect_method(Method::GET)
-        .expect_path("/queries/foo/level")
+    let m1 = Mock::new()
+        .expect_method(Method::PUT)
+        .expect_path("/dicom-web/servers/foo")
+        .expect_json_body(&json!({
+            "Url": "http://foo/dicom-web/",
+            "Username": "bar",
+            "Password": "baz",
+            "HttpHeaders": {"Foo": "qux"},
+            "ChunkedTransfers": false,
+        }))
+        .return_status(200)
+        .create_on(&mock_server);
+    let m2 = Mock::new()
+        .expect_method(Method::DELETE)
+        .expect_path("/dicom-web/servers/foo")
         .return_status(200)
-        .return_header("Content-Type", "application/json")
-        .return_body("Instance")
         .create_on(&mock_server);
 
     let cl = Client::new(url);
-    assert_eq!(cl.query_level("foo").unwrap(), EntityKind::Instance);
-    assert_eq!(m.times_called(), 1);
+    let dicomweb = cl.dicomweb();
+    let server = orthanc::dicomweb::DicomWebServer {
+        url: "http://foo/dicom-web/".to_string(),
+        username: Some("bar".to_string()),
+        password: Some("baz".to_string()),
+        http_headers: Some(HttpHeaders::Values(
+            hashmap! {"Foo".to_string() => "qux".to_string()},
+        )),
+        chunked_transfers: Some(false),
+        ..Default::default()
+    };
+    dicomweb.create_server("foo", server.clone()).unwrap();
+    dicomweb.modify_server("foo", server.clone()).unwrap();
+    assert_eq!(
+        dicomweb
+            .modify_server(
+                "foo",
+                orthanc::dicomweb::DicomWebServer {
+                    http_headers: Some(HttpHeaders::Names(vec!["Foo".to_string()])),
+                    ..server
+                },
+            )
+            .unwrap_err(),
+        Error {
+            message: "Values of HTTP headers of DICOMweb server foo are unknown: Foo"
+                .to_string(),
+            details: None,
+            kind: None,
+        }
+    );
+    dicomweb.delete_server("foo").unwrap();
+
+    assert_eq!(m1.times_called(), 2);
+    assert_eq!(m2.times_called(), 1);
 }
 
+#[cfg(feature = "dicomweb")]
 #[test]
-fn test_get_query_level_error() {
+fn test_dicomweb_store_to_server() {
     let mock_server = MockServer::start();
     let url = mock_server.url("");
 
     let m = Mock::new()
-        .expect_method(Method::GET)
-        .expect_path("/queries/foo/level")
+        .expect_method(Method::POST)
+        .expect_path("/dicom-web/servers/foo/stow")
+        .expect_json_body(&json!({"Resources": ["bar", "baz"], "Synchronous": false}))
         .return_status(200)
-        .return_header("Content-Type", "application/json")
-        .return_body("Foobar")
+        .return_body(r#"{"ID": "qux", "Path": "/jobs/qux"}"#)
         .create_on(&mock_server);
 
     let cl = Client::new(url);
+    let job = cl
+        .dicomweb()
+        .store_to_server("foo", &["bar", "baz"])
+        .unwrap();
+
     assert_eq!(
-        cl.query_level("foo").unwrap_err(),
-        Error {
-            message: "Unknown entity kind: Foobar".to_string(),
-            details: None
+        job,
+        CreatedJob {
+            id: "qux".to_string(),
+            path: "/jobs/qux".to_string(),
         }
     );
     assert_eq!(m.times_called(), 1);
 }
 
+#[cfg(feature = "dicomweb")]
 #[test]
-fn test_get_query_modality() {
+fn test_dicomweb_retrieve_from_server() {
     let mock_server = MockServer::start();
     let url = mock_server.url("");
 
     let m = Mock::new()
-        .expect_method(Method::GET)
-        .expect_path("/queries/foo/modality")
+        .expect_method(Method::POST)
+        .expect_path("/dicom-web/servers/foo/retrieve")
+        .expect_json_body(&json!({
+            "Resources": [
+                {"Study": "1.2.3"},
+                {"Study": "4.5.6", "Series": ["7.8.9"]},
+            ],
+            "Synchronous": false,
+        }))
         .return_status(200)
-        .return_header("Content-Type", "application/json")
-        .return_body("bar")
+        .return_body(r#"{"ID": "qux", "Path": "/jobs/qux"}"#)
         .create_on(&mock_server);
 
     let cl = Client::new(url);
-    assert_eq!(cl.query_modality("foo").unwrap(), "bar".to_string());
+    let job = cl
+        .dicomweb()
+        .retrieve_from_server(
+            "foo",
+            &[
+                orthanc::dicomweb::RemoteResource {
+                    study: "1.2.3".to_string(),
+                    series: None,
+                },
+                orthanc::dicomweb::RemoteResource {
+                    study: "4.5.6".to_string(),
+                    series: Some(vec!["7.8.9".to_string()]),
+                },
+            ],
+        )
+        .unwrap();
+
+    assert_eq!(job.id, "qux");
     assert_eq!(m.times_called(), 1);
 }
 
+#[cfg(feature = "dicomweb")]
 #[test]
-fn test_get_query_query() {
+fn test_dicomweb_search_get_server() {
     let mock_server = MockServer::start();
     let url = mock_server.url("");
 
-    let body = r#"
-        {
-            "0008,0018": {
-                "Name": "SOPInstanceUID",
-                "Type": "String",
-                "Value": "1.3.46.670589.11.1.5.0.10176.2012103017543590042"
-            }
-        }
-    "#;
-
-    let m = Mock::new()
-        .expect_method(Method::GET)
-        .expect_path("/queries/foo/query")
+    let m1 = Mock::new()
+        .expect_method(Method::POST)
+        .expect_path("/dicom-web/servers/foo/qido")
+        .expect_json_body(&json!({
+            "Uri": "/studies",
+            "Arguments": {"PatientID": "C137", "limit": "1"},
+        }))
         .return_status(200)
-        .return_header("Content-Type", "application/json")
-        .return_body(body)
+        .return_body(r#"[{"0020000D": {"vr": "UI", "Value": ["1.2.3"]}}]"#)
+        .create_on(&mock_server);
+    let m2 = Mock::new()
+        .expect_method(Method::POST)
+        .expect_path("/dicom-web/servers/foo/get")
+        .expect_json_body(&json!({"Uri": "/studies/1.2.3/metadata"}))
+        .return_status(200)
+        .return_body("[]")
         .create_on(&mock_server);
 
     let cl = Client::new(url);
+    let dicomweb = cl.dicomweb();
+    let query = orthanc::dicomweb::QidoQuery::default()
+        .filter("PatientID", "C137")
+        .limit(1);
+    let studies = dicomweb.search_server("foo", "/studies", &query).unwrap();
+    assert_eq!(studies[0].study_instance_uid(), Some("1.2.3".to_string()));
+    assert_eq!(
+        dicomweb
+            .get_from_server("foo", "/studies/1.2.3/metadata")
+            .unwrap(),
+        "[]"
+    );
 
-    let resp = cl.query_query("foo").unwrap();
-    let expected_resp: Value = serde_json::from_str(body).unwrap();
-    assert_eq!(resp, expected_resp);
-    assert_eq!(m.times_called(), 1);
+    assert_eq!(m1.times_called(), 1);
+    assert_eq!(m2.times_called(), 1);
 }
 
+#[cfg(feature = "transfers")]
 #[test]
-fn test_list_query_answers() {
+fn test_transfer_peers() {
     let mock_server = MockServer::start();
     let url = mock_server.url("");
 
     let m = Mock::new()
         .expect_method(Method::GET)
-        .expect_path("/queries/foo/answers")
+        .expect_path("/transfers/peers")
         .return_status(200)
-        .return_header("Content-Type", "application/json")
-        .return_body(r#"["0", "1", "42"]"#)
+        .return_body(r#"{"foo": "installed", "bar": "bidirectional", "baz": "disabled"}"#)
+        .create_on(&mock_server);
+    let plugins = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/plugins")
+        .return_status(200)
+        .return_body(r#"["dicom-web", "transfers"]"#)
         .create_on(&mock_server);
 
     let cl = Client::new(url);
-    let queries = cl.query_answers("foo").unwrap();
-
-    assert_eq!(queries, ["0", "1", "42"]);
-    assert_eq!(m.times_called(), 1);
+    assert_eq!(
+        cl.transfer_peers().unwrap(),
+        hashmap! {
+            "foo".to_string() => orthanc::transfers::PeerTransfers::Installed,
+            "bar".to_string() => orthanc::transfers::PeerTransfers::Bidirectional,
+            "baz".to_string() => orthanc::transfers::PeerTransfers::Disabled,
+        }
+    );
+    assert!(cl.peer_has_transfers("bar").unwrap());
+    assert!(!cl.peer_has_transfers("baz").unwrap());
+    assert_eq!(
+        cl.peer_has_transfers("qux").unwrap_err(),
+        Error {
+            message: "Unknown peer: qux".to_string(),
+            details: None,
+            kind: None,
+        }
+    );
+    assert_eq!(m.times_called(), 4);
+    assert_eq!(plugins.times_called(), 3);
 }
 
+#[cfg(feature = "transfers")]
 #[test]
-fn test_get_query_answer() {
+fn test_peer_has_transfers_no_plugin() {
     let mock_server = MockServer::start();
     let url = mock_server.url("");
 
-    let body = r#"
-        {
-            "0008,0018": {
-                "Name": "SOPInstanceUID",
-                "Type": "String",
-                "Value": "1.3.46.670589.11.1.5.0.10176.2012103017543590042"
-            },
-            "0008,0050": {
-                "Name": "AccessionNumber",
-                "Type": "String",
-                "Value": "REMOVED"
-            },
-            "0008,0052": {
-                "Name": "QueryRetrieveLevel",
-                "Type": "String",
-                "Value": "IMAGE"
-            },
-            "0008,0054": {
-                "Name": "RetrieveAETitle",
-                "Type": "String",
-                "Value": "ORTHANC"
-            }
-        }
-    "#;
-
-    let m = Mock::new()
+    let m1 = Mock::new()
         .expect_method(Method::GET)
-        .expect_path("/queries/foo/answers/0/content")
+        .expect_path("/transfers/peers")
+        .return_status(404)
+        .create_on(&mock_server);
+    let mut m2 = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/plugins")
         .return_status(200)
-        .return_header("Content-Type", "application/json")
-        .return_body(body)
+        .return_body(r#"["dicom-web"]"#)
         .create_on(&mock_server);
 
     let cl = Client::new(url);
+    assert!(!cl.peer_has_transfers("foo").unwrap());
+    assert_eq!(m1.times_called(), 0);
+    assert_eq!(m2.times_called(), 1);
+    m2.delete();
 
-    let resp = cl.query_answer("foo", "0").unwrap();
-    let expected_resp: Value = serde_json::from_str(body).unwrap();
-    assert_eq!(resp, expected_resp);
-    assert_eq!(m.times_called(), 1);
+    // Failing to list the plugins is not mistaken for the plugin being absent
+    Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/plugins")
+        .return_status(500)
+        .create_on(&mock_server);
+    assert_eq!(
+        cl.peer_has_transfers("foo").unwrap_err(),
+        Error {
+            message: "API error: 500 Internal Server Error".to_string(),
+            details: None,
+            kind: None,
+        }
+    );
 }
 
+#[cfg(feature = "transfers")]
 #[test]
-fn test_retrieve_query_answer() {
+fn test_transfer_send() {
     let mock_server = MockServer::start();
     let url = mock_server.url("");
 
     let m = Mock::new()
         .expect_method(Method::POST)
-        .expect_path("/queries/foo/answers/0/retrieve")
+        .expect_path("/transfers/send")
+        .expect_json_body(&json!({
+            "Peer": "foo",
+            "Resources": [{"Level": "Study", "ID": "bar"}, {"Level": "Instance", "ID": "baz"}],
+            "Compression": "gzip",
+            "Priority": 5,
+        }))
         .return_status(200)
-        .return_header("Content-Type", "application/json")
+        .return_body(r#"{"ID": "qux", "Path": "/jobs/qux"}"#)
         .create_on(&mock_server);
 
     let cl = Client::new(url);
-    let resp = cl.retrieve_query_answer("foo", "0", None).unwrap();
+    let job = cl
+        .transfer_send(
+            "foo",
+            &[
+                orthanc::transfers::TransferResource::new(EntityKind::Study, "bar"),
+                orthanc::transfers::TransferResource::new(EntityKind::Instance, "baz"),
+            ],
+            &orthanc::transfers::TransferOptions::default()
+                .compression(orthanc::transfers::Compression::Gzip)
+                .priority(5),
+        )
+        .unwrap();
 
-    assert_eq!(resp, ());
+    assert_eq!(
+        job,
+        CreatedJob {
+            id: "qux".to_string(),
+            path: "/jobs/qux".to_string(),
+        }
+    );
     assert_eq!(m.times_called(), 1);
 }
 
+#[cfg(feature = "transfers")]
 #[test]
-fn test_retrieve_query_answer_with_target_aet() {
+fn test_transfer_pull() {
     let mock_server = MockServer::start();
     let url = mock_server.url("");
 
     let m = Mock::new()
         .expect_method(Method::POST)
-        .expect_path("/queries/foo/answers/0/retrieve")
-        .expect_body(r#"{"TargetAet":"BAZ"}"#)
+        .expect_path("/transfers/pull")
+        .expect_json_body(&json!({
+            "Peer": "foo",
+            "Resources": [{"Level": "Patient", "ID": "bar"}],
+            "Compression": "none",
+        }))
         .return_status(200)
-        .return_header("Content-Type", "application/json")
+        .return_body(r#"{"ID": "qux", "Path": "/jobs/qux"}"#)
         .create_on(&mock_server);
 
     let cl = Client::new(url);
-    let resp = cl.retrieve_query_answer("foo", "0", Some("BAZ")).unwrap();
+    let job = cl
+        .transfer_pull(
+            "foo",
+            &[orthanc::transfers::TransferResource::new(
+                EntityKind::Patient,
+                "bar",
+            )],
+            &orthanc::transfers::TransferOptions::default()
+                .compression(orthanc::transfers::Compression::None),
+        )
+        .unwrap();
 
-    assert_eq!(resp, ());
+    assert_eq!(job.id, "qux");
     assert_eq!(m.times_called(), 1);
 }
 
+#[cfg(feature = "worklists")]
 #[test]
-fn test_retrieve_query_answers() {
+fn test_worklists() {
     let mock_server = MockServer::start();
     let url = mock_server.url("");
 
-    let m = Mock::new()
-        .expect_method(Method::POST)
-        .expect_path("/queries/foo/retrieve")
+    let m1 = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/worklists")
         .return_status(200)
-        .return_header("Content-Type", "application/json")
+        .return_body(
+            r#"
+                [
+                    {
+                        "ID": "foo",
+                        "Tags": {"PatientID": "C137", "PatientName": "Sanchez^Rick"}
+                    }
+                ]
+            "#,
+        )
+        .create_on(&mock_server);
+    let m2 = Mock::new()
+        .expect_method(Method::GET)
+        .expect_path("/worklists/foo")
+        .return_status(200)
+        .return_body(r#"{"ID": "foo", "Tags": {"PatientID": "C137"}, "Foo": 42}"#)
         .create_on(&mock_server);
 
     let cl = Client::new(url);
-    let resp = cl.retrieve_query_answers("foo", None).unwrap();
+    let worklists = cl.worklists().list().unwrap();
+    assert_eq!(
+        worklists,
+        vec![orthanc::worklists::Worklist {
+            id: "foo".to_string(),
+            tags: hashmap! {
+                "PatientID".to_string() => json!("C137"),
+                "PatientName".to_string() => json!("Sanchez^Rick"),
+            },
+            extra: HashMap::new(),
+        }]
+    );
+    let worklist = cl.worklists().get("foo").unwrap();
+    assert_eq!(worklist.tags["PatientID"], json!("C137"));
+    assert_eq!(worklist.extra["Foo"], json!(42));
 
-    assert_eq!(resp, ());
-    assert_eq!(m.times_called(), 1);
+    assert_eq!(m1.times_called(), 1);
+    assert_eq!(m2.times_called(), 1);
 }
 
+#[cfg(feature = "worklists")]
 #[test]
-fn test_retrieve_query_answers_with_target_aet() {
+fn test_worklists_create_delete() {
     let mock_server = MockServer::start();
     let url = mock_server.url("");
 
-    let m = Mock::new()
+    let m1 = Mock::new()
         .expect_method(Method::POST)
-        .expect_path("/queries/foo/retrieve")
-        .expect_body(r#"{"TargetAet":"BAZ"}"#)
+        .expect_path("/worklists/create")
+        .expect_json_body(&json!({
+            "Tags": {
+                "PatientID": "C137",
+                "ScheduledProcedureStepSequence": [{"Modality": "MR"}],
+            }
+        }))
+        .return_status(200)
+        .return_body(r#"{"ID": "foo", "Path": "/worklists/foo"}"#)
+        .create_on(&mock_server);
+    let m2 = Mock::new()
+        .expect_method(Method::DELETE)
+        .expect_path("/worklists/foo")
         .return_status(200)
-        .return_header("Content-Type", "application/json")
         .create_on(&mock_server);
 
     let cl = Client::new(url);
-    let resp = cl.retrieve_query_answers("foo", Some("BAZ")).unwrap();
+    let tags = hashmap! {
+        "PatientID".to_string() => json!("C137"),
+        "ScheduledProcedureStepSequence".to_string() => json!([{"Modality": "MR"}]),
+    };
+    let worklist = cl.worklists().create(&tags).unwrap();
+    assert_eq!(
+        worklist,
+        orthanc::worklists::CreatedWorklist {
+            id: "foo".to_string(),
+            path: "/worklists/foo".to_string(),
+        }
+    );
+    cl.worklists().delete(&worklist.id).unwrap();
 
-    assert_eq!(resp, ());
-    assert_eq!(m.times_called(), 1);
+    assert_eq!(m1.times_called(), 1);
+    assert_eq!(m2.times_called(), 1);
 }
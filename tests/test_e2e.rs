@@ -3,16 +3,15 @@ use maplit::hashmap;
 use orthanc::entity::*;
 use orthanc::error::ApiError;
 use orthanc::models::*;
+use orthanc::pseudonymize::{MemoryStore, Pseudonymizer};
 use orthanc::Client;
 use orthanc::Error;
 use regex::Regex;
-use reqwest;
-use serde_json;
 use serde_json::{from_str, json, Value};
 use std::env;
 use std::fs;
 use std::io::BufReader;
-use zip;
+use std::time::Duration;
 
 const DEFAULT_DINO_HOST: &str = "dino"; // docker-compose
 const DEFAULT_DINO_PORT: &str = "5252";
@@ -53,6 +52,19 @@ fn client_peer() -> Client {
     )
 }
 
+// Orthanc with the official plugins (e.g. DICOMweb) loaded
+#[cfg(feature = "dicomweb")]
+fn client_plugins() -> Client {
+    Client::new(env::var("ORC_PLUGINS_ADDRESS").unwrap()).auth(
+        env::var("ORC_ORTHANC_USERNAME").unwrap(),
+        env::var("ORC_ORTHANC_PASSWORD").unwrap(),
+    )
+}
+
+fn supports(feature: Feature) -> bool {
+    client_main().capabilities().unwrap().supports(feature)
+}
+
 fn client_modality_one() -> Client {
     Client::new(env::var("ORC_MODALITY_ONE_ADDRESS").unwrap()).auth(
         env::var("ORC_ORTHANC_USERNAME").unwrap(),
@@ -84,43 +96,35 @@ fn first_instance() -> String {
 }
 
 fn find_instance_by_sop_instance_uid(sop_instance_uid: &str) -> Option<Instance> {
-    let instances = client_main().instances_expanded().unwrap();
-    for i in instances {
-        if i.main_dicom_tags["SOPInstanceUID"] == sop_instance_uid {
-            return Some(i);
-        }
-    }
-    return None;
+    client_main()
+        .instances_expanded()
+        .unwrap()
+        .into_iter()
+        .find(|i| i.main_dicom_tags["SOPInstanceUID"] == sop_instance_uid)
 }
 
 fn find_series_by_series_instance_uid(series_instance_uid: &str) -> Option<Series> {
-    let series = client_main().series_expanded().unwrap();
-    for s in series {
-        if s.main_dicom_tags["SeriesInstanceUID"] == series_instance_uid {
-            return Some(s);
-        }
-    }
-    return None;
+    client_main()
+        .series_expanded()
+        .unwrap()
+        .into_iter()
+        .find(|s| s.main_dicom_tags["SeriesInstanceUID"] == series_instance_uid)
 }
 
 fn find_study_by_study_instance_uid(study_instance_uid: &str) -> Option<Study> {
-    let studies = client_main().studies_expanded().unwrap();
-    for s in studies {
-        if s.main_dicom_tags["StudyInstanceUID"] == study_instance_uid {
-            return Some(s);
-        }
-    }
-    return None;
+    client_main()
+        .studies_expanded()
+        .unwrap()
+        .into_iter()
+        .find(|s| s.main_dicom_tags["StudyInstanceUID"] == study_instance_uid)
 }
 
 fn find_patient_by_patient_id(patient_id: &str) -> Option<Patient> {
-    let patients = client_main().patients_expanded().unwrap();
-    for p in patients {
-        if p.main_dicom_tags["PatientID"] == patient_id {
-            return Some(p);
-        }
-    }
-    return None;
+    client_main()
+        .patients_expanded()
+        .unwrap()
+        .into_iter()
+        .find(|p| p.main_dicom_tags["PatientID"] == patient_id)
 }
 
 fn get(url: &str) -> String {
@@ -165,7 +169,7 @@ fn assert_tag_value_matches(path: &str, tag_id: &str, pattern: &str) {
     let re = Regex::new(pattern).unwrap();
     let obj = open_file(path).unwrap();
     let tag_value = obj.element_by_name(tag_id).unwrap().to_str().unwrap();
-    assert!(re.is_match(&tag_value.trim()));
+    assert!(re.is_match(tag_value.trim()));
 }
 
 fn assert_tag_is_empty(path: &str, tag_id: &str) {
@@ -183,7 +187,7 @@ fn assert_tag_is_empty(path: &str, tag_id: &str) {
 fn assert_tag_is_absent(path: &str, tag_id: &str) {
     let obj = open_file(path).unwrap();
     let res = obj.element_by_name(tag_id).unwrap_err();
-    assert!(matches!(res, DicomError::NoSuchDataElementAlias{..}));
+    assert!(matches!(res, DicomError::NoSuchDataElementAlias { .. }));
 }
 
 fn expected_response(path: &str) -> Value {
@@ -203,7 +207,8 @@ fn test_no_auth() {
         resp.unwrap_err(),
         Error {
             message: "API error: 401 Unauthorized".to_string(),
-            details: None
+            details: None,
+            kind: None,
         }
     );
 }
@@ -216,7 +221,8 @@ fn test_wrong_auth() {
         resp.unwrap_err(),
         Error {
             message: "API error: 401 Unauthorized".to_string(),
-            details: None
+            details: None,
+            kind: None,
         }
     );
 }
@@ -229,6 +235,26 @@ fn test_get_system_info() {
     );
 }
 
+#[test]
+fn test_capabilities() {
+    let system = client_main().system().unwrap();
+    let capabilities = client_main().capabilities().unwrap();
+    assert_eq!(capabilities.api_version, system.api_version);
+    assert_eq!(capabilities.version.unwrap().to_string(), system.version);
+}
+
+#[test]
+fn test_plugins() {
+    let plugins = client_main().plugins().unwrap();
+    assert_eq!(json!(plugins), expected_response("plugins"));
+    for id in plugins {
+        assert_eq!(
+            json!(client_main().plugin(&id).unwrap()),
+            expected_response(&format!("plugins/{}", id))
+        );
+    }
+}
+
 #[test]
 fn test_list_patients() {
     assert_eq!(
@@ -436,6 +462,282 @@ fn test_get_intance_dicom() {
     assert_tag_value_contains("/tmp/instance_dicom", "SOPInstanceUID", SOP_INSTANCE_UID);
 }
 
+#[test]
+fn test_download_to_file() {
+    let instance = find_instance_by_sop_instance_uid(SOP_INSTANCE_UID).unwrap();
+    client_main()
+        .download_to_file(EntityKind::Instance, &instance.id, "/tmp/instance_download")
+        .unwrap();
+    assert_tag_value_contains("/tmp/instance_download", "SOPInstanceUID", SOP_INSTANCE_UID);
+
+    client_main()
+        .download_to_file(
+            EntityKind::Series,
+            &instance.parent_series,
+            "/tmp/series_download",
+        )
+        .unwrap();
+    let file = fs::File::open("/tmp/series_download").unwrap();
+    let zip = zip::ZipArchive::new(file).unwrap();
+    assert!(!zip.is_empty());
+}
+
+#[test]
+fn test_export_to_dir() {
+    let instance = find_instance_by_sop_instance_uid(SOP_INSTANCE_UID).unwrap();
+    let dir = "/tmp/orthanc_export";
+    let _ = fs::remove_dir_all(dir);
+    let path = format!(
+        "{}/{}/{}/{}/{}.dcm",
+        dir, PATIENT_ID, STUDY_INSTANCE_UID, SERIES_INSTANCE_UID, SOP_INSTANCE_UID
+    );
+
+    let report = client_main()
+        .export_to_dir(
+            &instance.id,
+            EntityKind::Instance,
+            dir,
+            &ExportLayout::default(),
+        )
+        .unwrap();
+    assert_eq!(report.exported, vec![std::path::PathBuf::from(&path)]);
+    assert_tag_value_contains(&path, "SOPInstanceUID", SOP_INSTANCE_UID);
+
+    let report = client_main()
+        .export_to_dir(
+            &instance.parent_series,
+            EntityKind::Series,
+            dir,
+            &ExportLayout::default(),
+        )
+        .unwrap();
+    assert_eq!(report.skipped, vec![std::path::PathBuf::from(&path)]);
+}
+
+#[test]
+fn test_generic_entity_operations() {
+    let instance = find_instance_by_sop_instance_uid(SOP_INSTANCE_UID).unwrap();
+    assert_eq!(
+        client_main().get::<Instance>(&instance.id).unwrap(),
+        instance
+    );
+    assert_eq!(
+        client_main()
+            .get::<Series>(&instance.parent_series)
+            .unwrap(),
+        client_main().series(&instance.parent_series).unwrap()
+    );
+    assert_eq!(
+        client_main().list::<Study>().unwrap(),
+        client_main().studies().unwrap()
+    );
+    assert_eq!(
+        client_main().list_expanded::<Patient>().unwrap(),
+        client_main().patients_expanded().unwrap()
+    );
+
+    let mut data = vec![];
+    client_main()
+        .download::<Instance, _>(&instance.id, &mut data)
+        .unwrap();
+    let mut expected = vec![];
+    client_main()
+        .instance_dicom(&instance.id, &mut expected)
+        .unwrap();
+    assert_eq!(data, expected);
+}
+
+#[test]
+fn test_hierarchy_navigation() {
+    let instance = find_instance_by_sop_instance_uid(SOP_INSTANCE_UID).unwrap();
+    let series = client_main().instance_series(&instance.id).unwrap();
+    assert_eq!(series.id, instance.parent_series);
+    let study = client_main().series_study(&series.id).unwrap();
+    assert_eq!(study.id, series.parent_study);
+    let patient = client_main().instance_patient(&instance.id).unwrap();
+    assert_eq!(patient.id, study.parent_patient);
+    assert_eq!(client_main().study_patient(&study.id).unwrap(), patient);
+    assert!(client_main()
+        .study_instances(&study.id)
+        .unwrap()
+        .contains(&instance));
+
+    let tree = client_main().resource_tree(&patient.id).unwrap();
+    assert_eq!(tree.patient, patient);
+    assert!(tree.instances().any(|i| i.id == instance.id));
+    assert_eq!(
+        tree.instances().count(),
+        client_main().patient_instances(&patient.id).unwrap().len()
+    );
+}
+
+#[test]
+fn test_pseudonymizer() {
+    let client = client_main();
+    let study = find_study_by_study_instance_uid(STUDY_INSTANCE_UID).unwrap();
+    let mut pseudonymizer = Pseudonymizer::new(&client, MemoryStore::new());
+
+    let first = pseudonymizer.anonymize::<Study>(&study.id, None).unwrap();
+    let second = pseudonymizer.anonymize::<Study>(&study.id, None).unwrap();
+    assert_eq!(first.id, second.id);
+    assert_eq!(first.patient_id, second.patient_id);
+
+    let mapping = pseudonymizer.mapping().unwrap();
+    let anonymized = client.study(&first.id).unwrap();
+    assert_eq!(
+        anonymized.main_dicom_tag("StudyInstanceUID").unwrap(),
+        mapping["StudyInstanceUID"][STUDY_INSTANCE_UID]
+    );
+    assert_eq!(
+        anonymized.main_dicom_tag("PatientID").unwrap(),
+        mapping["PatientID"][PATIENT_ID]
+    );
+
+    client.delete_study(&first.id).unwrap();
+}
+
+#[test]
+fn test_bulk_modify() {
+    let study = find_study_by_study_instance_uid(STUDY_INSTANCE_UID).unwrap();
+    let result = client_main().bulk_modify(
+        &[&study.id],
+        Modification::default().replace("InstitutionName", "Bulk"),
+    );
+    if !supports(Feature::BulkTools) {
+        assert!(result.unwrap_err().unsupported().is_some());
+        return;
+    }
+    let result = result.unwrap();
+    assert_eq!(result.failed_instances_count, 0);
+    assert!(result.instances_count > 0);
+    let modified = &result.resources[&study.id];
+    assert_eq!(modified.entity, EntityKind::Study);
+    assert_ne!(modified.id, study.id);
+    client_main().delete_study(&modified.id).unwrap();
+}
+
+#[test]
+fn test_bulk_anonymize_async() {
+    let study = find_study_by_study_instance_uid(STUDY_INSTANCE_UID).unwrap();
+    let job = client_main().bulk_anonymize_async(&[&study.id], None);
+    if !supports(Feature::BulkTools) {
+        assert!(job.unwrap_err().unsupported().is_some());
+        return;
+    }
+    let job = job.unwrap();
+    let job = client_main()
+        .wait_for_job(
+            &job.id,
+            Duration::from_millis(100),
+            Duration::from_secs(60),
+            |_| (),
+        )
+        .unwrap();
+    let result = client_main().bulk_modification_result(&job).unwrap();
+    assert_eq!(result.failed_instances_count, 0);
+    let anonymized = &result.resources[&study.id];
+    assert_eq!(
+        client_main().study(&anonymized.id).unwrap().anonymized_from,
+        Some(study.id)
+    );
+    client_main().delete_study(&anonymized.id).unwrap();
+}
+
+#[test]
+fn test_store_modified_instance() {
+    let instance = find_instance_by_sop_instance_uid(SOP_INSTANCE_UID).unwrap();
+    let result = client_main()
+        .store_modified_instance(
+            &instance.id,
+            Modification::default().replace("InstitutionName", "Stored"),
+            false,
+        )
+        .unwrap();
+    assert_ne!(result.id, instance.id);
+    assert_eq!(
+        client_main().instance(&result.id).unwrap().modified_from,
+        Some(instance.id.clone())
+    );
+    // The original is kept
+    client_main().instance(&instance.id).unwrap();
+    client_main().delete_instance(&result.id).unwrap();
+}
+
+#[test]
+fn test_delete_dry_run() {
+    let study = find_study_by_study_instance_uid(STUDY_INSTANCE_UID).unwrap();
+    let options = DeleteOptions {
+        dry_run: true,
+        require_stable: false,
+    };
+    let report = client_main()
+        .delete_with_options::<Study>(&study.id, &options)
+        .unwrap();
+    let instances = client_main().study_instances(&study.id).unwrap();
+    assert_eq!(report.instances.len(), instances.len());
+    assert_eq!(
+        report.size,
+        instances.iter().map(|i| i.file_size).sum::<u64>()
+    );
+    assert!(report.remaining_ancestors.contains_key(&study.id));
+
+    let report = client_main()
+        .bulk_delete_with_options(&[(EntityKind::Study, study.id.as_str())], &options)
+        .unwrap();
+    assert_eq!(report.instances.len(), instances.len());
+    // Nothing is deleted in a dry run
+    client_main().study(&study.id).unwrap();
+}
+
+#[test]
+fn test_patient_protection() {
+    let patient = find_patient_by_patient_id(PATIENT_ID).unwrap();
+    assert!(!client_main().is_patient_protected(&patient.id).unwrap());
+
+    // Protecting twice is fine
+    client_main().protect_patient(&patient.id).unwrap();
+    client_main().protect_patient(&patient.id).unwrap();
+    assert!(client_main().is_patient_protected(&patient.id).unwrap());
+
+    client_main().unprotect_patient(&patient.id).unwrap();
+    assert!(!client_main().is_patient_protected(&patient.id).unwrap());
+
+    let resp = client_main().protect_patient("foo");
+    assert_eq!(resp.unwrap_err().details.unwrap().http_status, 404);
+    let resp = client_main().is_patient_protected("foo");
+    assert_eq!(resp.unwrap_err().details.unwrap().http_status, 404);
+}
+
+#[test]
+fn test_labels() {
+    let series = find_series_by_series_instance_uid(SERIES_INSTANCE_UID).unwrap();
+    let resp = client_main().add_label(EntityKind::Series, &series.id, "e2e_label-1");
+    if !supports(Feature::Labels) {
+        assert!(resp.unwrap_err().unsupported().is_some());
+        return;
+    }
+    resp.unwrap();
+    assert!(client_main()
+        .labels(EntityKind::Series, &series.id)
+        .unwrap()
+        .contains(&"e2e_label-1".to_string()));
+    assert!(client_main()
+        .all_labels()
+        .unwrap()
+        .contains(&"e2e_label-1".to_string()));
+
+    client_main()
+        .remove_label(EntityKind::Series, &series.id, "e2e_label-1")
+        .unwrap();
+    assert!(!client_main()
+        .labels(EntityKind::Series, &series.id)
+        .unwrap()
+        .contains(&"e2e_label-1".to_string()));
+
+    let resp = client_main().add_label(EntityKind::Series, &series.id, "e2e/label");
+    assert_eq!(resp.unwrap_err().message, "Invalid label: e2e/label");
+}
+
 #[test]
 fn test_delete() {
     let instance = find_instance_by_sop_instance_uid(SOP_INSTANCE_UID_DELETE).unwrap();
@@ -461,6 +763,7 @@ fn test_delete() {
         Error {
             message: "API error: 404 Not Found".to_string(),
             details: None,
+            kind: None,
         },
     );
 
@@ -482,6 +785,7 @@ fn test_delete() {
         Error {
             message: "API error: 404 Not Found".to_string(),
             details: None,
+            kind: None,
         },
     );
 
@@ -503,6 +807,7 @@ fn test_delete() {
         Error {
             message: "API error: 404 Not Found".to_string(),
             details: None,
+            kind: None,
         },
     );
 
@@ -520,6 +825,7 @@ fn test_delete() {
         Error {
             message: "API error: 404 Not Found".to_string(),
             details: None,
+            kind: None,
         },
     );
 }
@@ -537,6 +843,7 @@ fn test_modify_instance() {
         replace: Some(replace),
         remove: Some(remove),
         force: None,
+        ..Default::default()
     };
     let path = "/tmp/modified_instance";
     let mut file = fs::File::create(path).unwrap();
@@ -568,6 +875,7 @@ fn test_modify_series() {
         replace: Some(replace),
         remove: Some(remove),
         force: None,
+        ..Default::default()
     };
     let resp = client_main()
         .modify_series(&series.id, modification)
@@ -599,6 +907,7 @@ fn test_modify_study() {
         replace: Some(replace),
         remove: Some(remove),
         force: None,
+        ..Default::default()
     };
     let resp = client_main().modify_study(&study.id, modification).unwrap();
     let modified_study = client_main().study(&resp.id).unwrap();
@@ -629,6 +938,7 @@ fn test_modify_patient() {
         replace: Some(replace),
         remove: Some(remove),
         force: Some(true),
+        ..Default::default()
     };
     let resp = client_main()
         .modify_patient(&patient.id, modification)
@@ -664,6 +974,7 @@ fn test_modify_patient_keep_patient_id() {
         replace: Some(replace),
         remove: Some(remove),
         force: Some(true),
+        ..Default::default()
     };
     let resp = client_main()
         .modify_patient(&patient.id, modification)
@@ -687,6 +998,7 @@ fn test_modify_without_patient_id() {
         replace: Some(replace),
         remove: None,
         force: None,
+        ..Default::default()
     };
     let resp = client_main().modify_patient(&patient.id, modification);
 
@@ -694,7 +1006,7 @@ fn test_modify_without_patient_id() {
         resp.unwrap_err(),
         Error {
             message: "API error: 400 Bad Request".to_string(),
-            details: Some(ApiError {
+            details: Some(Box::new(ApiError {
                 method: "POST".to_string(),
                 uri: format!("/patients/{}/modify", &patient.id).to_string(),
                 message: "Bad request".to_string(),
@@ -706,7 +1018,8 @@ fn test_modify_without_patient_id() {
                 http_error: "Bad Request".to_string(),
                 orthanc_status: 8,
                 orthanc_error: "Bad request".to_string(),
-            },),
+            }),),
+            kind: None,
         },
     );
 }
@@ -721,6 +1034,7 @@ fn test_modify_patient_id_without_force() {
         replace: Some(replace),
         remove: None,
         force: None,
+        ..Default::default()
     };
     let resp = client_main().modify_patient(&patient.id, modification);
 
@@ -728,7 +1042,7 @@ fn test_modify_patient_id_without_force() {
         resp.unwrap_err(),
         Error {
             message: "API error: 400 Bad Request".to_string(),
-            details: Some(ApiError {
+            details: Some(Box::new(ApiError {
                 method: "POST".to_string(),
                 uri: format!("/patients/{}/modify", &patient.id).to_string(),
                 message: "Bad request".to_string(),
@@ -739,7 +1053,8 @@ fn test_modify_patient_id_without_force() {
                 http_error: "Bad Request".to_string(),
                 orthanc_status: 8,
                 orthanc_error: "Bad request".to_string(),
-            },),
+            }),),
+            kind: None,
         },
     );
 }
@@ -762,6 +1077,7 @@ fn test_anonymize_instance() {
         keep_private_tags: None,
         dicom_version: None,
         force: None,
+        ..Default::default()
     };
     let path = "/tmp/anonymized_instance";
     let mut file = fs::File::create(path).unwrap();
@@ -816,6 +1132,7 @@ fn test_anonymize_series() {
         keep_private_tags: None,
         dicom_version: None,
         force: None,
+        ..Default::default()
     };
     let resp = client_main()
         .anonymize_series(&series.id, Some(anonymization))
@@ -878,6 +1195,7 @@ fn test_anonymize_study() {
         keep_private_tags: None,
         dicom_version: None,
         force: None,
+        ..Default::default()
     };
     let resp = client_main()
         .anonymize_study(&study.id, Some(anonymization))
@@ -944,6 +1262,7 @@ fn test_anonymize_patient() {
         keep_private_tags: None,
         dicom_version: None,
         force: None,
+        ..Default::default()
     };
     let resp = client_main()
         .anonymize_patient(&patient.id, Some(anonymization))
@@ -999,6 +1318,7 @@ fn test_anonymize_without_force() {
         keep_private_tags: None,
         dicom_version: None,
         force: None,
+        ..Default::default()
     };
     let resp = client_main().anonymize_patient(&patient.id, Some(anonymization));
 
@@ -1006,7 +1326,7 @@ fn test_anonymize_without_force() {
         resp.unwrap_err(),
         Error {
             message: "API error: 400 Bad Request".to_string(),
-            details: Some(ApiError {
+            details: Some(Box::new(ApiError {
                 method: "POST".to_string(),
                 uri: format!("/patients/{}/anonymize", &patient.id).to_string(),
                 message: "Bad request".to_string(),
@@ -1017,7 +1337,8 @@ fn test_anonymize_without_force() {
                 http_error: "Bad Request".to_string(),
                 orthanc_status: 8,
                 orthanc_error: "Bad request".to_string(),
-            },),
+            }),),
+            kind: None,
         },
     );
 }
@@ -1034,6 +1355,7 @@ fn test_anonymize_with_force() {
         keep_private_tags: None,
         dicom_version: None,
         force: Some(true),
+        ..Default::default()
     };
     let resp = client_main()
         .anonymize_patient(&patient.id, Some(anonymization))
@@ -1065,6 +1387,203 @@ fn test_upload_dicom() {
     assert_eq!(resp.status, "AlreadyStored");
 }
 
+#[test]
+fn test_upload_reader() {
+    let file = fs::File::open(format!(
+        "{}/{}",
+        env::var("ORC_DATAFILES_PATH").unwrap_or("./data/dicom".to_string()),
+        UPLOAD_INSTANCE_FILE_PATH
+    ))
+    .unwrap();
+    let length = file.metadata().unwrap().len();
+
+    let resp = client_main().upload_reader(file, Some(length)).unwrap();
+    assert_eq!(resp.len(), 1);
+    assert!(resp[0].status == "Success" || resp[0].status == "AlreadyStored");
+}
+
+#[test]
+fn test_upload_dir() {
+    // All of these have already been uploaded by the populate script
+    let report = client_main()
+        .upload_dir(
+            format!(
+                "{}/initial",
+                env::var("ORC_DATAFILES_PATH").unwrap_or("./data/dicom".to_string())
+            ),
+            &UploadOptions {
+                concurrency: 4,
+                detect_duplicates: true,
+            },
+        )
+        .unwrap();
+    assert!(!report.files.is_empty());
+    assert_eq!(report.failed().count(), 0);
+    assert_eq!(report.duplicates.len(), report.files.len());
+}
+
+#[cfg(feature = "dicom")]
+#[test]
+fn test_upload_validated_dicom() {
+    let data = fs::read(format!(
+        "{}/{}",
+        env::var("ORC_DATAFILES_PATH").unwrap_or("./data/dicom".to_string()),
+        UPLOAD_INSTANCE_FILE_PATH
+    ))
+    .unwrap();
+
+    let ids = orthanc::dicom::DicomFile::parse(&data)
+        .unwrap()
+        .orthanc_ids();
+    let resp = client_main().upload_validated(&data).unwrap();
+    assert_eq!(resp.id, ids.instance);
+    assert_eq!(resp.parent_series, ids.series);
+    assert_eq!(resp.parent_study, ids.study);
+    assert_eq!(resp.parent_patient, ids.patient);
+
+    let resp = client_main().upload_validated(b"foobar");
+    assert_eq!(
+        resp.unwrap_err(),
+        Error {
+            message: "Invalid DICOM file: DICOM preamble is missing".to_string(),
+            details: None,
+            kind: Some(orthanc::ErrorKind::InvalidDicom(
+                orthanc::dicom::ValidationError::MissingPreamble
+            )),
+        },
+    );
+}
+
+#[cfg(feature = "dicomweb")]
+#[test]
+fn test_dicomweb() {
+    let path = format!(
+        "{}/{}",
+        env::var("ORC_DATAFILES_PATH").unwrap_or("./data/dicom".to_string()),
+        UPLOAD_INSTANCE_FILE_PATH
+    );
+    let client = client_plugins();
+    let dicomweb = client.dicomweb();
+
+    // STOW-RS
+    let result = dicomweb.store_files(&[&path]).unwrap();
+    assert!(result.failed.is_empty());
+    assert_eq!(result.referenced.len(), 1);
+    let sop_instance_uid = result.referenced[0].sop_instance_uid().unwrap();
+
+    // QIDO-RS
+    let query =
+        orthanc::dicomweb::QidoQuery::default().filter("SOPInstanceUID", &sop_instance_uid);
+    let instances = dicomweb.search_instances(&query).unwrap();
+    assert_eq!(instances.len(), 1);
+    let study = instances[0].study_instance_uid().unwrap();
+    let series = instances[0].series_instance_uid().unwrap();
+    let studies = dicomweb
+        .search_studies(
+            &orthanc::dicomweb::QidoQuery::default().filter("StudyInstanceUID", &study),
+        )
+        .unwrap();
+    assert_eq!(studies.len(), 1);
+    let query =
+        orthanc::dicomweb::QidoQuery::default().filter("StudyInstanceUID", "1.2.3.4.0");
+    assert!(dicomweb.search_studies(&query).unwrap().is_empty());
+
+    // WADO-RS
+    let metadata = dicomweb
+        .instance_metadata(&study, &series, &sop_instance_uid)
+        .unwrap();
+    assert_eq!(metadata.sop_instance_uid(), Some(sop_instance_uid.clone()));
+    let data = dicomweb
+        .retrieve_instance(&study, &series, &sop_instance_uid)
+        .unwrap();
+    assert_eq!(&data[128..132], b"DICM");
+
+    let dir = env::temp_dir().join(format!("orthanc_e2e_dicomweb_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let count = dicomweb
+        .retrieve_study_to(&study, |i| fs::File::create(dir.join(format!("{}.dcm", i))))
+        .unwrap();
+    assert!(count >= 1);
+    for i in 0..count {
+        let retrieved = fs::read(dir.join(format!("{}.dcm", i))).unwrap();
+        assert_eq!(&retrieved[128..132], b"DICM");
+    }
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(dicomweb.retrieve_study(&study).unwrap().len(), count);
+}
+
+#[cfg(feature = "dicomweb")]
+#[test]
+fn test_dicomweb_servers() {
+    let data = fs::read(format!(
+        "{}/{}",
+        env::var("ORC_DATAFILES_PATH").unwrap_or("./data/dicom".to_string()),
+        UPLOAD_INSTANCE_FILE_PATH
+    ))
+    .unwrap();
+    let client = client_plugins();
+    let dicomweb = client.dicomweb();
+    let instance = client.upload(&data).unwrap();
+    let study = client.study(&instance.parent_study).unwrap();
+
+    // The server itself, as seen from inside its container. The password is fixed below
+    let server = orthanc::dicomweb::DicomWebServer {
+        url: "http://localhost:8042/dicom-web/".to_string(),
+        username: Some(env::var("ORC_ORTHANC_USERNAME").unwrap()),
+        password: Some("wrong".to_string()),
+        ..Default::default()
+    };
+    dicomweb.create_server("e2e_self", server).unwrap();
+    assert!(dicomweb
+        .servers()
+        .unwrap()
+        .contains(&"e2e_self".to_string()));
+
+    let mut server = dicomweb.servers_expanded().unwrap()["e2e_self"].clone();
+    assert_eq!(server.url, "http://localhost:8042/dicom-web/");
+    server.password = Some(env::var("ORC_ORTHANC_PASSWORD").unwrap());
+    dicomweb.modify_server("e2e_self", server).unwrap();
+
+    let job = dicomweb
+        .store_to_server("e2e_self", &[&instance.id])
+        .unwrap();
+    let job = client
+        .wait_for_job(
+            &job.id,
+            Duration::from_millis(100),
+            Duration::from_secs(60),
+            |_| (),
+        )
+        .unwrap();
+    assert_eq!(job.state, JobState::Success);
+
+    let resources = [orthanc::dicomweb::RemoteResource {
+        study: study
+            .main_dicom_tag("StudyInstanceUID")
+            .unwrap()
+            .to_string(),
+        series: None,
+    }];
+    let job = dicomweb
+        .retrieve_from_server("e2e_self", &resources)
+        .unwrap();
+    let job = client
+        .wait_for_job(
+            &job.id,
+            Duration::from_millis(100),
+            Duration::from_secs(60),
+            |_| (),
+        )
+        .unwrap();
+    assert_eq!(job.state, JobState::Success);
+
+    dicomweb.delete_server("e2e_self").unwrap();
+    assert!(!dicomweb
+        .servers()
+        .unwrap()
+        .contains(&"e2e_self".to_string()));
+}
+
 // These just test the method access
 #[test]
 fn test_get_dicom_tag_value_patient() {
@@ -1155,7 +1674,7 @@ fn _test_modalities() {
                     allow_c_store: Some(true),
                     allow_n_action: Some(true),
                     allow_n_event_report: Some(true),
-                    allow_transcoding: allow_transcoding,
+                    allow_transcoding,
                 }
             );
             created = true;
@@ -1265,7 +1784,7 @@ fn _test_modalities() {
                     allow_c_store: Some(true),
                     allow_n_action: Some(true),
                     allow_n_event_report: Some(true),
-                    allow_transcoding: allow_transcoding,
+                    allow_transcoding,
                 }
             );
             modified = true;
@@ -1288,9 +1807,9 @@ fn _test_peers() {
         url: "http://orthanc_peer:8029".to_string(),
         username: Some("orthanc".to_string()),
         password: Some("orthanc".to_string()),
-        http_headers: Some(
+        http_headers: Some(HttpHeaders::Values(
             hashmap! {"Foo".to_string() => "foo".to_string(), "Bar".to_string() => "bar".to_string()},
-        ),
+        )),
         certificate_file: None,
         certificate_key_file: None,
         certificate_key_password: None,
@@ -1306,7 +1825,10 @@ fn _test_peers() {
                     url: "http://orthanc_peer:8029/".to_string(),
                     username: Some("orthanc".to_string()),
                     password: None, // empty for security reasons
-                    http_headers: None,
+                    http_headers: Some(HttpHeaders::Names(vec![
+                        "Bar".to_string(),
+                        "Foo".to_string()
+                    ])),
                     certificate_file: None,
                     certificate_key_file: None,
                     certificate_key_password: None,
@@ -1348,7 +1870,10 @@ fn _test_peers() {
             url: "http://orthanc_peer:8029/".to_string(),
             username: Some("orthanc".to_string()),
             password: None, // empty for security reasons
-            http_headers: None,
+            http_headers: Some(HttpHeaders::Names(vec![
+                "Bar".to_string(),
+                "Foo".to_string()
+            ])),
             certificate_file: None,
             certificate_key_file: None,
             certificate_key_password: None,
@@ -1360,7 +1885,7 @@ fn _test_peers() {
             url: "http://orthanc_peer:8092/".to_string(),
             username: None,
             password: None,
-            http_headers: None,
+            http_headers: Some(HttpHeaders::Names(vec![])),
             certificate_file: None,
             certificate_key_file: None,
             certificate_key_password: None,
@@ -1388,7 +1913,7 @@ fn _test_peers() {
                     url: "http://random_peer:1234/".to_string(),
                     username: Some("foo".to_string()),
                     password: None, // empty for security reasons
-                    http_headers: None,
+                    http_headers: Some(HttpHeaders::Names(vec![])),
                     certificate_file: None,
                     certificate_key_file: None,
                     certificate_key_password: None,
@@ -1408,6 +1933,7 @@ fn _test_peers() {
 }
 
 #[test]
+#[allow(deprecated)]
 fn test_modality_echo() {
     let modality = Modality {
         aet: env::var("DINO_SCP_AET").unwrap_or(DEFAULT_DINO_AET.to_string()),
@@ -1430,9 +1956,24 @@ fn test_modality_echo() {
 
     assert_eq!(client_main().modality_echo("dino", None).unwrap(), ());
     assert_eq!(client_main().echo("dino", None).unwrap(), ());
+
+    let report = client_main().modality_echo_report("dino", None).unwrap();
+    assert_eq!(
+        report.remote_aet,
+        Some(env::var("DINO_SCP_AET").unwrap_or(DEFAULT_DINO_AET.to_string()))
+    );
+    assert_eq!(report.status, EchoStatus::Success);
+    assert_eq!(
+        client_main().check_all_modalities(None, 4).unwrap()["dino"]
+            .as_ref()
+            .unwrap()
+            .status,
+        EchoStatus::Success
+    );
 }
 
 #[test]
+#[allow(deprecated)]
 fn test_modality_store() {
     let modality = Modality {
         aet: env::var("DINO_SCP_AET").unwrap_or(DEFAULT_DINO_AET.to_string()),
@@ -1492,6 +2033,11 @@ fn test_peer_store() {
     };
     client_main().create_peer("orthanc-peer", peer).unwrap();
 
+    assert_eq!(
+        client_main().peer_system("orthanc-peer").unwrap(),
+        client_peer().system().unwrap()
+    );
+
     assert_eq!(client_peer().studies().unwrap().len(), 0);
 
     client_main()
@@ -1804,7 +2350,7 @@ fn test_modality_find() {
         .unwrap();
 
     // Upload instances to modality_one
-    for p in vec![QR_INSTANCE1_FILE_PATH, QR_INSTANCE2_FILE_PATH] {
+    for p in [QR_INSTANCE1_FILE_PATH, QR_INSTANCE2_FILE_PATH] {
         let data = fs::read(format!(
             "{}/{}",
             env::var("ORC_DATAFILES_PATH").unwrap_or("./data/dicom".to_string()),
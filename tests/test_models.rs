@@ -0,0 +1,185 @@
+use maplit::hashmap;
+use orthanc::entity::*;
+use orthanc::models::*;
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+
+/// Orthanc versions, the responses of which are reproduced in `tests/data/models`
+///
+/// The fixtures are recorded from the Docker images of each version with
+/// `make record_model_fixtures`. The values depend on the uploaded DICOM file, so the tests only
+/// check the fixtures against each other, not against fixed IDs.
+const VERSIONS: &[&str] = &["1.6.1", "1.9.1", "1.12.5"];
+
+fn fixture_path(version: &str, name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/data/models")
+        .join(version)
+        .join(format!("{}.json", name))
+}
+
+fn fixture<T: DeserializeOwned>(version: &str, name: &str) -> T {
+    let path = fixture_path(version, name);
+    let data = fs::read(&path).unwrap();
+    serde_json::from_slice(&data)
+        .unwrap_or_else(|e| panic!("Failed to decode {}: {}", path.display(), e))
+}
+
+fn has_labels(version: &str) -> bool {
+    version == "1.12.5"
+}
+
+#[test]
+fn test_system() {
+    for version in VERSIONS {
+        let system: System = fixture(version, "system");
+        assert_eq!(&system.version, version);
+        assert_eq!(system.name, "Orthanc");
+        assert_eq!(system.extra, HashMap::new());
+        assert_eq!(
+            Capabilities::from_system(&system).supports(Feature::Labels),
+            has_labels(version)
+        );
+        // Nothing is lost in a round trip
+        assert_eq!(
+            serde_json::to_value(&system).unwrap(),
+            fixture::<Value>(version, "system")
+        );
+    }
+
+    let system: System = fixture("1.12.5", "system");
+    assert_eq!(system.check_revisions, Some(false));
+    assert_eq!(system.maximum_storage_mode, Some("Recycle".to_string()));
+    assert_eq!(system.user_metadata, Some(HashMap::new()));
+    assert_eq!(system.capabilities.unwrap().has_labels, Some(true));
+}
+
+#[test]
+fn test_patient() {
+    for version in VERSIONS {
+        let patient: Patient = fixture(version, "patient");
+        let study: Study = fixture(version, "study");
+        assert_eq!(patient.entity, EntityKind::Patient);
+        assert_eq!(
+            patient.main_dicom_tag("PatientID"),
+            Some(study.patient_main_dicom_tags["PatientID"].as_str())
+        );
+        assert_eq!(patient.children(), &[study.id]);
+        assert_eq!(patient.labels.is_empty(), !has_labels(version));
+        assert_eq!(patient.extra, HashMap::new());
+    }
+}
+
+#[test]
+fn test_study() {
+    for version in VERSIONS {
+        let study: Study = fixture(version, "study");
+        let patient: Patient = fixture(version, "patient");
+        let series: Series = fixture(version, "series");
+        assert_eq!(study.entity, EntityKind::Study);
+        assert_eq!(study.parent_id(), Some(patient.id.as_str()));
+        assert_eq!(study.children(), &[series.id]);
+        assert_eq!(study.labels.is_empty(), !has_labels(version));
+        assert_eq!(study.extra, HashMap::new());
+    }
+}
+
+#[test]
+fn test_series() {
+    for version in VERSIONS {
+        let series: Series = fixture(version, "series");
+        let study: Study = fixture(version, "study");
+        let instance: Instance = fixture(version, "instance");
+        assert_eq!(series.entity, EntityKind::Series);
+        assert_eq!(series.parent_id(), Some(study.id.as_str()));
+        assert_eq!(series.children(), &[instance.id]);
+        assert_eq!(series.status, "Unknown");
+        assert_eq!(series.expected_number_of_instances, None);
+        assert_eq!(series.main_dicom_tag("Modality"), Some("MR"));
+        assert_eq!(series.labels.is_empty(), !has_labels(version));
+        assert_eq!(series.extra, HashMap::new());
+    }
+}
+
+#[test]
+fn test_instance() {
+    for version in VERSIONS {
+        let instance: Instance = fixture(version, "instance");
+        let series: Series = fixture(version, "series");
+        assert_eq!(instance.entity, EntityKind::Instance);
+        assert_eq!(instance.parent_id(), Some(series.id.as_str()));
+        assert!(instance.index().is_some());
+        assert!(instance.size() > 0);
+        assert_eq!(instance.labels.is_empty(), !has_labels(version));
+        assert_eq!(instance.extra, HashMap::new());
+    }
+}
+
+#[test]
+fn test_job() {
+    for version in VERSIONS {
+        let job: Job = fixture(version, "job");
+        let study: Study = fixture(version, "study");
+        assert_eq!(job.job_type, "Archive");
+        assert_eq!(job.content["ParentResources"], json!([study.id]));
+        assert_eq!(job.state, JobState::Success);
+        assert_eq!(job.progress, 100);
+    }
+}
+
+#[test]
+fn test_unknown_fields() {
+    let mut json = fixture::<Value>("1.12.5", "patient");
+    json["Revision"] = json!(3);
+    json["RequestedTags"] = json!({"PatientComments": "Foo"});
+
+    let patient: Patient = serde_json::from_value(json.clone()).unwrap();
+    assert_eq!(
+        patient.extra,
+        hashmap! {
+            "Revision".to_string() => json!(3),
+            "RequestedTags".to_string() => json!({"PatientComments": "Foo"}),
+        }
+    );
+    // Unknown fields are written back
+    let written = serde_json::to_value(&patient).unwrap();
+    assert_eq!(written["Revision"], json["Revision"]);
+    assert_eq!(written["RequestedTags"], json["RequestedTags"]);
+}
+
+#[test]
+fn test_unknown_variants() {
+    let mut json = fixture::<Value>("1.12.5", "instance");
+    json["Type"] = json!("Frame");
+    let instance: Instance = serde_json::from_value(json).unwrap();
+    assert_eq!(instance.entity, EntityKind::Unknown("Frame".to_string()));
+    assert_eq!(
+        serde_json::to_value(&instance.entity).unwrap(),
+        json!("Frame")
+    );
+
+    let mut json = fixture::<Value>("1.12.5", "job");
+    json["State"] = json!("Cancelled");
+    let job: Job = serde_json::from_value(json).unwrap();
+    assert_eq!(job.state, JobState::Unknown("Cancelled".to_string()));
+    assert_eq!(
+        serde_json::to_value(&job.state).unwrap(),
+        json!("Cancelled")
+    );
+
+    for (kind, name) in &[
+        (EntityKind::Patient, "Patient"),
+        (EntityKind::Study, "Study"),
+        (EntityKind::Series, "Series"),
+        (EntityKind::Instance, "Instance"),
+    ] {
+        assert_eq!(serde_json::to_value(kind).unwrap(), json!(name));
+        assert_eq!(
+            &serde_json::from_value::<EntityKind>(json!(name)).unwrap(),
+            kind
+        );
+    }
+}